@@ -6,15 +6,41 @@ use serde_json::Error as SerdeError;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
 
+use crate::php::PhpVersion;
 use crate::php_namespace::{PhpNamespace, SegmentPool};
 use crate::uri_ext::UriExt;
 
+#[derive(Deserialize)]
+struct RequireScheme {
+    require: Option<HashMap<String, String>>,
+}
+
 #[derive(Deserialize)]
 struct ComposerScheme {
     autoload: Option<AutoloadScheme>,
+    #[serde(rename(deserialize = "autoload-dev"))]
+    autoload_dev: Option<AutoloadScheme>,
+}
+
+/// `installed.json` is a list of packages in Composer 1, and an object holding it in Composer 2.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InstalledScheme {
+    Packages { packages: Vec<PackageScheme> },
+    List(Vec<PackageScheme>),
+}
+
+#[derive(Deserialize)]
+struct PackageScheme {
+    name: String,
+    autoload: Option<AutoloadScheme>,
+    #[serde(rename(deserialize = "install-path"))]
+    install_path: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -23,6 +49,7 @@ struct AutoloadScheme {
     psr4: Option<NamespacePathScheme>,
     #[serde(rename(deserialize = "psr-0"))]
     psr0: Option<NamespacePathScheme>,
+    classmap: Option<Vec<String>>,
     files: Option<Vec<String>>,
 }
 
@@ -36,8 +63,20 @@ enum PathScheme {
     MultiplePaths(Vec<String>),
 }
 
+impl PathScheme {
+    fn to_pathbufs(&self) -> Vec<PathBuf> {
+        match self {
+            PathScheme::SinglePath(p) => vec![PathBuf::from_str(p).unwrap()],
+            PathScheme::MultiplePaths(vec) => {
+                vec.iter().map(|p| PathBuf::from_str(p).unwrap()).collect()
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum AutoloadError {
+    BadRead(std::io::Error),
     BadDeserde(SerdeError),
     NoAutoload,
     NoPSR4,
@@ -56,6 +95,12 @@ impl PartialEq for AutoloadError {
     }
 }
 
+impl From<std::io::Error> for AutoloadError {
+    fn from(value: std::io::Error) -> Self {
+        Self::BadRead(value)
+    }
+}
+
 impl From<SerdeError> for AutoloadError {
     fn from(value: SerdeError) -> Self {
         Self::BadDeserde(value)
@@ -65,9 +110,10 @@ impl From<SerdeError> for AutoloadError {
 impl Display for AutoloadError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            AutoloadError::BadRead(e) => write!(f, "read error: {}", e),
             AutoloadError::BadDeserde(e) => write!(f, "serde error: {}", e),
             AutoloadError::NoAutoload => write!(f, "no autoload given"),
-            AutoloadError::NoPSR4 => write!(f, "no psr-4 in autoload"),
+            AutoloadError::NoPSR4 => write!(f, "no psr-4, psr-0, classmap or files in autoload"),
         }
     }
 }
@@ -90,6 +136,14 @@ type PSR4 = HashMap<PhpNamespace, Vec<PathBuf>>;
 #[derive(Debug, PartialEq)]
 pub struct Autoload {
     pub psr4: PSR4,
+    /// PSR-0 roots, as the directories each namespace's classes are in so they can be looked up
+    /// like PSR-4 ones. Underscores in class names still have to be split up; see
+    /// [`crate::class_file`].
+    pub psr0: PSR4,
+    /// Files and directories to look for classes in, whatever their names.
+    pub classmap: Vec<PathBuf>,
+    /// Files that are always loaded, usually declaring global functions.
+    pub files: Vec<PathBuf>,
 }
 
 impl Autoload {
@@ -152,28 +206,167 @@ impl Autoload {
         Err(ResolutionError::NamespaceNotFound(ns.clone()))
     }
 
-    pub fn from_reader<R>(rdr: R, pool: &mut SegmentPool) -> Result<Self, AutoloadError>
+    /// Read the autoload of a `composer.json` file, with every path made absolute from the
+    /// directory it's in; they're relative to that, not to wherever we were started from.
+    pub fn from_file(
+        composer_file: &Path,
+        pool: &mut SegmentPool,
+        include_dev: bool,
+    ) -> Result<Self, AutoloadError> {
+        let reader = BufReader::new(File::open(composer_file)?);
+        let mut autoload = Self::from_reader(reader, pool, include_dev)?;
+        autoload.anchor(composer_file.parent().unwrap_or(Path::new("")));
+
+        Ok(autoload)
+    }
+
+    /// Put every path under `root`, unless it's absolute already.
+    pub fn anchor(&mut self, root: &Path) {
+        let anchored = |path: &mut PathBuf| *path = normalized_path(&root.join(&path));
+        self.psr4
+            .values_mut()
+            .chain(self.psr0.values_mut())
+            .flatten()
+            .chain(&mut self.classmap)
+            .chain(&mut self.files)
+            .for_each(anchored);
+    }
+
+    /// Read the autoload of a `composer.json`, along with its `autoload-dev` if `include_dev`.
+    pub fn from_reader<R>(
+        rdr: R,
+        pool: &mut SegmentPool,
+        include_dev: bool,
+    ) -> Result<Self, AutoloadError>
     where
         R: std::io::Read,
     {
-        let mut psr4_ret = HashMap::new();
-
         let composer: ComposerScheme = serde_json::from_reader(rdr)?;
-        let autoload = composer.autoload.ok_or(AutoloadError::NoAutoload)?;
-        let psr4 = autoload.psr4.ok_or(AutoloadError::NoPSR4)?;
-        for (ns_str, paths) in &psr4.0 {
-            let ns = pool.intern_str(ns_str);
-            let paths = match paths {
-                PathScheme::SinglePath(p) => vec![PathBuf::from_str(p).unwrap()],
-                PathScheme::MultiplePaths(vec) => {
-                    vec.iter().map(|p| PathBuf::from_str(p).unwrap()).collect()
-                }
-            };
-            psr4_ret.insert(ns, paths);
+        let dev = composer.autoload_dev.filter(|_| include_dev);
+        if composer.autoload.is_none() && dev.is_none() {
+            return Err(AutoloadError::NoAutoload);
+        }
+        let autoload = Self::from_schemes(composer.autoload.iter().chain(&dev), pool);
+
+        if autoload.psr4.is_empty()
+            && autoload.psr0.is_empty()
+            && autoload.classmap.is_empty()
+            && autoload.files.is_empty()
+        {
+            return Err(AutoloadError::NoPSR4);
+        }
+
+        Ok(autoload)
+    }
+
+    /// The autoloads of the packages in Composer's `vendor/composer/installed.json`, each anchored
+    /// at where the package is installed.
+    pub fn from_installed(
+        installed_file: &Path,
+        pool: &mut SegmentPool,
+    ) -> Result<Vec<Self>, AutoloadError> {
+        let reader = BufReader::new(File::open(installed_file)?);
+        let packages = match serde_json::from_reader(reader)? {
+            InstalledScheme::Packages { packages } => packages,
+            InstalledScheme::List(packages) => packages,
+        };
+        let composer_dir = installed_file.parent().unwrap_or(Path::new(""));
+
+        Ok(packages
+            .into_iter()
+            .filter_map(|package| {
+                let autoload = package.autoload?;
+                // Composer 1 didn't write down where packages went, since it was always here
+                let root = match package.install_path {
+                    Some(install_path) => composer_dir.join(install_path),
+                    None => composer_dir.parent()?.join(package.name),
+                };
+                let mut autoload = Self::from_schemes([&autoload].into_iter(), pool);
+                autoload.anchor(&root);
+                Some(autoload)
+            })
+            .collect())
+    }
+
+    fn from_schemes<'a>(
+        schemes: impl Iterator<Item = &'a AutoloadScheme>,
+        pool: &mut SegmentPool,
+    ) -> Self {
+        let mut psr4_ret: PSR4 = HashMap::new();
+        let mut psr0_ret: PSR4 = HashMap::new();
+        let mut classmap = Vec::new();
+        let mut files = Vec::new();
+
+        for autoload in schemes {
+            for (ns_str, paths) in autoload.psr4.iter().flat_map(|psr4| &psr4.0) {
+                let ns = pool.intern_str(ns_str);
+                psr4_ret.entry(ns).or_default().extend(paths.to_pathbufs());
+            }
+            // PSR-0 keeps the whole namespace in the path, so `Foo\` in `src/` is PSR-4's `Foo\`
+            // in `src/Foo`. A prefix can also end partway through a class name, like `Twig_`;
+            // only its namespace part narrows down the directory.
+            for (prefix, paths) in autoload.psr0.iter().flat_map(|psr0| &psr0.0) {
+                let namespace = prefix
+                    .rsplit_once('\\')
+                    .map_or("", |(namespace, _)| namespace);
+                let ns = pool.intern_str(namespace);
+                let dirs = paths.to_pathbufs().into_iter().map(|dir| {
+                    ns.0.iter()
+                        .fold(dir, |dir, segment| dir.join(segment.as_ref()))
+                });
+                psr0_ret.entry(ns.clone()).or_default().extend(dirs);
+            }
+            classmap.extend(autoload.classmap.iter().flatten().map(PathBuf::from));
+            files.extend(autoload.files.iter().flatten().map(PathBuf::from));
         }
 
-        Ok(Self { psr4: psr4_ret })
+        Self {
+            psr4: psr4_ret,
+            psr0: psr0_ret,
+            classmap,
+            files,
+        }
+    }
+}
+
+/// The oldest PHP a `composer.json` says it runs on, going by what it requires.
+pub fn required_php(composer_file: &Path) -> Option<PhpVersion> {
+    let reader = BufReader::new(File::open(composer_file).ok()?);
+    let composer: RequireScheme = serde_json::from_reader(reader).ok()?;
+
+    lowest_allowed(composer.require?.get("php")?)
+}
+
+/// The oldest version a constraint like `^8.1`, `>=7.4 <8.3` or `~7.4.0 || ^8.0` allows.
+fn lowest_allowed(constraint: &str) -> Option<PhpVersion> {
+    constraint
+        .split('|')
+        .filter_map(|alternative| {
+            // upper bounds don't tell us anything about how old we can go
+            alternative
+                .split([' ', ','])
+                .filter(|bound| !bound.is_empty() && !bound.starts_with(['<', '!']))
+                .find_map(|bound| PhpVersion::parse(bound.trim_start_matches(['^', '~', '>', '='])))
+        })
+        .min()
+}
+
+/// `path` without `.` and `..`, so that it compares equal to the paths of the files in it.
+///
+/// Symlinks aren't followed; `..` just drops the component before it.
+pub fn normalized_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
     }
+
+    normalized
 }
 
 /**
@@ -227,6 +420,9 @@ mod test {
 
             Autoload {
                 psr4: m,
+                psr0: HashMap::new(),
+                classmap: Vec::new(),
+                files: Vec::new(),
             }
         })
     }
@@ -242,7 +438,7 @@ mod test {
         }));
 
         assert_eq!(
-            Autoload::from_reader(data, &mut SegmentPool::new()),
+            Autoload::from_reader(data, &mut SegmentPool::new(), true),
             Err(AutoloadError::NoAutoload)
         );
     }
@@ -257,7 +453,7 @@ mod test {
         }));
 
         assert_eq!(
-            Autoload::from_reader(data, &mut SegmentPool::new()),
+            Autoload::from_reader(data, &mut SegmentPool::new(), true),
             Err(AutoloadError::NoPSR4)
         );
     }
@@ -274,7 +470,7 @@ mod test {
             },
         }));
 
-        match Autoload::from_reader(data, &mut SegmentPool::new()) {
+        match Autoload::from_reader(data, &mut SegmentPool::new(), true) {
             Err(AutoloadError::BadDeserde(_)) => {}
             x => panic!("{:?}", x),
         }
@@ -294,7 +490,7 @@ mod test {
             },
         }));
         let mut pool = SegmentPool::new();
-        let a = match Autoload::from_reader(data, &mut pool) {
+        let a = match Autoload::from_reader(data, &mut pool, true) {
             Ok(x) => x,
             Err(e) => panic!("{:?}", e),
         };
@@ -315,6 +511,170 @@ mod test {
         assert_eq!(a.psr4[&vns], vec![vendor, namespace]);
     }
 
+    #[test]
+    fn psr0() {
+        let data = to_cursor(json!({
+            "autoload": {
+                "psr-0": {
+                    "Monolog\\": "src/",
+                    "Twig_": "lib/",
+                    "Symfony\\Component\\": ["vendor/", "more/"],
+                },
+            },
+        }));
+        let mut pool = SegmentPool::new();
+        let a = Autoload::from_reader(data, &mut pool, true).unwrap();
+
+        assert!(a.psr4.is_empty());
+        assert_eq!(a.psr0.len(), 3);
+        assert_eq!(
+            a.psr0[&pool.intern_str("Monolog")],
+            vec![path!("src/Monolog")]
+        );
+        assert_eq!(a.psr0[&pool.intern_str("")], vec![path!("lib/")]);
+        assert_eq!(
+            a.psr0[&pool.intern_str("Symfony\\Component")],
+            vec![
+                path!("vendor/Symfony/Component"),
+                path!("more/Symfony/Component")
+            ]
+        );
+    }
+
+    #[test]
+    fn classmap() {
+        let data = to_cursor(json!({
+            "autoload": {
+                "classmap": ["src/", "lib/Legacy.php"],
+            },
+        }));
+        let a = Autoload::from_reader(data, &mut SegmentPool::new(), true).unwrap();
+
+        assert!(a.psr4.is_empty());
+        assert_eq!(a.classmap, vec![path!("src/"), path!("lib/Legacy.php")]);
+    }
+
+    #[test]
+    fn autoload_dev() {
+        let composer = json!({
+            "autoload": {
+                "psr-4": { "App\\": "src/" },
+            },
+            "autoload-dev": {
+                "psr-4": { "App\\Tests\\": "tests/", "App\\": "dev/" },
+                "files": ["tests/helpers.php"],
+            },
+        });
+        let mut pool = SegmentPool::new();
+        let app = pool.intern_str("App\\");
+        let tests = pool.intern_str("App\\Tests\\");
+
+        let a = Autoload::from_reader(to_cursor(composer.clone()), &mut pool, true).unwrap();
+        assert_eq!(a.psr4[&app], vec![path!("src/"), path!("dev/")]);
+        assert_eq!(a.psr4[&tests], vec![path!("tests/")]);
+        assert_eq!(a.files, vec![path!("tests/helpers.php")]);
+
+        let a = Autoload::from_reader(to_cursor(composer), &mut pool, false).unwrap();
+        assert_eq!(a.psr4.len(), 1);
+        assert_eq!(a.psr4[&app], vec![path!("src/")]);
+        assert!(a.files.is_empty());
+    }
+
+    #[test]
+    fn anchored_paths() {
+        let data = to_cursor(json!({
+            "autoload": {
+                "psr-4": { "App\\": "./src/", "App\\Tests\\": "lib/../tests" },
+                "psr-0": { "Legacy_": ["lib", "/opt/shared"] },
+                "classmap": ["database/"],
+                "files": ["helpers.php"],
+            },
+        }));
+        let mut pool = SegmentPool::new();
+        let mut a = Autoload::from_reader(data, &mut pool, true).unwrap();
+        a.anchor(&path!("/proj"));
+
+        assert_eq!(a.psr4[&pool.intern_str("App")], vec![path!("/proj/src")]);
+        assert_eq!(
+            a.psr4[&pool.intern_str("App\\Tests")],
+            vec![path!("/proj/tests")]
+        );
+        assert_eq!(
+            a.psr0[&pool.intern_str("")],
+            vec![path!("/proj/lib"), path!("/opt/shared")]
+        );
+        assert_eq!(a.classmap, vec![path!("/proj/database")]);
+        assert_eq!(a.files, vec![path!("/proj/helpers.php")]);
+    }
+
+    #[test]
+    fn installed_packages() {
+        let dir = std::env::temp_dir().join(format!("pls-installed-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("vendor/composer")).unwrap();
+        let installed = json!({
+            "packages": [
+                {
+                    "name": "monolog/monolog",
+                    "autoload": { "psr-4": { "Monolog\\\\": "src/Monolog" } },
+                    "install-path": "../monolog/monolog",
+                },
+                { "name": "no/autoload" },
+            ],
+        });
+        let installed_file = dir.join("vendor/composer/installed.json");
+        std::fs::write(&installed_file, installed.to_string()).unwrap();
+
+        let mut pool = SegmentPool::new();
+        let packages = Autoload::from_installed(&installed_file, &mut pool).unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(
+            packages[0].psr4[&pool.intern_str("Monolog")],
+            vec![dir.join("vendor/monolog/monolog/src/Monolog")]
+        );
+
+        // Composer 1
+        let installed = json!([
+            { "name": "monolog/monolog", "autoload": { "files": ["helpers.php"] } },
+        ]);
+        std::fs::write(&installed_file, installed.to_string()).unwrap();
+        let packages = Autoload::from_installed(&installed_file, &mut pool).unwrap();
+        assert_eq!(
+            packages[0].files,
+            vec![dir.join("vendor/monolog/monolog/helpers.php")]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lowest_allowed_php() {
+        use super::lowest_allowed;
+        use crate::php::PhpVersion;
+
+        assert_eq!(lowest_allowed("^8.1"), Some(PhpVersion::new(8, 1)));
+        assert_eq!(lowest_allowed(">=7.4 <8.3"), Some(PhpVersion::new(7, 4)));
+        assert_eq!(lowest_allowed("~8.2.0"), Some(PhpVersion::new(8, 2)));
+        assert_eq!(
+            lowest_allowed("^8.0 || ~7.4.0"),
+            Some(PhpVersion::new(7, 4))
+        );
+        assert_eq!(lowest_allowed("8.3.*"), Some(PhpVersion::new(8, 3)));
+        assert_eq!(lowest_allowed(">=8.1,<9"), Some(PhpVersion::new(8, 1)));
+        assert_eq!(lowest_allowed("<8.0"), None);
+    }
+
+    #[test]
+    fn files() {
+        let data = to_cursor(json!({
+            "autoload": {
+                "files": ["src/helpers.php"],
+            },
+        }));
+        let a = Autoload::from_reader(data, &mut SegmentPool::new(), true).unwrap();
+
+        assert_eq!(a.files, vec![path!("src/helpers.php")]);
+    }
+
     #[test]
     fn no_matching_ns() {
         let mut pool = SegmentPool::new();
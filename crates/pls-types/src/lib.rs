@@ -5,5 +5,5 @@ mod uri_ext;
 
 pub use composer::*;
 pub use php::*;
-pub use php_namespace::{PhpNamespace, SegmentPool, resolve_ns};
+pub use php_namespace::{PhpNamespace, SegmentPool, class_file, expected_namespaces, resolve_ns};
 pub use uri_ext::UriExt;
@@ -1,18 +1,31 @@
 /// PHP types.
+use serde::{Deserialize, Serialize};
 use tree_sitter::Node;
 
 use std::boxed::Box;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use crate::php_namespace::PhpNamespace;
 
+/// Turns a class name as written in the source (`Foo`, `Bar\Foo`, `\Foo`, `self`, ...) into its
+/// FQN, according to the scope the name appears in.
+pub type NameResolver<'a> = dyn FnMut(&str) -> PhpNamespace + 'a;
+
 pub trait FromNode {
-    fn from_node(n: Node<'_>, content: &str) -> Result<Self, TypeError>
+    fn from_node(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Result<Self, TypeError>
     where
         Self: std::marker::Sized;
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum Scalar {
     String,
     Integer,
@@ -27,11 +40,13 @@ pub enum Scalar {
     Null,
 }
 
-#[derive(Clone, Debug)]
+/// All of the types at once, e.g. `Countable&Traversable`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Union(pub Vec<Type>);
-#[derive(Clone, Debug)]
+/// Any one of the types, e.g. `int|string`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Or(pub Vec<Type>);
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Nullable(pub Box<Type>);
 
 #[derive(Clone, Debug)]
@@ -43,7 +58,7 @@ pub enum TypeError {
     UnsupportedType(String),
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum Type {
     CustomType(PhpNamespace),
     Scalar(Scalar),
@@ -61,87 +76,150 @@ pub enum Type {
     Nullable(Nullable),
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum Visibility {
     Public,
     Protected,
     Private,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Argument {
     pub name: String,
 
+    /// `mixed` if the parameter isn't typed.
     pub t: Type,
+
+    pub by_ref: bool,
+    /// Has a default value, is variadic, or only exists in some versions of PHP.
+    pub optional: bool,
+    pub variadic: bool,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Method {
     pub name: String,
 
     pub arguments: Vec<Argument>,
+    /// `mixed` if the method doesn't declare a return type.
     pub return_type: Type,
+    /// What iterating over the returned value gives, from a `@return Generator<K, V>` tag.
+    pub yields: Option<Type>,
 
     pub visibility: Visibility,
     pub r#static: bool,
     pub r#abstract: bool,
+    /// Why the method is deprecated, if it is.
+    pub deprecated: Option<String>,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Property {
     pub name: String,
+    /// `mixed` if the property isn't typed.
     pub t: Type,
 
     pub visibility: Visibility,
     pub r#static: bool,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Constant {
+    pub name: String,
+    /// `mixed` if the constant isn't typed.
+    pub t: Type,
+
+    pub visibility: Visibility,
+    /// Why the constant is deprecated, if it is.
+    pub deprecated: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Function {
     pub name: String,
 
     pub arguments: Vec<Argument>,
+    /// `mixed` if the function doesn't declare a return type.
     pub return_type: Type,
+    /// What iterating over the returned value gives, from a `@return Generator<K, V>` tag.
+    pub yields: Option<Type>,
+    /// The PHP version it was added in, for built-ins.
+    pub since: Option<PhpVersion>,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+/// A PHP release, as far as what the language and its built-ins can do goes.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub struct PhpVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl PhpVersion {
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Read a version like `8.1`, `8.1.3` or `8`; anything past the minor version is ignored.
+    pub fn parse(version: &str) -> Option<Self> {
+        let mut parts = version.trim().trim_start_matches('v').split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = match parts.next() {
+            None | Some("*") => 0,
+            Some(minor) => minor.parse().ok()?,
+        };
+
+        Some(Self { major, minor })
+    }
+}
+
+impl std::fmt::Display for PhpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Trait {
     pub name: String,
 
-    pub constants: HashMap<String, Type>,
+    pub constants: HashMap<String, Constant>,
     pub properties: HashMap<String, Property>,
     pub methods: HashMap<String, Method>,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Interface {
     pub name: String,
 
-    pub constants: HashMap<String, Type>,
+    pub constants: HashMap<String, Constant>,
     pub properties: HashMap<String, Property>,
     pub methods: HashMap<String, Method>,
 
     pub parent_interfaces: Vec<PhpNamespace>,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Enumeration {
     pub name: String,
 
-    // FIXME values can be backed by different things
+    /// The cases, in declaration order.
     pub values: Vec<String>,
-    pub constants: HashMap<String, Type>,
+    /// The scalar type of a backed enum, `int` or `string`.
+    pub backed_by: Option<String>,
+    /// What each case of a backed enum is backed by, as written.
+    pub backing_values: HashMap<String, String>,
+    pub constants: HashMap<String, Constant>,
     pub methods: HashMap<String, Method>,
 
     pub implemented_interfaces: Vec<PhpNamespace>,
     pub traits_used: Vec<PhpNamespace>,
 }
 
-#[derive(PartialEq, Clone, Debug, Default)]
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Class {
     pub name: String,
 
-    pub constants: HashMap<String, Type>,
+    pub constants: HashMap<String, Constant>,
     pub properties: HashMap<String, Property>,
     pub methods: HashMap<String, Method>,
 
@@ -151,10 +229,12 @@ pub struct Class {
 
     pub readonly: bool,
     pub r#abstract: bool,
+    /// Marked `#[AllowDynamicProperties]`, so properties can be added on the fly even on PHP 8.2.
+    pub dynamic_properties: bool,
 }
 
 /// A PHP type that isn't a part of the standard.
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum CustomType {
     Class(Class),
     Interface(Interface),
@@ -169,19 +249,462 @@ pub enum CustomType {
 ///
 /// Should be updated every time the type is edited, and the custom type's dependencies, ad
 /// infinitum. Probably a good use case for salsa, but I'm not smart enough to figure this out.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CustomTypeMeta {
     pub t: CustomType,
     pub markup: Option<String>,
+    /// Why the type is deprecated, if it is.
+    pub deprecated: Option<String>,
+    #[serde(with = "range")]
     pub src_range: tree_sitter::Range,
+    /// File the type was declared in.
+    pub src_path: PathBuf,
 }
 
+/// A `tree_sitter::Range` as its bytes and rows and columns, since tree-sitter doesn't do serde.
+mod range {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use tree_sitter::{Point, Range};
+
+    type Fields = (usize, usize, usize, usize, usize, usize);
+
+    pub fn serialize<S: Serializer>(range: &Range, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields: Fields = (
+            range.start_byte,
+            range.end_byte,
+            range.start_point.row,
+            range.start_point.column,
+            range.end_point.row,
+            range.end_point.column,
+        );
+        fields.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Range, D::Error> {
+        let (start_byte, end_byte, start_row, start_column, end_row, end_column) =
+            Fields::deserialize(deserializer)?;
+
+        Ok(Range {
+            start_byte,
+            end_byte,
+            start_point: Point::new(start_row, start_column),
+            end_point: Point::new(end_row, end_column),
+        })
+    }
+}
+
+/// A member along with the type that declared it.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Inherited<T> {
+    pub declared_in: PhpNamespace,
+    pub member: T,
+}
+
+/// Every member visible on a class-like type, including the ones it inherits.
+///
+/// Members declared closer to the type win: own members shadow trait members, which shadow parent
+/// members, which shadow interface members. The exception is an inherited abstract method, which
+/// loses to a concrete one from any ancestor, since that's what implements it.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct EffectiveMembers {
+    pub constants: HashMap<String, Inherited<Constant>>,
+    pub properties: HashMap<String, Inherited<Property>>,
+    pub methods: HashMap<String, Inherited<Method>>,
+
+    /// Parents, interfaces and traits that were found, nearest first.
+    pub ancestors: Vec<PhpNamespace>,
+    /// Parents, interfaces and traits that aren't in the database (yet).
+    pub unresolved: Vec<PhpNamespace>,
+}
+
+impl EffectiveMembers {
+    /// Look up a method the way PHP does, ignoring case.
+    pub fn method(&self, name: &str) -> Option<&Inherited<Method>> {
+        self.methods.get(name).or_else(|| {
+            self.methods
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, m)| m)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemberResolutionError {
+    TypeNotFound(PhpNamespace),
+    NotClassLike(PhpNamespace),
+    InheritanceCycle(PhpNamespace),
+}
+
+impl std::fmt::Display for MemberResolutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TypeNotFound(ns) => write!(f, "type `{}` not found", ns),
+            Self::NotClassLike(ns) => write!(f, "type `{}` cannot have members", ns),
+            Self::InheritanceCycle(ns) => write!(f, "type `{}` inherits from itself", ns),
+        }
+    }
+}
+
+impl std::error::Error for MemberResolutionError {}
+
+/// Why a method can't stand in for the one it overrides.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverrideError {
+    /// The overridden method takes this many parameters, but we take fewer.
+    TooFewParameters(usize),
+    /// Parameter must be optional, since the overridden method doesn't require it.
+    ParameterRequired(String),
+    /// Parameter must (not) be taken by reference, like in the overridden method.
+    ByRefMismatch(String, bool),
+    /// Parameter must accept at least the type of the overridden method's parameter.
+    ParameterType(String, Type),
+    /// Return type must fit in the return type of the overridden method.
+    ReturnType(Type),
+}
+
+impl std::fmt::Display for OverrideError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooFewParameters(n) => write!(f, "must accept at least {} parameters", n),
+            Self::ParameterRequired(name) => write!(f, "parameter `{}` must be optional", name),
+            Self::ByRefMismatch(name, true) => {
+                write!(f, "parameter `{}` must be passed by reference", name)
+            }
+            Self::ByRefMismatch(name, false) => {
+                write!(f, "parameter `{}` must not be passed by reference", name)
+            }
+            Self::ParameterType(name, t) => {
+                write!(f, "parameter `{}` must accept `{}`", name, t)
+            }
+            Self::ReturnType(t) => write!(f, "return type must be compatible with `{}`", t),
+        }
+    }
+}
+
+impl std::error::Error for OverrideError {}
+
+type MembersCache = RefCell<HashMap<PhpNamespace, Rc<EffectiveMembers>>>;
+/// Everywhere a type has been declared, since only one of them makes it into the database.
+type Declarations = HashMap<PhpNamespace, Vec<(PathBuf, tree_sitter::Range)>>;
+/// Functions by their lower-cased FQN, since PHP doesn't care about the case of function names.
+type FoldedFunctions = HashMap<String, PhpNamespace>;
+
+/// All the custom types we know about, keyed by their FQN.
+///
+/// Insert and remove types through [`CustomTypesDatabase::insert()`] and
+/// [`CustomTypesDatabase::remove()`] so that the cached effective members and the declarations
+/// stay coherent.
 #[derive(Clone, Debug)]
-pub struct CustomTypesDatabase(pub HashMap<PhpNamespace, CustomTypeMeta>);
+pub struct CustomTypesDatabase(
+    pub HashMap<PhpNamespace, CustomTypeMeta>,
+    MembersCache,
+    Declarations,
+    FoldedFunctions,
+);
+
+impl Default for CustomTypesDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl CustomTypesDatabase {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self(
+            HashMap::new(),
+            RefCell::new(HashMap::new()),
+            HashMap::new(),
+            HashMap::new(),
+        )
+    }
+
+    /// Add a type, replacing whatever had the same FQN. Both declarations are remembered, see
+    /// [`CustomTypesDatabase::declarations()`].
+    pub fn insert(&mut self, ns: PhpNamespace, meta: CustomTypeMeta) -> Option<CustomTypeMeta> {
+        self.1.borrow_mut().clear();
+        let declaration = (meta.src_path.clone(), meta.src_range);
+        let declarations = self.2.entry(ns.clone()).or_default();
+        if !declarations.contains(&declaration) {
+            declarations.push(declaration);
+        }
+        if let CustomType::Function(_) = meta.t {
+            self.3.insert(ns.to_string().to_lowercase(), ns.clone());
+        }
+        self.0.insert(ns, meta)
+    }
+
+    pub fn remove(&mut self, ns: &PhpNamespace) -> Option<CustomTypeMeta> {
+        self.1.borrow_mut().clear();
+        self.2.remove(ns);
+        let folded = ns.to_string().to_lowercase();
+        if self.3.get(&folded) == Some(ns) {
+            self.3.remove(&folded);
+        }
+        self.0.remove(ns)
+    }
+
+    /// The function called `ns`, ignoring case like PHP does, along with the FQN it was declared
+    /// with.
+    pub fn function(&self, ns: &PhpNamespace) -> Option<(&PhpNamespace, &CustomTypeMeta)> {
+        let is_function = |(_, meta): &(&PhpNamespace, &CustomTypeMeta)| {
+            matches!(meta.t, CustomType::Function(_))
+        };
+        self.0.get_key_value(ns).filter(is_function).or_else(|| {
+            self.3
+                .get(&ns.to_string().to_lowercase())
+                .and_then(|ns| self.0.get_key_value(ns))
+                .filter(is_function)
+        })
+    }
+
+    /// Every file and range a type has been declared in. More than one means the FQN is
+    /// ambiguous, and which one PHP ends up loading depends on the autoloader.
+    pub fn declarations(&self, ns: &PhpNamespace) -> &[(PathBuf, tree_sitter::Range)] {
+        self.2.get(ns).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Drop the declarations made in a file, before it gets ingested again.
+    pub fn forget_declarations(&mut self, path: &Path) {
+        self.2.retain(|_, declarations| {
+            declarations.retain(|(p, _)| p != path);
+            !declarations.is_empty()
+        });
+    }
+
+    /// Constants, properties and methods of a type, walking up parents, interfaces and traits.
+    ///
+    /// Results are cached until the database changes. Ancestors missing from the database are
+    /// skipped and listed in [`EffectiveMembers::unresolved`] so the caller can go look for them.
+    pub fn effective_members(
+        &self,
+        ns: &PhpNamespace,
+    ) -> Result<Rc<EffectiveMembers>, MemberResolutionError> {
+        self.effective_members_visiting(ns, &mut Vec::new())
+    }
+
+    fn effective_members_visiting(
+        &self,
+        ns: &PhpNamespace,
+        visiting: &mut Vec<PhpNamespace>,
+    ) -> Result<Rc<EffectiveMembers>, MemberResolutionError> {
+        if let Some(members) = self.1.borrow().get(ns) {
+            return Ok(members.clone());
+        }
+
+        if visiting.contains(ns) {
+            return Err(MemberResolutionError::InheritanceCycle(ns.clone()));
+        }
+
+        let meta = self
+            .0
+            .get(ns)
+            .ok_or(MemberResolutionError::TypeNotFound(ns.clone()))?;
+        let empty = HashMap::new();
+        let none = Vec::new();
+        let (constants, properties, methods, supertypes) = match &meta.t {
+            CustomType::Class(c) => (
+                &c.constants,
+                &c.properties,
+                &c.methods,
+                [&c.traits_used, &c.parent_classes, &c.implemented_interfaces],
+            ),
+            CustomType::Interface(i) => (
+                &i.constants,
+                &i.properties,
+                &i.methods,
+                [&none, &i.parent_interfaces, &none],
+            ),
+            CustomType::Enumeration(e) => (
+                &e.constants,
+                &empty,
+                &e.methods,
+                [&e.traits_used, &none, &e.implemented_interfaces],
+            ),
+            CustomType::Trait(t) => (&t.constants, &t.properties, &t.methods, [&none; 3]),
+            CustomType::Function(_) => {
+                return Err(MemberResolutionError::NotClassLike(ns.clone()));
+            }
+        };
+
+        let mut members = EffectiveMembers::default();
+        for (name, c) in constants {
+            members.constants.insert(
+                name.clone(),
+                Inherited {
+                    declared_in: ns.clone(),
+                    member: c.clone(),
+                },
+            );
+        }
+        for (name, p) in properties {
+            members.properties.insert(
+                name.clone(),
+                Inherited {
+                    declared_in: ns.clone(),
+                    member: p.clone(),
+                },
+            );
+        }
+        for (name, m) in methods {
+            members.methods.insert(
+                name.clone(),
+                Inherited {
+                    declared_in: ns.clone(),
+                    member: m.clone(),
+                },
+            );
+        }
+
+        visiting.push(ns.clone());
+        for supertype in supertypes.into_iter().flatten() {
+            let inherited = match self.effective_members_visiting(supertype, visiting) {
+                Ok(inherited) => inherited,
+                Err(MemberResolutionError::TypeNotFound(missing)) => {
+                    members.unresolved.push(missing);
+                    continue;
+                }
+                Err(e) => {
+                    visiting.pop();
+                    return Err(e);
+                }
+            };
+
+            members.ancestors.push(supertype.clone());
+            for ancestor in inherited.ancestors.iter() {
+                if !members.ancestors.contains(ancestor) {
+                    members.ancestors.push(ancestor.clone());
+                }
+            }
+            for missing in inherited.unresolved.iter() {
+                if !members.unresolved.contains(missing) {
+                    members.unresolved.push(missing.clone());
+                }
+            }
+
+            for (name, c) in inherited.constants.iter() {
+                members
+                    .constants
+                    .entry(name.clone())
+                    .or_insert_with(|| c.clone());
+            }
+            for (name, p) in inherited.properties.iter() {
+                members
+                    .properties
+                    .entry(name.clone())
+                    .or_insert_with(|| p.clone());
+            }
+            // method names ignore case, so `handle()` overrides `Handle()`
+            let folded: HashMap<_, _> = members
+                .methods
+                .keys()
+                .map(|name| (name.to_lowercase(), name.clone()))
+                .collect();
+            for (name, m) in inherited.methods.iter() {
+                let name = folded.get(&name.to_lowercase()).unwrap_or(name);
+                match members.methods.entry(name.clone()) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(m.clone());
+                    }
+                    Entry::Occupied(mut entry) => {
+                        let existing = entry.get();
+                        if existing.member.r#abstract
+                            && !m.member.r#abstract
+                            && existing.declared_in != *ns
+                        {
+                            entry.insert(m.clone());
+                        }
+                    }
+                }
+            }
+        }
+        visiting.pop();
+
+        let members = Rc::new(members);
+        self.1.borrow_mut().insert(ns.clone(), members.clone());
+
+        Ok(members)
+    }
+
+    /// Return true if a value of type `sub` can be used where `sup` is expected.
+    ///
+    /// Like [`Type::is_subtype_of()`], but also knows about class hierarchies. When the hierarchy
+    /// of `sub` isn't fully known, we give it the benefit of the doubt and return `true`, so that
+    /// diagnostics built on top of this don't fire on code we can't see.
+    pub fn is_subtype(&self, sub: &Type, sup: &Type) -> bool {
+        let (sub, sup) = (sub.normalize(), sup.normalize());
+        if sub == sup {
+            return true;
+        }
+
+        match (&sub, &sup) {
+            (_, Type::Any) | (Type::Never, _) => true,
+            (Type::Or(Or(subs)), _) => subs.iter().all(|t| self.is_subtype(t, &sup)),
+            (_, Type::Or(Or(sups))) => sups.iter().any(|t| self.is_subtype(&sub, t)),
+            (_, Type::Union(Union(sups))) => sups.iter().all(|t| self.is_subtype(&sub, t)),
+            (Type::Union(Union(subs)), _) => subs.iter().any(|t| self.is_subtype(t, &sup)),
+            (Type::CustomType(_), Type::Object) => true,
+            (Type::CustomType(ns), Type::Callable) => ns.to_string() == "\\Closure",
+            (Type::CustomType(a), Type::CustomType(b)) => match self.effective_members(a) {
+                Ok(members) => members.ancestors.contains(b) || !members.unresolved.is_empty(),
+                Err(MemberResolutionError::TypeNotFound(_)) => true,
+                Err(_) => false,
+            },
+            (Type::Scalar(a), Type::Scalar(b)) => matches!(
+                (a, b),
+                (Scalar::StringLiteral(_), Scalar::String)
+                    | (Scalar::IntegerLiteral(_), Scalar::Integer)
+                    | (Scalar::FloatLiteral(_), Scalar::Float)
+                    | (Scalar::BooleanLiteral(_), Scalar::Boolean)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Check that `method` can override `overridden`, the way PHP does when it complains that a
+    /// declaration "must be compatible" with the parent's.
+    ///
+    /// Parameters are contravariant (we may accept more), return types are covariant (we may
+    /// return less).
+    pub fn check_override(
+        &self,
+        method: &Method,
+        overridden: &Method,
+    ) -> Result<(), OverrideError> {
+        let variadic = method.arguments.last().filter(|a| a.variadic);
+        for (i, expected) in overridden.arguments.iter().enumerate() {
+            let Some(actual) = method.arguments.get(i).or(variadic) else {
+                return Err(OverrideError::TooFewParameters(overridden.arguments.len()));
+            };
+
+            if expected.optional && !actual.optional {
+                return Err(OverrideError::ParameterRequired(actual.name.clone()));
+            }
+            if expected.by_ref != actual.by_ref {
+                return Err(OverrideError::ByRefMismatch(
+                    actual.name.clone(),
+                    expected.by_ref,
+                ));
+            }
+            if !self.is_subtype(&expected.t, &actual.t) {
+                return Err(OverrideError::ParameterType(
+                    actual.name.clone(),
+                    expected.t.clone(),
+                ));
+            }
+        }
+
+        for extra in method.arguments.iter().skip(overridden.arguments.len()) {
+            if !extra.optional {
+                return Err(OverrideError::ParameterRequired(extra.name.clone()));
+            }
+        }
+
+        if !self.is_subtype(&method.return_type, &overridden.return_type) {
+            return Err(OverrideError::ReturnType(overridden.return_type.clone()));
+        }
+
+        Ok(())
     }
 }
 
@@ -244,7 +767,11 @@ impl Array {
 }
 
 impl FromNode for Visibility {
-    fn from_node(n: Node<'_>, content: &str) -> Result<Self, TypeError> {
+    fn from_node(
+        n: Node<'_>,
+        content: &str,
+        _resolve: &mut NameResolver<'_>,
+    ) -> Result<Self, TypeError> {
         let text = &content[n.byte_range()];
         if text == "protected" {
             Ok(Self::Protected)
@@ -257,15 +784,23 @@ impl FromNode for Visibility {
 }
 
 impl FromNode for Property {
-    fn from_node(n: Node<'_>, content: &str) -> Result<Self, TypeError> {
-        let mut name = None;
+    /// Works on both `property_declaration` and constructor `property_promotion_parameter` nodes.
+    fn from_node(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Result<Self, TypeError> {
+        let mut name = n
+            .child_by_field_name("name")
+            .filter(|_| n.kind() == "property_promotion_parameter")
+            .map(|name| content[name.byte_range()].to_string());
         let mut visibility = Visibility::Public;
         let mut r#static = false;
 
         let mut cursor = n.walk();
         for child in n.children(&mut cursor) {
             if child.kind() == "visibility_modifier" {
-                if let Ok(v) = Visibility::from_node(child, content) {
+                if let Ok(v) = Visibility::from_node(child, content, resolve) {
                     visibility = v;
                 }
             } else if child.kind() == "static_modifier" {
@@ -279,21 +814,238 @@ impl FromNode for Property {
 
         let t = n
             .child_by_field_name("type")
-            .map(|t| Type::from_node(t, content).unwrap());
-        match (name, t) {
-            (Some(name), Some(t)) => Ok(Self {
+            .and_then(|t| Type::from_node(t, content, resolve).ok())
+            .unwrap_or(Type::Any);
+        match name {
+            Some(name) => Ok(Self {
                 name,
                 t,
                 visibility,
                 r#static,
             }),
-            _ => Err(TypeError::NoName),
+            None => Err(TypeError::NoName),
+        }
+    }
+}
+
+impl Constant {
+    /// All the constants in a `const_declaration`; a single declaration may hold several.
+    pub fn from_declaration(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Vec<Self> {
+        let mut visibility = Visibility::Public;
+        let mut elements = Vec::new();
+
+        let mut cursor = n.walk();
+        for child in n.children(&mut cursor) {
+            if child.kind() == "visibility_modifier" {
+                if let Ok(v) = Visibility::from_node(child, content, resolve) {
+                    visibility = v;
+                }
+            } else if child.kind() == "const_element" {
+                elements.push(child);
+            }
+        }
+
+        let t = n
+            .child_by_field_name("type")
+            .and_then(|t| Type::from_node(t, content, resolve).ok())
+            .unwrap_or(Type::Any);
+        let deprecated = deprecation(n, content);
+        elements
+            .into_iter()
+            .filter_map(|element| element.named_child(0))
+            .map(|name| Self {
+                name: content[name.byte_range()].to_string(),
+                t: t.clone(),
+                visibility: visibility.clone(),
+                deprecated: deprecated.clone(),
+            })
+            .collect()
+    }
+}
+
+/// The values a function hands out when iterated over, from a `@return` tag like
+/// `Generator<int, User>`, `iterable<User>` or `User[]`.
+///
+/// With one type argument that's the value type; with more, it's the second one, after the key.
+pub fn yielded_type(n: Node<'_>, content: &str, resolve: &mut NameResolver<'_>) -> Option<Type> {
+    let comment = n.prev_sibling().filter(|c| c.kind() == "comment")?;
+    let comment = &content[comment.byte_range()];
+    if !comment.starts_with("/**") {
+        return None;
+    }
+    let (_, rest) = comment.split_once("@return")?;
+    let rest = rest.trim_start();
+
+    // the type runs up to the first whitespace outside of angle brackets
+    let mut depth = 0;
+    let end = rest
+        .char_indices()
+        .find(|&(_, c)| {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                _ => {}
+            }
+            depth == 0 && c.is_whitespace()
+        })
+        .map_or(rest.len(), |(i, _)| i);
+    let t = rest[..end].trim_end_matches("*/");
+
+    if let Some(value) = t.strip_suffix("[]") {
+        return Type::from_phpdoc(value, resolve);
+    }
+    let (container, arguments) = t.strip_suffix('>')?.split_once('<')?;
+    if !matches!(
+        container.trim_start_matches('\\').to_lowercase().as_str(),
+        "generator" | "iterator" | "iterable" | "traversable" | "iteratoraggregate" | "array"
+    ) {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut arguments = arguments
+        .split(|c| {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                _ => {}
+            }
+            depth == 0 && c == ','
+        })
+        .map(str::trim);
+    let first = arguments.next()?;
+    let value = arguments.next().unwrap_or(first);
+
+    Type::from_phpdoc(value, resolve)
+}
+
+/// The PHP version a built-in was added in, from the `@since` tag the stubs give it.
+pub fn available_since(n: Node<'_>, content: &str) -> Option<PhpVersion> {
+    let comment = n.prev_sibling().filter(|c| c.kind() == "comment")?;
+    let comment = &content[comment.byte_range()];
+    if !comment.starts_with("/**") {
+        return None;
+    }
+    let (_, rest) = comment.split_once("@since")?;
+
+    PhpVersion::parse(rest.split_whitespace().next()?)
+}
+
+/// Why a declaration is deprecated, from the `@deprecated` tag in its docblock or its
+/// `#[Deprecated]` attribute.
+///
+/// The reason is empty if the declaration doesn't give one.
+pub fn deprecation(n: Node<'_>, content: &str) -> Option<String> {
+    if let Some(comment) = n.prev_sibling().filter(|c| c.kind() == "comment") {
+        let comment = &content[comment.byte_range()];
+        if comment.starts_with("/**")
+            && let Some((_, rest)) = comment.split_once("@deprecated")
+        {
+            let reason = rest.lines().next().unwrap_or_default();
+            return Some(reason.trim().trim_end_matches("*/").trim().to_string());
+        }
+    }
+
+    let attributes = n.child_by_field_name("attributes")?;
+    let mut cursor = attributes.walk();
+    let groups = attributes.named_children(&mut cursor).collect::<Vec<_>>();
+    let attribute = groups.into_iter().find_map(|group| {
+        let mut cursor = group.walk();
+        group.named_children(&mut cursor).find(|attribute| {
+            attribute.named_child(0).is_some_and(|name| {
+                content[name.byte_range()].rsplit('\\').next() == Some("Deprecated")
+            })
+        })
+    })?;
+
+    // the PhpStorm stubs say `reason`, PHP 8.4 says `message`
+    let mut reason = String::new();
+    if let Some(arguments) = attribute.child_by_field_name("parameters") {
+        let mut cursor = arguments.walk();
+        for argument in arguments.named_children(&mut cursor) {
+            let name = argument
+                .child_by_field_name("name")
+                .map(|name| &content[name.byte_range()]);
+            if !matches!(name, None | Some("reason" | "message")) {
+                continue;
+            }
+            if let Some(value) = argument.named_children(&mut argument.walk()).last()
+                && value.kind().ends_with("string")
+            {
+                reason = content[value.byte_range()]
+                    .trim_matches(|c| c == '"' || c == '\'')
+                    .to_string();
+                break;
+            }
+        }
+    }
+
+    Some(reason)
+}
+
+impl Argument {
+    /// All the parameters of a function-like node.
+    pub fn from_parameters(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Vec<Self> {
+        let mut arguments = Vec::new();
+        if let Some(parameters) = n.child_by_field_name("parameters") {
+            let mut cursor = parameters.walk();
+            for parameter in parameters.named_children(&mut cursor) {
+                if let Ok(argument) = Argument::from_node(parameter, content, resolve) {
+                    arguments.push(argument);
+                }
+            }
         }
+
+        arguments
+    }
+}
+
+impl FromNode for Argument {
+    fn from_node(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Result<Self, TypeError> {
+        let name = n
+            .child_by_field_name("name")
+            .map(|name| content[name.byte_range()].to_string())
+            .ok_or(TypeError::NoName)?;
+        // an unsupported type shouldn't make the parameter disappear, otherwise all the ones
+        // after it would shift over
+        let t = n
+            .child_by_field_name("type")
+            .and_then(|t| Type::from_node(t, content, resolve).ok())
+            .unwrap_or(Type::Any);
+        let variadic = n.kind() == "variadic_parameter";
+        // the PhpStorm stubs list parameters that only exist in some versions of PHP
+        let versioned = n
+            .child_by_field_name("attributes")
+            .is_some_and(|a| content[a.byte_range()].contains("PhpStormStubsElementAvailable"));
+
+        Ok(Self {
+            name,
+            t,
+            by_ref: n.child_by_field_name("reference_modifier").is_some(),
+            optional: variadic || versioned || n.child_by_field_name("default_value").is_some(),
+            variadic,
+        })
     }
 }
 
 impl FromNode for Method {
-    fn from_node(n: Node<'_>, content: &str) -> Result<Self, TypeError> {
+    fn from_node(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Result<Self, TypeError> {
         let mut visibility = Visibility::Public;
         let mut r#static = false;
         let mut r#abstract = false;
@@ -301,7 +1053,7 @@ impl FromNode for Method {
         let mut cursor = n.walk();
         for child in n.children(&mut cursor) {
             if child.kind() == "visibility_modifier" {
-                if let Ok(v) = Visibility::from_node(child, content) {
+                if let Ok(v) = Visibility::from_node(child, content, resolve) {
                     visibility = v;
                 }
             } else if child.kind() == "static_modifier" {
@@ -311,38 +1063,63 @@ impl FromNode for Method {
             }
         }
 
+        let arguments = Argument::from_parameters(n, content, resolve);
         let name = n
             .child_by_field_name("name")
-            .map(|name| content[name.byte_range()].to_string());
+            .map(|name| content[name.byte_range()].to_string())
+            .ok_or(TypeError::NoName)?;
         let return_type = n
             .child_by_field_name("return_type")
-            .and_then(|t| Type::from_node(t, content).ok());
+            .and_then(|t| Type::from_node(t, content, resolve).ok())
+            .unwrap_or(Type::Any);
+
+        Ok(Method {
+            name,
+            arguments,
+            return_type,
+            yields: yielded_type(n, content, resolve),
+            visibility,
+            r#static,
+            r#abstract,
+            deprecated: deprecation(n, content),
+        })
+    }
+}
 
-        match (name, return_type) {
-            (Some(name), Some(return_type)) => Ok(Method {
-                name,
-                arguments: Vec::new(),
-                return_type,
-                visibility,
-                r#static,
-                r#abstract,
-            }),
-            (Some(name), None) => Ok(Method {
-                name,
-                arguments: Vec::new(),
-                return_type: Type::Void,
-                visibility,
-                r#static,
-                r#abstract,
-            }),
-            _ => Err(TypeError::NoName),
-        }
+impl FromNode for Function {
+    fn from_node(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Result<Self, TypeError> {
+        let name = n
+            .child_by_field_name("name")
+            .map(|name| content[name.byte_range()].to_string())
+            .ok_or(TypeError::NoName)?;
+        let return_type = n
+            .child_by_field_name("return_type")
+            .and_then(|t| Type::from_node(t, content, resolve).ok())
+            .unwrap_or(Type::Any);
+
+        Ok(Self {
+            name,
+            arguments: Argument::from_parameters(n, content, resolve),
+            return_type,
+            yields: yielded_type(n, content, resolve),
+            // only the stubs know, see `available_since()`
+            since: None,
+        })
     }
 }
 
 impl FromNode for Type {
-    fn from_node(n: Node<'_>, content: &str) -> Result<Self, TypeError> {
-        if n.kind() == "primitive_type" {
+    fn from_node(
+        n: Node<'_>,
+        content: &str,
+        resolve: &mut NameResolver<'_>,
+    ) -> Result<Self, TypeError> {
+        let kind = n.kind();
+        if kind == "primitive_type" || kind == "bottom_type" {
             let t = &content[n.byte_range()];
             if t == "int" {
                 Ok(Type::Scalar(Scalar::Integer))
@@ -362,15 +1139,95 @@ impl FromNode for Type {
                 Ok(Type::Scalar(Scalar::Null))
             } else if t == "array" {
                 Ok(Type::Array)
+            } else if t == "mixed" {
+                Ok(Type::Any)
+            } else if t == "object" {
+                Ok(Type::Object)
+            } else if t == "callable" {
+                Ok(Type::Callable)
+            } else if t == "never" {
+                Ok(Type::Never)
+            } else if t == "iterable" {
+                Ok(Type::Or(Or(vec![
+                    Type::Array,
+                    Type::CustomType(resolve("\\Traversable")),
+                ])))
             } else {
                 Err(TypeError::UnsupportedType(t.to_owned()))
             }
-        } else if n.kind() == "optional_type" {
-            let inner_type = Self::from_node(n.child(1).ok_or(TypeError::ExpectedType)?, content)?;
+        } else if kind == "named_type" {
+            let name = n.named_child(0).ok_or(TypeError::ExpectedType)?;
+            Ok(Type::CustomType(resolve(&content[name.byte_range()])))
+        } else if kind == "optional_type" {
+            let inner_type = Self::from_node(
+                n.named_child(0).ok_or(TypeError::ExpectedType)?,
+                content,
+                resolve,
+            )?;
             Ok(Type::Nullable(Nullable(Box::new(inner_type))))
+        } else if kind == "union_type"
+            || kind == "intersection_type"
+            || kind == "disjunctive_normal_form_type"
+        {
+            let mut types = Vec::new();
+            let mut cursor = n.walk();
+            for child in n.named_children(&mut cursor) {
+                types.push(Self::from_node(child, content, resolve)?);
+            }
+
+            if kind == "intersection_type" {
+                Ok(Type::Union(Union(types)))
+            } else {
+                Ok(Type::Or(Or(types)))
+            }
         } else {
-            dbg!("{:?}", n.to_sexp());
-            Err(TypeError::UnsupportedType(n.kind().to_owned()))
+            Err(TypeError::UnsupportedType(kind.to_owned()))
+        }
+    }
+}
+
+impl std::fmt::Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::String => write!(f, "string"),
+            Self::Integer => write!(f, "int"),
+            Self::Float => write!(f, "float"),
+            Self::Boolean => write!(f, "bool"),
+            Self::StringLiteral(s) => write!(f, "{:?}", s),
+            Self::IntegerLiteral(i) => write!(f, "{}", i),
+            Self::FloatLiteral(x) => write!(f, "{}", x),
+            Self::BooleanLiteral(b) => write!(f, "{}", b),
+            Self::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |f: &mut std::fmt::Formatter<'_>, types: &[Type], sep| {
+            for (i, t) in types.iter().enumerate() {
+                if i != 0 {
+                    write!(f, "{}", sep)?;
+                }
+                write!(f, "{}", t)?;
+            }
+
+            Ok(())
+        };
+
+        match self {
+            Self::CustomType(ns) => write!(f, "{}", ns),
+            Self::Scalar(s) => write!(f, "{}", s),
+            Self::Array => write!(f, "array"),
+            Self::Object => write!(f, "object"),
+            Self::Callable => write!(f, "callable"),
+            Self::Any => write!(f, "mixed"),
+            Self::Resource => write!(f, "resource"),
+            Self::Never => write!(f, "never"),
+            Self::Void => write!(f, "void"),
+            Self::Union(Union(types)) => join(f, types, "&"),
+            Self::Or(Or(types)) => join(f, types, "|"),
+            Self::Nullable(Nullable(t)) => write!(f, "?{}", t),
         }
     }
 }
@@ -420,7 +1277,7 @@ impl Type {
     /// - Turns nested `Union(...Union(...))` into singular `Union(...)` statements
     /// - Turns nested `Or(...)` with singular element into that singular element
     /// - Turns nested `Union(...)` with singular element into that singular element
-    fn normalize(&self) -> Self {
+    pub fn normalize(&self) -> Self {
         match self {
             Self::Union(Union(types)) => {
                 if types.len() == 1 {
@@ -474,11 +1331,76 @@ impl Type {
             _ => self.clone(),
         }
     }
+
+    /// Parse a type written in a docblock, like `int|string|null` or `?Foo`.
+    ///
+    /// Only the types that can also be written natively are understood. Generics, array shapes
+    /// and pseudo-types like `list` give `None`.
+    pub fn from_phpdoc(s: &str, resolve: &mut NameResolver<'_>) -> Option<Self> {
+        if let Some(inner) = s.strip_prefix('?') {
+            let inner = Self::from_phpdoc(inner, resolve)?;
+            return Some(Self::Nullable(Nullable(Box::new(inner))));
+        }
+        if s.contains('|') {
+            let types = s
+                .split('|')
+                .map(|t| Self::from_phpdoc(t, resolve))
+                .collect::<Option<Vec<_>>>()?;
+            return Some(Self::Or(Or(types)));
+        }
+        if s.contains('&') {
+            let types = s
+                .split('&')
+                .map(|t| Self::from_phpdoc(t, resolve))
+                .collect::<Option<Vec<_>>>()?;
+            return Some(Self::Union(Union(types)));
+        }
+        if s.is_empty()
+            || !s
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '\\')
+        {
+            return None;
+        }
+
+        let t = match s.to_lowercase().as_str() {
+            "int" | "integer" => Self::Scalar(Scalar::Integer),
+            "string" => Self::Scalar(Scalar::String),
+            "bool" | "boolean" => Self::Scalar(Scalar::Boolean),
+            "float" | "double" => Self::Scalar(Scalar::Float),
+            "false" => Self::Scalar(Scalar::BooleanLiteral(false)),
+            "true" => Self::Scalar(Scalar::BooleanLiteral(true)),
+            "null" => Self::Scalar(Scalar::Null),
+            "void" => Self::Void,
+            "array" => Self::Array,
+            "mixed" => Self::Any,
+            "object" => Self::Object,
+            "callable" => Self::Callable,
+            "never" => Self::Never,
+            "resource" => Self::Resource,
+            "iterable" => Self::Or(Or(vec![
+                Self::Array,
+                Self::CustomType(resolve("\\Traversable")),
+            ])),
+            "list" | "scalar" | "numeric" | "number" => return None,
+            _ => Self::CustomType(resolve(s)),
+        };
+
+        Some(t)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Nullable, Or, Scalar, Type, Union};
+    use super::{
+        Argument, Class, Constant, CustomType, CustomTypeMeta, CustomTypesDatabase, Interface,
+        MemberResolutionError, Method, Nullable, Or, OverrideError, PhpVersion, Scalar, Trait,
+        Type, Union, Visibility,
+    };
+    use crate::php_namespace::SegmentPool;
+
+    use std::collections::HashMap;
+    use std::path::PathBuf;
 
     macro_rules! nullable {
         ($e:expr) => {
@@ -582,4 +1504,438 @@ mod test {
             assert!(child.is_subtype_of(&parent));
         }
     }
+
+    #[test]
+    fn from_phpdoc() {
+        let mut pool = SegmentPool::new();
+        let mut resolve = |name: &str| pool.intern_str(name);
+        let foo = Type::CustomType(SegmentPool::new().intern_str("Foo"));
+
+        assert_eq!(
+            Type::from_phpdoc("integer|null", &mut resolve),
+            Some(or!(scalar!(Integer), scalar!(Null)))
+        );
+        assert_eq!(
+            Type::from_phpdoc("?Foo", &mut resolve),
+            Some(nullable!(foo.clone()))
+        );
+        assert_eq!(
+            Type::from_phpdoc("Foo&Countable", &mut resolve),
+            Some(union!(
+                foo,
+                Type::CustomType(SegmentPool::new().intern_str("Countable"))
+            ))
+        );
+        assert_eq!(Type::from_phpdoc("int[]", &mut resolve), None);
+        assert_eq!(Type::from_phpdoc("array<string>", &mut resolve), None);
+        assert_eq!(Type::from_phpdoc("list", &mut resolve), None);
+        assert_eq!(Type::from_phpdoc("string|list", &mut resolve), None);
+    }
+
+    fn meta(t: CustomType) -> CustomTypeMeta {
+        let point = tree_sitter::Point { row: 0, column: 0 };
+        CustomTypeMeta {
+            t,
+            markup: None,
+            deprecated: None,
+            src_range: tree_sitter::Range {
+                start_byte: 0,
+                end_byte: 0,
+                start_point: point,
+                end_point: point,
+            },
+            src_path: PathBuf::new(),
+        }
+    }
+
+    fn methods(names: &[&str], r#abstract: bool) -> HashMap<String, Method> {
+        names
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    Method {
+                        name: name.to_string(),
+                        arguments: Vec::new(),
+                        return_type: Type::Void,
+                        yields: None,
+                        visibility: Visibility::Public,
+                        r#static: false,
+                        r#abstract,
+                        deprecated: None,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn effective_members_walk_the_chain() {
+        let mut pool = SegmentPool::new();
+        let mut db = CustomTypesDatabase::new();
+        db.insert(
+            pool.intern_str("App\\Child"),
+            meta(CustomType::Class(Class {
+                name: "Child".to_string(),
+                methods: methods(&["own", "shadowed"], false),
+                parent_classes: vec![pool.intern_str("App\\Base")],
+                implemented_interfaces: vec![pool.intern_str("App\\Missing")],
+                ..Class::default()
+            })),
+        );
+        db.insert(
+            pool.intern_str("App\\Base"),
+            meta(CustomType::Class(Class {
+                name: "Base".to_string(),
+                methods: methods(&["shadowed", "inherited"], false),
+                traits_used: vec![pool.intern_str("App\\Greets")],
+                implemented_interfaces: vec![pool.intern_str("App\\Contract")],
+                ..Class::default()
+            })),
+        );
+        db.insert(
+            pool.intern_str("App\\Greets"),
+            meta(CustomType::Trait(Trait {
+                name: "Greets".to_string(),
+                constants: HashMap::new(),
+                properties: HashMap::new(),
+                methods: methods(&["greet"], false),
+            })),
+        );
+        db.insert(
+            pool.intern_str("App\\Contract"),
+            meta(CustomType::Interface(Interface {
+                name: "Contract".to_string(),
+                constants: HashMap::from([(
+                    "VERSION".to_string(),
+                    Constant {
+                        name: "VERSION".to_string(),
+                        t: Type::Scalar(Scalar::Integer),
+                        visibility: Visibility::Public,
+                        deprecated: None,
+                    },
+                )]),
+                properties: HashMap::new(),
+                methods: methods(&["greet", "promised"], true),
+                parent_interfaces: vec![],
+            })),
+        );
+
+        let child = pool.intern_str("App\\Child");
+        let members = db.effective_members(&child).unwrap();
+
+        assert_eq!(members.methods.len(), 5);
+        assert_eq!(members.methods["shadowed"].declared_in, child);
+        assert_eq!(
+            members.methods["inherited"].declared_in,
+            pool.intern_str("App\\Base")
+        );
+        assert_eq!(
+            members.methods["greet"].declared_in,
+            pool.intern_str("App\\Greets")
+        );
+        assert!(members.methods["promised"].member.r#abstract);
+        assert!(members.constants.contains_key("VERSION"));
+        assert_eq!(members.ancestors.len(), 3);
+        assert_eq!(members.unresolved, vec![pool.intern_str("App\\Missing")]);
+    }
+
+    #[test]
+    fn effective_members_prefer_concrete_methods() {
+        let mut pool = SegmentPool::new();
+        let mut db = CustomTypesDatabase::new();
+        db.insert(
+            pool.intern_str("App\\Child"),
+            meta(CustomType::Class(Class {
+                name: "Child".to_string(),
+                parent_classes: vec![pool.intern_str("App\\Base")],
+                traits_used: vec![pool.intern_str("App\\Needs")],
+                ..Class::default()
+            })),
+        );
+        db.insert(
+            pool.intern_str("App\\Base"),
+            meta(CustomType::Class(Class {
+                name: "Base".to_string(),
+                methods: methods(&["handle"], false),
+                ..Class::default()
+            })),
+        );
+        db.insert(
+            pool.intern_str("App\\Needs"),
+            meta(CustomType::Trait(Trait {
+                name: "Needs".to_string(),
+                constants: HashMap::new(),
+                properties: HashMap::new(),
+                methods: methods(&["handle", "missing"], true),
+            })),
+        );
+
+        let members = db
+            .effective_members(&pool.intern_str("App\\Child"))
+            .unwrap();
+        assert_eq!(
+            members.methods["handle"].declared_in,
+            pool.intern_str("App\\Base")
+        );
+        assert!(!members.methods["handle"].member.r#abstract);
+        assert!(members.methods["missing"].member.r#abstract);
+    }
+
+    #[test]
+    fn effective_members_ignore_method_case() {
+        let mut pool = SegmentPool::new();
+        let mut db = CustomTypesDatabase::new();
+        db.insert(
+            pool.intern_str("App\\Child"),
+            meta(CustomType::Class(Class {
+                name: "Child".to_string(),
+                parent_classes: vec![pool.intern_str("App\\Base")],
+                methods: methods(&["handle"], false),
+                ..Class::default()
+            })),
+        );
+        db.insert(
+            pool.intern_str("App\\Base"),
+            meta(CustomType::Class(Class {
+                name: "Base".to_string(),
+                methods: methods(&["Handle"], true),
+                ..Class::default()
+            })),
+        );
+
+        let members = db
+            .effective_members(&pool.intern_str("App\\Child"))
+            .unwrap();
+        assert_eq!(members.methods.len(), 1, "{:?}", members.methods.keys());
+        assert_eq!(
+            members.methods["handle"].declared_in,
+            pool.intern_str("App\\Child")
+        );
+    }
+
+    #[test]
+    fn effective_members_detect_cycles() {
+        let mut pool = SegmentPool::new();
+        let mut db = CustomTypesDatabase::new();
+        for (name, parent) in [("A", "B"), ("B", "A")] {
+            db.insert(
+                pool.intern_str(name),
+                meta(CustomType::Class(Class {
+                    name: name.to_string(),
+                    parent_classes: vec![pool.intern_str(parent)],
+                    ..Class::default()
+                })),
+            );
+        }
+
+        assert!(matches!(
+            db.effective_members(&pool.intern_str("A")),
+            Err(MemberResolutionError::InheritanceCycle(_))
+        ));
+    }
+
+    #[test]
+    fn effective_members_cache_invalidated_on_insert() {
+        let mut pool = SegmentPool::new();
+        let mut db = CustomTypesDatabase::new();
+        let child = pool.intern_str("Child");
+        db.insert(
+            child.clone(),
+            meta(CustomType::Class(Class {
+                name: "Child".to_string(),
+                parent_classes: vec![pool.intern_str("Base")],
+                ..Class::default()
+            })),
+        );
+
+        assert!(db.effective_members(&child).unwrap().methods.is_empty());
+
+        db.insert(
+            pool.intern_str("Base"),
+            meta(CustomType::Class(Class {
+                name: "Base".to_string(),
+                methods: methods(&["late"], false),
+                ..Class::default()
+            })),
+        );
+
+        assert!(
+            db.effective_members(&child)
+                .unwrap()
+                .methods
+                .contains_key("late")
+        );
+    }
+
+    fn arg(name: &str, t: Type) -> Argument {
+        Argument {
+            name: name.to_string(),
+            t,
+            by_ref: false,
+            optional: false,
+            variadic: false,
+        }
+    }
+
+    fn method(arguments: Vec<Argument>, return_type: Type) -> Method {
+        Method {
+            name: "m".to_string(),
+            arguments,
+            return_type,
+            yields: None,
+            visibility: Visibility::Public,
+            r#static: false,
+            r#abstract: false,
+            deprecated: None,
+        }
+    }
+
+    #[test]
+    fn is_subtype_follows_class_hierarchy() {
+        let mut pool = SegmentPool::new();
+        let mut db = CustomTypesDatabase::new();
+        db.insert(
+            pool.intern_str("Child"),
+            meta(CustomType::Class(Class {
+                name: "Child".to_string(),
+                parent_classes: vec![pool.intern_str("Base")],
+                ..Class::default()
+            })),
+        );
+        db.insert(
+            pool.intern_str("Base"),
+            meta(CustomType::Class(Class {
+                name: "Base".to_string(),
+                ..Class::default()
+            })),
+        );
+
+        let child = Type::CustomType(pool.intern_str("Child"));
+        let base = Type::CustomType(pool.intern_str("Base"));
+        let unknown = Type::CustomType(pool.intern_str("Unknown"));
+        assert!(db.is_subtype(&child, &base));
+        assert!(db.is_subtype(&child, &nullable!(base.clone())));
+        assert!(db.is_subtype(&child, &Type::Object));
+        assert!(!db.is_subtype(&base, &child));
+        assert!(!db.is_subtype(&nullable!(child.clone()), &base));
+        assert!(db.is_subtype(&unknown, &base));
+        assert!(!db.is_subtype(&base, &scalar!(Integer)));
+    }
+
+    #[test]
+    fn compatible_overrides() {
+        let db = CustomTypesDatabase::new();
+        let overridden = method(vec![arg("$a", scalar!(Integer))], scalar!(String));
+        let overrides = [
+            method(vec![arg("$a", scalar!(Integer))], scalar!(String)),
+            method(vec![arg("$b", Type::Any)], scalar!(String)),
+            method(
+                vec![arg("$a", or!(scalar!(Integer), scalar!(String)))],
+                scalar!(String),
+            ),
+            method(
+                vec![
+                    arg("$a", scalar!(Integer)),
+                    Argument {
+                        optional: true,
+                        ..arg("$extra", Type::Any)
+                    },
+                ],
+                Type::Never,
+            ),
+            method(
+                vec![Argument {
+                    optional: true,
+                    variadic: true,
+                    ..arg("$rest", Type::Any)
+                }],
+                scalar!(String),
+            ),
+        ];
+
+        for m in overrides {
+            assert_eq!(
+                db.check_override(&m, &overridden),
+                Ok(()),
+                "method = {:?}",
+                m
+            );
+        }
+    }
+
+    #[test]
+    fn incompatible_overrides() {
+        let db = CustomTypesDatabase::new();
+        let overridden = method(
+            vec![Argument {
+                optional: true,
+                ..arg("$a", scalar!(Integer))
+            }],
+            scalar!(String),
+        );
+        let cases = [
+            (
+                method(vec![], scalar!(String)),
+                OverrideError::TooFewParameters(1),
+            ),
+            (
+                method(vec![arg("$a", scalar!(Integer))], scalar!(String)),
+                OverrideError::ParameterRequired("$a".to_string()),
+            ),
+            (
+                method(
+                    vec![Argument {
+                        optional: true,
+                        by_ref: true,
+                        ..arg("$a", scalar!(Integer))
+                    }],
+                    scalar!(String),
+                ),
+                OverrideError::ByRefMismatch("$a".to_string(), false),
+            ),
+            (
+                method(
+                    vec![Argument {
+                        optional: true,
+                        ..arg("$a", scalar!(String))
+                    }],
+                    scalar!(String),
+                ),
+                OverrideError::ParameterType("$a".to_string(), scalar!(Integer)),
+            ),
+            (
+                method(
+                    vec![Argument {
+                        optional: true,
+                        ..arg("$a", scalar!(Integer))
+                    }],
+                    Type::Any,
+                ),
+                OverrideError::ReturnType(scalar!(String)),
+            ),
+        ];
+
+        for (m, e) in cases {
+            assert_eq!(
+                db.check_override(&m, &overridden),
+                Err(e),
+                "method = {:?}",
+                m
+            );
+        }
+    }
+
+    #[test]
+    fn php_versions() {
+        assert_eq!(PhpVersion::parse("8.1"), Some(PhpVersion::new(8, 1)));
+        assert_eq!(PhpVersion::parse("8.2.13"), Some(PhpVersion::new(8, 2)));
+        assert_eq!(PhpVersion::parse("8"), Some(PhpVersion::new(8, 0)));
+        assert_eq!(PhpVersion::parse("7.*"), Some(PhpVersion::new(7, 0)));
+        assert_eq!(PhpVersion::parse("latest"), None);
+
+        assert!(PhpVersion::new(7, 4) < PhpVersion::new(8, 0));
+        assert!(PhpVersion::new(8, 10) > PhpVersion::new(8, 2));
+        assert_eq!(PhpVersion::new(8, 1).to_string(), "8.1");
+    }
 }
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use crate::composer::ResolutionError;
@@ -134,6 +136,27 @@ impl std::fmt::Display for PhpNamespace {
     }
 }
 
+/// Written out the way it's displayed, like `\App\Models\User`.
+impl Serialize for PhpNamespace {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Read back without a [`SegmentPool`], so the segments aren't interned.
+impl<'de> Deserialize<'de> for PhpNamespace {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        Ok(Self(
+            name.split('\\')
+                .filter(|segment| !segment.is_empty())
+                .map(Rc::from)
+                .collect(),
+        ))
+    }
+}
+
 /// Resolves a namespace into a `PathBuf`.
 ///
 /// Can guarantee that at the time of calling, the resolved path exists. Which isn't saying
@@ -161,8 +184,62 @@ pub fn resolve_ns(
     Err(ResolutionError::NamespaceNotFound(ns.clone()))
 }
 
+/// The file declaring class `base`, given the directory its namespace resolved to.
+///
+/// PSR-0 also turns underscores in class names into directories, so `Twig_Environment` can be in
+/// `Twig/Environment.php`; a file named after the whole class wins.
+pub fn class_file(dir: &Path, base: &str) -> Option<PathBuf> {
+    let file = dir.join(format!("{base}.php"));
+    if file.exists() {
+        return Some(file);
+    }
+
+    let (parents, name) = base.rsplit_once('_')?;
+    let file = parents
+        .split('_')
+        .fold(dir.to_path_buf(), |dir, parent| dir.join(parent))
+        .join(format!("{name}.php"));
+    file.exists().then_some(file)
+}
+
+/// The namespaces PSR-4 allows the classes in a file to be declared in, going by the directory
+/// the file is in.
+///
+/// A file can be under several autoloaded directories; namespaces for the deepest directory come
+/// first. Empty if the file isn't autoloaded at all.
+pub fn expected_namespaces(
+    path: &Path,
+    ns_to_dir: &HashMap<PhpNamespace, Vec<PathBuf>>,
+    pool: &mut SegmentPool,
+) -> Vec<PhpNamespace> {
+    let Some(file_dir) = path.parent() else {
+        return Vec::new();
+    };
+
+    let mut candidates = Vec::new();
+    for (prefix, dirs) in ns_to_dir.iter() {
+        for dir in dirs.iter() {
+            let Ok(relative) = file_dir.strip_prefix(dir) else {
+                continue;
+            };
+            let mut ns = prefix.clone();
+            ns.extend(
+                pool.intern(relative.iter().map(|segment| segment.to_string_lossy()))
+                    .0,
+            );
+            candidates.push((dir.components().count(), ns));
+        }
+    }
+    candidates.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    candidates.into_iter().map(|(_, ns)| ns).collect()
+}
+
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
     use super::SegmentPool;
 
     #[test]
@@ -189,6 +266,53 @@ mod test {
         }
     }
 
+    #[test]
+    fn expected_namespaces() {
+        let mut pool = SegmentPool::new();
+        let ns_to_dir = HashMap::from([
+            (pool.intern_str("App\\"), vec![PathBuf::from("/proj/src")]),
+            (
+                pool.intern_str("App\\Tests\\"),
+                vec![PathBuf::from("/proj/tests/"), PathBuf::from("/proj/more")],
+            ),
+        ]);
+        let expected = |path: &str, pool: &mut SegmentPool| {
+            super::expected_namespaces(Path::new(path), &ns_to_dir, pool)
+                .iter()
+                .map(|ns| ns.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(expected("/proj/src/Foo.php", &mut pool), vec!["\\App"]);
+        assert_eq!(
+            expected("/proj/src/Http/Controllers/Foo.php", &mut pool),
+            vec!["\\App\\Http\\Controllers"]
+        );
+        assert_eq!(
+            expected("/proj/tests/Unit/FooTest.php", &mut pool),
+            vec!["\\App\\Tests\\Unit"]
+        );
+        assert!(expected("/proj/bin/console.php", &mut pool).is_empty());
+    }
+
+    #[test]
+    fn class_files() {
+        let dir = std::env::temp_dir().join(format!("pls-class-file-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Twig")).unwrap();
+        std::fs::write(dir.join("Foo.php"), "").unwrap();
+        std::fs::write(dir.join("Twig/Environment.php"), "").unwrap();
+
+        assert_eq!(super::class_file(&dir, "Foo"), Some(dir.join("Foo.php")));
+        assert_eq!(
+            super::class_file(&dir, "Twig_Environment"),
+            Some(dir.join("Twig/Environment.php"))
+        );
+        assert_eq!(super::class_file(&dir, "Twig_Loader"), None);
+        assert_eq!(super::class_file(&dir, "Bar"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[test]
     fn is_not_within() {
         let mut pool = SegmentPool::new();
@@ -1,98 +1,20 @@
-use tower_lsp_server::{lsp_types::*, Client, UriExt};
+use lsp_types::*;
 
-use tree_sitter::{Node, Parser};
-use tree_sitter_php::LANGUAGE_PHP;
+use tree_sitter::Node;
 
-use tokio::sync::mpsc::Receiver;
-use tokio::sync::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
 
-use std::collections::VecDeque;
-use std::sync::Arc;
-
-use crate::backend::BackendData;
-use crate::compat::to_range;
-use crate::messages::{AnalysisThreadMessage, AnalysisThreadQueueItem};
-use crate::php_namespace::{resolve_ns, PhpNamespace, SegmentPool};
-use crate::scope::{Scope, SUPERGLOBALS};
-use crate::types::{
-    Class, CustomType, CustomTypeMeta, CustomTypesDatabase, FromNode, Method, Property, Type,
-    Visibility,
+use pls_types::{
+    Class, Constant, CustomType, CustomTypeMeta, CustomTypesDatabase, Enumeration, FromNode,
+    Function, Interface, Method, NameResolver, PhpNamespace, Property, SegmentPool, Trait, Type,
+    UriExt, Visibility, available_since, deprecation,
 };
 
-pub async fn main_thread(
-    mut rx: Receiver<AnalysisThreadMessage>,
-    data: Arc<RwLock<BackendData>>,
-    client: Client,
-) {
-    let mut q = VecDeque::new();
-
-    /// Max number of items from queue to run per `recv`
-    const PROCESS_ITEMS_PER_RECV: usize = 10;
-
-    let mut php_parser = Parser::new();
-    php_parser.set_language(&LANGUAGE_PHP.into()).unwrap();
-
-    while let Some(msg) = rx.recv().await {
-        use AnalysisThreadMessage::*;
-
-        match msg {
-            Shutdown => break,
-            AnalyzeUri(uri) => q.push_back(AnalysisThreadQueueItem::Uri(uri)),
-            AnalyzeNs(ns) => q.push_back(AnalysisThreadQueueItem::Ns(ns)),
-        }
-
-        for _ in 0..PROCESS_ITEMS_PER_RECV {
-            let data_lock = &mut *data.write().await;
-            match q.pop_back() {
-                Some(AnalysisThreadQueueItem::Uri(uri)) => {
-                    let dependencies = if let Some(filedata) = data_lock.file_trees.get(&uri) {
-                        injest_types(
-                            filedata.php_tree.root_node(),
-                            &filedata.contents,
-                            &mut data_lock.ns_store,
-                            &mut data_lock.types,
-                        )
-                    } else {
-                        todo!("they should be processed, idk why they aren't");
-                    };
-
-                    for dep_ns in dependencies.into_iter() {
-                        q.push_back(AnalysisThreadQueueItem::Ns(dep_ns));
-                    }
-                }
-                Some(AnalysisThreadQueueItem::Ns(mut ns)) => {
-                    match ns.pop() {
-                        Some(base) => {
-                            match resolve_ns(&ns, &data_lock.ns_to_dir) {
-                                Ok(dir) => {
-                                    let path = dir.join(format!("{base}.php"));
-                                    match std::fs::read_to_string(path) {
-                                        Ok(contents) => {
-                                            let php_tree = php_parser.parse(&contents, None).unwrap();
-                                            let dependencies = injest_types(
-                                                php_tree.root_node(),
-                                                &contents,
-                                                &mut data_lock.ns_store,
-                                                &mut data_lock.types,
-                                            );
-                                            for dep_ns in dependencies.into_iter() {
-                                                q.push_back(AnalysisThreadQueueItem::Ns(dep_ns));
-                                            }
-                                        }
-                                        Err(e) => client.log_message(MessageType::ERROR, e.to_string()).await,
-                                    }
-                                },
-                                Err(e) => client.log_message(MessageType::ERROR, e.to_string()).await,
-                            }
-                        },
-                        None => {},
-                    }
-                }
-                _ => break,
-            }
-        }
-    }
-}
+use crate::code_action::QuickFix;
+use crate::compat::{to_position, to_range};
+use crate::scope::{SUPERGLOBALS, Scope};
 
 fn function_parameters(
     params: Node<'_>,
@@ -123,30 +45,150 @@ fn function_parameters(
     symbols
 }
 
-/// LHS of an assignment expression.
+/// Parameters taken by reference.
+fn reference_parameters(params: Node<'_>, content: &str) -> Vec<String> {
+    let mut cursor = params.walk();
+    params
+        .children(&mut cursor)
+        .filter(|p| p.child_by_field_name("reference_modifier").is_some())
+        .filter_map(|p| p.child_by_field_name("name"))
+        .map(|name| content[name.byte_range()].to_string())
+        .collect()
+}
+
+/// Count every variable in the node as read, without checking that it's defined.
+fn mark_read(node: Node<'_>, content: &str, scope: &mut Scope) {
+    if node.kind() == "variable_name" {
+        scope.read.insert(content[node.byte_range()].to_string());
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        mark_read(child, content, scope);
+    }
+}
+
+/// Flag the variables a function writes to but never reads.
+fn unused_variables(scope: &Scope, diagnostics: &mut Vec<Diagnostic>) {
+    for (name, range) in scope.unused_variables() {
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("unused-variable".to_string())),
+            source: Some("unused".to_string()),
+            message: format!("variable {} is never read", name),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..Default::default()
+        });
+    }
+}
+
+/// LHS of an assignment expression: the variables it binds, and the nodes it reads from, like
+/// the keys of `['x' => $x] = $point` or the `$a` of `$a[] = 1`.
 ///
 /// I'm not basing this off of the PHP standard, so there will be things that I get wrong.
-fn expression_left(left: Node<'_>, content: &str) -> Vec<String> {
-    if left.kind() == "variable_name" {
-        vec![content[left.byte_range()].to_string()]
-    } else if left.kind() == "list_literal" {
-        let mut cursor = left.walk();
-        left.children(&mut cursor)
-            .into_iter()
-            .filter_map(|n| {
-                (n.kind() == "variable_name").then_some(content[n.byte_range()].to_string())
-            })
-            .collect()
-    } else {
-        Vec::new()
+fn expression_left<'a>(left: Node<'a>, bound: &mut Vec<Node<'a>>, read: &mut Vec<Node<'a>>) {
+    match left.kind() {
+        "variable_name" => bound.push(left),
+        // `[&$a] = $array` writes to the array through `$a` later on
+        "by_ref" => {
+            if let Some(variable) = left.named_child(0) {
+                bound.push(variable);
+            }
+            read.push(left);
+        }
+        "list_literal" => {
+            let mut cursor = left.walk();
+            for element in left.named_children(&mut cursor) {
+                let is_key = element.next_sibling().is_some_and(|n| n.kind() == "=>");
+                if is_key {
+                    read.push(element);
+                } else {
+                    expression_left(element, bound, read);
+                }
+            }
+        }
+        _ => read.push(left),
+    }
+}
+
+/// `compact('a', ['b'])` reads `$a` and `$b`.
+///
+/// Anything that isn't a plain string could name any variable, so it's only checked for the
+/// variables it reads itself.
+fn compact_arguments(
+    node: Node<'_>,
+    content: &str,
+    scope: &mut Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "argument" | "array_creation_expression" | "array_element_initializer" => {
+                compact_arguments(child, content, scope, diagnostics)
+            }
+            "string" | "encapsed_string"
+                if child.named_child_count() <= 1
+                    && child
+                        .named_child(0)
+                        .is_none_or(|c| c.kind() == "string_content") =>
+            {
+                let name = content[child.byte_range()].trim_matches(['\'', '"']);
+                let quote = |suggestion: &str| format!("'{}'", &suggestion[1..]);
+                read_variable(child, &format!("${}", name), quote, scope, diagnostics);
+            }
+            _ => mark_read(child, content, scope),
+        }
+    }
+}
+
+/// Count the variable as read, and flag it if it isn't defined.
+///
+/// `rewrite` turns the name we suggest instead into the text that replaces `node`, like `{$name}`
+/// for `"${name}"` so the text after it stays out of the name.
+fn read_variable(
+    node: Node<'_>,
+    name: &str,
+    rewrite: impl Fn(&str) -> String,
+    scope: &mut Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    scope.read.insert(name.to_string());
+    if scope.symbols.contains(name) || scope.extracted {
+        return;
     }
+
+    let suggestion = scope.closest_symbol(name);
+    let message = match suggestion {
+        Some(suggestion) => {
+            format!("undefined variable {}, did you mean {}?", name, suggestion)
+        }
+        None => format!("undefined variable {}", name),
+    };
+    let fix = suggestion.map(|suggestion| QuickFix {
+        title: format!("Change to `{}`", suggestion),
+        edits: vec![TextEdit {
+            range: to_range(&node.range()),
+            new_text: rewrite(suggestion),
+        }],
+    });
+    diagnostics.push(Diagnostic {
+        range: to_range(&node.range()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("undef".to_string()),
+        message,
+        data: fix.and_then(|fix| serde_json::to_value(fix).ok()),
+        ..Default::default()
+    });
 }
 
 fn expression_right(
     right: Node<'_>,
     content: &str,
     ns_store: &mut SegmentPool,
-    scope: &Scope,
+    scope: &mut Scope,
     diagnostics: &mut Vec<Diagnostic>,
 ) {
     let mut cursor = right.walk();
@@ -157,21 +199,66 @@ fn expression_right(
         let kind = n.kind();
         if kind == "variable_name" {
             let name = &content[n.byte_range()];
-            if !scope.symbols.contains(name) {
-                diagnostics.push(Diagnostic {
-                    range: to_range(&n.range()),
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    source: Some("undef".to_string()),
-                    message: format!("undefined variable {}", name),
-                    ..Default::default()
-                });
+            read_variable(n, name, str::to_string, scope, diagnostics);
+        } else if kind == "dynamic_variable_name"
+            && n.parent()
+                .is_some_and(|p| matches!(p.kind(), "encapsed_string" | "heredoc_body"))
+        {
+            // `"${name}"` and `"${name[0]}"` are the old way of writing `"{$name}"`
+            let Some(inner) = n.named_child(0) else {
+                continue;
+            };
+            let (name, index) = match inner.kind() {
+                "name" => (inner, None),
+                "subscript_expression" => {
+                    (inner.named_child(0).unwrap_or(inner), inner.named_child(1))
+                }
+                _ => {
+                    stack.push(inner);
+                    continue;
+                }
+            };
+            if name.kind() == "name" {
+                let variable = format!("${}", &content[name.byte_range()]);
+                let braced = |suggestion: &str| format!("{{{}}}", suggestion);
+                read_variable(n, &variable, braced, scope, diagnostics);
             }
+            stack.extend(index);
+        } else if kind == "function_call_expression"
+            && let Some(function) = n.child_by_field_name("function")
+            && let Some(arguments) = n.child_by_field_name("arguments")
+            && matches!(
+                content[function.byte_range()]
+                    .trim_start_matches('\\')
+                    .to_lowercase()
+                    .as_str(),
+                "compact" | "extract"
+            )
+        {
+            if content[function.byte_range()]
+                .to_lowercase()
+                .ends_with("compact")
+            {
+                compact_arguments(arguments, content, scope, diagnostics);
+                continue;
+            }
+
+            scope.extracted = true;
+            diagnostics.push(Diagnostic {
+                range: to_range(&n.range()),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("extract".to_string()),
+                message: "extract() defines variables we can't check".to_string(),
+                ..Default::default()
+            });
+            stack.push(arguments);
         } else if kind == "arrow_function" {
             let mut arrow_function_scope = scope.clone();
+            let mut params = Vec::new();
             if let Some(params_node) = n.child_by_field_name("parameters") {
-                let params = function_parameters(params_node, content, diagnostics);
-                for param in params {
-                    arrow_function_scope.symbols.insert(param);
+                params = function_parameters(params_node, content, diagnostics);
+                for param in params.iter() {
+                    arrow_function_scope.symbols.insert(param.clone());
                 }
             }
 
@@ -184,25 +271,68 @@ fn expression_right(
                     diagnostics,
                 );
             }
+
+            // arrow functions capture whatever they read
+            for name in arrow_function_scope.read {
+                if !params.contains(&name) {
+                    scope.read.insert(name);
+                }
+            }
+        } else if kind == "match_expression" {
+            walk_match_expression(n, content, ns_store, scope, diagnostics);
+        } else if kind == "anonymous_class" {
+            // the constructor arguments come from out here, the body is a class like any other
+            let mut cursor = n.walk();
+            for child in n.children(&mut cursor) {
+                if child.kind() == "arguments" {
+                    stack.push(child);
+                } else if child.kind() == "declaration_list" {
+                    walk_class_body(child, content, ns_store, scope, diagnostics);
+                }
+            }
         } else if kind == "anonymous_function" {
-            let mut anonymous_scope = scope.clone();
+            let mut anonymous_scope = scope.function();
             if let Some(params_node) = n.child_by_field_name("parameters") {
                 let params = function_parameters(params_node, content, diagnostics);
                 for param in params {
                     anonymous_scope.symbols.insert(param);
                 }
+                anonymous_scope
+                    .references
+                    .extend(reference_parameters(params_node, content));
             }
 
             let mut cursor = n.walk();
             for child in n.children(&mut cursor) {
                 if child.kind() == "anonymous_function_use_clause" {
-                    stack.push(child);
+                    // captured variables belong to the outer function
+                    let mut captured = Scope::empty();
+                    mark_read(child, content, &mut captured);
+                    anonymous_scope.references.extend(captured.read);
+
+                    let mut cursor = child.walk();
+                    for variable in child.named_children(&mut cursor) {
+                        if variable.kind() != "by_ref" {
+                            stack.push(variable);
+                            continue;
+                        }
+                        // capturing by reference defines it out here, and whatever the closure
+                        // assigns to it shows up here too
+                        let Some(name) = variable.named_child(0) else {
+                            continue;
+                        };
+                        let name = content[name.byte_range()].to_string();
+                        scope.symbols.insert(name.clone());
+                        scope.read.insert(name.clone());
+                        anonymous_scope.symbols.insert(name);
+                    }
                     break;
                 }
             }
 
             if let Some(body) = n.child_by_field_name("body") {
                 walk_statement(body, content, ns_store, &mut anonymous_scope, diagnostics);
+                unused_variables(&anonymous_scope, diagnostics);
             }
         } else {
             stack.extend(n.children(&mut cursor));
@@ -221,15 +351,65 @@ fn walk_assignment_expression(
         assign.child_by_field_name("left"),
         assign.child_by_field_name("right"),
     ) {
-        let symbols = expression_left(left, content);
+        // `$a .= 'b'` reads `$a` before writing it, but `$a ??= 'b'` is fine with it undefined
+        if assign.kind() == "augmented_assignment_expression" {
+            let mut cursor = assign.walk();
+            if assign.children(&mut cursor).any(|c| c.kind() == "??=") {
+                mark_read(left, content, scope);
+            } else {
+                walk_expression(left, content, ns_store, scope, diagnostics);
+            }
+        }
+
+        let (mut variables, mut read) = (Vec::new(), Vec::new());
+        expression_left(left, &mut variables, &mut read);
         walk_expression(right, content, ns_store, scope, diagnostics);
 
-        for symbol in symbols {
-            scope.symbols.insert(symbol);
+        // `$a[] = 1` and `$a->b = 1` make use of whatever `$a` is
+        for node in read {
+            mark_read(node, content, scope);
+        }
+        for variable in variables {
+            let name = &content[variable.byte_range()];
+            scope.symbols.insert(name.to_string());
+            scope.assign(name, to_range(&variable.range()));
         }
     }
 }
 
+/// Flag `if ($a = 1)`, which is usually a typo of `==` or `===`.
+///
+/// Wrapping the assignment in another pair of parentheses says it's on purpose, like in
+/// `while (($line = fgets($f)) !== false)`.
+fn assignment_in_condition(condition: Node<'_>, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(assignment) = condition
+        .named_child(0)
+        .filter(|a| condition.named_child_count() == 1 && a.kind() == "assignment_expression")
+    else {
+        return;
+    };
+    let mut cursor = assignment.walk();
+    let Some(operator) = assignment.children(&mut cursor).find(|c| c.kind() == "=") else {
+        return;
+    };
+
+    let fix = QuickFix {
+        title: "Change `=` to `===`".to_string(),
+        edits: vec![TextEdit {
+            range: to_range(&operator.range()),
+            new_text: "===".to_string(),
+        }],
+    };
+    diagnostics.push(Diagnostic {
+        range: to_range(&assignment.range()),
+        severity: Some(DiagnosticSeverity::WARNING),
+        source: Some("condition".to_string()),
+        message: "assignment in condition".to_string(),
+        data: serde_json::to_value(fix).ok(),
+        ..Default::default()
+    });
+}
+
 fn walk_if_statement(
     stmt: Node<'_>,
     content: &str,
@@ -243,6 +423,7 @@ fn walk_if_statement(
     if let Some(condition) = stmt.child_by_field_name("condition") {
         let mut s = scope.clone();
         // i'm pretty sure that you can also do assignments in conditionals
+        assignment_in_condition(condition, diagnostics);
         walk_expression(condition, content, ns_store, &mut s, diagnostics);
         scopes.push(s);
     }
@@ -259,6 +440,7 @@ fn walk_if_statement(
         if kind == "else_if_clause" {
             if let Some(condition) = alt.child_by_field_name("condition") {
                 let mut s = scope.clone();
+                assignment_in_condition(condition, diagnostics);
                 walk_expression(condition, content, ns_store, &mut s, diagnostics);
                 scopes.push(s);
             }
@@ -276,39 +458,72 @@ fn walk_if_statement(
     }
 }
 
-fn walk_class_declaration(
-    decl: Node<'_>,
+/// `match` evaluates its subject, then only one arm: its conditions and, if one matches, its
+/// result.
+fn walk_match_expression(
+    expression: Node<'_>,
     content: &str,
     ns_store: &mut SegmentPool,
     scope: &mut Scope,
     diagnostics: &mut Vec<Diagnostic>,
 ) {
-    let mut t = Class::default();
-    let mut markup = None;
+    if let Some(condition) = expression.child_by_field_name("condition") {
+        walk_expression(condition, content, ns_store, scope, diagnostics);
+    }
 
-    if let Some(prev) = decl.prev_sibling() {
-        if prev.kind() == "comment" {
-            let comment = &content[prev.byte_range()];
-            if comment.starts_with("/**") {
-                markup = Some(comment.to_string());
+    let mut scopes = Vec::new();
+    if let Some(body) = expression.child_by_field_name("body") {
+        let mut cursor = body.walk();
+        for arm in body.named_children(&mut cursor) {
+            let mut s = scope.clone();
+            if let Some(conditions) = arm.child_by_field_name("conditional_expressions") {
+                let mut cursor = conditions.walk();
+                for condition in conditions.named_children(&mut cursor) {
+                    walk_expression(condition, content, ns_store, &mut s, diagnostics);
+                }
             }
+            if let Some(result) = arm.child_by_field_name("return_expression") {
+                walk_expression(result, content, ns_store, &mut s, diagnostics);
+            }
+            scopes.push(s);
         }
     }
 
+    for s in scopes {
+        scope.absorb(s);
+    }
+}
+
+fn walk_class_declaration(
+    decl: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    scope: &mut Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
     if let Some(name) = decl.child_by_field_name("name") {
         scope.symbols.insert(content[name.byte_range()].to_string());
-        t.name = content[name.byte_range()].to_string();
     }
 
     if let Some(body) = decl.child_by_field_name("body") {
-        if body.kind() == "declaration_list" {
-            let mut cursor = body.walk();
-            for child in body.children(&mut cursor) {
-                // each declaration should have it's own scope
-                let mut scope = scope.clone();
-                scope.symbols.insert("self".to_string());
-                walk_declaration(child, content, ns_store, &mut scope, diagnostics);
-            }
+        walk_class_body(body, content, ns_store, scope, diagnostics);
+    }
+}
+
+fn walk_class_body(
+    body: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    scope: &mut Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if body.kind() == "declaration_list" {
+        let mut cursor = body.walk();
+        for child in body.children(&mut cursor) {
+            // each declaration should have it's own scope
+            let mut scope = scope.clone();
+            scope.symbols.insert("self".to_string());
+            walk_declaration(child, content, ns_store, &mut scope, diagnostics);
         }
     }
 }
@@ -324,17 +539,21 @@ fn walk_function_declaration(
         scope.symbols.insert(content[name.byte_range()].to_string());
     }
 
-    let mut function_scope = scope.clone();
+    let mut function_scope = scope.function();
 
     if let Some(params_node) = decl.child_by_field_name("parameters") {
         let params = function_parameters(params_node, content, diagnostics);
         for param in params {
             function_scope.symbols.insert(param);
         }
+        function_scope
+            .references
+            .extend(reference_parameters(params_node, content));
     }
 
     if let Some(body) = decl.child_by_field_name("body") {
         walk_statement(body, content, ns_store, &mut function_scope, diagnostics);
+        unused_variables(&function_scope, diagnostics);
     }
 }
 
@@ -365,6 +584,59 @@ fn walk_declaration(
         walk_function_declaration(decl, content, ns_store, scope, diagnostics)
     } else if kind == "method_declaration" {
         walk_method_declaration(decl, content, ns_store, scope, diagnostics)
+    } else if kind == "property_declaration" {
+        walk_property_hooks(decl, content, ns_store, scope, diagnostics)
+    }
+}
+
+/// Property hooks are little methods: `$this` is around, and a `set` hook without a parameter
+/// list gets the new value as `$value`.
+fn walk_property_hooks(
+    decl: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    scope: &mut Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut cursor = decl.walk();
+    let Some(hooks) = decl
+        .children(&mut cursor)
+        .find(|child| child.kind() == "property_hook_list")
+    else {
+        return;
+    };
+
+    let mut cursor = hooks.walk();
+    for hook in hooks.named_children(&mut cursor) {
+        if hook.kind() != "property_hook" {
+            continue;
+        }
+        let mut hook_scope = scope.function();
+        hook_scope.symbols.insert("$this".to_string());
+        match hook.child_by_field_name("parameters") {
+            Some(params_node) => {
+                for param in function_parameters(params_node, content, diagnostics) {
+                    hook_scope.symbols.insert(param);
+                }
+            }
+            None if hook
+                .named_child(0)
+                .is_some_and(|name| content[name.byte_range()].eq_ignore_ascii_case("set")) =>
+            {
+                hook_scope.symbols.insert("$value".to_string());
+            }
+            None => {}
+        }
+
+        let Some(body) = hook.child_by_field_name("body") else {
+            continue;
+        };
+        if body.kind() == "compound_statement" {
+            walk_statement(body, content, ns_store, &mut hook_scope, diagnostics);
+        } else {
+            walk_expression(body, content, ns_store, &mut hook_scope, diagnostics);
+        }
+        unused_variables(&hook_scope, diagnostics);
     }
 }
 
@@ -379,6 +651,8 @@ fn walk_expression(
 
     if kind.ends_with("assignment_expression") {
         walk_assignment_expression(expression, content, ns_store, scope, diagnostics)
+    } else if kind == "match_expression" {
+        walk_match_expression(expression, content, ns_store, scope, diagnostics)
     } else if kind == "parenthesized_expression" {
         if let Some(expr) = expression.child(1) {
             walk_expression(expr, content, ns_store, scope, diagnostics)
@@ -430,15 +704,23 @@ fn walk_foreach_statement(
             let mut cursor = child.walk();
             for x in child.children(&mut cursor) {
                 scope.symbols.insert(content[x.byte_range()].to_string());
+                if x.kind() == "variable_name" {
+                    scope.assign(&content[x.byte_range()], to_range(&x.range()));
+                } else if x.kind() == "by_ref" {
+                    mark_read(x, content, scope);
+                }
             }
         } else if child.kind() == "variable_name" {
             scope
                 .symbols
                 .insert(content[child.byte_range()].to_string());
+            scope.assign(&content[child.byte_range()], to_range(&child.range()));
         } else if child.kind() == "by_ref" {
             if let Some(v) = child.child(1) {
                 scope.symbols.insert(content[v.byte_range()].to_string());
             }
+            // writing to it writes to the array
+            mark_read(child, content, scope);
         }
     }
 
@@ -455,6 +737,7 @@ fn walk_while_statement(
     diagnostics: &mut Vec<Diagnostic>,
 ) {
     if let Some(condition) = statement.child_by_field_name("condition") {
+        assignment_in_condition(condition, diagnostics);
         walk_expression(condition, content, ns_store, scope, diagnostics);
     }
 
@@ -475,10 +758,134 @@ fn walk_do_statement(
     }
 
     if let Some(condition) = statement.child_by_field_name("condition") {
+        assignment_in_condition(condition, diagnostics);
         walk_expression(condition, content, ns_store, scope, diagnostics);
     }
 }
 
+/// Return true if the call is `trigger_error(..., E_USER_ERROR)`, which stops the script like an
+/// uncaught exception would.
+pub fn raises_fatal_error(call: Node<'_>, content: &str) -> bool {
+    let Some(function) = call.child_by_field_name("function") else {
+        return false;
+    };
+    let name = content[function.byte_range()].trim_start_matches('\\');
+    if !name.eq_ignore_ascii_case("trigger_error") && !name.eq_ignore_ascii_case("user_error") {
+        return false;
+    }
+
+    call.child_by_field_name("arguments")
+        .and_then(|arguments| arguments.named_child(1))
+        .is_some_and(|level| content[level.byte_range()].trim_start_matches('\\') == "E_USER_ERROR")
+}
+
+/// Return true if control never makes it past the statement, so a `case` ending with it doesn't
+/// fall through.
+fn leaves_case(statement: Node<'_>, content: &str) -> bool {
+    let mut cursor = statement.walk();
+    match statement.kind() {
+        "break_statement" | "continue_statement" | "return_statement" | "exit_statement"
+        | "goto_statement" => true,
+        "expression_statement" => statement.named_child(0).is_some_and(|e| {
+            let callee = match e.kind() {
+                "throw_expression" => return true,
+                "function_call_expression" if raises_fatal_error(e, content) => return true,
+                "function_call_expression" => e.child_by_field_name("function"),
+                _ => Some(e),
+            };
+            callee.is_some_and(|c| {
+                c.kind() == "name"
+                    && matches!(
+                        content[c.byte_range()].to_lowercase().as_str(),
+                        "die" | "exit"
+                    )
+            })
+        }),
+        "compound_statement" => statement
+            .named_children(&mut cursor)
+            .filter(|s| s.kind() != "comment")
+            .last()
+            .is_some_and(|s| leaves_case(s, content)),
+        "if_statement" => {
+            let alternatives: Vec<_> = statement
+                .children_by_field_name("alternative", &mut cursor)
+                .collect();
+            statement
+                .child_by_field_name("body")
+                .is_some_and(|b| leaves_case(b, content))
+                && alternatives.iter().any(|a| a.kind() == "else_clause")
+                && alternatives.iter().all(|a| {
+                    a.child_by_field_name("body")
+                        .is_some_and(|b| leaves_case(b, content))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Flag `case`s that run into the next one without a `break`.
+///
+/// Empty `case`s are how you match several values, and a `// no break` or `// fallthrough`
+/// comment says it's on purpose.
+fn switch_fallthrough(body: Node<'_>, content: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let is_marker = |comment: Node<'_>| {
+        let text = content[comment.byte_range()].to_lowercase();
+        ["no break", "fallthrough", "fall through", "falls through"]
+            .iter()
+            .any(|marker| text.contains(marker))
+    };
+
+    let mut cursor = body.walk();
+    let children: Vec<_> = body.named_children(&mut cursor).collect();
+    for (i, case) in children.iter().enumerate() {
+        if case.kind() != "case_statement" && case.kind() != "default_statement" {
+            continue;
+        }
+        // comments after the last statement end up between the cases
+        let following = &children[i + 1..];
+        let Some(next) = following
+            .iter()
+            .position(|c| c.kind() == "case_statement" || c.kind() == "default_statement")
+        else {
+            continue;
+        };
+        if following[..next].iter().any(|&c| is_marker(c)) {
+            continue;
+        }
+
+        let value = case.child_by_field_name("value");
+        let mut cursor = case.walk();
+        let statements: Vec<_> = case
+            .named_children(&mut cursor)
+            .filter(|&s| Some(s) != value)
+            .collect();
+        let Some(last) = statements.iter().rposition(|s| s.kind() != "comment") else {
+            continue;
+        };
+        if leaves_case(statements[last], content)
+            || statements[last + 1..].iter().any(|&c| is_marker(c))
+        {
+            continue;
+        }
+
+        let mut cursor = case.walk();
+        let label_end = case
+            .children(&mut cursor)
+            .find(|c| c.kind() == ":" || c.kind() == ";")
+            .map_or(case.end_position(), |c| c.end_position());
+        diagnostics.push(Diagnostic {
+            range: Range {
+                start: to_position(&case.start_position()),
+                end: to_position(&label_end),
+            },
+            severity: Some(DiagnosticSeverity::WARNING),
+            source: Some("fallthrough".to_string()),
+            message: "case falls through to the next one without a break".to_string(),
+            ..Default::default()
+        });
+    }
+}
+
 fn walk_switch_statement(
     statement: Node<'_>,
     content: &str,
@@ -491,6 +898,8 @@ fn walk_switch_statement(
     }
 
     if let Some(body) = statement.child_by_field_name("body") {
+        switch_fallthrough(body, content, diagnostics);
+
         let mut cursor = body.walk();
         for statement in body.children(&mut cursor) {
             if statement.kind() == "case_statement" || statement.kind() == "default_statement" {
@@ -507,7 +916,49 @@ fn walk_switch_statement(
     }
 }
 
-fn walk_statement(
+/// The `try` body might have stopped anywhere, so what it assigns is only maybe defined in the
+/// `catch` clauses and afterwards; like with `if`, we give it the benefit of the doubt.
+fn walk_try_statement(
+    statement: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    scope: &mut Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut try_scope = scope.clone();
+    if let Some(body) = statement.child_by_field_name("body") {
+        walk_statement(body, content, ns_store, &mut try_scope, diagnostics);
+    }
+
+    let mut scopes = Vec::new();
+    let mut finally = None;
+    let mut cursor = statement.walk();
+    for clause in statement.named_children(&mut cursor) {
+        if clause.kind() == "catch_clause" {
+            let mut s = try_scope.clone();
+            // catching without using the exception is fine, so it's not an assignment
+            if let Some(name) = clause.child_by_field_name("name") {
+                s.symbols.insert(content[name.byte_range()].to_string());
+            }
+            if let Some(body) = clause.child_by_field_name("body") {
+                walk_statement(body, content, ns_store, &mut s, diagnostics);
+            }
+            scopes.push(s);
+        } else if clause.kind() == "finally_clause" {
+            finally = clause.child_by_field_name("body");
+        }
+    }
+
+    scope.absorb(try_scope);
+    for s in scopes {
+        scope.absorb(s);
+    }
+    if let Some(body) = finally {
+        walk_statement(body, content, ns_store, scope, diagnostics);
+    }
+}
+
+pub fn walk_statement(
     statement: Node<'_>,
     content: &str,
     ns_store: &mut SegmentPool,
@@ -537,21 +988,60 @@ fn walk_statement(
         walk_do_statement(statement, content, ns_store, scope, diagnostics);
     } else if kind == "switch_statement" {
         walk_switch_statement(statement, content, ns_store, scope, diagnostics);
+    } else if kind == "global_declaration" {
+        let mut cursor = statement.walk();
+        for variable in statement.named_children(&mut cursor) {
+            if variable.kind() == "variable_name" {
+                let name = content[variable.byte_range()].to_string();
+                // writing to it writes to the file's variable
+                scope.references.insert(name.clone());
+                scope.symbols.insert(name);
+            }
+        }
+    } else if kind == "function_static_declaration" {
+        let mut cursor = statement.walk();
+        for declaration in statement.named_children(&mut cursor) {
+            if let Some(value) = declaration.child_by_field_name("value") {
+                walk_expression(value, content, ns_store, scope, diagnostics);
+            }
+            if let Some(name) = declaration.child_by_field_name("name") {
+                let variable = &content[name.byte_range()];
+                scope.symbols.insert(variable.to_string());
+                scope.assign(variable, to_range(&name.range()));
+            }
+        }
+    } else if kind == "try_statement" {
+        walk_try_statement(statement, content, ns_store, scope, diagnostics);
+    } else if kind == "goto_statement" || kind == "named_label_statement" {
+        // labels aren't variables, so there's nothing in here for us
     } else if kind == "echo_statement" {
         let mut cursor = statement.walk();
         for child in statement.children(&mut cursor) {
             walk_expression(child, content, ns_store, scope, diagnostics);
         }
+    } else {
+        // we don't look into these yet, so assume they use everything they mention
+        mark_read(statement, content, scope);
     }
 }
 
-pub fn walk_ns_use_clause(
+/// The FQN a `use` clause imports, prefix of its group included, and whether it's a `use function`
+/// or a `use const`.
+pub fn use_clause_fqn(
     node: Node<'_>,
     content: &str,
     ns_store: &mut SegmentPool,
-    scope: &mut Scope,
-    diagnostics: &mut Vec<Diagnostic>,
-) {
+) -> Option<(Option<&'static str>, PhpNamespace)> {
+    // clauses in a group (`use Foo\{Bar, Baz}`) take their prefix and type from the declaration
+    let group = node
+        .parent()
+        .filter(|parent| parent.kind() == "namespace_use_group");
+    let declaration = group.and_then(|group| group.parent());
+    let use_type = node
+        .child_by_field_name("type")
+        .or_else(|| declaration.and_then(|d| d.child_by_field_name("type")))
+        .map(|t| t.kind());
+
     let mut ns = None;
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
@@ -560,60 +1050,321 @@ pub fn walk_ns_use_clause(
             break;
         }
     }
-
-    if let Some(ns) = ns {
-        if let Some(alias) = node.child_by_field_name("alias") {
-            if scope.ns_aliases.contains_key(&content[alias.byte_range()]) {
-                diagnostics.push(Diagnostic {
-                    range: to_range(&node.range()),
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    source: Some("dupe".to_string()),
-                    message: format!(
-                        "namespace alias {} already declared",
-                        &content[alias.byte_range()]
-                    ),
-                    ..Default::default()
-                });
-            } else {
-                scope
-                    .ns_aliases
-                    .insert(content[alias.byte_range()].to_string(), ns);
-            }
-        } else {
-            let alias = ns.0[ns.len() - 1].to_string();
-            if scope.ns_aliases.contains_key(&alias) {
-                diagnostics.push(Diagnostic {
-                    range: to_range(&node.range()),
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    source: Some("dupe".to_string()),
-                    message: format!("namespace alias {} already declared", &alias),
-                    ..Default::default()
-                });
-            } else {
-                scope.ns_aliases.insert(alias, ns);
-            }
+    let mut ns = ns?;
+    if let Some(declaration) = declaration {
+        let mut cursor = declaration.walk();
+        let prefix = declaration
+            .children(&mut cursor)
+            .find(|child| child.kind() == "namespace_name");
+        if let Some(prefix) = prefix {
+            let mut full = ns_store.intern_str(&content[prefix.byte_range()]);
+            full.extend(ns.0);
+            ns = full;
         }
     }
+
+    Some((use_type, ns))
 }
 
-pub fn walk_ns_use_declaration(
+/// What a `use` clause imports: whether it's a `"function"` or a `"namespace"`, the alias it's
+/// imported as, and its FQN.
+///
+/// Returns `None` for `use const`.
+fn use_clause_target(
     node: Node<'_>,
     content: &str,
     ns_store: &mut SegmentPool,
-    scope: &mut Scope,
-    diagnostics: &mut Vec<Diagnostic>,
-) {
-    let mut cursor = node.walk();
-    for child in node.children(&mut cursor) {
-        if child.kind() == "namespace_use_clause" {
-            walk_ns_use_clause(child, content, ns_store, scope, diagnostics);
-        }
+) -> Option<(&'static str, String, PhpNamespace)> {
+    let (use_type, ns) = use_clause_fqn(node, content, ns_store)?;
+    // TODO track `use const` once we know about constants
+    if use_type == Some("const") {
+        return None;
     }
+
+    let what = if use_type == Some("function") {
+        "function"
+    } else {
+        "namespace"
+    };
+    let alias = match node.child_by_field_name("alias") {
+        Some(alias) => content[alias.byte_range()].to_string(),
+        None => ns.0[ns.len() - 1].to_string(),
+    };
+
+    Some((what, alias, ns))
 }
 
-pub fn walk(node: Node<'_>, content: &str, ns_store: &mut SegmentPool) -> Vec<Diagnostic> {
-    let mut cursor = node.walk();
-    let mut diagnostics = Vec::new();
+/// Bring the alias of a `use` clause into scope, remembering where it was declared.
+///
+/// If the alias was already taken, the scope is left alone and we get back what kind of alias it
+/// was, its name, and where it was first declared.
+fn declare_alias(
+    node: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    scope: &mut Scope,
+) -> Option<(&'static str, String, Range)> {
+    let (what, alias, ns) = use_clause_target(node, content, ns_store)?;
+
+    let aliases = if what == "function" {
+        &mut scope.fn_aliases
+    } else {
+        &mut scope.ns_aliases
+    };
+    if let Some((_, range)) = aliases.get(&alias) {
+        return Some((what, alias, *range));
+    }
+    aliases.insert(alias, (ns, to_range(&node.range())));
+
+    None
+}
+
+/// All `use` clauses of a declaration, including the ones in groups.
+pub fn use_clauses(node: Node<'_>) -> Vec<Node<'_>> {
+    let mut clauses = Vec::new();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == "namespace_use_clause" {
+            clauses.push(child);
+        } else if child.kind() == "namespace_use_group" {
+            let mut cursor = child.walk();
+            clauses.extend(
+                child
+                    .children(&mut cursor)
+                    .filter(|clause| clause.kind() == "namespace_use_clause"),
+            );
+        }
+    }
+
+    clauses
+}
+
+/// Bring the aliases of a `use` declaration into scope, without checking them.
+pub fn import_aliases(
+    node: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    scope: &mut Scope,
+) {
+    for clause in use_clauses(node) {
+        declare_alias(clause, content, ns_store, scope);
+    }
+}
+
+/// The file's namespace and imports.
+pub fn file_scope(node: Node<'_>, content: &str, ns_store: &mut SegmentPool) -> Scope {
+    let mut scope = Scope::empty();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "namespace_definition" if child.child_by_field_name("body").is_none() => {
+                scope.ns = child
+                    .child_by_field_name("name")
+                    .map(|name| ns_store.intern_str(&content[name.byte_range()]));
+            }
+            "namespace_use_declaration" => import_aliases(child, content, ns_store, &mut scope),
+            _ => {}
+        }
+    }
+
+    scope
+}
+
+pub fn walk_ns_use_declaration(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    ns_store: &mut SegmentPool,
+    scope: &mut Scope,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for clause in use_clauses(node) {
+        let Some((what, alias, first)) = declare_alias(clause, content, ns_store, scope) else {
+            continue;
+        };
+
+        let related_information = Uri::from_file_path(path).map(|uri| {
+            vec![DiagnosticRelatedInformation {
+                location: Location { uri, range: first },
+                message: format!("{} first declared here", alias),
+            }]
+        });
+        diagnostics.push(Diagnostic {
+            range: to_range(&clause.range()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("dupe".to_string()),
+            message: format!("{} alias {} already declared", what, &alias),
+            related_information,
+            ..Default::default()
+        });
+    }
+}
+
+/// Collect the names a file refers to that could be `use` aliases: unqualified names, and the first
+/// segment of qualified ones. Words in docblocks count too, since they're often types.
+pub fn referenced_names(node: Node<'_>, content: &str, names: &mut HashSet<String>) {
+    let kind = node.kind();
+    if kind == "namespace_use_declaration" || kind == "namespace_definition" {
+        return;
+    } else if kind == "qualified_name" {
+        let name = &content[node.byte_range()];
+        if !name.starts_with('\\') {
+            let first = name.split('\\').next().unwrap_or(name);
+            names.insert(first.to_lowercase());
+        }
+        return;
+    } else if kind == "name" {
+        let parent = node.parent().map(|p| p.kind());
+        if !matches!(parent, Some("variable_name" | "relative_name")) {
+            names.insert(content[node.byte_range()].to_lowercase());
+        }
+        return;
+    } else if kind == "comment" {
+        let comment = &content[node.byte_range()];
+        if comment.starts_with("/**") {
+            for word in comment.split(|c: char| !c.is_alphanumeric() && c != '_' && c != '\\') {
+                if let Some(first) = word.split('\\').next().filter(|w| !w.is_empty()) {
+                    names.insert(first.to_lowercase());
+                }
+            }
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        referenced_names(child, content, names);
+    }
+}
+
+/// The whole line(s) a node is on, if there's nothing else there.
+pub fn whole_lines(node: Node<'_>, content: &str) -> Range {
+    let mut range = to_range(&node.range());
+
+    let line_start = content[..node.start_byte()]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let rest = &content[node.end_byte()..];
+    if content[line_start..node.start_byte()].trim().is_empty()
+        && (rest.starts_with('\n') || rest.starts_with("\r\n"))
+    {
+        range.start.character = 0;
+        range.end = Position {
+            line: range.end.line + 1,
+            character: 0,
+        };
+    }
+
+    range
+}
+
+/// What to delete to get rid of a `use` clause along with its comma, or the whole declaration if
+/// it's the only clause in there.
+pub fn use_clause_removal(clause: Node<'_>, content: &str) -> Option<Range> {
+    let parent = clause.parent()?;
+    let declaration = if parent.kind() == "namespace_use_group" {
+        parent.parent()?
+    } else {
+        parent
+    };
+
+    let mut cursor = parent.walk();
+    let siblings: Vec<_> = parent
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "namespace_use_clause")
+        .collect();
+    let i = siblings.iter().position(|c| *c == clause)?;
+    let range = if siblings.len() == 1 {
+        whole_lines(declaration, content)
+    } else if let Some(next) = siblings.get(i + 1) {
+        Range {
+            start: to_position(&clause.start_position()),
+            end: to_position(&next.start_position()),
+        }
+    } else {
+        Range {
+            start: to_position(&siblings[i - 1].end_position()),
+            end: to_position(&clause.end_position()),
+        }
+    };
+
+    Some(range)
+}
+
+/// Flag `use` clauses whose alias never shows up in the file, with a quick fix that removes them.
+fn unused_imports(
+    node: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut names = HashSet::new();
+    referenced_names(node, content, &mut names);
+
+    let mut clauses = Vec::new();
+    let mut cursor = node.walk();
+    for declaration in node.children(&mut cursor) {
+        if declaration.kind() != "namespace_use_declaration" {
+            continue;
+        }
+
+        let mut cursor = declaration.walk();
+        for child in declaration.children(&mut cursor) {
+            if child.kind() == "namespace_use_clause" {
+                clauses.push(child);
+            } else if child.kind() == "namespace_use_group" {
+                let mut cursor = child.walk();
+                clauses.extend(
+                    child
+                        .children(&mut cursor)
+                        .filter(|c| c.kind() == "namespace_use_clause"),
+                );
+            }
+        }
+    }
+
+    for clause in clauses {
+        let Some((what, alias, ns)) = use_clause_target(clause, content, ns_store) else {
+            continue;
+        };
+        if names.contains(&alias.to_lowercase()) {
+            continue;
+        }
+
+        let fix = use_clause_removal(clause, content).map(|range| QuickFix {
+            title: "Remove unused import".to_string(),
+            edits: vec![TextEdit {
+                range,
+                new_text: String::new(),
+            }],
+        });
+        let message = if what == "function" {
+            format!("unused function import {}", ns)
+        } else {
+            format!("unused import {}", ns)
+        };
+        diagnostics.push(Diagnostic {
+            range: to_range(&clause.range()),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("unused-import".to_string())),
+            source: Some("unused".to_string()),
+            message,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: fix.and_then(|fix| serde_json::to_value(fix).ok()),
+            ..Default::default()
+        });
+    }
+}
+
+pub fn walk(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    ns_store: &mut SegmentPool,
+) -> Vec<Diagnostic> {
+    let mut cursor = node.walk();
+    let mut diagnostics = Vec::new();
 
     let kind = node.kind();
     if kind == "program" {
@@ -628,13 +1379,22 @@ pub fn walk(node: Node<'_>, content: &str, ns_store: &mut SegmentPool) -> Vec<Di
                     scope.ns = Some(ns);
                 }
             } else if kind == "namespace_use_declaration" {
-                walk_ns_use_declaration(child, content, ns_store, &mut scope, &mut diagnostics);
+                walk_ns_use_declaration(
+                    child,
+                    content,
+                    path,
+                    ns_store,
+                    &mut scope,
+                    &mut diagnostics,
+                );
             } else if kind.ends_with("_declaration") || kind == "function_definition" {
                 walk_declaration(child, content, ns_store, &mut scope, &mut diagnostics);
             } else if kind.ends_with("_statement") {
                 walk_statement(child, content, ns_store, &mut scope, &mut diagnostics);
             }
         }
+
+        unused_imports(node, content, ns_store, &mut diagnostics);
     }
 
     diagnostics
@@ -649,6 +1409,7 @@ pub fn walk(node: Node<'_>, content: &str, ns_store: &mut SegmentPool) -> Vec<Di
 pub fn injest_types(
     node: Node<'_>,
     content: &str,
+    path: &Path,
     ns_store: &mut SegmentPool,
     types: &mut CustomTypesDatabase,
 ) -> Vec<PhpNamespace> {
@@ -657,6 +1418,7 @@ pub fn injest_types(
 
     let kind = node.kind();
     if kind == "program" {
+        types.forget_declarations(path);
         let mut scope = Scope::empty();
         for child in node.children(&mut cursor) {
             let kind = child.kind();
@@ -668,12 +1430,48 @@ pub fn injest_types(
                     scope.ns = Some(ns);
                 }
             } else if kind == "namespace_use_declaration" {
-                // XXX create new fn for mutating scope without diagnostics
-                walk_ns_use_declaration(child, content, ns_store, &mut scope, &mut Vec::new());
+                import_aliases(child, content, ns_store, &mut scope);
             } else if kind == "class_declaration" {
                 injest_class_declaration(
                     child,
                     content,
+                    path,
+                    &scope,
+                    ns_store,
+                    types,
+                    &mut dependencies,
+                );
+            } else if kind == "trait_declaration" {
+                injest_trait_declaration(child, content, path, &scope, ns_store, types);
+            } else if kind == "enum_declaration" {
+                injest_enum_declaration(
+                    child,
+                    content,
+                    path,
+                    &scope,
+                    ns_store,
+                    types,
+                    &mut dependencies,
+                );
+            } else if kind == "function_definition" {
+                injest_function_definition(child, content, path, &scope, ns_store, types);
+            } else if kind == "if_statement"
+                && let Some(body) = child.child_by_field_name("body")
+            {
+                // helpers tend to be declared as `if (!function_exists('app')) { function app() }`
+                let mut cursor = body.walk();
+                for statement in body.named_children(&mut cursor) {
+                    if statement.kind() == "function_definition" {
+                        injest_function_definition(
+                            statement, content, path, &scope, ns_store, types,
+                        );
+                    }
+                }
+            } else if kind == "interface_declaration" {
+                injest_interface_declaration(
+                    child,
+                    content,
+                    path,
                     &scope,
                     ns_store,
                     types,
@@ -697,7 +1495,194 @@ pub fn injest_types(
     dependencies
 }
 
-fn node_markup(node: Node<'_>, content: &str) -> Option<String> {
+/// Like [`injest_types()`], for a file of the PhpStorm stubs. Their functions also note the PHP
+/// version they came with, which `@since` means in the stubs and nowhere else.
+pub fn injest_stub_types(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    ns_store: &mut SegmentPool,
+    types: &mut CustomTypesDatabase,
+) -> Vec<PhpNamespace> {
+    let dependencies = injest_types(node, content, path, ns_store, types);
+    for meta in types.0.values_mut().filter(|meta| meta.src_path == path) {
+        if let CustomType::Function(f) = &mut meta.t
+            && let Some(declaration) =
+                node.descendant_for_byte_range(meta.src_range.start_byte, meta.src_range.end_byte)
+        {
+            f.since = available_since(declaration, content);
+        }
+    }
+
+    dependencies
+}
+
+/// Flag types and functions declared more than once, whether in this file or some other one.
+///
+/// Run this after the file's types have been ingested.
+pub fn duplicate_declarations(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    ns_store: &mut SegmentPool,
+    types: &CustomTypesDatabase,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if node.kind() != "program" {
+        return diagnostics;
+    }
+
+    let mut scope = Scope::empty();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        let kind = child.kind();
+        if kind == "namespace_definition" {
+            if let Some(name) = child.child_by_field_name("name") {
+                scope.ns = Some(ns_store.intern_str(&content[name.byte_range()]));
+            }
+            continue;
+        } else if !matches!(
+            kind,
+            "class_declaration"
+                | "interface_declaration"
+                | "trait_declaration"
+                | "enum_declaration"
+                | "function_definition"
+        ) {
+            continue;
+        }
+        let Some(name) = child.child_by_field_name("name") else {
+            continue;
+        };
+
+        let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+        ns.push(Rc::from(&content[name.byte_range()]));
+        let others = types
+            .declarations(&ns)
+            .iter()
+            .filter(|(p, range)| p != path || *range != child.range())
+            .collect::<Vec<_>>();
+        if others.is_empty() {
+            continue;
+        }
+
+        let related_information = others
+            .iter()
+            .filter_map(|(p, range)| {
+                Some(DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: Uri::from_file_path(p)?,
+                        range: to_range(range),
+                    },
+                    message: format!("{} also declared here", ns),
+                })
+            })
+            .collect();
+        diagnostics.push(Diagnostic {
+            range: to_range(&name.range()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            source: Some("dupe".to_string()),
+            message: format!("{} is declared more than once", ns),
+            related_information: Some(related_information),
+            ..Default::default()
+        });
+    }
+
+    diagnostics
+}
+
+/// Flag `==` and `!=`, which juggle types before comparing, with a fix to `===` and `!==`.
+///
+/// A side that's explicitly cast, like `(int) $a == $b`, says the juggling is understood. So does
+/// comparing to `null` (which also matches `''`, `0` and `[]`), unless `strict_null` asks for it.
+pub fn loose_comparisons(node: Node<'_>, content: &str, strict_null: bool) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut cursor = node.walk();
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        stack.extend(node.named_children(&mut cursor));
+        if node.kind() != "binary_expression" {
+            continue;
+        }
+        let (Some(left), Some(operator), Some(right)) = (
+            node.child_by_field_name("left"),
+            node.child_by_field_name("operator"),
+            node.child_by_field_name("right"),
+        ) else {
+            continue;
+        };
+        let strict = match operator.kind() {
+            "==" => "===",
+            "!=" | "<>" => "!==",
+            _ => continue,
+        };
+        if [left, right]
+            .iter()
+            .any(|side| side.kind() == "cast_expression" || !strict_null && side.kind() == "null")
+        {
+            continue;
+        }
+
+        let loose = &content[operator.byte_range()];
+        let fix = QuickFix {
+            title: format!("Change `{}` to `{}`", loose, strict),
+            edits: vec![TextEdit {
+                range: to_range(&operator.range()),
+                new_text: strict.to_string(),
+            }],
+        };
+        diagnostics.push(Diagnostic {
+            range: to_range(&node.range()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("loose-comparison".to_string())),
+            source: Some("comparison".to_string()),
+            message: format!("loose comparison with `{}`", loose),
+            data: serde_json::to_value(fix).ok(),
+            ..Default::default()
+        });
+    }
+
+    diagnostics
+}
+
+/// Flag `@`, which hides every warning the expression raises, real failures included, with a
+/// fix that takes it out.
+pub fn error_suppressions(node: Node<'_>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut cursor = node.walk();
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        stack.extend(node.named_children(&mut cursor));
+        if node.kind() != "error_suppression_expression" {
+            continue;
+        }
+        let Some(operator) = node.child(0).filter(|c| c.kind() == "@") else {
+            continue;
+        };
+
+        let fix = QuickFix {
+            title: "Remove `@`".to_string(),
+            edits: vec![TextEdit {
+                range: to_range(&operator.range()),
+                new_text: String::new(),
+            }],
+        };
+        diagnostics.push(Diagnostic {
+            range: to_range(&node.range()),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("error-suppression".to_string())),
+            source: Some("suppression".to_string()),
+            message: "`@` hides errors from this expression".to_string(),
+            data: serde_json::to_value(fix).ok(),
+            ..Default::default()
+        });
+    }
+
+    diagnostics
+}
+
+/// The docblock right before a declaration, if there is one.
+pub fn node_markup(node: Node<'_>, content: &str) -> Option<String> {
     if let Some(prev) = node.prev_sibling() {
         if prev.kind() == "comment" {
             let comment = &content[prev.byte_range()];
@@ -710,6 +1695,76 @@ fn node_markup(node: Node<'_>, content: &str) -> Option<String> {
     None
 }
 
+/// Properties declared through constructor promotion.
+fn promoted_properties(
+    node: Node<'_>,
+    content: &str,
+    resolve: &mut NameResolver<'_>,
+) -> Vec<Property> {
+    let Some(parameters) = node.child_by_field_name("parameters") else {
+        return Vec::new();
+    };
+
+    let mut cursor = parameters.walk();
+    parameters
+        .named_children(&mut cursor)
+        .filter(|p| p.kind() == "property_promotion_parameter")
+        .filter_map(|p| Property::from_node(p, content, resolve).ok())
+        .collect()
+}
+
+/// Magic properties declared with `@property`, `@property-read` and `@property-write` in a
+/// docblock.
+///
+/// TODO parse the types once we have a phpdoc parser
+fn markup_properties(markup: &str) -> Vec<Property> {
+    let mut properties = Vec::new();
+    for line in markup.lines() {
+        let line = line
+            .trim_start()
+            .trim_start_matches(['/', '*'])
+            .trim_start();
+        let mut words = line.split_whitespace();
+        if !words.next().is_some_and(|tag| tag.starts_with("@property")) {
+            continue;
+        }
+
+        if let Some(name) = words.find(|word| word.starts_with('$')) {
+            properties.push(Property {
+                name: name.to_string(),
+                t: Type::Any,
+                visibility: Visibility::Public,
+                r#static: false,
+            });
+        }
+    }
+
+    properties
+}
+
+/// Resolve a class name as written in the source into its FQN, following the namespace and the
+/// `use` aliases in scope.
+pub fn resolve_class_name(name: &str, scope: &Scope, ns_store: &mut SegmentPool) -> PhpNamespace {
+    if name.starts_with("\\") {
+        return ns_store.intern_str(name);
+    }
+
+    let relative_ns = ns_store.intern_str(name);
+    let Some(first_segment) = relative_ns.0.first() else {
+        return relative_ns;
+    };
+
+    if let Some((ns, _)) = scope.ns_aliases.get(first_segment.as_ref()) {
+        let mut ns = ns.clone();
+        ns.extend(relative_ns.0[1..].iter().cloned());
+        ns
+    } else {
+        let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+        ns.extend(relative_ns.0);
+        ns
+    }
+}
+
 /// Get all children that have `node.kind() == "name"`.
 ///
 /// Return a list of FQN.
@@ -723,127 +1778,376 @@ pub fn clause_fqn_names(
     let mut names = Vec::new();
 
     for child in node.children(&mut cursor) {
-        if !child.kind().ends_with("name") {
+        if child.kind() == "name" || child.kind() == "qualified_name" {
+            let name = &content[child.byte_range()];
+            names.push(resolve_class_name(name, scope, ns_store));
+        }
+    }
+
+    names
+}
+
+pub fn injest_class_declaration(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    scope: &Scope,
+    ns_store: &mut SegmentPool,
+    types: &mut CustomTypesDatabase,
+    dependencies: &mut Vec<PhpNamespace>,
+) {
+    let mut t = Class::default();
+    let markup = node_markup(node, content);
+
+    if let Some(name) = node.child_by_field_name("name") {
+        t.name = content[name.byte_range()].to_string();
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == "abstract_modifier" {
+            t.r#abstract = true;
+            continue;
+        } else if child.kind() == "readonly_modifier" {
+            t.readonly = true;
+            continue;
+        } else if child.kind() == "attribute_list" {
+            t.dynamic_properties |= content[child.byte_range()].contains("AllowDynamicProperties");
+            continue;
+        } else if !child.kind().ends_with("_clause") {
             continue;
         }
 
-        let name = &content[child.byte_range()];
-        if child.kind() == "name" {
-            if let Some(ns) = scope.ns_aliases.get(name) {
-                names.push(ns.clone());
-            } else {
-                let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
-                ns.0.push(Arc::from(name));
-                names.push(ns);
+        let names = clause_fqn_names(child, content, scope, ns_store);
+        if child.kind() == "base_clause" {
+            t.parent_classes.extend(names.clone());
+        } else if child.kind() == "class_interface_clause" {
+            t.implemented_interfaces.extend(names.clone());
+        } else {
+            panic!("unsupported `_clause` = `{}`", child.kind());
+        }
+
+        dependencies.extend(names);
+    }
+
+    let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+    ns.push(Rc::from(t.name.as_str()));
+    let parent = t.parent_classes.first().cloned();
+    let mut resolve = |name: &str| match name.to_lowercase().as_str() {
+        "self" | "static" => ns.clone(),
+        "parent" => parent.clone().unwrap_or_else(|| ns_store.intern_str(name)),
+        _ => resolve_class_name(name, scope, ns_store),
+    };
+
+    if let Some(body) = node.child_by_field_name("body") {
+        if body.kind() == "declaration_list" {
+            let mut cursor = body.walk();
+            for child in body.children(&mut cursor) {
+                if child.kind() == "property_declaration" {
+                    if let Ok(property) = Property::from_node(child, content, &mut resolve) {
+                        t.properties.insert(property.name.clone(), property);
+                    }
+                } else if child.kind() == "const_declaration" {
+                    for constant in Constant::from_declaration(child, content, &mut resolve) {
+                        t.constants.insert(constant.name.clone(), constant);
+                    }
+                } else if child.kind() == "method_declaration" {
+                    if let Ok(method) = Method::from_node(child, content, &mut resolve) {
+                        if method.name.eq_ignore_ascii_case("__construct") {
+                            for property in promoted_properties(child, content, &mut resolve) {
+                                t.properties.insert(property.name.clone(), property);
+                            }
+                        }
+                        t.methods.insert(method.name.clone(), method);
+                    }
+                } else if child.kind() == "use_declaration" {
+                    let mut cursor = child.walk();
+                    for name in child.children(&mut cursor) {
+                        if name.kind() == "name" || name.kind() == "qualified_name" {
+                            let trait_name = resolve(&content[name.byte_range()]);
+                            t.traits_used.push(trait_name.clone());
+                            dependencies.push(trait_name);
+                        }
+                    }
+                }
             }
-        } else if child.kind() == "qualified_name" {
-            if name.starts_with("\\") {
-                names.push(ns_store.intern_str(name));
-            } else {
-                let relative_ns = ns_store.intern_str(name);
-                if let Some(first_segment) = relative_ns.0.get(0) {
-                    if let Some(ns) = scope.ns_aliases.get(first_segment.as_ref()) {
-                        let mut ns = ns.clone();
-                        ns.pop();
-                        ns.extend(relative_ns.0.into_iter());
-                        names.push(ns);
-                    } else {
-                        let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
-                        ns.extend(relative_ns.0.into_iter());
-                        names.push(ns);
+        }
+    }
+
+    if let Some(markup) = &markup {
+        for property in markup_properties(markup) {
+            t.properties
+                .entry(property.name.clone())
+                .or_insert(property);
+        }
+    }
+
+    if t.name != "" {
+        types.insert(
+            ns,
+            CustomTypeMeta {
+                t: CustomType::Class(t),
+                markup,
+                deprecated: deprecation(node, content),
+                src_range: node.range(),
+                src_path: path.to_path_buf(),
+            },
+        );
+    }
+}
+
+pub fn injest_interface_declaration(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    scope: &Scope,
+    ns_store: &mut SegmentPool,
+    types: &mut CustomTypesDatabase,
+    dependencies: &mut Vec<PhpNamespace>,
+) {
+    let Some(name) = node.child_by_field_name("name") else {
+        return;
+    };
+    let mut t = Interface {
+        name: content[name.byte_range()].to_string(),
+        constants: HashMap::new(),
+        properties: HashMap::new(),
+        methods: HashMap::new(),
+        parent_interfaces: Vec::new(),
+    };
+    let markup = node_markup(node, content);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == "base_clause" {
+            let names = clause_fqn_names(child, content, scope, ns_store);
+            t.parent_interfaces.extend(names.clone());
+            dependencies.extend(names);
+        }
+    }
+
+    let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+    ns.push(Rc::from(t.name.as_str()));
+    let mut resolve = |name: &str| match name.to_lowercase().as_str() {
+        "self" | "static" => ns.clone(),
+        _ => resolve_class_name(name, scope, ns_store),
+    };
+
+    if let Some(body) = node.child_by_field_name("body") {
+        let mut cursor = body.walk();
+        for child in body.children(&mut cursor) {
+            if child.kind() == "const_declaration" {
+                for constant in Constant::from_declaration(child, content, &mut resolve) {
+                    t.constants.insert(constant.name.clone(), constant);
+                }
+            } else if child.kind() == "method_declaration" {
+                if let Ok(mut method) = Method::from_node(child, content, &mut resolve) {
+                    method.r#abstract = true;
+                    t.methods.insert(method.name.clone(), method);
+                }
+            }
+        }
+    }
+
+    types.insert(
+        ns,
+        CustomTypeMeta {
+            t: CustomType::Interface(t),
+            markup,
+            deprecated: deprecation(node, content),
+            src_range: node.range(),
+            src_path: path.to_path_buf(),
+        },
+    );
+}
+
+pub fn injest_trait_declaration(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    scope: &Scope,
+    ns_store: &mut SegmentPool,
+    types: &mut CustomTypesDatabase,
+) {
+    let Some(name) = node.child_by_field_name("name") else {
+        return;
+    };
+    let mut t = Trait {
+        name: content[name.byte_range()].to_string(),
+        constants: HashMap::new(),
+        properties: HashMap::new(),
+        methods: HashMap::new(),
+    };
+    let markup = node_markup(node, content);
+
+    let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+    ns.push(Rc::from(t.name.as_str()));
+    // `static` is whatever class ends up using the trait, but that's the best we can do
+    let mut resolve = |name: &str| match name.to_lowercase().as_str() {
+        "self" | "static" => ns.clone(),
+        _ => resolve_class_name(name, scope, ns_store),
+    };
+
+    if let Some(body) = node.child_by_field_name("body") {
+        let mut cursor = body.walk();
+        for child in body.children(&mut cursor) {
+            if child.kind() == "property_declaration" {
+                if let Ok(property) = Property::from_node(child, content, &mut resolve) {
+                    t.properties.insert(property.name.clone(), property);
+                }
+            } else if child.kind() == "const_declaration" {
+                for constant in Constant::from_declaration(child, content, &mut resolve) {
+                    t.constants.insert(constant.name.clone(), constant);
+                }
+            } else if child.kind() == "method_declaration" {
+                if let Ok(method) = Method::from_node(child, content, &mut resolve) {
+                    t.methods.insert(method.name.clone(), method);
+                }
+            }
+        }
+    }
+
+    types.insert(
+        ns,
+        CustomTypeMeta {
+            t: CustomType::Trait(t),
+            markup,
+            deprecated: deprecation(node, content),
+            src_range: node.range(),
+            src_path: path.to_path_buf(),
+        },
+    );
+}
+
+pub fn injest_enum_declaration(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    scope: &Scope,
+    ns_store: &mut SegmentPool,
+    types: &mut CustomTypesDatabase,
+    dependencies: &mut Vec<PhpNamespace>,
+) {
+    let Some(name) = node.child_by_field_name("name") else {
+        return;
+    };
+    let mut t = Enumeration {
+        name: content[name.byte_range()].to_string(),
+        values: Vec::new(),
+        backed_by: None,
+        backing_values: HashMap::new(),
+        constants: HashMap::new(),
+        methods: HashMap::new(),
+        implemented_interfaces: Vec::new(),
+        traits_used: Vec::new(),
+    };
+    let markup = node_markup(node, content);
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == "primitive_type" {
+            t.backed_by = Some(content[child.byte_range()].to_lowercase());
+        } else if child.kind() == "class_interface_clause" {
+            let names = clause_fqn_names(child, content, scope, ns_store);
+            t.implemented_interfaces.extend(names.clone());
+            dependencies.extend(names);
+        }
+    }
+
+    let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+    ns.push(Rc::from(t.name.as_str()));
+    let mut resolve = |name: &str| match name.to_lowercase().as_str() {
+        "self" | "static" => ns.clone(),
+        _ => resolve_class_name(name, scope, ns_store),
+    };
+
+    if let Some(body) = node.child_by_field_name("body") {
+        let mut cursor = body.walk();
+        for child in body.children(&mut cursor) {
+            if child.kind() == "enum_case" {
+                if let Some(name) = child.child_by_field_name("name") {
+                    let name = content[name.byte_range()].to_string();
+                    if let Some(value) = child.child_by_field_name("value") {
+                        let value = content[value.byte_range()].to_string();
+                        t.backing_values.insert(name.clone(), value);
+                    }
+                    t.values.push(name);
+                }
+            } else if child.kind() == "const_declaration" {
+                for constant in Constant::from_declaration(child, content, &mut resolve) {
+                    t.constants.insert(constant.name.clone(), constant);
+                }
+            } else if child.kind() == "method_declaration" {
+                if let Ok(method) = Method::from_node(child, content, &mut resolve) {
+                    t.methods.insert(method.name.clone(), method);
+                }
+            } else if child.kind() == "use_declaration" {
+                let mut cursor = child.walk();
+                for name in child.children(&mut cursor) {
+                    if name.kind() == "name" || name.kind() == "qualified_name" {
+                        let trait_name = resolve(&content[name.byte_range()]);
+                        t.traits_used.push(trait_name.clone());
+                        dependencies.push(trait_name);
                     }
                 }
             }
         }
     }
 
-    names
+    types.insert(
+        ns,
+        CustomTypeMeta {
+            t: CustomType::Enumeration(t),
+            markup,
+            deprecated: deprecation(node, content),
+            src_range: node.range(),
+            src_path: path.to_path_buf(),
+        },
+    );
 }
 
-pub fn injest_class_declaration(
+pub fn injest_function_definition(
     node: Node<'_>,
     content: &str,
+    path: &Path,
     scope: &Scope,
     ns_store: &mut SegmentPool,
     types: &mut CustomTypesDatabase,
-    dependencies: &mut Vec<PhpNamespace>,
 ) {
-    let mut t = Class::default();
-    let markup = node_markup(node, content);
-
-    if let Some(name) = node.child_by_field_name("name") {
-        t.name = content[name.byte_range()].to_string();
-    }
-
-    if let Some(body) = node.child_by_field_name("body") {
-        if body.kind() == "declaration_list" {
-            let mut cursor = body.walk();
-            for child in body.children(&mut cursor) {
-                if child.kind() == "property_declaration" {
-                    if let Ok(property) = Property::from_node(child, content) {
-                        t.properties.insert(property.name.clone(), property);
-                    }
-                } else if child.kind() == "method_declaration" {
-                    if let Ok(method) = Method::from_node(child, content) {
-                        t.methods.insert(method.name.clone(), method);
-                    }
-                } else if child.kind() == "use_declaration" {
-                    let trait_names = clause_fqn_names(child, content, scope, ns_store);
-                    t.traits_used.extend(trait_names.clone());
-                    dependencies.extend(trait_names);
-                }
-            }
-        }
-    }
-
-    let mut cursor = node.walk();
-    for child in node.children(&mut cursor) {
-        if !child.kind().ends_with("_clause") {
-            continue;
-        }
-
-        let names = clause_fqn_names(child, content, scope, ns_store);
-        if child.kind() == "base_clause" {
-            t.parent_classes.extend(names.clone());
-        } else if child.kind() == "class_interface_clause" {
-            t.implemented_interfaces.extend(names.clone());
-        } else {
-            panic!("unsupported `_clause` = `{}`", child.kind());
-        }
-
-        dependencies.extend(names);
-    }
+    let mut resolve = |name: &str| resolve_class_name(name, scope, ns_store);
+    let Ok(f) = Function::from_node(node, content, &mut resolve) else {
+        return;
+    };
 
-    if t.name != "" {
-        let ns = if let Some(ns) = &scope.ns {
-            let mut ns = ns.clone();
-            ns.push(Arc::from(t.name.as_str()));
-            ns
-        } else {
-            PhpNamespace::empty()
-        };
-        types.0.insert(
-            ns,
-            CustomTypeMeta {
-                t: CustomType::Class(t),
-                markup,
-                src_range: node.range(),
-            },
-        );
-    }
+    let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+    ns.push(Rc::from(f.name.as_str()));
+    types.insert(
+        ns,
+        CustomTypeMeta {
+            t: CustomType::Function(f),
+            markup: node_markup(node, content),
+            deprecated: deprecation(node, content),
+            src_range: node.range(),
+            src_path: path.to_path_buf(),
+        },
+    );
 }
 
 #[cfg(test)]
 mod test {
+    use lsp_types::{DiagnosticSeverity, DiagnosticTag, Position};
     use tree_sitter::Parser;
     use tree_sitter_php::LANGUAGE_PHP;
 
-    use crate::php_namespace::SegmentPool;
-    use crate::scope::Scope;
-    use crate::types::{
-        Array, CustomType, CustomTypesDatabase, Nullable, Scalar, Type, Visibility,
+    use std::path::Path;
+
+    use pls_types::{
+        CustomType, CustomTypesDatabase, Nullable, Or, Scalar, SegmentPool, Type, Visibility,
     };
 
+    use crate::code_action::QuickFix;
+    use crate::scope::Scope;
+
     fn parser() -> Parser {
         let mut parser = Parser::new();
         parser
@@ -863,7 +2167,10 @@ mod test {
         let tree = parser().parse(src, None).unwrap();
         let root_node = tree.root_node();
         let mut pool = SegmentPool::new();
-        let diags = super::walk(root_node, src, &mut pool);
+        let diags: Vec<_> = super::walk(root_node, src, Path::new("/test.php"), &mut pool)
+            .into_iter()
+            .filter(|d| d.source.as_deref() != Some("unused"))
+            .collect();
         assert!(diags.is_empty(), "src = {}\ndiags = {:?}", src, diags);
         assert_eq!(pool.0.len(), 4, "pool = {:?}", pool.0);
     }
@@ -878,9 +2185,271 @@ mod test {
         let tree = parser().parse(src, None).unwrap();
         let root_node = tree.root_node();
         let mut pool = SegmentPool::new();
-        let diags = super::walk(root_node, src, &mut pool);
+        let diags: Vec<_> = super::walk(root_node, src, Path::new("/test.php"), &mut pool)
+            .into_iter()
+            .filter(|d| d.source.as_deref() != Some("unused"))
+            .collect();
         assert_eq!(diags.len(), 1, "src = {}\ndiags = {:?}", src, diags);
         assert_eq!(pool.0.len(), 4, "pool = {:?}", pool.0);
+
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[0].location.uri.as_str(), "file:///test.php");
+        assert_eq!(related[0].location.range.start.line, 3);
+        assert_eq!(related[0].location.range.start.character, 12);
+        assert_eq!(related[0].message, "Bar first declared here");
+    }
+
+    #[test]
+    fn unused_aliases() {
+        let src = "<?php
+        namespace Foo;
+
+        use Foo\\Bar, Foo\\Bar\\Blah;
+        use Foo\\Bah as Cat;
+
+        new Cat();";
+        let tree = parser().parse(src, None).unwrap();
+        let diags = super::walk(
+            tree.root_node(),
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        );
+        let messages: Vec<_> = diags
+            .iter()
+            .filter(|d| d.source.as_deref() == Some("unused"))
+            .map(|d| d.message.as_str())
+            .collect();
+        // `Bah` is used through its alias
+        assert_eq!(
+            messages,
+            vec!["unused import \\Foo\\Bar", "unused import \\Foo\\Bar\\Blah"]
+        );
+    }
+
+    #[test]
+    fn unused_imports() {
+        let src = "<?php
+namespace App;
+
+use Lib\\Used;
+use Lib\\Unused;
+use Lib\\{Grouped, Lonely};
+use Lib\\First, Lib\\Second;
+use function Lib\\helper;
+use Lib\\Documented;
+use Lib\\Qualified;
+
+/** @param Documented[] $d */
+function f(Used $u, $d) {
+    helper(new Qualified\\Thing(), Grouped::X, $lonely);
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let root_node = tree.root_node();
+        let diags: Vec<_> = super::walk(
+            root_node,
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        )
+        .into_iter()
+        .filter(|d| d.source.as_deref() == Some("unused"))
+        .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "unused import \\Lib\\Unused",
+                "unused import \\Lib\\Lonely",
+                "unused import \\Lib\\First",
+                "unused import \\Lib\\Second",
+            ]
+        );
+        assert_eq!(diags[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+
+        let removal = |i: usize| {
+            let fix: QuickFix = serde_json::from_value(diags[i].data.clone().unwrap()).unwrap();
+            let range = fix.edits[0].range;
+            (
+                range.start.line,
+                range.start.character,
+                range.end.line,
+                range.end.character,
+            )
+        };
+        // the whole line, the clause and the comma before it, and the clause with the comma after
+        assert_eq!(removal(0), (4, 0, 5, 0));
+        assert_eq!(removal(1), (5, 16, 5, 24));
+        assert_eq!(removal(2), (6, 4, 6, 15));
+        assert_eq!(removal(3), (6, 13, 6, 25));
+    }
+
+    #[test]
+    fn switch_fallthrough() {
+        let src = "<?php
+function f($x) {
+    switch ($x) {
+        case 1:
+        case 2:
+            echo 1;
+        case 3:
+            echo 3;
+            // no break
+        case 4:
+            if ($x) {
+                return 1;
+            } else {
+                throw new Exception();
+            }
+        case 5: {
+            echo 5;
+        }
+        case 6:
+            echo 6;
+            // falls through
+            echo 7;
+        case 7:
+            trigger_error('bad', E_USER_ERROR);
+        default:
+            echo 'last';
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let diags: Vec<_> = super::walk(
+            tree.root_node(),
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        )
+        .into_iter()
+        .filter(|d| d.source.as_deref() == Some("fallthrough"))
+        .collect();
+        let ranges: Vec<_> = diags
+            .iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.range.end.character,
+                )
+            })
+            .collect();
+        assert_eq!(ranges, vec![(4, 8, 15), (15, 8, 15), (18, 8, 15)]);
+    }
+
+    #[test]
+    fn assignment_in_condition() {
+        let src = "<?php
+function f($a, $b) {
+    if ($a = 1) {
+    } elseif ($b = 2) {
+    }
+    while (($line = fgets($a)) !== false) {}
+    do {} while ($a = $b);
+    while (($b = next($a))) {}
+    if ($a == 1) {}
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let diags: Vec<_> = super::walk(
+            tree.root_node(),
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        )
+        .into_iter()
+        .filter(|d| d.source.as_deref() == Some("condition"))
+        .collect();
+        let lines: Vec<_> = diags.iter().map(|d| d.range.start.line).collect();
+        assert_eq!(lines, vec![2, 3, 6]);
+
+        let fix: QuickFix = serde_json::from_value(diags[0].data.clone().unwrap()).unwrap();
+        let edit = &fix.edits[0];
+        assert_eq!(edit.new_text, "===");
+        assert_eq!(
+            (edit.range.start.character, edit.range.end.character),
+            (11, 12)
+        );
+    }
+
+    #[test]
+    fn assignment_in_condition_still_binds() {
+        let src = "<?php
+            while ($i = 0) {
+                echo $i;
+            }";
+        let tree = parser().parse(src, None).unwrap();
+        let diags = super::walk(
+            tree.root_node(),
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        );
+        let sources: Vec<_> = diags.iter().map(|d| d.source.as_deref()).collect();
+        assert_eq!(sources, vec![Some("condition")]);
+    }
+
+    #[test]
+    fn loose_comparisons() {
+        let src = "<?php
+if ($a == 1 || $b <> $c) {}
+if ((int) $a == $b || $a != null || $a === 1) {}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let diags = super::loose_comparisons(tree.root_node(), src, false);
+        let mut ranges: Vec<_> = diags
+            .iter()
+            .map(|d| (d.range.start.line, d.range.start.character))
+            .collect();
+        ranges.sort();
+        assert_eq!(ranges, vec![(1, 4), (1, 15)]);
+
+        let diag = diags
+            .iter()
+            .find(|d| d.range.start.character == 15)
+            .unwrap();
+        let fix: QuickFix = serde_json::from_value(diag.data.clone().unwrap()).unwrap();
+        assert_eq!(fix.title, "Change `<>` to `!==`");
+        let edit = &fix.edits[0];
+        assert_eq!(edit.new_text, "!==");
+        assert_eq!(
+            (edit.range.start.character, edit.range.end.character),
+            (18, 20)
+        );
+
+        let diags = super::loose_comparisons(tree.root_node(), src, true);
+        assert_eq!(diags.len(), 3);
+        assert!(diags.iter().any(|d| d.range.start == Position::new(2, 22)));
+    }
+
+    #[test]
+    fn error_suppressions() {
+        let src = "<?php
+$lines = @file('x');
+echo $a;
+@$b['c'];
+";
+        let tree = parser().parse(src, None).unwrap();
+        let diags = super::error_suppressions(tree.root_node());
+        let mut ranges: Vec<_> = diags
+            .iter()
+            .map(|d| (d.range.start.line, d.range.start.character))
+            .collect();
+        ranges.sort();
+        assert_eq!(ranges, vec![(1, 9), (3, 0)]);
+
+        let diag = diags.iter().find(|d| d.range.start.line == 1).unwrap();
+        assert_eq!(diag.range.end.character, 19);
+        let fix: QuickFix = serde_json::from_value(diag.data.clone().unwrap()).unwrap();
+        let edit = &fix.edits[0];
+        assert_eq!(edit.new_text, "");
+        assert_eq!(
+            (edit.range.start.character, edit.range.end.character),
+            (9, 10)
+        );
     }
 
     #[test]
@@ -889,7 +2458,12 @@ mod test {
         function foo(int $_GET) {}";
         let tree = parser().parse(src, None).unwrap();
         let root_node = tree.root_node();
-        let diags = super::walk(root_node, src, &mut SegmentPool::new());
+        let diags = super::walk(
+            root_node,
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        );
         assert!(diags.len() == 1, "src = {}\ndiags = {:?}", src, diags);
     }
 
@@ -898,7 +2472,12 @@ mod test {
         let src = "<?php var_dump($_GET);";
         let tree = parser().parse(src, None).unwrap();
         let root_node = tree.root_node();
-        let diags = super::walk(root_node, src, &mut SegmentPool::new());
+        let diags = super::walk(
+            root_node,
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        );
         assert!(diags.is_empty(), "src = {}\ndiags = {:?}", src, diags);
     }
 
@@ -919,7 +2498,8 @@ mod test {
         let root_node = tree.root_node();
         let mut types = CustomTypesDatabase::new();
         let mut pool = SegmentPool::new();
-        let deps = super::injest_types(root_node, src, &mut pool, &mut types);
+        let deps =
+            super::injest_types(root_node, src, Path::new("test.php"), &mut pool, &mut types);
         assert!(deps.is_empty(), "src = {}\ndeps = {:?}", src, deps);
         assert_eq!(types.0.len(), 1);
 
@@ -941,6 +2521,77 @@ mod test {
         assert_eq!(p.t, Type::Nullable(Nullable(Box::new(Type::Array))));
     }
 
+    #[test]
+    fn conditional_functions_in_types_db() {
+        let src = "<?php
+        namespace Helpers;
+
+        if (! function_exists('app')) {
+            function app(string $abstract = null) {}
+        }
+        function env(string $key) {}
+        ";
+        let tree = parser().parse(src, None).unwrap();
+        let mut types = CustomTypesDatabase::new();
+        let mut pool = SegmentPool::new();
+        super::injest_types(
+            tree.root_node(),
+            src,
+            Path::new("helpers.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        for name in ["Helpers\\app", "Helpers\\env"] {
+            let meta = types.0.get(&pool.intern_str(name)).unwrap();
+            assert!(matches!(meta.t, CustomType::Function(_)), "{name}");
+        }
+    }
+
+    #[test]
+    fn method_signature_in_types_db() {
+        let src = "<?php
+        namespace Foo;
+
+        use Other\\Thing;
+
+        interface Baz {
+            public function bar(Thing &$a, ?self $b = null, int ...$rest): static|false;
+        }
+        ";
+        let tree = parser().parse(src, None).unwrap();
+        let root_node = tree.root_node();
+        let mut types = CustomTypesDatabase::new();
+        let mut pool = SegmentPool::new();
+        super::injest_types(root_node, src, Path::new("test.php"), &mut pool, &mut types);
+
+        let baz = pool.intern_str("Foo\\Baz");
+        let m = match &types.0.get(&baz).unwrap().t {
+            CustomType::Interface(i) => i.methods.get("bar").unwrap(),
+            _ => unreachable!("type should only be an interface"),
+        };
+        assert!(m.r#abstract);
+        assert_eq!(m.arguments.len(), 3);
+        assert_eq!(
+            m.arguments[0].t,
+            Type::CustomType(pool.intern_str("Other\\Thing"))
+        );
+        assert!(m.arguments[0].by_ref);
+        assert_eq!(
+            m.arguments[1].t,
+            Type::Nullable(Nullable(Box::new(Type::CustomType(baz.clone()))))
+        );
+        assert!(m.arguments[1].optional);
+        assert!(m.arguments[2].variadic);
+        assert_eq!(
+            m.return_type,
+            Type::Or(Or(vec![
+                Type::CustomType(baz),
+                Type::Scalar(Scalar::BooleanLiteral(false))
+            ]))
+        );
+    }
+
     #[test]
     fn class_decl_extends_with_ns() {
         let src = "<?php
@@ -958,7 +2609,8 @@ mod test {
         let root_node = tree.root_node();
         let mut types = CustomTypesDatabase::new();
         let mut pool = SegmentPool::new();
-        let deps = super::injest_types(root_node, src, &mut pool, &mut types);
+        let deps =
+            super::injest_types(root_node, src, Path::new("test.php"), &mut pool, &mut types);
 
         let baz = types.0.get(&pool.intern_str("Foo\\Bar\\Baz")).unwrap();
         let baz_t = match &baz.t {
@@ -966,16 +2618,22 @@ mod test {
             _ => unreachable!(),
         };
 
-        assert!(baz_t
-            .parent_classes
-            .contains(&pool.intern_str("Foo\\Bar\\Ta")));
+        assert!(
+            baz_t
+                .parent_classes
+                .contains(&pool.intern_str("Foo\\Bar\\Ta"))
+        );
         assert!(baz_t.parent_classes.contains(&pool.intern_str("Foo\\Da")));
-        assert!(baz_t
-            .traits_used
-            .contains(&pool.intern_str("Foo\\Sa\\Trait1")));
-        assert!(baz_t
-            .traits_used
-            .contains(&pool.intern_str("Foo\\Pa\\Trait2")));
+        assert!(
+            baz_t
+                .traits_used
+                .contains(&pool.intern_str("Foo\\Sa\\Trait1"))
+        );
+        assert!(
+            baz_t
+                .traits_used
+                .contains(&pool.intern_str("Foo\\Pa\\Trait2"))
+        );
 
         assert_eq!(deps.len(), 4);
         assert!(deps.contains(&pool.intern_str("Foo\\Bar\\Ta")));
@@ -1013,7 +2671,10 @@ mod test {
         super::walk_statement(stmt2, src, &mut SegmentPool::new(), &mut scope, &mut diags);
         assert_eq!(1, diags.len());
         let diag = &diags[0];
-        assert_eq!("undefined variable $var2", &diag.message);
+        assert_eq!(
+            "undefined variable $var2, did you mean $var1?",
+            &diag.message
+        );
         assert_eq!(11, scope.symbols.len());
 
         assert!(scope.symbols.contains("$var1"));
@@ -1025,13 +2686,130 @@ mod test {
         super::walk_statement(stmt3, src, &mut SegmentPool::new(), &mut scope, &mut diags);
         assert_eq!(1, diags.len());
         let diag = &diags[0];
-        assert_eq!("undefined variable $var4", &diag.message);
+        assert_eq!(
+            "undefined variable $var4, did you mean $var1?",
+            &diag.message
+        );
         assert_eq!(13, scope.symbols.len());
 
         assert!(scope.symbols.contains("$var3"));
         assert!(scope.symbols.contains("$var4"));
     }
 
+    #[test]
+    fn undefined_variable_suggestion() {
+        let src = "<?php
+function greet($userName, $greeting) {
+    echo $greeting . $userNmae . $address;
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let mut diags: Vec<_> = super::walk(
+            tree.root_node(),
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        )
+        .into_iter()
+        .filter(|d| d.source.as_deref() == Some("undef"))
+        .collect();
+        diags.sort_by_key(|d| d.range.start);
+        assert_eq!(diags.len(), 2, "diags = {:?}", diags);
+        assert_eq!(
+            diags[0].message,
+            "undefined variable $userNmae, did you mean $userName?"
+        );
+        let fix: QuickFix = serde_json::from_value(diags[0].data.clone().unwrap()).unwrap();
+        assert_eq!(fix.title, "Change to `$userName`");
+        assert_eq!(fix.edits[0].range, diags[0].range);
+        assert_eq!(fix.edits[0].new_text, "$userName");
+
+        assert_eq!(diags[1].message, "undefined variable $address");
+        assert!(diags[1].data.is_none());
+    }
+
+    #[test]
+    fn interpolated_variables() {
+        let src = "<?php
+function greet($userName, $count) {
+    echo \"Hello $userName, ${userNmae}s and {$user->name} ${count}x $missing[0]\";
+    echo <<<EOT
+    ${count} ${cuont} $items
+    EOT;
+    echo <<<'EOT'
+    $nothing
+    EOT;
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let mut diags: Vec<_> = super::walk(
+            tree.root_node(),
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        )
+        .into_iter()
+        .filter(|d| matches!(d.source.as_deref(), Some("undef" | "unused")))
+        .collect();
+        diags.sort_by_key(|d| d.range.start);
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "undefined variable $userNmae, did you mean $userName?",
+                "undefined variable $user",
+                "undefined variable $missing",
+                "undefined variable $cuont, did you mean $count?",
+                "undefined variable $items",
+            ]
+        );
+        let fix: QuickFix = serde_json::from_value(diags[0].data.clone().unwrap()).unwrap();
+        assert_eq!(fix.edits[0].new_text, "{$userName}");
+        assert_eq!(diags[0].range.start.character, 27);
+        let fix: QuickFix = serde_json::from_value(diags[3].data.clone().unwrap()).unwrap();
+        assert_eq!(fix.edits[0].new_text, "{$count}");
+    }
+
+    #[test]
+    fn compact_and_extract() {
+        let src = "<?php
+function view($title, $items) {
+    $count = count($items);
+    $view = compact('title', ['count', 'itemz'], $items);
+    return $view;
+}
+
+function render($data) {
+    echo $before;
+    extract($data);
+    echo $title;
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let mut diags: Vec<_> = super::walk(
+            tree.root_node(),
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        )
+        .into_iter()
+        .filter(|d| d.source.as_deref() != Some("unused"))
+        .collect();
+        diags.sort_by_key(|d| d.range.start);
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "undefined variable $itemz, did you mean $items?",
+                "undefined variable $before",
+                "extract() defines variables we can't check",
+            ]
+        );
+        let fix: QuickFix = serde_json::from_value(diags[0].data.clone().unwrap()).unwrap();
+        assert_eq!(fix.edits[0].new_text, "'items'");
+        assert_eq!(diags[2].severity, Some(DiagnosticSeverity::WARNING));
+    }
+
     #[test]
     fn no_undefineds() {
         let srcs = [
@@ -1114,16 +2892,155 @@ mod test {
             "<?php
             $a = 3;
             $b = &$a;",
+            "<?php
+            $add = function ($n) use (&$total, &$calls) {
+                $total += $n;
+                $calls = ($calls ?? 0) + 1;
+            };
+            $add(1);
+            echo $total / $calls;",
+            "<?php
+            $points = [[1, 2], [3, 4]];
+            [[$x1, $y1], [, $y2]] = $points;
+            $key = 'y';
+            ['x' => $x, $key => [$y, &$z]] = ['x' => 1, 'y' => [2, 3]];
+            echo $x1 + $y1 + $y2 + $x + $y + $z;",
+            "<?php
+            $label = match ($status = $_GET['status']) {
+                'on', 'yes' => $on = $status,
+                default => strtoupper($status),
+            };
+            echo $on;",
+            "<?php
+            $db = connect();
+            function query($sql) {
+                global $db, $log;
+                $log = $sql;
+                echo $db->query($sql);
+            }",
+            "<?php
+            $user = find_user();
+            $name = $user?->profile?->displayName($user)->first ?? 'anonymous';
+            echo $name;",
+            "<?php
+            function next_id() {
+                static $start = 100, $id = $start;
+                $id++;
+                return $id;
+            }",
+            "<?php
+            try {
+                $handle = fopen('/tmp/log', 'r');
+            } catch (\\RuntimeException | \\TypeError $e) {
+                echo $e->getMessage(), $handle;
+            } finally {
+                $closed = fclose($handle);
+            }
+            echo $e, $closed;",
+            "<?php
+            try {
+                $x = 1;
+            } catch (Exception) {
+                $x = 2;
+            }
+            echo $x;",
+            "<?php
+            $limit = 10;
+            echo count(match (true) {
+                $limit > 5 => [$limit],
+                default => [],
+            });",
+            "<?php
+            class User {
+                public string $name {
+                    get => ucfirst($this->name);
+                    set(string $name) { $this->name = trim($name); }
+                }
+                public string $email {
+                    set { $this->email = strtolower($value); }
+                }
+            }",
+            "<?php
+            $prefix = 'app';
+            $logger = new class($prefix) {
+                public function __construct(private string $prefix) {}
+                public function log(string $message): void {
+                    $line = $this->prefix . $message;
+                    echo $line;
+                }
+            };",
+            "<?php
+            $cache ??= [];
+            $cache['key'] ??= 1;
+            $total = 0;
+            $total += 1;
+            $name = 'a';
+            $name .= $total;
+            echo $cache, $name;",
         ];
 
         for src in srcs {
             let tree = parser().parse(src, None).unwrap();
             let root_node = tree.root_node();
-            let diags = super::walk(root_node, src, &mut SegmentPool::new());
+            let diags: Vec<_> = super::walk(
+                root_node,
+                src,
+                Path::new("/test.php"),
+                &mut SegmentPool::new(),
+            )
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("undef"))
+            .collect();
             assert!(diags.is_empty(), "src = {}\ndiags = {:?}", src, diags);
         }
     }
 
+    #[test]
+    fn unused_variables() {
+        let src = "<?php
+        function f(&$out, $param) {
+            $unused = 1;
+            $used = 2;
+            echo $used;
+            $out = 3;
+            $arr = [];
+            $arr[] = 1;
+            $_SESSION = [];
+            $captured = 1;
+            $byRef = 0;
+            $g = function () use ($captured, &$byRef) { $byRef = 1; $inner = 2; };
+            $h = fn() => $used + $captured;
+            foreach ([1] as $k => $v) { echo $v; }
+            return $g;
+        }
+        $top = 1;
+        ";
+        let tree = parser().parse(src, None).unwrap();
+        let root_node = tree.root_node();
+        let diags: Vec<_> = super::walk(
+            root_node,
+            src,
+            Path::new("/test.php"),
+            &mut SegmentPool::new(),
+        )
+        .into_iter()
+        .filter(|d| d.source.as_deref() == Some("unused"))
+        .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "variable $inner is never read",
+                "variable $unused is never read",
+                "variable $h is never read",
+                "variable $k is never read",
+            ]
+        );
+        assert_eq!(diags[1].range.start.line, 2);
+        assert_eq!(diags[1].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(diags[1].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+    }
+
     #[test]
     fn non_zero_undefineds() {
         let srcs = [
@@ -1163,13 +3080,158 @@ mod test {
             } while ($i = $x);",
             "<?php
             echo $x;",
+            "<?php
+            $f = function () use ($total) {};",
+            "<?php
+            [[$a, $b], ['c' => $c]] = $pairs;
+            echo $a + $b + $d;",
+            "<?php
+            function query($sql) {
+                echo $db->query($sql);
+            }",
+            "<?php
+            function next_id() {
+                static $id = $start;
+            }",
+            "<?php
+            try {} catch (Exception $e) {}
+            echo $f;",
+            "<?php
+            try {
+                $a = 1;
+            } catch (Exception $e) {
+                echo $e;
+            }
+            echo $b;",
+            "<?php
+            $a = match ($x) { default => 1 };",
+            "<?php
+            $a = match (1) {
+                1 => $b = 2,
+                2 => $b + 1,
+            };",
+            "<?php
+            $count += 1;",
+            "<?php
+            $logger = new class($missing) {};",
+            "<?php
+            $logger = new class {
+                public function log(): void {
+                    echo $message;
+                }
+            };",
+            "<?php
+            class User {
+                public string $name {
+                    get => $this->name . $suffix;
+                }
+            }",
+            "<?php
+            class User {
+                public string $name {
+                    set(string $name) { $this->name = $value; }
+                }
+            }",
+            "<?php
+            $name = 'a';
+            $name .= $suffix;",
+            "<?php
+            $cache ??= $fallback;",
         ];
 
         for src in srcs {
             let tree = parser().parse(src, None).unwrap();
             let root_node = tree.root_node();
-            let diags = super::walk(root_node, src, &mut SegmentPool::new());
+            let diags = super::walk(
+                root_node,
+                src,
+                Path::new("/test.php"),
+                &mut SegmentPool::new(),
+            );
             assert!(!diags.is_empty(), "src = {}\ndiags = {:?}", src, diags);
         }
     }
+
+    #[test]
+    fn duplicate_declarations() {
+        let first = "<?php
+        namespace App;
+        class Foo {}
+        function helper() {}
+        ";
+        let second = "<?php
+        namespace App;
+
+        class Foo {}
+        class Bar {}
+        class Bar {}
+        ";
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let (first_path, second_path) = (Path::new("/tmp/first.php"), Path::new("/tmp/second.php"));
+        let first_tree = parser().parse(first, None).unwrap();
+        let second_tree = parser().parse(second, None).unwrap();
+        super::injest_types(
+            first_tree.root_node(),
+            first,
+            first_path,
+            &mut pool,
+            &mut types,
+        );
+        super::injest_types(
+            second_tree.root_node(),
+            second,
+            second_path,
+            &mut pool,
+            &mut types,
+        );
+
+        let diags = super::duplicate_declarations(
+            first_tree.root_node(),
+            first,
+            first_path,
+            &mut pool,
+            &types,
+        );
+        assert_eq!(diags.len(), 1, "diags = {:?}", diags);
+        assert_eq!(diags[0].message, "\\App\\Foo is declared more than once");
+        assert_eq!(diags[0].range.start.line, 2);
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[0].location.uri.path().as_str(), "/tmp/second.php");
+        assert_eq!(related[0].location.range.start.line, 3);
+
+        let diags = super::duplicate_declarations(
+            second_tree.root_node(),
+            second,
+            second_path,
+            &mut pool,
+            &types,
+        );
+        let lines = diags.iter().map(|d| d.range.start.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 4, 5]);
+
+        // fixing the file makes the duplicates go away
+        let second = "<?php
+        namespace App;
+
+        class Baz {}
+        ";
+        let second_tree = parser().parse(second, None).unwrap();
+        super::injest_types(
+            second_tree.root_node(),
+            second,
+            second_path,
+            &mut pool,
+            &mut types,
+        );
+        let diags = super::duplicate_declarations(
+            first_tree.root_node(),
+            first,
+            first_path,
+            &mut pool,
+            &types,
+        );
+        assert!(diags.is_empty(), "diags = {:?}", diags);
+    }
 }
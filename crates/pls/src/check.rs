@@ -0,0 +1,398 @@
+use clap::ValueEnum;
+use lsp_server::Connection;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Uri};
+
+use pls_types::{UriExt, normalized_path};
+
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::file::parse;
+use crate::global_state::{FileInfo, GlobalState};
+use crate::messages::Task;
+use crate::rename::php_files;
+use crate::stubs::discover_stubs;
+
+/// Analyze `paths`, files or directories of them, the same as if they were opened in an editor
+/// with `root` as the workspace, and hand back what's wrong with each.
+pub fn check(
+    root: &Path,
+    paths: &[PathBuf],
+    stubs_filename: Option<PathBuf>,
+) -> anyhow::Result<Vec<(PathBuf, Diagnostic)>> {
+    let mut config = Config::new(vec![], Uri::from_file_path(root), stubs_filename);
+    if config.stubs_filename.is_none() {
+        config.stubs_filename = discover_stubs(&config.workspace_folders);
+    }
+    // diagnostics still get published, to a client that never reads them
+    let (connection, _client) = Connection::memory();
+    let mut state = GlobalState::offline(config, connection);
+
+    let mut files = Vec::new();
+    for path in paths {
+        let path = normalized_path(&root.join(path));
+        if path.is_dir() {
+            php_files(&path, &mut files);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    for file in &files {
+        let content = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("unable to read `{}`: {e}", file.display()))?;
+        let (php_ast, phpdoc_ast) = parse(&content, (None, None));
+        state.file_infos.insert(
+            file.clone(),
+            FileInfo {
+                file_name: file.clone(),
+                content,
+                version: 0,
+                php_ast,
+                phpdoc_ast,
+                diagnostics: Vec::new(),
+            },
+        );
+        state.worker_send.send(Task::AnalyzeFile(file.clone()))?;
+    }
+    // each dependency that turns up has the files analyzed again, so the last round sees them all
+    state.run_pending_tasks();
+
+    Ok(files
+        .into_iter()
+        .flat_map(|file| {
+            let diagnostics = state.file_infos[&file].diagnostics.clone();
+            diagnostics.into_iter().map(move |d| (file.clone(), d))
+        })
+        .collect())
+}
+
+/// How `pls check` writes out what it found.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum Format {
+    /// A line per diagnostic, for people.
+    #[default]
+    Text,
+    /// GitLab's code quality report, which is Code Climate's JSON.
+    Json,
+    /// Checkstyle XML, which most CI servers can annotate with.
+    Checkstyle,
+    /// SARIF 2.1.0, for GitHub code scanning.
+    Sarif,
+}
+
+/// Write out the diagnostics, with paths relative to `root`.
+pub fn render(format: Format, root: &Path, diagnostics: &[(PathBuf, Diagnostic)]) -> String {
+    match format {
+        Format::Text => diagnostics
+            .iter()
+            .map(|(path, d)| report(root, path, d) + "\n")
+            .collect(),
+        Format::Json => code_quality(root, diagnostics),
+        Format::Checkstyle => checkstyle(root, diagnostics),
+        Format::Sarif => sarif(root, diagnostics),
+    }
+}
+
+/// One diagnostic as a line like `src/User.php:3:5: error: ... [ts]`, with the position counted
+/// from 1 like other linters do.
+fn report(root: &Path, path: &Path, diagnostic: &Diagnostic) -> String {
+    let rule = match rule(diagnostic) {
+        Some(rule) => format!(" [{rule}]"),
+        None => String::new(),
+    };
+
+    format!(
+        "{}:{}:{}: {}: {}{rule}",
+        relative(root, path),
+        diagnostic.range.start.line + 1,
+        diagnostic.range.start.character + 1,
+        severity(diagnostic),
+        diagnostic.message,
+    )
+}
+
+fn code_quality(root: &Path, diagnostics: &[(PathBuf, Diagnostic)]) -> String {
+    let issues: Vec<_> = diagnostics
+        .iter()
+        .map(|(path, d)| {
+            let path = relative(root, path);
+            let check_name = rule(d).unwrap_or_else(|| "pls".to_string());
+            // the same problem in the same place is the same issue from one run to the next
+            let line = d.range.start.line.to_string();
+            let fingerprint = fingerprint(&[&path, &check_name, &d.message, &line]);
+            serde_json::json!({
+                "type": "issue",
+                "description": d.message,
+                "check_name": check_name,
+                "fingerprint": fingerprint,
+                "severity": match severity(d) {
+                    "error" => "major",
+                    "warning" => "minor",
+                    _ => "info",
+                },
+                "location": {
+                    "path": path,
+                    "lines": {
+                        "begin": d.range.start.line + 1,
+                        "end": d.range.end.line + 1,
+                    },
+                },
+            })
+        })
+        .collect();
+
+    serde_json::Value::Array(issues).to_string() + "\n"
+}
+
+/// FNV-1a over the parts, which unlike `DefaultHasher` gives the same hash from one build (and
+/// Rust version) to the next.
+fn fingerprint(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        // a separator, so that moving text from one part to the next changes the hash
+        for byte in part.bytes().chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    format!("{hash:016x}")
+}
+
+fn checkstyle(root: &Path, diagnostics: &[(PathBuf, Diagnostic)]) -> String {
+    let mut xml =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<checkstyle version=\"4.3\">\n");
+    // the diagnostics come grouped by file already
+    let mut last_file = None;
+    for (path, d) in diagnostics {
+        if last_file != Some(path) {
+            if last_file.is_some() {
+                xml.push_str("  </file>\n");
+            }
+            xml.push_str(&format!(
+                "  <file name=\"{}\">\n",
+                xml_escape(&relative(root, path))
+            ));
+            last_file = Some(path);
+        }
+        xml.push_str(&format!(
+            "    <error line=\"{}\" column=\"{}\" severity=\"{}\" message=\"{}\" source=\"pls.{}\"/>\n",
+            d.range.start.line + 1,
+            d.range.start.character + 1,
+            match severity(d) {
+                "hint" => "info",
+                severity => severity,
+            },
+            xml_escape(&d.message),
+            xml_escape(&rule(d).unwrap_or_default()),
+        ));
+    }
+    if last_file.is_some() {
+        xml.push_str("  </file>\n");
+    }
+    xml.push_str("</checkstyle>\n");
+
+    xml
+}
+
+fn sarif(root: &Path, diagnostics: &[(PathBuf, Diagnostic)]) -> String {
+    let results: Vec<_> = diagnostics
+        .iter()
+        .map(|(path, d)| {
+            serde_json::json!({
+                "ruleId": rule(d).unwrap_or_else(|| "pls".to_string()),
+                "level": match severity(d) {
+                    "error" => "error",
+                    "warning" => "warning",
+                    _ => "note",
+                },
+                "message": { "text": d.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": relative(root, path),
+                            "uriBaseId": "%SRCROOT%",
+                        },
+                        "region": {
+                            "startLine": d.range.start.line + 1,
+                            "startColumn": d.range.start.character + 1,
+                            "endLine": d.range.end.line + 1,
+                            "endColumn": d.range.end.character + 1,
+                        },
+                    },
+                }],
+            })
+        })
+        .collect();
+    let sarif = serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            },
+            "results": results,
+        }],
+    });
+
+    sarif.to_string() + "\n"
+}
+
+fn severity(diagnostic: &Diagnostic) -> &'static str {
+    match diagnostic.severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+/// The code of the diagnostic, or failing that, its source.
+fn rule(diagnostic: &Diagnostic) -> Option<String> {
+    match (&diagnostic.code, &diagnostic.source) {
+        (Some(NumberOrString::String(code)), _) => Some(code.clone()),
+        (Some(NumberOrString::Number(code)), _) => Some(code.to_string()),
+        (None, source) => source.clone(),
+    }
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Whether any of the diagnostics should fail the check. Without a severity, a diagnostic is an
+/// error, as the client would take it.
+pub fn has_errors(diagnostics: &[(PathBuf, Diagnostic)]) -> bool {
+    diagnostics
+        .iter()
+        .any(|(_, d)| d.severity.is_none_or(|s| s == DiagnosticSeverity::ERROR))
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+
+    use std::path::{Path, PathBuf};
+
+    use super::Format;
+
+    #[test]
+    fn check_directory() {
+        let root = std::env::temp_dir().join(format!("pls-check-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(
+            root.join("src/undefined.php"),
+            "<?php\nfunction f($a) {\n    echo $a . $x;\n}\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("src/broken.php"), "<?php\nfunction (\n").unwrap();
+        std::fs::write(root.join("src/fine.php"), "<?php\necho 1;\n").unwrap();
+
+        let diagnostics = super::check(&root, &[PathBuf::from("src")], None).unwrap();
+        let lines: Vec<String> = diagnostics
+            .iter()
+            .map(|(path, d)| super::report(&root, path, d))
+            .collect();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("src/undefined.php:3:15: error: ")),
+            "{lines:?}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("src/broken.php:2:") && l.contains(": error: ")),
+            "{lines:?}"
+        );
+        assert!(lines.iter().all(|l| !l.starts_with("src/fine.php")));
+        assert!(super::has_errors(&diagnostics));
+        assert!(!super::has_errors(&diagnostics[..0]));
+    }
+
+    #[test]
+    fn formats() {
+        let root = Path::new("/proj");
+        let diagnostics = vec![
+            (
+                PathBuf::from("/proj/src/A.php"),
+                Diagnostic {
+                    range: Range::new(Position::new(2, 4), Position::new(2, 6)),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("undef".to_string()),
+                    message: "undefined variable $x".to_string(),
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("/proj/src/A.php"),
+                Diagnostic {
+                    range: Range::new(Position::new(5, 0), Position::new(5, 3)),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("unused-import".to_string())),
+                    message: "unused import <Foo>".to_string(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        assert_eq!(
+            super::render(Format::Text, root, &diagnostics),
+            "src/A.php:3:5: error: undefined variable $x [undef]\n\
+             src/A.php:6:1: warning: unused import <Foo> [unused-import]\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&super::render(Format::Json, root, &diagnostics)).unwrap();
+        assert_eq!(json[0]["check_name"], "undef");
+        assert_eq!(json[0]["severity"], "major");
+        assert_eq!(json[0]["location"]["path"], "src/A.php");
+        assert_eq!(json[0]["location"]["lines"]["begin"], 3);
+        assert_eq!(json[1]["severity"], "minor");
+        assert_ne!(json[0]["fingerprint"], json[1]["fingerprint"]);
+        // has to stay the same across builds, or GitLab sees every issue as new
+        assert_eq!(json[0]["fingerprint"], "f60ed87c12600c31");
+
+        assert_eq!(
+            super::render(Format::Checkstyle, root, &diagnostics),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<checkstyle version=\"4.3\">
+  <file name=\"src/A.php\">
+    <error line=\"3\" column=\"5\" severity=\"error\" message=\"undefined variable $x\" source=\"pls.undef\"/>
+    <error line=\"6\" column=\"1\" severity=\"warning\" message=\"unused import &lt;Foo&gt;\" source=\"pls.unused-import\"/>
+  </file>
+</checkstyle>
+"
+        );
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&super::render(Format::Sarif, root, &diagnostics)).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+        let result = &sarif["runs"][0]["results"][1];
+        assert_eq!(result["ruleId"], "unused-import");
+        assert_eq!(result["level"], "warning");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/A.php");
+        assert_eq!(location["region"]["startLine"], 6);
+        assert_eq!(location["region"]["endColumn"], 4);
+    }
+}
@@ -0,0 +1,116 @@
+use tree_sitter::Node;
+
+use pls_types::{PhpNamespace, SegmentPool};
+
+/// The FQNs of the class-likes declared in a file, for Composer's `classmap` autoload.
+///
+/// Only top-level declarations count, in either namespace style; the autoloader never finds
+/// classes declared conditionally anyway.
+pub fn declared_classes(
+    node: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+) -> Vec<PhpNamespace> {
+    let mut classes = Vec::new();
+    let mut namespace = PhpNamespace::empty();
+    collect(node, content, &mut namespace, ns_store, &mut classes);
+
+    classes
+}
+
+fn collect(
+    node: Node<'_>,
+    content: &str,
+    namespace: &mut PhpNamespace,
+    ns_store: &mut SegmentPool,
+    classes: &mut Vec<PhpNamespace>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "namespace_definition" => {
+                *namespace = child
+                    .child_by_field_name("name")
+                    .map(|name| ns_store.intern_str(&content[name.byte_range()]))
+                    .unwrap_or_else(PhpNamespace::empty);
+                // `namespace Foo { ... }` only covers its body
+                if let Some(body) = child.child_by_field_name("body") {
+                    collect(body, content, namespace, ns_store, classes);
+                    *namespace = PhpNamespace::empty();
+                }
+            }
+            "class_declaration"
+            | "interface_declaration"
+            | "trait_declaration"
+            | "enum_declaration" => {
+                if let Some(name) = child.child_by_field_name("name") {
+                    let mut fqn = namespace.clone();
+                    fqn.extend(ns_store.intern_str(&content[name.byte_range()]).0);
+                    classes.push(fqn);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use pls_types::SegmentPool;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    fn declared_classes(src: &str) -> Vec<String> {
+        let mut pool = SegmentPool::new();
+        let tree = parser().parse(src, None).unwrap();
+        super::declared_classes(tree.root_node(), src, &mut pool)
+            .iter()
+            .map(|fqn| fqn.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn declared_classes_in_namespaces() {
+        let src = "<?php
+class Legacy_Thing {}
+namespace App\\Models;
+
+interface HasName {}
+final class User implements HasName {}
+enum Role {}
+if (true) {
+    class Hidden {}
+}
+function helper() {}
+";
+        assert_eq!(
+            declared_classes(src),
+            vec![
+                "\\Legacy_Thing",
+                "\\App\\Models\\HasName",
+                "\\App\\Models\\User",
+                "\\App\\Models\\Role",
+            ]
+        );
+
+        let src = "<?php
+namespace Foo {
+    trait Bar {}
+}
+namespace {
+    class Baz {}
+}
+";
+        assert_eq!(declared_classes(src), vec!["\\Foo\\Bar", "\\Baz"]);
+    }
+}
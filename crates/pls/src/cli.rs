@@ -0,0 +1,142 @@
+use clap::{Parser, Subcommand};
+use tracing_subscriber::filter::LevelFilter;
+
+use std::path::PathBuf;
+
+use crate::check::Format;
+
+/// A PHP language server. Without a command, it talks to an editor over stdin and stdout.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// The `PhpStormStubsMap.php` of the PhpStorm stubs; found on its own if left out.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub stubs: Option<PathBuf>,
+    /// Talk to the editor over stdin and stdout. That's the only way there is, but some clients
+    /// always ask for it.
+    #[arg(long, conflicts_with_all = ["listen", "socket"])]
+    pub stdio: bool,
+    /// Take clients over TCP at this address, like `127.0.0.1:9257`, one after another.
+    #[arg(long, value_name = "ADDR", conflicts_with = "socket")]
+    pub listen: Option<String>,
+    /// Take clients over the unix domain socket at this path, one after another.
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+    /// Write logs to this file instead of stderr. It's moved to `<PATH>.1` when it gets big.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+    /// One of off, error, warn, info, debug or trace. Wins over `RUST_LOG`.
+    #[arg(long, value_name = "LEVEL", global = true)]
+    pub log_level: Option<LevelFilter>,
+    /// The stubs map, from before `--stubs`.
+    #[arg(hide = true, conflicts_with = "stubs")]
+    pub stubs_map: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum CliCommand {
+    /// Analyze files the way the server would, and print the diagnostics. Exits with 1 if any of
+    /// them are errors.
+    Check {
+        /// How to write out the diagnostics.
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+        /// Files or directories to check; the current directory if there are none.
+        paths: Vec<PathBuf>,
+    },
+    /// Read the types of every PHP file in the current directory, `vendor` included, and save
+    /// them to `.pls/index.json` for the server to start with.
+    Index,
+    /// Manage the PhpStorm stubs.
+    Stubs {
+        #[command(subcommand)]
+        command: StubsCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum StubsCommand {
+    /// Download a pinned release of the stubs to where they're looked for.
+    Install,
+}
+
+impl Cli {
+    /// The stubs map we were told about, either way.
+    pub fn stubs_filename(&self) -> Option<PathBuf> {
+        self.stubs.clone().or(self.stubs_map.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use tracing_subscriber::filter::LevelFilter;
+
+    use std::path::PathBuf;
+
+    use super::{Cli, CliCommand, StubsCommand};
+    use crate::check::Format;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("pls").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn server_arguments() {
+        let cli = parse(&["--stdio", "--stubs", "/s/map.php", "--log-level", "debug"]).unwrap();
+        assert!(cli.stdio);
+        assert_eq!(cli.stubs_filename(), Some(PathBuf::from("/s/map.php")));
+        assert_eq!(cli.log_level, Some(LevelFilter::DEBUG));
+        assert_eq!(cli.command, None);
+
+        // the old way of giving the stubs still works
+        let cli = parse(&["/s/map.php"]).unwrap();
+        assert_eq!(cli.stubs_filename(), Some(PathBuf::from("/s/map.php")));
+        assert!(parse(&["/s/map.php", "--stubs", "/t/map.php"]).is_err());
+
+        let cli = parse(&["--listen", "127.0.0.1:9257"]).unwrap();
+        assert_eq!(cli.listen.as_deref(), Some("127.0.0.1:9257"));
+        assert!(parse(&["--stdio", "--listen", "127.0.0.1:9257"]).is_err());
+        assert!(parse(&["--listen", "127.0.0.1:9257", "--socket", "/tmp/pls.sock"]).is_err());
+
+        assert!(parse(&["--log-level", "loud"]).is_err());
+        assert!(parse(&["--nope"]).is_err());
+    }
+
+    #[test]
+    fn subcommands() {
+        let cli = parse(&["check", "--format", "sarif", "src", "tests"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(CliCommand::Check {
+                format: Format::Sarif,
+                paths: vec![PathBuf::from("src"), PathBuf::from("tests")],
+            })
+        );
+
+        let cli = parse(&["check", "--stubs", "/s/map.php"]).unwrap();
+        assert_eq!(cli.stubs_filename(), Some(PathBuf::from("/s/map.php")));
+        assert_eq!(
+            cli.command,
+            Some(CliCommand::Check {
+                format: Format::Text,
+                paths: vec![],
+            })
+        );
+        assert!(parse(&["check", "--format", "xml"]).is_err());
+
+        let cli = parse(&["index"]).unwrap();
+        assert_eq!(cli.command, Some(CliCommand::Index));
+
+        let cli = parse(&["stubs", "install"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(CliCommand::Stubs {
+                command: StubsCommand::Install
+            })
+        );
+    }
+}
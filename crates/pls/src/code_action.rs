@@ -1,25 +1,39 @@
 use lsp_types::*;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tree_sitter::{Node, Query, QueryCursor, StreamingIterator};
 use tree_sitter_php::LANGUAGE_PHP;
 
 use std::sync::LazyLock;
 
-use crate::compat::to_point;
-use crate::file::offset_to_position;
+use crate::compat::{to_point, to_position};
 use crate::global_state::FileInfo;
 
 pub const PHPECHO_TITLE: &'static str = "Convert `<?php echo` into `<?=`";
+pub const SHORTECHO_TITLE: &str = "Convert `<?=` into `<?php echo`";
 pub const TMPLSTR_TITLE: &'static str = "Use template string";
+pub const ORGIMPORTS_TITLE: &str = "Organize imports";
+pub const STRICT_TYPES_TITLE: &str = "Add `declare(strict_types=1)`";
 
 #[derive(Serialize, Deserialize)]
 pub struct PhpEchoParams {
     pub uri: Uri,
 }
 
-static PHPECHO_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"<\?php\s+echo\s+([^;]+);\s*\?>").unwrap());
+/// Which echo tags to convert: the ones that intersect `range`.
+#[derive(Serialize, Deserialize)]
+pub struct EchoTagParams {
+    pub uri: Uri,
+    pub range: Range,
+}
+
+/// A fix for a diagnostic, stashed in the diagnostic's `data` so that we can offer it when the
+/// client asks for code actions.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct QuickFix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
 static CONCAT_STR_QUERY: LazyLock<Query> = LazyLock::new(|| {
     Query::new(
         &LANGUAGE_PHP.into(),
@@ -104,28 +118,292 @@ pub fn changes_tmplstr(uri: &Uri, file_info: &FileInfo, range: &Range) -> Option
     }]))
 }
 
-pub fn changes_phpecho(uri: &Uri, contents: &str, version: i32) -> Option<DocumentChanges> {
-    let mut edits = vec![];
-    let text_document = OptionalVersionedTextDocumentIdentifier {
-        uri: uri.clone(),
-        version: Some(version),
-    };
+/// An edit that declares strict types for the file, if it doesn't yet.
+///
+/// The declaration goes right after the opening tag, or after the file's docblock if it has one.
+/// Files with anything but a shebang before the opening tag can't have the declaration, since it
+/// has to be the first statement.
+pub fn strict_types(node: Node<'_>, content: &str) -> Option<TextEdit> {
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    if children.iter().any(|child| {
+        child.kind() == "declare_statement" && content[child.byte_range()].contains("strict_types")
+    }) {
+        return None;
+    }
+
+    let tag = children
+        .iter()
+        .position(|child| child.kind() == "php_tag")?;
+    if children[..tag]
+        .iter()
+        .any(|child| child.kind() != "text" || !content[child.byte_range()].starts_with("#!"))
+    {
+        return None;
+    }
 
-    for captures in PHPECHO_RE.captures_iter(contents) {
-        let m = captures.get(0).unwrap();
-        let range = Range {
-            start: offset_to_position(contents, m.start()),
-            end: offset_to_position(contents, m.end()),
+    let mut after = children[tag];
+    if let Some(comment) = children.get(tag + 1)
+        && comment.kind() == "comment"
+        && content[comment.byte_range()].starts_with("/**")
+    {
+        // a docblock right above a declaration documents the declaration instead
+        let documents_next = children.get(tag + 2).is_some_and(|next| {
+            next.start_position().row <= comment.end_position().row + 1
+                && matches!(
+                    next.kind(),
+                    "class_declaration"
+                        | "interface_declaration"
+                        | "trait_declaration"
+                        | "enum_declaration"
+                        | "function_definition"
+                        | "const_declaration"
+                )
+        });
+        if !documents_next {
+            after = *comment;
+        }
+    }
+
+    let position = to_position(&after.end_position());
+    Some(TextEdit {
+        range: Range::new(position, position),
+        new_text: "\n\ndeclare(strict_types=1);".to_string(),
+    })
+}
+
+/// Quick fixes for the diagnostics that come with one.
+pub fn quick_fixes(
+    uri: &Uri,
+    version: i32,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeActionOrCommand> {
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let fix: QuickFix = serde_json::from_value(diagnostic.data.clone()?).ok()?;
+            let text_document = OptionalVersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: Some(version),
+            };
+
+            Some(
+                CodeAction {
+                    title: fix.title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                            text_document,
+                            edits: fix.edits.into_iter().map(OneOf::Left).collect(),
+                        }])),
+                        ..WorkspaceEdit::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..CodeAction::default()
+                }
+                .into(),
+            )
+        })
+        .collect()
+}
+
+/// The quick fixes of all of a file's diagnostics together, or just the ones with the given source
+/// or code.
+///
+/// Fixes that overlap one that's already in are left out, since they were made for the text as it
+/// is now; running this again picks them up. Edits that are already in, like the same import for
+/// two references, are only made once.
+pub fn batch_fixes(diagnostics: &[Diagnostic], only: Option<&str>) -> Vec<TextEdit> {
+    let mut edits: Vec<TextEdit> = Vec::new();
+    for diagnostic in diagnostics {
+        if let Some(only) = only {
+            let code = match &diagnostic.code {
+                Some(NumberOrString::String(code)) => Some(code.as_str()),
+                _ => None,
+            };
+            if code != Some(only) && diagnostic.source.as_deref() != Some(only) {
+                continue;
+            }
+        }
+        let Some(fix) = diagnostic
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<QuickFix>(data).ok())
+        else {
+            continue;
         };
 
-        let trimmed = captures.get(1).unwrap().as_str().trim_end();
-        let new_text = format!("<?= {} ?>", trimmed);
-        edits.push(OneOf::Left(TextEdit { range, new_text }));
+        let new: Vec<_> = fix
+            .edits
+            .into_iter()
+            .filter(|edit| !edits.contains(edit))
+            .collect();
+        let overlaps = new.iter().any(|edit| {
+            edits.iter().any(|taken| {
+                edit.range.start < taken.range.end && taken.range.start < edit.range.end
+            })
+        });
+        if !overlaps {
+            edits.extend(new);
+        }
+    }
+
+    edits
+}
+
+/// A statement that has a pair of PHP tags all to itself, and prints something: either
+/// `<?php echo $x; ?>` or `<?= $x ?>`.
+struct EchoTag<'a> {
+    /// From the opening tag to the closing one.
+    range: Range,
+    short: bool,
+    expression: Node<'a>,
+}
+
+pub fn previous_leaf(mut node: Node<'_>) -> Option<Node<'_>> {
+    let mut leaf = loop {
+        match node.prev_sibling() {
+            Some(previous) => break previous,
+            None => node = node.parent()?,
+        }
+    };
+    while leaf.child_count() > 0 {
+        leaf = leaf.child(leaf.child_count() - 1)?;
+    }
+
+    Some(leaf)
+}
+
+fn next_leaf(mut node: Node<'_>) -> Option<Node<'_>> {
+    let mut leaf = loop {
+        match node.next_sibling() {
+            Some(next) => break next,
+            None => node = node.parent()?,
+        }
+    };
+    while leaf.child_count() > 0 {
+        leaf = leaf.child(0)?;
+    }
+
+    Some(leaf)
+}
+
+/// Every echo tag under `node`.
+fn echo_tags<'a>(node: Node<'a>, content: &str, tags: &mut Vec<EchoTag<'a>>) {
+    if matches!(node.kind(), "echo_statement" | "expression_statement") {
+        let open = previous_leaf(node).filter(|tag| tag.kind() == "php_tag");
+        let close = next_leaf(node).filter(|tag| tag.kind() == "php_end_tag");
+        let mut cursor = node.walk();
+        let children: Vec<_> = node.named_children(&mut cursor).collect();
+        if let (Some(open), Some(close), [expression]) = (open, close, &children[..]) {
+            let short = &content[open.byte_range()] == "<?=";
+            let long = content[open.byte_range()].eq_ignore_ascii_case("<?php");
+            // `<?php $x ?>` doesn't print anything
+            if short && node.kind() == "expression_statement"
+                || long && node.kind() == "echo_statement"
+            {
+                tags.push(EchoTag {
+                    range: Range::new(
+                        to_position(&open.start_position()),
+                        to_position(&close.end_position()),
+                    ),
+                    short,
+                    expression: *expression,
+                });
+            }
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        echo_tags(child, content, tags);
     }
+}
+
+/// Which way to convert echo tags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EchoConversion {
+    /// `<?php echo $x; ?>` into `<?= $x ?>`.
+    Shorten,
+    /// `<?= $x ?>` into `<?php echo $x; ?>`, for when short tags are turned off.
+    Expand,
+}
+
+impl EchoConversion {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Shorten => PHPECHO_TITLE,
+            Self::Expand => SHORTECHO_TITLE,
+        }
+    }
+
+    pub fn from_title(title: &str) -> Option<Self> {
+        match title {
+            PHPECHO_TITLE => Some(Self::Shorten),
+            SHORTECHO_TITLE => Some(Self::Expand),
+            _ => None,
+        }
+    }
+}
+
+/// Edits that convert the echo tags intersecting `range`.
+fn echo_tag_edits(
+    node: Node<'_>,
+    contents: &str,
+    range: &Range,
+    conversion: EchoConversion,
+) -> Vec<TextEdit> {
+    let mut tags = Vec::new();
+    echo_tags(node, contents, &mut tags);
+
+    tags.into_iter()
+        .filter(|tag| tag.short == (conversion == EchoConversion::Expand))
+        .filter(|tag| tag.range.start <= range.end && range.start <= tag.range.end)
+        .map(|tag| {
+            let expression = &contents[tag.expression.byte_range()];
+            let new_text = match conversion {
+                EchoConversion::Shorten => format!("<?= {} ?>", expression),
+                EchoConversion::Expand => format!("<?php echo {}; ?>", expression),
+            };
+            TextEdit {
+                range: tag.range,
+                new_text,
+            }
+        })
+        .collect()
+}
+
+pub fn can_convert_echo_tags(
+    node: Node<'_>,
+    contents: &str,
+    range: &Range,
+    conversion: EchoConversion,
+) -> bool {
+    !echo_tag_edits(node, contents, range, conversion).is_empty()
+}
+
+pub fn changes_echo_tags(
+    uri: &Uri,
+    node: Node<'_>,
+    contents: &str,
+    version: i32,
+    range: &Range,
+    conversion: EchoConversion,
+) -> Option<DocumentChanges> {
+    let edits = echo_tag_edits(node, contents, range, conversion);
+    if edits.is_empty() {
+        return None;
+    }
+    let text_document = OptionalVersionedTextDocumentIdentifier {
+        uri: uri.clone(),
+        version: Some(version),
+    };
 
     Some(DocumentChanges::Edits(vec![TextDocumentEdit {
         text_document,
-        edits,
+        edits: edits.into_iter().map(OneOf::Left).collect(),
     }]))
 }
 
@@ -134,7 +412,22 @@ mod test {
     use lsp_types::*;
     use std::str::FromStr;
 
-    use super::changes_phpecho;
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use super::{
+        EchoConversion, QuickFix, batch_fixes, can_convert_echo_tags, changes_echo_tags,
+        quick_fixes, strict_types,
+    };
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
 
     macro_rules! unwrap_enum {
         ($value:expr, $variant:path) => {
@@ -158,8 +451,18 @@ mod test {
 
             <?php echo 34; ?>";
         let uri = Uri::from_str("https://google.ca").unwrap();
+        let tree = parser().parse(contents, None).unwrap();
+        let everything = Range::new(Position::new(0, 0), Position::new(3, 29));
         let edits = unwrap_enum!(
-            changes_phpecho(&uri, &contents, 1).unwrap(),
+            changes_echo_tags(
+                &uri,
+                tree.root_node(),
+                contents,
+                1,
+                &everything,
+                EchoConversion::Shorten
+            )
+            .unwrap(),
             DocumentChanges::Edits
         )[0]
         .edits
@@ -197,5 +500,176 @@ mod test {
                 character: 29,
             }
         );
+
+        // only the tags the selection touches
+        let second = Range::new(Position::new(3, 20), Position::new(3, 20));
+        let edits = unwrap_enum!(
+            changes_echo_tags(
+                &uri,
+                tree.root_node(),
+                contents,
+                1,
+                &second,
+                EchoConversion::Shorten
+            )
+            .unwrap(),
+            DocumentChanges::Edits
+        )[0]
+        .edits
+        .clone();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(unwrap_enum!(&edits[0], OneOf::Left).new_text, "<?= 34 ?>");
+        let between = Range::new(Position::new(1, 0), Position::new(2, 0));
+        assert!(!can_convert_echo_tags(
+            tree.root_node(),
+            contents,
+            &between,
+            EchoConversion::Shorten
+        ));
+
+        // the statements aren't alone in their tags
+        let contents = "<?php echo 1; echo 2; ?><?= 3 ?>";
+        let tree = parser().parse(contents, None).unwrap();
+        let everything = Range::new(Position::new(0, 0), Position::new(0, 32));
+        assert!(!can_convert_echo_tags(
+            tree.root_node(),
+            contents,
+            &everything,
+            EchoConversion::Shorten
+        ));
+        let edits = unwrap_enum!(
+            changes_echo_tags(
+                &uri,
+                tree.root_node(),
+                contents,
+                1,
+                &everything,
+                EchoConversion::Expand
+            )
+            .unwrap(),
+            DocumentChanges::Edits
+        )[0]
+        .edits
+        .clone();
+        let edit = unwrap_enum!(&edits[0], OneOf::Left);
+        assert_eq!(edit.new_text, "<?php echo 3; ?>");
+        assert_eq!(edit.range.start, Position::new(0, 24));
+    }
+
+    #[test]
+    fn quick_fixes_from_diagnostics() {
+        let uri = Uri::from_str("file:///tmp/file.php").unwrap();
+        let fix = QuickFix {
+            title: "Remove unused import".to_string(),
+            edits: vec![TextEdit {
+                range: Range::default(),
+                new_text: String::new(),
+            }],
+        };
+        let diagnostics = [
+            Diagnostic {
+                message: "unused import \\Foo".to_string(),
+                data: Some(serde_json::to_value(&fix).unwrap()),
+                ..Diagnostic::default()
+            },
+            Diagnostic {
+                message: "no fix".to_string(),
+                ..Diagnostic::default()
+            },
+        ];
+
+        let actions = quick_fixes(&uri, 3, &diagnostics);
+        assert_eq!(actions.len(), 1);
+        let action = unwrap_enum!(&actions[0], CodeActionOrCommand::CodeAction);
+        assert_eq!(action.title, "Remove unused import");
+        assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+        let changes = unwrap_enum!(
+            action
+                .edit
+                .as_ref()
+                .unwrap()
+                .document_changes
+                .as_ref()
+                .unwrap(),
+            DocumentChanges::Edits
+        );
+        assert_eq!(changes[0].text_document.version, Some(3));
+        assert_eq!(changes[0].edits.len(), 1);
+    }
+
+    #[test]
+    fn declare_strict_types() {
+        let edit = |src: &str| {
+            let tree = parser().parse(src, None).unwrap();
+            strict_types(tree.root_node(), src)
+        };
+
+        let inserted = edit("<?php\n\nnamespace App;\n").unwrap();
+        assert_eq!(inserted.range.start, Position::new(0, 5));
+        assert_eq!(inserted.new_text, "\n\ndeclare(strict_types=1);");
+
+        let src = "#!/usr/bin/env php
+<?php
+/**
+ * Runs the app.
+ */
+
+run();
+";
+        assert_eq!(edit(src).unwrap().range.start, Position::new(4, 3));
+
+        // the docblock belongs to the class
+        let src = "<?php
+/**
+ * A user.
+ */
+class User {}
+";
+        assert_eq!(edit(src).unwrap().range.start, Position::new(0, 5));
+
+        assert!(edit("<?php\ndeclare(strict_types=1);\n").is_none());
+        assert!(edit("<html>\n<?php echo 1;\n").is_none());
+    }
+
+    #[test]
+    fn batch_quick_fixes() {
+        let edit = |line, start, end, text: &str| TextEdit {
+            range: Range::new(Position::new(line, start), Position::new(line, end)),
+            new_text: text.to_string(),
+        };
+        let diagnostic = |code: &str, edits: Vec<TextEdit>| Diagnostic {
+            code: Some(NumberOrString::String(code.to_string())),
+            source: Some("test".to_string()),
+            data: serde_json::to_value(QuickFix {
+                title: String::new(),
+                edits,
+            })
+            .ok(),
+            ..Diagnostic::default()
+        };
+        let import = edit(1, 0, 0, "use Foo;\n");
+        let diagnostics = [
+            diagnostic("a", vec![import.clone(), edit(3, 4, 7, "Foo")]),
+            diagnostic("a", vec![import.clone(), edit(4, 4, 7, "Foo")]),
+            // overlaps the first one
+            diagnostic("b", vec![edit(3, 6, 9, "===")]),
+            diagnostic("b", vec![edit(5, 6, 8, "===")]),
+        ];
+
+        let edits = batch_fixes(&diagnostics, None);
+        assert_eq!(
+            edits,
+            vec![
+                import.clone(),
+                edit(3, 4, 7, "Foo"),
+                edit(4, 4, 7, "Foo"),
+                edit(5, 6, 8, "===")
+            ]
+        );
+        assert_eq!(
+            batch_fixes(&diagnostics, Some("b")),
+            vec![edit(3, 6, 9, "==="), edit(5, 6, 8, "===")]
+        );
+        assert_eq!(batch_fixes(&diagnostics, Some("test")).len(), 4);
     }
 }
@@ -0,0 +1,159 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::stubs::{FileMapping, STUBS_MAP};
+
+/// The release of `JetBrains/phpstorm-stubs` that gets installed.
+pub const STUBS_RELEASE: &str = "v2024.3";
+const STUBS_REPOSITORY: &str = "https://github.com/JetBrains/phpstorm-stubs.git";
+
+/// What clients can run through `workspace/executeCommand`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Read `composer.json` again and analyze the open files from scratch.
+    ReindexWorkspace,
+    /// Forget every type we've ingested; they get looked up again as files need them.
+    ClearCaches,
+    /// Apply all the quick fixes in a file at once. Takes the file's URI, and optionally the
+    /// diagnostic source or code to stick to.
+    ApplyFixes,
+    /// Run the workspace's tests, or the ones in the file whose URI is given.
+    RunTests,
+    /// Download the PhpStorm stubs and switch to them.
+    InstallStubs,
+}
+
+impl Command {
+    pub const ALL: [Command; 5] = [
+        Command::ReindexWorkspace,
+        Command::ClearCaches,
+        Command::ApplyFixes,
+        Command::RunTests,
+        Command::InstallStubs,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::ReindexWorkspace => "pls.reindexWorkspace",
+            Command::ClearCaches => "pls.clearCaches",
+            Command::ApplyFixes => "pls.applyFixes",
+            Command::RunTests => "pls.runTests",
+            Command::InstallStubs => "pls.installStubs",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+}
+
+/// The test runner installed in a workspace folder through Composer, set up to run `file`, or
+/// everything if there isn't one.
+///
+/// Pest comes first, since it pulls in PHPUnit as well.
+pub fn test_runner(folder: &Path, file: Option<&Path>) -> Option<process::Command> {
+    let runner = ["vendor/bin/pest", "vendor/bin/phpunit"]
+        .iter()
+        .map(|runner| folder.join(runner))
+        .find(|runner| runner.exists())?;
+
+    let mut command = process::Command::new(runner);
+    command.current_dir(folder).args(file);
+    Some(command)
+}
+
+/// A shallow clone of the pinned stubs release into `dir`.
+pub fn stubs_download(dir: &Path) -> process::Command {
+    let mut command = process::Command::new("git");
+    command
+        .args([
+            "clone",
+            "--quiet",
+            "--depth",
+            "1",
+            "--branch",
+            STUBS_RELEASE,
+        ])
+        .arg(STUBS_REPOSITORY)
+        .arg(dir);
+    command
+}
+
+/// Download the stubs into `dir`, and give back the path to their map. Whatever was in `dir` is
+/// only replaced once the download turns out to have a map we can read.
+pub fn install_stubs(dir: &Path) -> anyhow::Result<PathBuf> {
+    let download = dir.with_extension("download");
+    if download.exists() {
+        fs::remove_dir_all(&download)?;
+    }
+    if let Some(parent) = download.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let output = stubs_download(&download).output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "couldn't download the stubs: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    if FileMapping::from_filename(download.join(STUBS_MAP)).is_ok_and(|mapping| !mapping.is_empty())
+    {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::rename(&download, dir)?;
+        Ok(dir.join(STUBS_MAP))
+    } else {
+        fs::remove_dir_all(&download)?;
+        anyhow::bail!("the downloaded stubs don't have a readable {STUBS_MAP}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{Command, STUBS_RELEASE, stubs_download, test_runner};
+
+    #[test]
+    fn command_names() {
+        for command in Command::ALL {
+            assert_eq!(Command::from_name(command.name()), Some(command));
+        }
+        assert_eq!(Command::from_name("pls.unknown"), None);
+    }
+
+    #[test]
+    fn find_test_runner() {
+        let folder = std::env::temp_dir().join(format!("pls-test-runner-{}", std::process::id()));
+        fs::create_dir_all(folder.join("vendor/bin")).unwrap();
+        assert!(test_runner(&folder, None).is_none());
+
+        fs::write(folder.join("vendor/bin/phpunit"), "").unwrap();
+        let file = folder.join("tests/FooTest.php");
+        let command = test_runner(&folder, Some(&file)).unwrap();
+        assert_eq!(command.get_program(), folder.join("vendor/bin/phpunit"));
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            vec![file.as_os_str()]
+        );
+        assert_eq!(command.get_current_dir(), Some(folder.as_path()));
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn stubs_download_is_pinned() {
+        let command = stubs_download(Path::new("/tmp/stubs"));
+        assert_eq!(command.get_program(), "git");
+        let args: Vec<_> = command.get_args().collect();
+        assert!(
+            args.windows(2)
+                .any(|pair| pair == ["--branch", STUBS_RELEASE])
+        );
+        assert_eq!(args.last(), Some(&"/tmp/stubs".as_ref()));
+    }
+}
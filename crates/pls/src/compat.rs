@@ -20,3 +20,167 @@ pub fn to_range(range: &tree_sitter::Range) -> Range {
         end: to_position(&range.end_point),
     }
 }
+
+// LSP counts the characters of a position in UTF-16 code units, while tree-sitter, and so
+// everything here, counts bytes. Positions are converted on the way in and out, given the text
+// they're in.
+
+/// The text of a line of `content`, if it has that many.
+fn line_of(content: &str, line: u32) -> Option<&str> {
+    content.split('\n').nth(line as usize)
+}
+
+/// A position with its column in bytes, as the client counts it.
+pub fn to_utf16(content: &str, position: &Position) -> Position {
+    let Some(line) = line_of(content, position.line) else {
+        return *position;
+    };
+    let character: usize = line
+        .char_indices()
+        .take_while(|(offset, _)| *offset < position.character as usize)
+        .map(|(_, c)| c.len_utf16())
+        .sum();
+
+    Position {
+        line: position.line,
+        character: character as u32,
+    }
+}
+
+/// A position the client sent, with its column in bytes. A character past the end of the line
+/// means the end of the line.
+pub fn from_utf16(content: &str, position: &Position) -> Position {
+    let Some(line) = line_of(content, position.line) else {
+        return *position;
+    };
+    let mut units = 0;
+    let column = line
+        .char_indices()
+        .find(|(_, c)| {
+            let reached = units >= position.character as usize;
+            units += c.len_utf16();
+            reached
+        })
+        .map_or(line.len(), |(offset, _)| offset);
+
+    Position {
+        line: position.line,
+        character: column as u32,
+    }
+}
+
+pub fn range_to_utf16(content: &str, range: &Range) -> Range {
+    Range {
+        start: to_utf16(content, &range.start),
+        end: to_utf16(content, &range.end),
+    }
+}
+
+pub fn range_from_utf16(content: &str, range: &Range) -> Range {
+    Range {
+        start: from_utf16(content, &range.start),
+        end: from_utf16(content, &range.end),
+    }
+}
+
+/// Convert the ranges of edits to a file for the client.
+pub fn edits_to_utf16<'a>(content: &str, edits: impl IntoIterator<Item = &'a mut TextEdit>) {
+    if content.is_ascii() {
+        return;
+    }
+    for edit in edits {
+        edit.range = range_to_utf16(content, &edit.range);
+    }
+}
+
+/// Convert the ranges of diagnostics of a file for the client.
+pub fn diagnostics_to_utf16(content: &str, diagnostics: &mut [Diagnostic]) {
+    if content.is_ascii() {
+        return;
+    }
+    for diagnostic in diagnostics {
+        diagnostic.range = range_to_utf16(content, &diagnostic.range);
+    }
+}
+
+/// Convert the ranges of document symbols and the ones under them for the client.
+pub fn symbols_to_utf16(content: &str, symbols: &mut [DocumentSymbol]) {
+    if content.is_ascii() {
+        return;
+    }
+    for symbol in symbols {
+        symbol.range = range_to_utf16(content, &symbol.range);
+        symbol.selection_range = range_to_utf16(content, &symbol.selection_range);
+        if let Some(children) = &mut symbol.children {
+            symbols_to_utf16(content, children);
+        }
+    }
+}
+
+/// Convert the ranges of a workspace edit for the client, given what's in the files it edits.
+/// Edits to files that can't be read, like ones the edit creates, are left alone.
+pub fn edit_to_utf16(edit: &mut WorkspaceEdit, mut content: impl FnMut(&Uri) -> Option<String>) {
+    for (uri, edits) in edit.changes.iter_mut().flatten() {
+        if let Some(content) = content(uri) {
+            edits_to_utf16(&content, edits.iter_mut());
+        }
+    }
+
+    let document_edits: Vec<&mut TextDocumentEdit> = match &mut edit.document_changes {
+        None => Vec::new(),
+        Some(DocumentChanges::Edits(edits)) => edits.iter_mut().collect(),
+        Some(DocumentChanges::Operations(operations)) => operations
+            .iter_mut()
+            .filter_map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => Some(edit),
+                DocumentChangeOperation::Op(_) => None,
+            })
+            .collect(),
+    };
+    for document_edit in document_edits {
+        if let Some(content) = content(&document_edit.text_document.uri) {
+            edits_to_utf16(
+                &content,
+                document_edit.edits.iter_mut().map(|edit| match edit {
+                    OneOf::Left(edit) => edit,
+                    OneOf::Right(annotated) => &mut annotated.text_edit,
+                }),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Position, Range, TextEdit};
+
+    use super::{edits_to_utf16, from_utf16, range_from_utf16, to_utf16};
+
+    #[test]
+    fn multibyte_columns() {
+        // `é` is two bytes and one code unit, `🐘` four bytes and two code units
+        let content = "<?php\n$s = 'é🐘'; $t = 1;\n";
+        // the end of `$t`
+        let byte = Position::new(1, 17);
+        let utf16 = Position::new(1, 14);
+        assert_eq!(to_utf16(content, &byte), utf16);
+        assert_eq!(from_utf16(content, &utf16), byte);
+
+        // ASCII lines are the same either way, and past the end is the end of the line
+        assert_eq!(to_utf16(content, &Position::new(0, 3)), Position::new(0, 3));
+        assert_eq!(
+            from_utf16(content, &Position::new(1, 99)),
+            Position::new(1, 22)
+        );
+
+        let range = Range::new(Position::new(1, 5), utf16);
+        assert_eq!(
+            range_from_utf16(content, &range),
+            Range::new(Position::new(1, 5), byte)
+        );
+
+        let mut edits = [TextEdit::new(Range::new(byte, byte), "x".to_string())];
+        edits_to_utf16(content, edits.iter_mut());
+        assert_eq!(edits[0].range, Range::new(utf16, utf16));
+    }
+}
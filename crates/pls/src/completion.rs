@@ -0,0 +1,2472 @@
+use lsp_types::*;
+use serde::Deserialize;
+use tree_sitter::Node;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use pls_types::{
+    Argument, CustomType, CustomTypesDatabase, PhpNamespace, SegmentPool, Type, Visibility,
+};
+
+use crate::analyze::{file_scope, node_markup, resolve_class_name};
+use crate::code_action::previous_leaf;
+use crate::compat::{to_point, to_range};
+use crate::generate::missing_getter;
+use crate::scope::Scope;
+use crate::stubs::{FileMapping, StubDoc};
+use crate::usage::import_fix;
+
+/// What accepting a class from another namespace does to the file.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoImport {
+    /// Add a `use` statement, and write the short name.
+    #[default]
+    Import,
+    /// Write the fully qualified name, like `\App\Models\User`.
+    Qualify,
+}
+
+/// The name being typed at the cursor, if a class could go there.
+///
+/// Qualified names and names in `use` declarations are left alone, as are member names and names
+/// being declared.
+fn class_name_at<'a>(node: Node<'a>, position: &Position) -> Option<Node<'a>> {
+    let point = to_point(position);
+    let before = tree_sitter::Point {
+        column: point.column.checked_sub(1)?,
+        ..point
+    };
+    let name = node.descendant_for_point_range(before, before)?;
+    if name.kind() != "name" || name.end_position() != point {
+        return None;
+    }
+
+    let parent = name.parent()?;
+    let excluded = match parent.kind() {
+        "namespace_use_clause"
+        | "namespace_name"
+        | "qualified_name"
+        | "namespace_definition"
+        | "variable_name"
+        | "member_access_expression"
+        | "nullsafe_member_access_expression"
+        | "member_call_expression"
+        | "nullsafe_member_call_expression"
+        | "class_declaration"
+        | "interface_declaration"
+        | "trait_declaration"
+        | "enum_declaration"
+        | "enum_case"
+        | "function_definition"
+        | "method_declaration"
+        | "const_element"
+        | "property_element"
+        | "named_label_statement"
+        | "goto_statement" => true,
+        "scoped_call_expression"
+        | "scoped_property_access_expression"
+        | "class_constant_access_expression" => parent.named_child(0) != Some(name),
+        _ => false,
+    };
+
+    (!excluded).then_some(name)
+}
+
+/// Classes, interfaces, enums and traits we know about whose names start with what's being typed,
+/// along with the built-in classes from the stubs that haven't been ingested yet.
+///
+/// Picking one from another namespace imports it, or writes out its fully qualified name if
+/// `auto_import` says so or if its short name already means something else in the file.
+pub fn class_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    stubs: &FileMapping,
+    auto_import: AutoImport,
+    ns_store: &mut SegmentPool,
+) -> Vec<CompletionItem> {
+    let Some(name) = class_name_at(node, position) else {
+        return Vec::new();
+    };
+    let prefix = content[name.byte_range()].to_lowercase();
+
+    let scope = file_scope(node, content, ns_store);
+    let namespace = scope.ns.clone().unwrap_or(ns_store.intern_str(""));
+
+    let mut candidates = Vec::new();
+    for (fqn, meta) in types.0.iter() {
+        let kind = match meta.t {
+            CustomType::Class(_) | CustomType::Trait(_) => CompletionItemKind::CLASS,
+            CustomType::Interface(_) => CompletionItemKind::INTERFACE,
+            CustomType::Enumeration(_) => CompletionItemKind::ENUM,
+            CustomType::Function(_) => continue,
+        };
+        candidates.push((fqn.clone(), kind, meta.deprecated.is_some(), None));
+    }
+    let known: HashSet<String> = types
+        .0
+        .keys()
+        .map(|fqn| fqn.0.join("\\").to_lowercase())
+        .collect();
+    for name in stubs.classes.keys() {
+        if known.contains(&name.to_lowercase()) {
+            continue;
+        }
+        let short = name.rsplit('\\').next().unwrap_or(name);
+        if !short.to_lowercase().starts_with(&prefix) {
+            continue;
+        }
+        let data = serde_json::json!({ "stub": name });
+        candidates.push((
+            ns_store.intern_str(name),
+            CompletionItemKind::CLASS,
+            false,
+            Some(data),
+        ));
+    }
+
+    let mut items = Vec::new();
+    for (fqn, kind, deprecated, data) in candidates.iter() {
+        let Some(short) = fqn.0.last() else {
+            continue;
+        };
+        if !short.to_lowercase().starts_with(&prefix) {
+            continue;
+        }
+
+        let mut class_ns = fqn.clone();
+        class_ns.pop();
+        let alias = scope
+            .ns_aliases
+            .iter()
+            .find(|(_, (imported, _))| imported == fqn)
+            .map(|(alias, _)| alias.as_str());
+        let taken = scope
+            .ns_aliases
+            .keys()
+            .any(|alias| alias.eq_ignore_ascii_case(short));
+
+        let (label, new_text, additional_text_edits) = if let Some(alias) = alias {
+            (alias.to_string(), alias.to_string(), None)
+        } else if class_ns == namespace {
+            (short.to_string(), short.to_string(), None)
+        } else if auto_import == AutoImport::Qualify || taken {
+            (short.to_string(), format!("\\{}", fqn.0.join("\\")), None)
+        } else {
+            let edits = import_fix(name, fqn, content).edits;
+            (short.to_string(), short.to_string(), Some(edits))
+        };
+        items.push(CompletionItem {
+            label,
+            kind: Some(*kind),
+            detail: Some(fqn.0.join("\\")),
+            deprecated: deprecated.then_some(true),
+            filter_text: Some(short.to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: to_range(&name.range()),
+                new_text,
+            })),
+            additional_text_edits,
+            data: data.clone(),
+            ..CompletionItem::default()
+        });
+    }
+    items.sort_by(|a, b| (&a.label, &a.detail).cmp(&(&b.label, &b.detail)));
+
+    items
+}
+
+/// Whether the cursor is in a comment or a string, where there's nothing to complete.
+fn in_literal(node: Node<'_>, position: &Position) -> bool {
+    let point = to_point(position);
+    let Some(column) = point.column.checked_sub(1) else {
+        return false;
+    };
+    let before = tree_sitter::Point { column, ..point };
+    let mut leaf = node.descendant_for_point_range(before, before);
+    while let Some(ancestor) = leaf {
+        if matches!(
+            ancestor.kind(),
+            "comment" | "string" | "encapsed_string" | "heredoc" | "nowdoc"
+        ) {
+            return true;
+        }
+        leaf = ancestor.parent();
+    }
+
+    false
+}
+
+/// The qualified name being typed at the cursor, as fully qualified segments, with the last one
+/// possibly incomplete.
+///
+/// The tree is too broken while a qualified name is half typed to be of any help, so this goes by
+/// the text on the cursor's line.
+fn qualified_name_at(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    ns_store: &mut SegmentPool,
+) -> Option<Vec<String>> {
+    let line = content.lines().nth(position.line as usize)?;
+    let before = line.get(..position.character as usize)?;
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '\\'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let mut word = before[start..].to_string();
+    if word.is_empty() {
+        return None;
+    }
+
+    if in_literal(node, position) {
+        return None;
+    }
+
+    let head = before[..start].trim();
+    let used = head.strip_prefix("use").is_some_and(|rest| {
+        let rest = rest.trim_start();
+        let rest = rest
+            .strip_prefix("function")
+            .or(rest.strip_prefix("const"))
+            .unwrap_or(rest);
+        match rest.rfind("\\{") {
+            // in a group like `use App\{Models\User, Ser`
+            Some(open) if head.ends_with('{') || head.ends_with(',') => {
+                word.insert_str(0, &rest[..=open]);
+                true
+            }
+            Some(_) => false,
+            None => rest.trim().is_empty(),
+        }
+    });
+    if !used && !word.contains('\\') {
+        return None;
+    }
+
+    let absolute = used || word.starts_with('\\');
+    let mut segments: Vec<String> = word
+        .trim_start_matches('\\')
+        .split('\\')
+        .map(str::to_string)
+        .collect();
+    if !absolute {
+        let scope = file_scope(node, content, ns_store);
+        let alias = scope
+            .ns_aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(&segments[0]));
+        let prefix = match alias {
+            Some((_, (fqn, _))) => {
+                segments.remove(0);
+                fqn.clone()
+            }
+            None => scope.ns.unwrap_or_else(PhpNamespace::empty),
+        };
+        let prefix = prefix.0.iter().map(|segment| segment.to_string());
+        segments.splice(0..0, prefix);
+    }
+
+    Some(segments)
+}
+
+/// The next segment of the qualified name being typed, in a `use` declaration or anywhere else a
+/// qualified name goes: the namespaces from the autoload, the types database and the stubs, and
+/// the classes in them.
+///
+/// Namespaces are completed with a trailing backslash, so that `use App\Ser` offers `Service\`.
+pub fn namespace_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    stubs: &FileMapping,
+    ns_to_dir: &HashMap<PhpNamespace, Vec<PathBuf>>,
+    ns_store: &mut SegmentPool,
+) -> Vec<CompletionItem> {
+    let Some(mut segments) = qualified_name_at(node, content, position, ns_store) else {
+        return Vec::new();
+    };
+    let Some(partial) = segments.pop() else {
+        return Vec::new();
+    };
+
+    let mut known: Vec<(Vec<&str>, bool)> = Vec::new();
+    known.extend(
+        ns_to_dir
+            .keys()
+            .map(|ns| (ns.0.iter().map(|segment| segment.as_ref()).collect(), false)),
+    );
+    known.extend(
+        types
+            .0
+            .iter()
+            .filter(|(_, meta)| !matches!(meta.t, CustomType::Function(_)))
+            .map(|(fqn, _)| (fqn.0.iter().map(|segment| segment.as_ref()).collect(), true)),
+    );
+    known.extend(
+        stubs
+            .classes
+            .keys()
+            .map(|name| (name.split('\\').collect(), true)),
+    );
+
+    let partial_lower = partial.to_lowercase();
+    let mut next: BTreeMap<String, CompletionItemKind> = BTreeMap::new();
+    for (fqn, class) in known {
+        let Some(segment) = fqn.get(segments.len()) else {
+            continue;
+        };
+        let within = fqn
+            .iter()
+            .zip(segments.iter())
+            .all(|(a, b)| a.eq_ignore_ascii_case(b));
+        if !within || !segment.to_lowercase().starts_with(&partial_lower) {
+            continue;
+        }
+
+        if class && fqn.len() == segments.len() + 1 {
+            next.insert(segment.to_string(), CompletionItemKind::CLASS);
+        } else {
+            next.entry(format!("{segment}\\"))
+                .or_insert(CompletionItemKind::MODULE);
+        }
+    }
+
+    let range = Range {
+        start: Position {
+            character: position.character - partial.len() as u32,
+            ..*position
+        },
+        end: *position,
+    };
+    next.into_iter()
+        .map(|(label, kind)| CompletionItem {
+            filter_text: Some(label.trim_end_matches('\\').to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: label.clone(),
+            })),
+            label,
+            kind: Some(kind),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// The cases of the enum before the `::` at the cursor, along with `cases()`, and `from()` and
+/// `tryFrom()` if it's backed. The detail of a backed case is its value.
+pub fn enum_case_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Vec<CompletionItem> {
+    let Some(before) = content
+        .lines()
+        .nth(position.line as usize)
+        .and_then(|line| line.get(..position.character as usize))
+    else {
+        return Vec::new();
+    };
+    let member_start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let Some(class) = before[..member_start].strip_suffix("::") else {
+        return Vec::new();
+    };
+    let class_start = class
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '\\'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let class = &class[class_start..];
+    if class.is_empty() || in_literal(node, position) {
+        return Vec::new();
+    }
+
+    let scope = file_scope(node, content, ns_store);
+    let fqn = resolve_class_name(class, &scope, ns_store);
+    let Some(CustomType::Enumeration(enumeration)) = types.0.get(&fqn).map(|meta| &meta.t) else {
+        return Vec::new();
+    };
+
+    let prefix = before[member_start..].to_lowercase();
+    let range = Range {
+        start: Position {
+            character: member_start as u32,
+            ..*position
+        },
+        end: *position,
+    };
+    let item = |label: &str, kind, detail: Option<String>, new_text: String| CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        detail,
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+        ..CompletionItem::default()
+    };
+    let enum_type = serde_json::json!({ "type": fqn.0.join("\\") });
+
+    let mut items: Vec<_> = enumeration
+        .values
+        .iter()
+        .map(|case| {
+            let value = enumeration.backing_values.get(case).cloned();
+            let mut item = item(case, CompletionItemKind::ENUM_MEMBER, value, case.clone());
+            item.data = Some(enum_type.clone());
+            item
+        })
+        .collect();
+    let mut methods = vec![("cases", "cases(): array".to_string())];
+    if let Some(backed_by) = &enumeration.backed_by {
+        methods.push(("from", format!("from({backed_by} $value): static")));
+        methods.push(("tryFrom", format!("tryFrom({backed_by} $value): ?static")));
+    }
+    items.extend(methods.into_iter().map(|(method, signature)| {
+        item(
+            method,
+            CompletionItemKind::METHOD,
+            Some(signature),
+            format!("{method}()"),
+        )
+    }));
+    items.retain(|item| item.label.to_lowercase().starts_with(&prefix));
+
+    items
+}
+
+/// The keys of the `array{...}` shapes in `text`, if followed by `$variable` or by nothing.
+fn shape_keys(text: &str, variable: &str, keys: &mut Vec<String>) {
+    let mut rest = text;
+    while let Some(start) = rest.find("array{") {
+        let returned = rest[..start]
+            .rsplit_once('@')
+            .is_some_and(|(_, tag)| tag.starts_with("return"));
+        rest = &rest[start + "array{".len()..];
+        let mut depth = 0;
+        let mut end = rest.len();
+        let mut fields = Vec::new();
+        let mut field_start = 0;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '{' | '<' | '(' => depth += 1,
+                '}' | '>' | ')' if depth > 0 => depth -= 1,
+                '}' => {
+                    fields.push(&rest[field_start..i]);
+                    end = i + 1;
+                    break;
+                }
+                ',' if depth == 0 => {
+                    fields.push(&rest[field_start..i]);
+                    field_start = i + 1;
+                }
+                _ => {}
+            }
+        }
+
+        let described = rest[end.min(rest.len())..]
+            .split_whitespace()
+            .next()
+            .is_none_or(|word| word == variable || word == "*/");
+        if described && !returned {
+            keys.extend(fields.into_iter().filter_map(|field| {
+                let (key, _) = field.split_once(':')?;
+                let key = key.trim().trim_end_matches('?').trim_matches(['\'', '"']);
+                (!key.is_empty() && key.parse::<i64>().is_err()).then(|| key.to_string())
+            }));
+        }
+        rest = &rest[end.min(rest.len())..];
+    }
+}
+
+/// The string keys given to `variable` in `node`, by assigning a literal array or one key at a
+/// time, or by describing it with an array shape in a docblock. Functions declared inside are
+/// their own scope and are left out.
+fn array_keys(node: Node<'_>, content: &str, variable: &str, keys: &mut Vec<String>) {
+    let text = |node: Node<'_>| &content[node.byte_range()];
+    let string_key = |node: Node<'_>| match node.kind() {
+        "string" | "encapsed_string" => Some(text(node)[1..text(node).len() - 1].to_string()),
+        _ => None,
+    };
+
+    match node.kind() {
+        "comment" if text(node).starts_with("/**") => {
+            let documents_next = node.next_named_sibling().is_some_and(|next| {
+                text(next).strip_prefix(variable).is_some_and(|rest| {
+                    !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+                })
+            });
+            let mut found = Vec::new();
+            shape_keys(text(node), variable, &mut found);
+            if documents_next || text(node).contains(variable) {
+                keys.extend(found);
+            }
+        }
+        "assignment_expression" => {
+            let left = node.child_by_field_name("left");
+            let right = node.child_by_field_name("right");
+            match (left, right) {
+                (Some(left), Some(right))
+                    if text(left) == variable && right.kind() == "array_creation_expression" =>
+                {
+                    let mut cursor = right.walk();
+                    for element in right.named_children(&mut cursor) {
+                        if element.named_child_count() == 2 {
+                            keys.extend(element.named_child(0).and_then(string_key));
+                        }
+                    }
+                }
+                (Some(left), _) if left.kind() == "subscript_expression" => {
+                    let indexed = left.named_child(0).is_some_and(|v| text(v) == variable);
+                    if indexed {
+                        keys.extend(left.named_child(1).and_then(string_key));
+                    }
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if !matches!(
+            child.kind(),
+            "function_definition"
+                | "method_declaration"
+                | "anonymous_function"
+                | "class_declaration"
+                | "trait_declaration"
+                | "enum_declaration"
+        ) {
+            array_keys(child, content, variable, keys);
+        }
+    }
+}
+
+/// The function a node is in, or the whole file.
+fn enclosing_scope(node: Node<'_>) -> Node<'_> {
+    let mut scope = node;
+    while let Some(parent) = scope.parent() {
+        if matches!(
+            scope.kind(),
+            "function_definition" | "method_declaration" | "anonymous_function" | "arrow_function"
+        ) {
+            break;
+        }
+        scope = parent;
+    }
+
+    scope
+}
+
+/// The known string keys of the array being indexed at the cursor, like `$config['`, from literal
+/// arrays assigned to it and array shapes describing it in the same scope.
+pub fn array_key_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+) -> Vec<CompletionItem> {
+    let Some(before) = content
+        .lines()
+        .nth(position.line as usize)
+        .and_then(|line| line.get(..position.character as usize))
+    else {
+        return Vec::new();
+    };
+    let partial_start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let partial = &before[partial_start..];
+    let rest = &before[..partial_start];
+    let (quote, rest) = match rest.strip_suffix(['\'', '"']) {
+        Some(unquoted) => (rest[unquoted.len()..].to_string(), unquoted),
+        None if partial.is_empty() => (String::new(), rest),
+        None => return Vec::new(),
+    };
+    let Some(rest) = rest.trim_end().strip_suffix('[') else {
+        return Vec::new();
+    };
+    let name_start = rest
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|i| rest[*i..].starts_with('$'));
+    let Some(name_start) = name_start else {
+        return Vec::new();
+    };
+    let variable = &rest[name_start..];
+    if variable.len() < 2 {
+        return Vec::new();
+    }
+
+    let point = to_point(position);
+    let Some(at) = node.descendant_for_point_range(point, point) else {
+        return Vec::new();
+    };
+    if at.kind() == "comment" {
+        return Vec::new();
+    }
+    let scope = enclosing_scope(at);
+
+    let mut keys = Vec::new();
+    if scope.kind() != "program" {
+        let docblock = node_markup(scope, content).unwrap_or_default();
+        shape_keys(&docblock, variable, &mut keys);
+    }
+    array_keys(scope, content, variable, &mut keys);
+    keys.sort();
+    keys.dedup();
+
+    let range = Range {
+        start: Position {
+            character: partial_start as u32,
+            ..*position
+        },
+        end: *position,
+    };
+    keys.into_iter()
+        .filter(|key| key.to_lowercase().starts_with(&partial.to_lowercase()))
+        .map(|key| CompletionItem {
+            label: key.clone(),
+            kind: Some(CompletionItemKind::FIELD),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: if quote.is_empty() {
+                    format!("'{key}'")
+                } else {
+                    key.clone()
+                },
+            })),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Variables used in `node` before byte `end`, leaving out the functions declared inside.
+fn variables_before(node: Node<'_>, content: &str, end: usize, variables: &mut Vec<String>) {
+    if node.start_byte() >= end {
+        return;
+    }
+    if node.kind() == "variable_name" {
+        variables.push(content[node.byte_range()].to_string());
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if !matches!(
+            child.kind(),
+            "function_definition"
+                | "method_declaration"
+                | "anonymous_function"
+                | "arrow_function"
+                | "class_declaration"
+        ) {
+            variables_before(child, content, end, variables);
+        }
+    }
+}
+
+/// The class of `variable` in `scope`: the enclosing class for `$this`, or the class it's
+/// instantiated from or typed as a parameter.
+fn variable_class(
+    scope: Node<'_>,
+    content: &str,
+    variable: &str,
+    file: &Scope,
+    ns_store: &mut SegmentPool,
+) -> Option<PhpNamespace> {
+    let text = |node: Node<'_>| &content[node.byte_range()];
+    if variable == "$this" {
+        let mut class = scope.parent();
+        while let Some(node) = class {
+            if matches!(
+                node.kind(),
+                "class_declaration" | "trait_declaration" | "enum_declaration"
+            ) {
+                let name = text(node.child_by_field_name("name")?);
+                let mut fqn = file.ns.clone().unwrap_or_else(PhpNamespace::empty);
+                fqn.push(Rc::from(name));
+                return Some(fqn);
+            }
+            class = node.parent();
+        }
+        return None;
+    }
+
+    let mut stack = vec![scope];
+    while let Some(node) = stack.pop() {
+        let class_name = match node.kind() {
+            "simple_parameter" | "property_promotion_parameter"
+                if node.child_by_field_name("name").map(text) == Some(variable) =>
+            {
+                node.child_by_field_name("type")
+                    .filter(|t| t.kind() == "named_type")
+            }
+            // the first of the types caught
+            "catch_clause" if node.child_by_field_name("name").map(text) == Some(variable) => node
+                .child_by_field_name("type")
+                .and_then(|types| types.named_child(0))
+                .filter(|t| t.kind() == "named_type"),
+            "assignment_expression"
+                if node.child_by_field_name("left").map(text) == Some(variable) =>
+            {
+                node.child_by_field_name("right")
+                    .filter(|right| right.kind() == "object_creation_expression")
+                    .and_then(|creation| creation.named_child(0))
+                    .filter(|name| matches!(name.kind(), "name" | "qualified_name"))
+            }
+            _ => None,
+        };
+        if let Some(class_name) = class_name {
+            return Some(resolve_class_name(text(class_name), file, ns_store));
+        }
+
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor).filter(|child| {
+            !matches!(
+                child.kind(),
+                "function_definition" | "method_declaration" | "anonymous_function"
+            )
+        }));
+    }
+
+    None
+}
+
+/// Variables after a `$`, and members after `$object->`, in double-quoted strings and heredocs.
+///
+/// Methods are only offered in the `{$object->method()}` form, which is the only one that can
+/// call them.
+pub fn interpolation_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Vec<CompletionItem> {
+    let point = to_point(position);
+    let Some(column) = point.column.checked_sub(1) else {
+        return Vec::new();
+    };
+    let before_point = tree_sitter::Point { column, ..point };
+    let Some(at) = node.descendant_for_point_range(before_point, before_point) else {
+        return Vec::new();
+    };
+    let mut string = Some(at);
+    while let Some(ancestor) = string {
+        if matches!(ancestor.kind(), "encapsed_string" | "heredoc") {
+            break;
+        }
+        string = ancestor.parent();
+    }
+    let (Some(string), Some(before)) = (
+        string,
+        content
+            .lines()
+            .nth(position.line as usize)
+            .and_then(|line| line.get(..position.character as usize)),
+    ) else {
+        return Vec::new();
+    };
+
+    let partial_start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let partial = before[partial_start..].to_lowercase();
+    let rest = &before[..partial_start];
+    let item = |label: String, kind, start: usize, new_text: String| CompletionItem {
+        label,
+        kind: Some(kind),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range: Range {
+                start: Position {
+                    character: start as u32,
+                    ..*position
+                },
+                end: *position,
+            },
+            new_text,
+        })),
+        ..CompletionItem::default()
+    };
+    let scope = enclosing_scope(string);
+
+    if rest.ends_with('$') {
+        let start = partial_start - 1;
+        // not the variable the string is being assigned to
+        let mut statement = string;
+        while let Some(parent) = statement.parent()
+            && !matches!(parent.kind(), "compound_statement" | "program")
+        {
+            statement = parent;
+        }
+        let end = statement.start_byte();
+        let mut variables = Vec::new();
+        if scope.kind() == "method_declaration" {
+            variables.push("$this".to_string());
+        }
+        variables_before(scope, content, end, &mut variables);
+        variables.sort();
+        variables.dedup();
+
+        return variables
+            .into_iter()
+            .filter(|variable| variable[1..].to_lowercase().starts_with(&partial))
+            .map(|variable| {
+                item(
+                    variable.clone(),
+                    CompletionItemKind::VARIABLE,
+                    start,
+                    variable,
+                )
+            })
+            .collect();
+    }
+
+    let Some(object) = rest.strip_suffix("->") else {
+        return Vec::new();
+    };
+    let Some(variable_start) = object.rfind('$') else {
+        return Vec::new();
+    };
+    let variable = &object[variable_start..];
+    if !variable[1..]
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_')
+    {
+        return Vec::new();
+    }
+    let braced = object[..variable_start].ends_with('{');
+
+    let file = file_scope(node, content, ns_store);
+    let Some(class) = variable_class(scope, content, variable, &file, ns_store) else {
+        return Vec::new();
+    };
+    let Ok(members) = types.effective_members(&class) else {
+        return Vec::new();
+    };
+    let visible =
+        |visibility: &Visibility| variable == "$this" || *visibility == Visibility::Public;
+
+    let mut items: Vec<_> = members
+        .properties
+        .values()
+        .filter(|p| !p.member.r#static && visible(&p.member.visibility))
+        .map(|p| {
+            let name = p.member.name.trim_start_matches('$').to_string();
+            let mut item = item(
+                name.clone(),
+                CompletionItemKind::PROPERTY,
+                partial_start,
+                name,
+            );
+            item.detail = Some(p.member.t.to_string());
+            item
+        })
+        .collect();
+    if braced {
+        items.extend(
+            members
+                .methods
+                .values()
+                .filter(|m| !m.member.r#static && visible(&m.member.visibility))
+                .map(|m| {
+                    let name = m.member.name.clone();
+                    let mut item = item(
+                        name.clone(),
+                        CompletionItemKind::METHOD,
+                        partial_start,
+                        format!("{name}()"),
+                    );
+                    item.detail = Some(format!("{name}(): {}", m.member.return_type));
+                    item
+                }),
+        );
+    }
+    items.retain(|item| item.label.to_lowercase().starts_with(&partial));
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+
+    items
+}
+
+/// Directories and PHP files for the path being typed in a `require` or `include`, relative to
+/// the file (as with `__DIR__ . '/...'`) and to the workspace folders.
+pub fn path_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    file: &Path,
+    workspace_folders: &[PathBuf],
+) -> Vec<CompletionItem> {
+    let point = to_point(position);
+    let Some(column) = point.column.checked_sub(1) else {
+        return Vec::new();
+    };
+    let before = tree_sitter::Point { column, ..point };
+    let mut string = node.descendant_for_point_range(before, before);
+    while let Some(ancestor) = string {
+        if matches!(ancestor.kind(), "string" | "encapsed_string") {
+            break;
+        }
+        string = ancestor.parent();
+    }
+    let Some(string) = string else {
+        return Vec::new();
+    };
+
+    let mut expression = string;
+    let mut from_dir = false;
+    while let Some(parent) = expression.parent() {
+        match parent.kind() {
+            "binary_expression" => {
+                from_dir |= parent
+                    .child_by_field_name("left")
+                    .is_some_and(|left| content[left.byte_range()].contains("__DIR__"));
+            }
+            "parenthesized_expression" => {}
+            _ => break,
+        }
+        expression = parent;
+    }
+    let included = expression.parent().is_some_and(|parent| {
+        matches!(
+            parent.kind(),
+            "require_expression"
+                | "require_once_expression"
+                | "include_expression"
+                | "include_once_expression"
+        )
+    });
+    if !included {
+        return Vec::new();
+    }
+
+    let Some(typed) = content
+        .lines()
+        .nth(position.line as usize)
+        .and_then(|line| line.get(string.start_position().column + 1..point.column))
+    else {
+        return Vec::new();
+    };
+    let (dir, partial) = match typed.rsplit_once('/') {
+        Some((dir, partial)) => (format!("{dir}/"), partial),
+        None => (String::new(), typed),
+    };
+
+    let file_dir = file.parent().unwrap_or(Path::new(""));
+    let bases: Vec<PathBuf> = if from_dir {
+        vec![file_dir.join(dir.trim_start_matches('/'))]
+    } else if dir.starts_with('/') {
+        vec![PathBuf::from(&dir)]
+    } else {
+        std::iter::once(file_dir)
+            .chain(workspace_folders.iter().map(PathBuf::as_path))
+            .map(|base| base.join(&dir))
+            .collect()
+    };
+
+    let mut entries = BTreeMap::new();
+    for base in bases {
+        let Ok(read) = std::fs::read_dir(&base) else {
+            continue;
+        };
+        for entry in read.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(partial) || (name.starts_with('.') && !partial.starts_with('.')) {
+                continue;
+            }
+            if entry.path().is_dir() {
+                entries.insert(format!("{name}/"), CompletionItemKind::FOLDER);
+            } else if name.ends_with(".php") {
+                entries.insert(name, CompletionItemKind::FILE);
+            }
+        }
+    }
+
+    let range = Range {
+        start: Position {
+            character: position.character - partial.len() as u32,
+            ..*position
+        },
+        end: *position,
+    };
+    entries
+        .into_iter()
+        .map(|(label, kind)| CompletionItem {
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: label.clone(),
+            })),
+            label,
+            kind: Some(kind),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Built-in functions from the stubs whose names start with what's being typed.
+///
+/// Their signatures and documentation are filled in by [`resolve_stub()`], since that means
+/// parsing the stub file.
+pub fn stub_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    stubs: &FileMapping,
+) -> Vec<CompletionItem> {
+    let Some(name) = class_name_at(node, position) else {
+        return Vec::new();
+    };
+    let type_position = name.parent().is_some_and(|parent| {
+        matches!(
+            parent.kind(),
+            "named_type"
+                | "base_clause"
+                | "class_interface_clause"
+                | "object_creation_expression"
+                | "catch_clause"
+                | "type_list"
+                | "use_declaration"
+                | "attribute"
+        )
+    });
+    if type_position {
+        return Vec::new();
+    }
+    let prefix = content[name.byte_range()].to_lowercase();
+
+    let mut items: Vec<_> = stubs
+        .functions
+        .keys()
+        .filter(|function| !function.contains('\\') && function.to_lowercase().starts_with(&prefix))
+        .map(|function| CompletionItem {
+            label: function.clone(),
+            kind: Some(CompletionItemKind::FUNCTION),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: to_range(&name.range()),
+                new_text: function.clone(),
+            })),
+            data: Some(serde_json::json!({ "stub": function })),
+            ..CompletionItem::default()
+        })
+        .collect();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+
+    items
+}
+
+/// The stub name a completion item was made for, if it came from the stubs.
+pub fn stub_name(item: &CompletionItem) -> Option<&str> {
+    item.data.as_ref()?.get("stub")?.as_str()
+}
+
+/// Fill in the signature and documentation of a completion item from its stub.
+pub fn resolve_stub(item: &mut CompletionItem, doc: &StubDoc) {
+    item.detail = Some(doc.signature.clone());
+    item.documentation = doc.markdown.clone().map(|value| {
+        Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        })
+    });
+}
+
+/// Where the cursor is, as far as what can be written there goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context<'a> {
+    /// Outside of any function or class.
+    TopLevel,
+    /// In the body of a function, or of a control structure.
+    Statement,
+    /// In a class body, which we have unless the class is still missing its closing brace.
+    Member(Option<Node<'a>>),
+}
+
+/// The word being typed at the cursor, whether it's a name or already a keyword.
+fn typed_word<'a>(node: Node<'a>, content: &str, position: &Position) -> Option<Node<'a>> {
+    let point = to_point(position);
+    let before = tree_sitter::Point {
+        column: point.column.checked_sub(1)?,
+        ..point
+    };
+    let word = node.descendant_for_point_range(before, before)?;
+    let text = &content[word.byte_range()];
+    let is_word = word.child_count() == 0
+        && word.end_position() == point
+        && !text.is_empty()
+        && text.chars().all(|c| c.is_alphanumeric() || c == '_');
+    // variables, members and namespaces
+    let qualified = content[..word.start_byte()].ends_with(['$', '>', ':', '\\']);
+
+    (is_word && !qualified).then_some(word)
+}
+
+fn context_at<'a>(word: Node<'a>, content: &str) -> Option<Context<'a>> {
+    let mut ancestor = word.parent()?;
+    loop {
+        match ancestor.kind() {
+            "declaration_list" | "enum_declaration_list" => {
+                return Some(Context::Member(Some(ancestor)));
+            }
+            "compound_statement" => return Some(Context::Statement),
+            "program" => return Some(Context::TopLevel),
+            "string" | "encapsed_string" | "heredoc" | "nowdoc" | "comment" => return None,
+            // a class or function that isn't closed yet doesn't parse at all
+            "ERROR" => {
+                let text = &content[ancestor.start_byte()..word.start_byte()];
+                if let Some((head, _)) = text.split_once('{') {
+                    let mut words = head.split_whitespace();
+                    if words.clone().any(|w| w == "function") {
+                        return Some(Context::Statement);
+                    }
+                    if words.any(|w| matches!(w, "class" | "trait" | "interface" | "enum")) {
+                        return Some(Context::Member(None));
+                    }
+                }
+            }
+            _ => {}
+        }
+        // when the whole file is an error, it's about as good as the top level
+        match ancestor.parent() {
+            Some(parent) => ancestor = parent,
+            None => return Some(Context::TopLevel),
+        }
+    }
+}
+
+/// `$user` for `$users`, if it's a plural.
+fn singular(variable: &str) -> Option<String> {
+    if variable == "$this" {
+        return None;
+    }
+    if let Some(stem) = variable.strip_suffix("ies") {
+        return (stem.len() > 1).then(|| format!("{}y", stem));
+    }
+    if let Some(stem) = variable.strip_suffix("es")
+        && ["x", "ch", "sh", "ss"]
+            .iter()
+            .any(|end| stem.ends_with(end))
+    {
+        return Some(stem.to_string());
+    }
+
+    // `$status`, `$analysis` and `$address` aren't plurals
+    let stem = variable.strip_suffix('s')?;
+    (!stem.ends_with(['s', 'u', 'i']) && stem.len() > 1).then(|| stem.to_string())
+}
+
+/// The last plural-sounding variable before `word` in its function, along with its singular.
+fn iterated_variable(word: Node<'_>, content: &str) -> Option<(String, String)> {
+    let mut scope = word;
+    while !matches!(
+        scope.kind(),
+        "function_definition" | "method_declaration" | "anonymous_function" | "program"
+    ) {
+        scope = scope.parent()?;
+    }
+
+    let mut variables = Vec::new();
+    let mut stack = vec![scope];
+    while let Some(node) = stack.pop() {
+        if node.start_byte() >= word.start_byte() {
+            continue;
+        }
+        if node.kind() == "variable_name" {
+            variables.push(node);
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+    variables.sort_by_key(|variable| variable.start_byte());
+
+    variables.iter().rev().find_map(|variable| {
+        let name = &content[variable.byte_range()];
+        let singular = singular(name)?;
+        Some((name.to_string(), singular))
+    })
+}
+
+fn snippet(label: &str, detail: &str, word: Node<'_>, body: String) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        detail: Some(detail.to_string()),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range: to_range(&word.range()),
+            new_text: body,
+        })),
+        ..CompletionItem::default()
+    }
+}
+
+/// Snippets for the constructs that fit at the cursor, with their placeholders filled in from the
+/// code around it where we can: the loop variable for `foreach`, the property for `getter`, and
+/// the file name for `class`.
+///
+/// Only for clients that support snippets.
+pub fn snippet_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    file_stem: Option<&str>,
+) -> Vec<CompletionItem> {
+    let Some(word) = typed_word(node, content, position) else {
+        return Vec::new();
+    };
+    let Some(context) = context_at(word, content) else {
+        return Vec::new();
+    };
+    let prefix = content[word.byte_range()].to_lowercase();
+
+    let mut snippets = Vec::new();
+    if let Context::Member(body) = context {
+        snippets.push(snippet(
+            "construct",
+            "constructor",
+            word,
+            "public function __construct($1)\n{\n    $0\n}".to_string(),
+        ));
+        let (suffix, t, name) = body
+            .and_then(|body| missing_getter(body, content))
+            .unwrap_or(("Name".to_string(), None, "name"));
+        snippets.push(snippet(
+            "getter",
+            "getter method",
+            word,
+            format!(
+                "public function get${{1:{}}}(): ${{2:{}}}\n{{\n    return \\$this->${{3:{}}};\n}}",
+                suffix,
+                t.unwrap_or("mixed"),
+                name
+            ),
+        ));
+        snippets.push(snippet(
+            "pubfn",
+            "public method",
+            word,
+            "public function ${1:name}($2): ${3:void}\n{\n    $0\n}".to_string(),
+        ));
+    } else {
+        let (items, item) =
+            iterated_variable(word, content).unwrap_or(("$items".to_string(), "$item".to_string()));
+        snippets.push(snippet(
+            "foreach",
+            "foreach loop",
+            word,
+            format!(
+                "foreach (${{1:\\{}}} as ${{2:\\{}}}) {{\n    $0\n}}",
+                items, item
+            ),
+        ));
+        snippets.push(snippet(
+            "try",
+            "try/catch block",
+            word,
+            "try {\n    $0\n} catch (${1:\\\\Exception} ${2:\\$e}) {\n}".to_string(),
+        ));
+    }
+    if context == Context::TopLevel {
+        snippets.push(snippet(
+            "class",
+            "class declaration",
+            word,
+            format!(
+                "class ${{1:{}}}\n{{\n    $0\n}}",
+                file_stem.unwrap_or("Name")
+            ),
+        ));
+    }
+    snippets.retain(|snippet| snippet.label.starts_with(&prefix));
+
+    snippets
+}
+
+const MODIFIERS: [&str; 6] = [
+    "public",
+    "protected",
+    "private",
+    "static",
+    "abstract",
+    "final",
+];
+
+/// The token before `node`, skipping comments and tokens the parser made up.
+fn previous_token(node: Node<'_>) -> Option<Node<'_>> {
+    let mut token = previous_leaf(node)?;
+    while token.kind() == "comment" || token.is_missing() {
+        token = previous_leaf(token)?;
+    }
+
+    Some(token)
+}
+
+/// The keywords that can come after the tokens of a class-like declaration's header, from its
+/// keyword up to the cursor.
+fn header_keywords(header: &[&str]) -> Vec<&'static str> {
+    let Some(start) = header
+        .iter()
+        .position(|t| matches!(*t, "class" | "interface" | "enum"))
+    else {
+        return Vec::new();
+    };
+    let header = &header[start..];
+    // a name has to come right before the keyword
+    let Some(last) = header
+        .last()
+        .filter(|t| t.ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+    else {
+        return Vec::new();
+    };
+    if header.len() < 2 || ["extends", "implements"].contains(last) {
+        return Vec::new();
+    }
+
+    let extends = header.contains(&"extends");
+    let implements = header.contains(&"implements");
+    match header[0] {
+        "class" if header.len() == 2 => vec!["extends", "implements"],
+        "class" if extends && !implements => vec!["implements"],
+        "interface" if header.len() == 2 => vec!["extends"],
+        "enum" if header.len() == 2 => vec!["implements"],
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `token` ends an expression that `instanceof` can check: a variable, a call or an
+/// array access.
+fn ends_operand(token: Node<'_>, content: &str) -> bool {
+    match token.kind() {
+        "name" => content[..token.start_byte()].ends_with('$'),
+        ")" => token.parent().is_some_and(|p| p.kind() == "arguments"),
+        "]" => token
+            .parent()
+            .is_some_and(|p| p.kind() == "subscript_expression"),
+        _ => false,
+    }
+}
+
+/// Whether the block `brace` closes belongs to an `if`, or to a `try` or `catch`.
+fn closes(brace: Node<'_>, kinds: &[&str]) -> bool {
+    let Some(block) = brace
+        .parent()
+        .filter(|block| block.kind() == "compound_statement")
+    else {
+        return false;
+    };
+    // a `try` without any `catch` yet doesn't parse
+    block.parent().is_some_and(|p| kinds.contains(&p.kind()))
+        || block
+            .prev_sibling()
+            .is_some_and(|previous| kinds.contains(&previous.kind()))
+}
+
+/// Whether the cursor is somewhere `break` and `continue` make sense.
+fn in_loop(word: Node<'_>) -> bool {
+    let mut node = word;
+    while let Some(parent) = node.parent() {
+        match parent.kind() {
+            "for_statement" | "foreach_statement" | "while_statement" | "do_statement"
+            | "switch_statement" => return true,
+            "function_definition"
+            | "method_declaration"
+            | "anonymous_function"
+            | "arrow_function" => return false,
+            _ => node = parent,
+        }
+    }
+
+    false
+}
+
+/// Keywords that can go where the cursor is, going by the tokens before it: `extends` and
+/// `implements` after a class name, modifiers at the start of a class member, `instanceof` after
+/// an operand, `else` after an `if` block, and statements at the start of a statement.
+pub fn keyword_completions(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+) -> Vec<CompletionItem> {
+    let Some(word) = typed_word(node, content, position) else {
+        return Vec::new();
+    };
+    let Some(context) = context_at(word, content) else {
+        return Vec::new();
+    };
+    let prefix = content[word.byte_range()].to_lowercase();
+    let previous = previous_token(word);
+    let at_start = previous
+        .is_none_or(|p| matches!(p.kind(), "{" | "}" | ";" | "php_tag" | "text_interpolation"));
+
+    let mut keywords = Vec::new();
+    match context {
+        Context::Member(_) => {
+            // the modifiers already written for this member
+            let mut written = Vec::new();
+            let mut token = previous;
+            while let Some(t) =
+                token.filter(|t| MODIFIERS.contains(&t.kind()) || t.kind() == "readonly")
+            {
+                written.push(t.kind());
+                token = previous_token(t);
+            }
+            let starts_member = token.is_none_or(|t| matches!(t.kind(), "{" | "}" | ";" | "]"));
+            if starts_member {
+                let visible = written
+                    .iter()
+                    .any(|w| matches!(*w, "public" | "protected" | "private"));
+                keywords.extend(
+                    MODIFIERS
+                        .iter()
+                        .chain(&["readonly"])
+                        .filter(|m| !written.contains(m))
+                        .filter(|m| !visible || !matches!(**m, "public" | "protected" | "private")),
+                );
+                keywords.extend(["function", "const"]);
+                if written.is_empty() {
+                    keywords.extend(["use", "case"]);
+                }
+            }
+        }
+        Context::Statement | Context::TopLevel => {
+            // the declaration header the cursor is in, if it's in one
+            let mut header = Vec::new();
+            let mut token = previous;
+            while let Some(t) = token.filter(|t| !matches!(t.kind(), "{" | "}" | ";" | "php_tag")) {
+                header.push(&content[t.byte_range()]);
+                token = previous_token(t);
+            }
+            header.reverse();
+            keywords.extend(header_keywords(&header));
+
+            if previous.is_some_and(|p| ends_operand(p, content)) {
+                keywords.push("instanceof");
+            }
+            if let Some(brace) = previous.filter(|p| p.kind() == "}") {
+                if closes(brace, &["if_statement", "else_if_clause"]) {
+                    keywords.extend(["else", "elseif"]);
+                }
+                if closes(brace, &["try", "try_statement", "catch_clause"]) {
+                    keywords.extend(["catch", "finally"]);
+                }
+            }
+            if at_start {
+                keywords.extend([
+                    "if", "foreach", "for", "while", "do", "switch", "return", "throw", "try",
+                    "echo", "unset",
+                ]);
+                if in_loop(word) {
+                    keywords.extend(["break", "continue"]);
+                }
+                if context == Context::TopLevel {
+                    keywords.extend([
+                        "namespace",
+                        "use",
+                        "function",
+                        "class",
+                        "interface",
+                        "trait",
+                        "enum",
+                        "abstract",
+                        "final",
+                        "readonly",
+                        "const",
+                    ]);
+                }
+            }
+        }
+    }
+
+    keywords
+        .into_iter()
+        .filter(|keyword| keyword.starts_with(&prefix) && **keyword != prefix)
+        .map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// The parameters of the function, method or constructor `call` calls, when we know it.
+fn call_parameters(
+    node: Node<'_>,
+    call: Node<'_>,
+    content: &str,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Option<Vec<Argument>> {
+    let text = |node: Node<'_>| &content[node.byte_range()];
+    let file = file_scope(node, content, ns_store);
+    let scope = enclosing_scope(call);
+    let method = |class: PhpNamespace, name: &str| {
+        let members = types.effective_members(&class).ok()?;
+        Some(members.method(name)?.member.arguments.clone())
+    };
+
+    match call.kind() {
+        "function_call_expression" => {
+            let function = text(call.child_by_field_name("function")?);
+            let global = ns_store.intern_str(function);
+            let mut local = file.ns.clone().unwrap_or_else(PhpNamespace::empty);
+            local.extend(global.0.iter().cloned());
+            [local, global]
+                .iter()
+                .find_map(|fqn| match &types.0.get(fqn)?.t {
+                    CustomType::Function(f) => Some(f.arguments.clone()),
+                    _ => None,
+                })
+        }
+        "member_call_expression" | "nullsafe_member_call_expression" => {
+            let object = call.child_by_field_name("object")?;
+            if object.kind() != "variable_name" {
+                return None;
+            }
+            let class = variable_class(scope, content, text(object), &file, ns_store)?;
+            method(class, text(call.child_by_field_name("name")?))
+        }
+        "scoped_call_expression" => {
+            let class = text(call.child_by_field_name("scope")?);
+            let class = match class.to_lowercase().as_str() {
+                "self" | "static" => variable_class(scope, content, "$this", &file, ns_store)?,
+                _ => resolve_class_name(class, &file, ns_store),
+            };
+            method(class, text(call.child_by_field_name("name")?))
+        }
+        "object_creation_expression" => {
+            let class = call.named_child(0)?;
+            if !matches!(class.kind(), "name" | "qualified_name") {
+                return None;
+            }
+            let class = resolve_class_name(text(class), &file, ns_store);
+            method(class, "__construct")
+        }
+        _ => None,
+    }
+}
+
+/// The type of the value being written at the cursor, going by the parameter it's passed to, the
+/// property it's assigned to, or the function it's returned from.
+pub fn expected_type(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Option<Type> {
+    let point = to_point(position);
+    let before = tree_sitter::Point {
+        column: point.column.checked_sub(1)?,
+        ..point
+    };
+    let text = |node: Node<'_>| &content[node.byte_range()];
+
+    let mut child = node.descendant_for_point_range(before, before)?;
+    let mut ancestor = Some(child);
+    while let Some(current) = ancestor {
+        match current.kind() {
+            "argument" => {
+                let arguments = current.parent()?;
+                let parameters =
+                    call_parameters(node, arguments.parent()?, content, types, ns_store)?;
+                let parameter = match current.child_by_field_name("name") {
+                    Some(name) => {
+                        let name = text(name);
+                        parameters
+                            .iter()
+                            .find(|p| p.name.trim_start_matches('$') == name)
+                    }
+                    None => {
+                        let mut cursor = arguments.walk();
+                        let index = arguments
+                            .named_children(&mut cursor)
+                            .take_while(|argument| argument != &current)
+                            .count();
+                        parameters
+                            .get(index)
+                            .or(parameters.last().filter(|p| p.variadic))
+                    }
+                };
+                return Some(parameter?.t.clone());
+            }
+            "assignment_expression" if current.child_by_field_name("right") == Some(child) => {
+                let left = current.child_by_field_name("left")?;
+                let object = left.child_by_field_name("object")?;
+                if left.kind() != "member_access_expression" || text(object) != "$this" {
+                    return None;
+                }
+                let file = file_scope(node, content, ns_store);
+                let class =
+                    variable_class(enclosing_scope(current), content, "$this", &file, ns_store)?;
+                let members = types.effective_members(&class).ok()?;
+                let name = text(left.child_by_field_name("name")?);
+                return Some(
+                    members
+                        .properties
+                        .get(&format!("${name}"))?
+                        .member
+                        .t
+                        .clone(),
+                );
+            }
+            "return_statement" => {
+                let scope = enclosing_scope(current);
+                let name = text(scope.child_by_field_name("name")?);
+                let file = file_scope(node, content, ns_store);
+                return match scope.kind() {
+                    "method_declaration" => {
+                        let class = variable_class(scope, content, "$this", &file, ns_store)?;
+                        let members = types.effective_members(&class).ok()?;
+                        Some(members.method(name)?.member.return_type.clone())
+                    }
+                    "function_definition" => {
+                        let mut fqn = file.ns.unwrap_or_else(PhpNamespace::empty);
+                        fqn.push(Rc::from(name));
+                        match &types.0.get(&fqn)?.t {
+                            CustomType::Function(f) => Some(f.return_type.clone()),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+            }
+            "expression_statement" | "compound_statement" | "program" => return None,
+            _ => {}
+        }
+        child = current;
+        ancestor = current.parent();
+    }
+
+    None
+}
+
+/// The type of the value a completion item writes, if we can tell.
+fn item_type(item: &CompletionItem, ns_store: &mut SegmentPool) -> Option<Type> {
+    let fqn = match item.kind? {
+        CompletionItemKind::CLASS | CompletionItemKind::INTERFACE | CompletionItemKind::ENUM => {
+            item.detail.as_deref()?
+        }
+        CompletionItemKind::ENUM_MEMBER => item.data.as_ref()?.get("type")?.as_str()?,
+        _ => return None,
+    };
+
+    Some(Type::CustomType(ns_store.intern_str(fqn)))
+}
+
+/// Sort the items that fit `expected` first and the ones that don't last, keeping the ones we
+/// can't tell about in between, and preselect the first one that fits.
+pub fn rank_by_type(
+    items: &mut [CompletionItem],
+    expected: &Type,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) {
+    if *expected == Type::Any {
+        return;
+    }
+
+    for item in items.iter_mut() {
+        let rank = match item_type(item, ns_store) {
+            Some(t) if types.is_subtype(&t, expected) => 0,
+            Some(_) => 2,
+            None => 1,
+        };
+        item.sort_text = Some(format!("{rank}{}", item.label));
+    }
+    if let Some(best) = items
+        .iter_mut()
+        .filter(|item| item.sort_text.as_ref().is_some_and(|s| s.starts_with('0')))
+        .min_by(|a, b| a.sort_text.cmp(&b.sort_text))
+    {
+        best.preselect = Some(true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{
+        CompletionItem, CompletionItemKind, CompletionTextEdit, Documentation, MarkupContent,
+        MarkupKind, Position,
+    };
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use pls_types::{CustomTypesDatabase, SegmentPool, Type};
+
+    use super::{
+        AutoImport, array_key_completions, class_completions, enum_case_completions, expected_type,
+        interpolation_completions, keyword_completions, namespace_completions, path_completions,
+        rank_by_type, resolve_stub, singular, snippet_completions, stub_completions, stub_name,
+    };
+    use crate::analyze::injest_types;
+    use crate::stubs::{FileMapping, StubDoc, StubKind};
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    #[test]
+    fn complete_class_names() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        for (path, src) in [
+            (
+                "/src/Models/User.php",
+                "<?php namespace App\\Models; class User {}",
+            ),
+            (
+                "/src/Http/UserController.php",
+                "<?php namespace App\\Http; class UserController {}",
+            ),
+            (
+                "/src/Auth/User.php",
+                "<?php namespace App\\Auth; interface UserProvider {}",
+            ),
+            (
+                "/stubs/Exception.php",
+                "<?php class UnexpectedValueException {} function usleep() {}",
+            ),
+        ] {
+            let tree = parser().parse(src, None).unwrap();
+            injest_types(
+                tree.root_node(),
+                src,
+                Path::new(path),
+                &mut pool,
+                &mut types,
+            );
+        }
+
+        let src = "<?php
+namespace App\\Http;
+
+use App\\Auth\\UserProvider as Provider;
+
+$user = new U
+";
+        let tree = parser().parse(src, None).unwrap();
+        let complete = |auto_import, pool: &mut SegmentPool| {
+            class_completions(
+                tree.root_node(),
+                src,
+                &Position::new(5, 13),
+                &types,
+                &FileMapping::default(),
+                auto_import,
+                pool,
+            )
+        };
+
+        let items = complete(AutoImport::Import, &mut pool);
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "Provider",
+                "UnexpectedValueException",
+                "User",
+                "UserController"
+            ]
+        );
+        let new_text = |i: usize| match &items[i].text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit.new_text.clone(),
+            _ => panic!("expected a text edit"),
+        };
+        // already imported, and in the same namespace
+        assert!(items[0].additional_text_edits.is_none());
+        assert!(items[3].additional_text_edits.is_none());
+        let imports = items[2].additional_text_edits.as_ref().unwrap();
+        assert_eq!(imports[0].new_text, "\nuse App\\Models\\User;");
+        assert_eq!(imports[0].range.start, Position::new(3, 38));
+        assert_eq!(new_text(2), "User");
+        assert_eq!(
+            items[1].additional_text_edits.as_ref().unwrap()[0].new_text,
+            "\nuse UnexpectedValueException;"
+        );
+
+        let items = complete(AutoImport::Qualify, &mut pool);
+        assert!(items[2].additional_text_edits.is_none());
+        assert_eq!(
+            match &items[2].text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => edit.new_text.as_str(),
+                _ => "",
+            },
+            "\\App\\Models\\User"
+        );
+
+        // not where a class can go
+        let src = "<?php $user->U";
+        let tree = parser().parse(src, None).unwrap();
+        assert!(
+            class_completions(
+                tree.root_node(),
+                src,
+                &Position::new(0, 14),
+                &types,
+                &FileMapping::default(),
+                AutoImport::Import,
+                &mut pool,
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn complete_stubs() {
+        let mut stubs = FileMapping::default();
+        for (name, kind) in [
+            ("array_filter", StubKind::Function),
+            ("array_map", StubKind::Function),
+            ("Ds\\array_nope", StubKind::Function),
+            ("DateTimeImmutable", StubKind::Class),
+            ("DateTimeZone", StubKind::Class),
+            ("PHP_EOL", StubKind::Constant),
+        ] {
+            stubs.insert(kind, name, PathBuf::from("standard/standard.php"));
+        }
+        let mut pool = SegmentPool::new();
+        let types = CustomTypesDatabase::new();
+
+        let src = "<?php
+namespace App;
+
+$evens = array_f
+$date = new DateTimeI
+";
+        let tree = parser().parse(src, None).unwrap();
+        let items = stub_completions(tree.root_node(), src, &Position::new(3, 16), &stubs);
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["array_filter"]);
+        assert_eq!(stub_name(&items[0]), Some("array_filter"));
+
+        // functions can't be instantiated
+        assert!(stub_completions(tree.root_node(), src, &Position::new(4, 21), &stubs).is_empty());
+        let items = class_completions(
+            tree.root_node(),
+            src,
+            &Position::new(4, 21),
+            &types,
+            &stubs,
+            AutoImport::Qualify,
+            &mut pool,
+        );
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "DateTimeImmutable");
+        assert_eq!(stub_name(&items[0]), Some("DateTimeImmutable"));
+        assert_eq!(
+            match &items[0].text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => edit.new_text.as_str(),
+                _ => "",
+            },
+            "\\DateTimeImmutable"
+        );
+
+        let mut item = items[0].clone();
+        let doc = StubDoc {
+            signature: "class DateTimeImmutable implements DateTimeInterface".to_string(),
+            markdown: Some("Immutable date and time.".to_string()),
+        };
+        resolve_stub(&mut item, &doc);
+        assert_eq!(item.detail, Some(doc.signature));
+        assert_eq!(
+            item.documentation,
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "Immutable date and time.".to_string(),
+            }))
+        );
+    }
+
+    #[test]
+    fn complete_namespaces() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let src = "<?php namespace App\\Service\\Billing; class Invoice {}";
+        let tree = parser().parse(src, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/src/Service/Billing/Invoice.php"),
+            &mut pool,
+            &mut types,
+        );
+        let mut stubs = FileMapping::default();
+        stubs.insert(StubKind::Class, "Ds\\Vector", PathBuf::from("ds/ds.php"));
+        let mut ns_to_dir = HashMap::new();
+        ns_to_dir.insert(pool.intern_str("App"), vec![PathBuf::from("/src")]);
+        ns_to_dir.insert(
+            pool.intern_str("App\\Settings"),
+            vec![PathBuf::from("/settings")],
+        );
+
+        let mut complete = |src: &str| {
+            let (line, column) = src
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| Some((i, line.find('|')?)))
+                .unwrap();
+            let src = src.replace('|', "");
+            let tree = parser().parse(&src, None).unwrap();
+            namespace_completions(
+                tree.root_node(),
+                &src,
+                &Position::new(line as u32, column as u32),
+                &types,
+                &stubs,
+                &ns_to_dir,
+                &mut pool,
+            )
+            .into_iter()
+            .map(|item| item.label)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            complete("<?php\nuse App\\Se|"),
+            vec!["Service\\", "Settings\\"]
+        );
+        assert_eq!(complete("<?php\nuse Ap|"), vec!["App\\"]);
+        assert_eq!(
+            complete("<?php\nuse App\\Service\\Billing\\|"),
+            vec!["Invoice"]
+        );
+        assert_eq!(
+            complete("<?php\nuse App\\{Models\\User, Ser|"),
+            vec!["Service\\"]
+        );
+        assert_eq!(complete("<?php\n$v = new \\Ds\\V|;"), vec!["Vector"]);
+        // relative to the namespace, or to an import
+        assert_eq!(
+            complete("<?php\nnamespace App;\n$i = new Service\\B|;"),
+            vec!["Billing\\"]
+        );
+        assert_eq!(
+            complete("<?php\nuse App\\Service;\n$i = new Service\\Billing\\I|;"),
+            vec!["Invoice"]
+        );
+
+        assert!(complete("<?php\n$i = new Invo|;").is_empty());
+        assert!(complete("<?php\n// see App\\Se|").is_empty());
+    }
+
+    #[test]
+    fn complete_enum_cases() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let src = "<?php
+namespace App\\Enums;
+
+enum Status: string {
+    case Active = 'active';
+    case Archived = 'archived';
+}
+
+enum Suit {
+    case Hearts;
+    case Spades;
+}";
+        let tree = parser().parse(src, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/src/Enums.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        let mut complete = |src: &str| {
+            let (line, column) = src
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| Some((i, line.find('|')?)))
+                .unwrap();
+            let src = src.replace('|', "");
+            let tree = parser().parse(&src, None).unwrap();
+            enum_case_completions(
+                tree.root_node(),
+                &src,
+                &Position::new(line as u32, column as u32),
+                &types,
+                &mut pool,
+            )
+            .into_iter()
+            .map(|item| (item.label, item.detail))
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            complete("<?php\nuse App\\Enums\\Status;\n$s = Status::|"),
+            vec![
+                ("Active".to_string(), Some("'active'".to_string())),
+                ("Archived".to_string(), Some("'archived'".to_string())),
+                ("cases".to_string(), Some("cases(): array".to_string())),
+                (
+                    "from".to_string(),
+                    Some("from(string $value): static".to_string())
+                ),
+                (
+                    "tryFrom".to_string(),
+                    Some("tryFrom(string $value): ?static".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            complete("<?php\n$s = \\App\\Enums\\Suit::|"),
+            vec![
+                ("Hearts".to_string(), None),
+                ("Spades".to_string(), None),
+                ("cases".to_string(), Some("cases(): array".to_string())),
+            ]
+        );
+        assert_eq!(
+            complete("<?php\nnamespace App\\Enums;\nfoo(Suit::S|);"),
+            vec![("Spades".to_string(), None)]
+        );
+        // not an enum
+        assert!(complete("<?php\n$s = Carbon::|").is_empty());
+    }
+
+    #[test]
+    fn complete_array_keys() {
+        let complete = |src: &str| {
+            let (line, column) = src
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| Some((i, line.find('|')?)))
+                .unwrap();
+            let src = src.replace('|', "");
+            let tree = parser().parse(&src, None).unwrap();
+            array_key_completions(
+                tree.root_node(),
+                &src,
+                &Position::new(line as u32, column as u32),
+            )
+            .into_iter()
+            .map(|item| match item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => edit.new_text,
+                _ => panic!("expected a text edit"),
+            })
+            .collect::<Vec<_>>()
+        };
+
+        let src = "<?php
+$config = ['debug' => true, \"name\" => 'app', 3 => 'three', 'list'];
+$config['cache'] = 'redis';
+$other = ['nope' => 1];
+echo $config[|];
+echo $config['d|'];
+";
+        assert_eq!(complete(src), vec!["'cache'", "'debug'", "'name'"]);
+        assert_eq!(complete(&src.replace("|]", "]")), vec!["debug"]);
+
+        let src = "<?php
+$config = ['outside' => 1];
+
+/**
+ * @param array{host: string, 'port'?: int, options: array{timeout: int}} $db
+ * @return array{result: bool}
+ */
+function connect(array $db) {
+    /** @var array{user: string} $auth */
+    $auth = credentials();
+    echo $db['|'], $auth[''];
+}
+";
+        assert_eq!(complete(src), vec!["host", "options", "port"]);
+        assert_eq!(
+            complete(
+                &src.replace("$db['|']", "$db['']")
+                    .replace("$auth['']", "$auth['|']")
+            ),
+            vec!["user"]
+        );
+
+        // not indexing
+        assert!(complete("<?php\n$config = ['debug' => 1];\necho $config|;").is_empty());
+    }
+
+    #[test]
+    fn complete_interpolations() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let src = "<?php
+namespace App;
+
+class User {
+    public string $name;
+    public static int $count;
+    private string $password;
+
+    public function fullName(): string {}
+
+    public function greet(Mailer $mailer) {
+        $greeting = \"Hello {$this->|}\";
+    }
+}";
+        let declaration = src.replace('|', "");
+        let tree = parser().parse(&declaration, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            &declaration,
+            Path::new("/src/User.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        let mut complete = |src: &str| {
+            let (line, column) = src
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| Some((i, line.find('|')?)))
+                .unwrap();
+            let src = src.replace('|', "");
+            let tree = parser().parse(&src, None).unwrap();
+            interpolation_completions(
+                tree.root_node(),
+                &src,
+                &Position::new(line as u32, column as u32),
+                &types,
+                &mut pool,
+            )
+            .into_iter()
+            .map(|item| item.label)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(complete(src), vec!["fullName", "greet", "name", "password"]);
+        assert_eq!(
+            complete(&src.replace("{$this->|}", "$|")),
+            vec!["$mailer", "$this"]
+        );
+
+        let src = "<?php
+use App\\User;
+
+$user = new User();
+$count = 1;
+echo \"$user->| and $c\";
+echo <<<EOT
+  {$user->f} $u
+  EOT;
+echo '$u';
+";
+        assert_eq!(complete(src), vec!["name"]);
+        assert_eq!(
+            complete(&src.replace("$user->|", "$user->").replace("$c\"", "$c|\"")),
+            vec!["$count"]
+        );
+        assert_eq!(
+            complete(
+                &src.replace("$user->|", "$user->")
+                    .replace("{$user->f}", "{$user->f|}")
+            ),
+            vec!["fullName"]
+        );
+        assert_eq!(
+            complete(&src.replace("$user->|", "$user->").replace("} $u", "} $u|")),
+            vec!["$user"]
+        );
+        // not interpolated
+        assert!(complete(&src.replace("$user->|", "$user->").replace("'$u'", "'$u|'")).is_empty());
+
+        let src = "<?php
+use App\\User;
+
+try {
+    register();
+} catch (User $e) {
+    echo \"{$e->f|}\";
+}
+";
+        assert_eq!(complete(src), vec!["fullName"]);
+    }
+
+    #[test]
+    fn complete_paths() {
+        let root = std::env::temp_dir().join(format!("pls-complete-paths-{}", std::process::id()));
+        for dir in ["src/Http", "config", ".git"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "src/helpers.php",
+            "src/README.md",
+            "config/app.php",
+            "bootstrap.php",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let file = root.join("src/index.php");
+
+        let complete = |src: &str| {
+            let (line, column) = src
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| Some((i, line.find('|')?)))
+                .unwrap();
+            let src = src.replace('|', "");
+            let tree = parser().parse(&src, None).unwrap();
+            path_completions(
+                tree.root_node(),
+                &src,
+                &Position::new(line as u32, column as u32),
+                &file,
+                std::slice::from_ref(&root),
+            )
+            .into_iter()
+            .map(|item| item.label)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            complete("<?php\nrequire __DIR__ . '/|';"),
+            vec!["Http/", "helpers.php"]
+        );
+        assert_eq!(
+            complete("<?php\nrequire_once(\"../config/|\");"),
+            vec!["app.php"]
+        );
+        // from the file and the workspace folder
+        assert_eq!(complete("<?php\ninclude 'b|';"), vec!["bootstrap.php"]);
+        assert_eq!(
+            complete("<?php\ninclude_once '|';"),
+            vec!["Http/", "bootstrap.php", "config/", "helpers.php", "src/"]
+        );
+        assert!(complete("<?php\necho '|';").is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rank_by_expected_type() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let src = "<?php
+namespace App;
+
+interface Shape {}
+class Circle implements Shape {}
+class Dog {}
+enum Color { case Red; }
+
+function draw(Shape $shape, Color ...$colors) {}
+
+class Canvas {
+    private Shape $shape;
+
+    public function __construct(Color $background) {}
+
+    public function fill(): Color {}
+}";
+        let tree = parser().parse(src, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/src/Canvas.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        let mut expected = |body: &str| {
+            let src = format!("{}\n{body}\n", src.trim_end_matches('}'));
+            let (line, column) = src
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| Some((i, line.find('|')?)))
+                .unwrap();
+            let src = src.replace('|', "");
+            let tree = parser().parse(&src, None).unwrap();
+            expected_type(
+                tree.root_node(),
+                &src,
+                &Position::new(line as u32, column as u32),
+                &types,
+                &mut pool,
+            )
+            .map(|t| t.to_string())
+        };
+
+        let shape = Some("\\App\\Shape".to_string());
+        let color = Some("\\App\\Color".to_string());
+        let call = |call: &str| format!("public function paint() {{ {call}; }}}}");
+        assert_eq!(expected(&call("draw(new Ci|)")), shape);
+        assert_eq!(expected(&call("draw($c, $d, Co|)")), color);
+        assert_eq!(expected(&call("draw(colors: Co|)")), color);
+        assert_eq!(expected(&call("new Canvas(C|)")), color);
+        assert_eq!(expected(&call("$this->shape = new C|")), shape);
+        assert_eq!(expected(&call("return C|")), None);
+        assert_eq!(expected(&call("$this->fill(Co|)")), None);
+        assert_eq!(
+            expected("public function fill(): Color { return C|; }}"),
+            color
+        );
+
+        let item = |label: &str, kind, detail: &str| CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            detail: Some(detail.to_string()),
+            ..CompletionItem::default()
+        };
+        let mut items = vec![
+            item("Dog", CompletionItemKind::CLASS, "App\\Dog"),
+            item("Circle", CompletionItemKind::CLASS, "App\\Circle"),
+            item("class", CompletionItemKind::KEYWORD, ""),
+            item("Shape", CompletionItemKind::INTERFACE, "App\\Shape"),
+        ];
+        let shape = Type::CustomType(pool.intern_str("App\\Shape"));
+        rank_by_type(&mut items, &shape, &types, &mut pool);
+        items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+        let ranked: Vec<_> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.preselect))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                ("Circle", Some(true)),
+                ("Shape", None),
+                ("class", None),
+                ("Dog", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn singulars() {
+        assert_eq!(singular("$users").as_deref(), Some("$user"));
+        assert_eq!(singular("$categories").as_deref(), Some("$category"));
+        assert_eq!(singular("$matches").as_deref(), Some("$match"));
+        assert_eq!(singular("$addresses").as_deref(), Some("$address"));
+        assert_eq!(singular("$this"), None);
+        assert_eq!(singular("$status"), None);
+        assert_eq!(singular("$analysis"), None);
+        assert_eq!(singular("$address"), None);
+        assert_eq!(singular("$user"), None);
+    }
+
+    #[test]
+    fn complete_snippets() {
+        let complete = |src: &str, position| {
+            let tree = parser().parse(src, None).unwrap();
+            snippet_completions(tree.root_node(), src, &position, Some("Invoice"))
+        };
+        let body = |item: &CompletionItem| match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit.new_text.clone(),
+            _ => panic!("expected a text edit"),
+        };
+
+        let items = complete(
+            "<?php
+function f() {
+    $categories = [];
+    fore
+}",
+            Position::new(3, 8),
+        );
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            body(&items[0]),
+            "foreach (${1:\\$categories} as ${2:\\$category}) {\n    $0\n}"
+        );
+        // neither `$status` nor `$this` is something to loop over
+        let items = complete(
+            "<?php
+class Shelf {
+    public function f($boxes) {
+        $status = $this->status;
+        fore
+    }
+}",
+            Position::new(4, 12),
+        );
+        assert_eq!(
+            body(&items[0]),
+            "foreach (${1:\\$boxes} as ${2:\\$box}) {\n    $0\n}"
+        );
+
+        let items = complete("<?php\nc", Position::new(1, 1));
+        assert_eq!(body(&items[0]), "class ${1:Invoice}\n{\n    $0\n}");
+
+        let src = "<?php
+class Invoice {
+    private int $total;
+    g
+}";
+        let items = complete(src, Position::new(3, 5));
+        assert_eq!(
+            body(&items[0]),
+            "public function get${1:Total}(): ${2:int}\n{\n    return \\$this->${3:total};\n}"
+        );
+        // the class isn't closed yet
+        let items = complete(&src[..src.len() - 2], Position::new(3, 5));
+        assert_eq!(items[0].label, "getter");
+
+        // no classes in functions, and no snippets for members
+        assert!(complete("<?php function f() { cl }", Position::new(0, 23)).is_empty());
+        assert!(complete("<?php $a->fo", Position::new(0, 12)).is_empty());
+        assert!(complete("<?php // fo", Position::new(0, 11)).is_empty());
+    }
+
+    #[test]
+    fn complete_keywords() {
+        // `|` marks the cursor
+        let complete = |src: &str| {
+            let (before, after) = src.split_once('|').unwrap_or((src, ""));
+            let line = before.matches('\n').count() as u32;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1);
+            let src = format!("{}{}", before, after);
+            let tree = parser().parse(&src, None).unwrap();
+            keyword_completions(tree.root_node(), &src, &Position::new(line, column as u32))
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(complete("<?php\nclass A e"), vec!["extends"]);
+        assert_eq!(
+            complete("<?php\nfinal class A extends B i"),
+            vec!["implements"]
+        );
+        assert!(complete("<?php\nclass A implements B i").is_empty());
+        assert_eq!(complete("<?php\ninterface A e"), vec!["extends"]);
+
+        assert_eq!(
+            complete("<?php\nclass A {\n    p"),
+            vec!["public", "protected", "private"]
+        );
+        assert_eq!(complete("<?php\nclass A {\n    public s"), vec!["static"]);
+        assert!(
+            complete("<?php\nclass A {\n    public function f() {}\n    public $a = p").is_empty()
+        );
+
+        assert_eq!(complete("<?php\n$b = $a i"), vec!["instanceof"]);
+        assert_eq!(complete("<?php\nif ($a) {\n} el"), vec!["else", "elseif"]);
+        assert_eq!(
+            complete("<?php\ntry {\n} f"),
+            vec!["finally", "foreach", "for", "function", "final"]
+        );
+        assert_eq!(
+            complete("<?php\nfunction f() {\n    foreach ($a as $b) {\n        b|\n    }\n}"),
+            vec!["break"]
+        );
+        assert!(complete("<?php\nfunction f() {\n    b|\n}").is_empty());
+        assert!(complete("<?php\necho $a->i").is_empty());
+    }
+}
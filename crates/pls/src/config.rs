@@ -1,19 +1,77 @@
 use lsp_types::{Uri, WorkspaceFolder};
+use pls_types::{PhpVersion, UriExt};
+
+use serde::Deserialize;
 
 use std::path::PathBuf;
-use std::str::FromStr;
+
+use crate::completion::AutoImport;
+use crate::diagnostics::DiagnosticsOptions;
+use crate::imports::ImportStyle;
+
+/// How picky the type checks are about scalars.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Strictness {
+    /// Don't check argument types at all.
+    Off,
+    /// Let scalars coerce into each other, unless the file declares `strict_types`.
+    #[default]
+    Normal,
+    /// Check every file as if it declared `strict_types`.
+    Strict,
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub stubs_filename: PathBuf,
+    /// The PhpStorm stubs map, if we were given one or found one.
+    pub stubs_filename: Option<PathBuf>,
     pub workspace_folders: Vec<PathBuf>,
+    pub strictness: Strictness,
+    pub diagnostics: DiagnosticsOptions,
+    pub import_style: ImportStyle,
+    /// Flag `$a == null` as a loose comparison too.
+    pub strict_null_comparisons: bool,
+    pub auto_import: AutoImport,
+    /// Read `autoload-dev` as well as `autoload` from `composer.json`.
+    pub autoload_dev: bool,
+    /// The PHP version to check code against, over what `composer.json` requires.
+    pub php_version: Option<PhpVersion>,
+    /// Whether the client can take completions as snippets.
+    pub snippet_support: bool,
+}
+
+/// What the client can tell us through `initializationOptions`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeOptions {
+    /// Where `PhpStormStubsMap.php` is; relative paths are from the first workspace folder. Wins
+    /// over the command line argument.
+    #[serde(default)]
+    pub stubs: Option<PathBuf>,
+    #[serde(default)]
+    pub diagnostics: DiagnosticsOptions,
+    #[serde(default)]
+    pub strictness: Strictness,
+    #[serde(default)]
+    pub import_style: ImportStyle,
+    #[serde(default)]
+    pub strict_null_comparisons: bool,
+    #[serde(default)]
+    pub auto_import: AutoImport,
+    /// On unless turned off.
+    #[serde(default)]
+    pub autoload_dev: Option<bool>,
+    /// Like `8.1`; what `require.php` in `composer.json` allows otherwise.
+    #[serde(default)]
+    pub php_version: Option<String>,
 }
 
 impl Config {
     pub fn new(
         mut workspace_folders: Vec<WorkspaceFolder>,
         root_uri: Option<Uri>,
-        stubs_filename: PathBuf,
+        stubs_filename: Option<PathBuf>,
     ) -> Self {
         if workspace_folders.is_empty() {
             if let Some(root_uri) = root_uri {
@@ -28,8 +86,16 @@ impl Config {
             stubs_filename,
             workspace_folders: workspace_folders
                 .into_iter()
-                .filter_map(|f| PathBuf::from_str(&f.uri.to_string()).ok())
+                .filter_map(|f| f.uri.to_file_path().map(|p| p.to_path_buf()))
                 .collect(),
+            strictness: Strictness::default(),
+            diagnostics: DiagnosticsOptions::default(),
+            import_style: ImportStyle::default(),
+            strict_null_comparisons: false,
+            auto_import: AutoImport::default(),
+            autoload_dev: true,
+            php_version: None,
+            snippet_support: false,
         }
     }
 }
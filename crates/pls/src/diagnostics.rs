@@ -5,6 +5,7 @@ use tree_sitter_php::LANGUAGE_PHP;
 
 use serde::Deserialize;
 
+use std::collections::HashMap;
 use std::sync::LazyLock;
 
 use crate::compat::to_range;
@@ -13,22 +14,53 @@ static MISSING_QUERY: LazyLock<Query> =
     LazyLock::new(|| Query::new(&LANGUAGE_PHP.into(), "(MISSING) @missings").unwrap());
 static ERROR_QUERY: LazyLock<Query> =
     LazyLock::new(|| Query::new(&LANGUAGE_PHP.into(), "(ERROR) @error").unwrap());
+static COMMENT_QUERY: LazyLock<Query> =
+    LazyLock::new(|| Query::new(&LANGUAGE_PHP.into(), "(comment) @comment").unwrap());
 
-#[derive(Deserialize)]
-pub struct DiagnosticsOptions {
-    #[serde(default)]
-    pub syntax: bool,
-
-    #[serde(default)]
-    pub undefined: bool,
+/// What to report a rule's diagnostics as.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSeverity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+    Off,
 }
 
-impl Default for DiagnosticsOptions {
-    fn default() -> Self {
-        Self {
-            syntax: true,
-            undefined: true,
+/// Severities by diagnostic source (`undef`) or code (`unused-import`). Rules that aren't
+/// mentioned keep their own severity.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct DiagnosticsOptions(pub HashMap<String, RuleSeverity>);
+
+impl DiagnosticsOptions {
+    /// Give the diagnostics their configured severities, and drop the ones that are turned off.
+    ///
+    /// A code is more specific than a source, so it wins when both are configured.
+    pub fn apply(&self, diagnostics: &mut Vec<Diagnostic>) {
+        if self.0.is_empty() {
+            return;
         }
+
+        diagnostics.retain_mut(|d| {
+            let code = match &d.code {
+                Some(NumberOrString::String(code)) => self.0.get(code),
+                Some(NumberOrString::Number(code)) => self.0.get(&code.to_string()),
+                None => None,
+            };
+            let source = d.source.as_ref().and_then(|source| self.0.get(source));
+            let severity = match code.or(source) {
+                None => return true,
+                Some(RuleSeverity::Off) => return false,
+                Some(RuleSeverity::Error) => DiagnosticSeverity::ERROR,
+                Some(RuleSeverity::Warning) => DiagnosticSeverity::WARNING,
+                Some(RuleSeverity::Info) => DiagnosticSeverity::INFORMATION,
+                Some(RuleSeverity::Hint) => DiagnosticSeverity::HINT,
+            };
+            d.severity = Some(severity);
+            true
+        });
     }
 }
 
@@ -41,6 +73,49 @@ pub fn syntax(node: Node<'_>, content: &str) -> Vec<Diagnostic> {
     missings
 }
 
+/// Drop the diagnostics that comments ask us to ignore.
+///
+/// `// @pls-ignore-next-line` (or PHPStan's `@phpstan-ignore-next-line`) ignores everything on the
+/// line after the comment, and `// @pls-ignore undef dupe` only ignores those sources.
+pub fn suppress(node: Node<'_>, content: &str, diagnostics: &mut Vec<Diagnostic>) {
+    // line => sources to ignore there, or `None` for all of them
+    let mut ignored: HashMap<u32, Option<Vec<&str>>> = HashMap::new();
+
+    let mut cursor = QueryCursor::new();
+    let mut captures = cursor.captures(&COMMENT_QUERY, node, content.as_bytes());
+    while let Some((m, _)) = captures.next() {
+        for c in m.captures.iter() {
+            let comment = &content[c.node.byte_range()];
+            let line = c.node.end_position().row as u32 + 1;
+            if comment.contains("@pls-ignore-next-line")
+                || comment.contains("@phpstan-ignore-next-line")
+            {
+                ignored.insert(line, None);
+            } else if let Some((_, rest)) = comment.split_once("@pls-ignore") {
+                let sources = rest
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .trim_end_matches("*/")
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|s| !s.is_empty());
+                if let Some(ignored) = ignored.entry(line).or_insert(Some(Vec::new())) {
+                    ignored.extend(sources);
+                }
+            }
+        }
+    }
+
+    diagnostics.retain(|d| match ignored.get(&d.range.start.line) {
+        None => true,
+        Some(None) => false,
+        Some(Some(sources)) => !d
+            .source
+            .as_deref()
+            .is_some_and(|source| sources.contains(&source)),
+    });
+}
+
 fn get_tree_diagnostics_missing(node: Node<'_>, content: &str) -> Vec<Diagnostic> {
     let mut cursor = QueryCursor::new();
     let mut captures = cursor.captures(&MISSING_QUERY, node, content.as_bytes());
@@ -92,6 +167,7 @@ fn get_tree_diagnostics_errors(node: Node<'_>, content: &str) -> Vec<Diagnostic>
 
 #[cfg(test)]
 mod test {
+    use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
     use tree_sitter::Parser;
     use tree_sitter_php::LANGUAGE_PHP;
 
@@ -133,4 +209,84 @@ mod test {
         let tree = parser().parse(SOURCE, None).unwrap();
         assert_eq!(0, super::syntax(tree.root_node(), SOURCE).len());
     }
+
+    #[test]
+    fn rule_severities() {
+        let options: super::DiagnosticsOptions = serde_json::from_value(serde_json::json!({
+            "undef": "warning",
+            "unused": "off",
+            "unused-import": "info",
+        }))
+        .unwrap();
+        let diagnostic = |source: &str, code: Option<&str>| Diagnostic {
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: code.map(|c| NumberOrString::String(c.to_string())),
+            source: Some(source.to_string()),
+            ..Default::default()
+        };
+        let mut diagnostics = vec![
+            diagnostic("undef", None),
+            diagnostic("unused", Some("unused-variable")),
+            diagnostic("unused", Some("unused-import")),
+            diagnostic("ts", None),
+        ];
+
+        options.apply(&mut diagnostics);
+        let left: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.source.as_deref().unwrap(), d.severity.unwrap()))
+            .collect();
+        assert_eq!(
+            left,
+            vec![
+                ("undef", DiagnosticSeverity::WARNING),
+                ("unused", DiagnosticSeverity::INFORMATION),
+                ("ts", DiagnosticSeverity::ERROR),
+            ]
+        );
+        assert!(
+            serde_json::from_value::<super::DiagnosticsOptions>(serde_json::json!({
+                "undef": "loud",
+            }))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn suppression_comments() {
+        let src = "<?php
+            // @pls-ignore-next-line
+            everything();
+            // @pls-ignore undef, args
+            some();
+            /** @phpstan-ignore-next-line */
+            phpstan();
+            not_the_next_one(); // @pls-ignore undef
+            ";
+        let diagnostic = |line: u32, source: &str| Diagnostic {
+            range: Range {
+                start: Position { line, character: 0 },
+                end: Position { line, character: 1 },
+            },
+            source: Some(source.to_string()),
+            ..Default::default()
+        };
+        let mut diagnostics = vec![
+            diagnostic(2, "undef"),
+            diagnostic(2, "ts"),
+            diagnostic(4, "undef"),
+            diagnostic(4, "args"),
+            diagnostic(4, "unused"),
+            diagnostic(6, "undef"),
+            diagnostic(7, "undef"),
+        ];
+
+        let tree = parser().parse(src, None).unwrap();
+        super::suppress(tree.root_node(), src, &mut diagnostics);
+        let left: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.source.as_deref().unwrap()))
+            .collect();
+        assert_eq!(left, vec![(4, "unused"), (7, "undef")]);
+    }
 }
@@ -1,6 +1,6 @@
 use lsp_types::*;
 
-use tree_sitter::{InputEdit, Node, Parser, Query, QueryCursor, StreamingIterator, Tree};
+use tree_sitter::{InputEdit, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree};
 use tree_sitter_php::LANGUAGE_PHP;
 use tree_sitter_phpdoc::language as language_phpdoc;
 
@@ -11,7 +11,6 @@ use std::sync::{Arc, OnceLock};
 use pls_types::PhpNamespace;
 use pls_types::Type;
 
-use crate::compat::to_point;
 use crate::global_state::FileInfo;
 
 pub struct FileData {
@@ -41,36 +40,11 @@ impl Display for FileError {
 impl FileInfo {
     pub fn change(&mut self, event: TextDocumentContentChangeEvent) -> anyhow::Result<()> {
         if let Some(r) = event.range {
-            if let (Some(start_byte), Some(end_byte)) = (
-                byte_offset(&self.content, &r.start),
-                byte_offset(&self.content, &r.end),
-            ) {
-                let input_edit = InputEdit {
-                    start_byte,
-                    old_end_byte: end_byte,
-                    new_end_byte: start_byte + event.text.len(),
-                    start_position: to_point(&r.start),
-                    old_end_position: to_point(&r.end),
-                    new_end_position: {
-                        let mut row = r.start.line as usize;
-                        let mut column = r.start.character as usize;
-
-                        for c in event.text.chars() {
-                            if c == '\n' {
-                                row += 1;
-                                column = 0;
-                            } else {
-                                column += 1;
-                            }
-                        }
-
-                        tree_sitter::Point { row, column }
-                    },
-                };
+            if let Some(input_edit) = input_edit(&self.content, &r, &event.text) {
                 self.php_ast.edit(&input_edit);
                 self.phpdoc_ast.edit(&input_edit);
                 self.content
-                    .replace_range(start_byte..end_byte, &event.text);
+                    .replace_range(input_edit.start_byte..input_edit.old_end_byte, &event.text);
             } else {
                 return Err(anyhow::anyhow!("invalid file range {r:?}"));
             }
@@ -85,36 +59,11 @@ impl FileInfo {
 impl FileData {
     pub fn change(&mut self, event: TextDocumentContentChangeEvent) -> Result<(), FileError> {
         if let Some(r) = event.range {
-            if let (Some(start_byte), Some(end_byte)) = (
-                byte_offset(&self.contents, &r.start),
-                byte_offset(&self.contents, &r.end),
-            ) {
-                let input_edit = InputEdit {
-                    start_byte,
-                    old_end_byte: end_byte,
-                    new_end_byte: start_byte + event.text.len(),
-                    start_position: to_point(&r.start),
-                    old_end_position: to_point(&r.end),
-                    new_end_position: {
-                        let mut row = r.start.line as usize;
-                        let mut column = r.start.character as usize;
-
-                        for c in event.text.chars() {
-                            if c == '\n' {
-                                row += 1;
-                                column = 0;
-                            } else {
-                                column += 1;
-                            }
-                        }
-
-                        tree_sitter::Point { row, column }
-                    },
-                };
+            if let Some(input_edit) = input_edit(&self.contents, &r, &event.text) {
                 self.php_tree.edit(&input_edit);
                 self.comments_tree.edit(&input_edit);
                 self.contents
-                    .replace_range(start_byte..end_byte, &event.text);
+                    .replace_range(input_edit.start_byte..input_edit.old_end_byte, &event.text);
             } else {
                 return Err(FileError::InvalidFileRange(r));
             }
@@ -200,62 +149,75 @@ pub fn parse(contents: &str, (php_tree, doc_tree): (Option<&Tree>, Option<&Tree>
     (php_tree, doc_tree)
 }
 
-/// Convert character offset into a position.
-///
-/// If the offset is outside the contents given, return the last position of the file.
-pub fn offset_to_position(contents: &str, mut offset: usize) -> Position {
-    let mut line = 0;
-    let mut character = 0;
-    for c in contents.chars() {
-        if offset == 0 {
-            return Position { line, character };
-        }
-
-        if c == '\n' {
-            line += 1;
-            character = 0;
-        } else {
-            character += 1;
-        }
-
-        offset -= 1;
-    }
-
-    Position { line, character }
-}
-
 /// Get byte offset given some row and column position in a file.
 ///
-/// For example, line 0 character 0 should have offset of 0 (0-indexing). We don't check that the
-/// column is within the current line (e.g. line 0 character 2000 gives offset of 2000 even if the
-/// line isn't that long).
+/// For example, line 0 character 0 should have offset of 0 (0-indexing). Characters count UTF-16
+/// code units, like LSP positions do, so a line with `é` or an emoji in it (heredocs are full of
+/// those) takes more bytes than characters. A character past the end of the line means the end of
+/// the line.
 ///
-/// Return None if the position is invalid (i.e. not in the file, out of range of current line,
-/// etc.)
+/// Return None if the line isn't in the file.
 pub fn byte_offset(text: &str, r: &Position) -> Option<usize> {
-    let mut current_line = 0;
-    let mut current_offset = 0usize;
+    let mut line_start = 0;
+    for _ in 0..r.line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
 
-    for c in text.chars() {
-        if current_line == r.line {
-            return Some(current_offset + r.character as usize);
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= r.character as usize {
+            return Some(line_start + offset);
         }
+        units += c.len_utf16();
+    }
 
-        if c == '\n' {
-            current_line += 1;
-        }
+    Some(line_start + line.len())
+}
 
-        current_offset += 1;
+/// Where a byte offset is in the file, with the column in bytes like tree-sitter counts it.
+fn point_at(text: &str, byte: usize) -> Point {
+    let before = &text[..byte];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+
+    Point {
+        row: before.matches('\n').count(),
+        column: byte - line_start,
     }
+}
 
-    None
+/// What replacing a range of the file with some text does to its syntax trees.
+fn input_edit(text: &str, range: &Range, new_text: &str) -> Option<InputEdit> {
+    let start_byte = byte_offset(text, &range.start)?;
+    let old_end_byte = byte_offset(text, &range.end)?.max(start_byte);
+    let start_position = point_at(text, start_byte);
+    let new_end_position = match new_text.rfind('\n') {
+        Some(newline) => Point {
+            row: start_position.row + new_text.matches('\n').count(),
+            column: new_text.len() - newline - 1,
+        },
+        None => Point {
+            row: start_position.row,
+            column: start_position.column + new_text.len(),
+        },
+    };
+
+    Some(InputEdit {
+        start_byte,
+        old_end_byte,
+        new_end_byte: start_byte + new_text.len(),
+        start_position,
+        old_end_position: point_at(text, old_end_byte),
+        new_end_position,
+    })
 }
 
 #[cfg(test)]
 mod test {
     use lsp_types::*;
 
-    use super::byte_offset;
+    use super::{byte_offset, input_edit, parse};
 
     const SOURCE: &'static str = "<?php
             class Whatever {
@@ -306,6 +268,39 @@ mod test {
         }
     }
 
+    #[test]
+    fn multibyte_byte_offsets() {
+        let s = "<?php\n$a = <<<EOT\n  café 😀 $b\n  EOT;\n";
+        // `é` is 2 bytes and 1 unit, `😀` is 4 bytes and 2 units
+        assert_eq!(byte_offset(s, &Position::new(2, 7)), Some(26));
+        assert_eq!(byte_offset(s, &Position::new(2, 10)), Some(31));
+        assert_eq!(byte_offset(s, &Position::new(2, 200)), Some(33));
+        assert_eq!(byte_offset(s, &Position::new(4, 0)), Some(s.len()));
+    }
+
+    #[test]
+    fn edits_after_heredocs() {
+        let before = "<?php\n$a = <<<EOT\n  café 😀 $b\n  EOT;\necho $a;\n";
+        let after = "<?php\n$a = <<<EOT\n  café 😀 $b\n  EOT;\necho $a . \"ü\";\necho 1;\n";
+        let range = Range::new(Position::new(4, 7), Position::new(4, 8));
+
+        let (mut tree, _) = parse(before, (None, None));
+        let edit = input_edit(before, &range, " . \"ü\";\necho 1;").unwrap();
+        assert_eq!(edit.new_end_position.row, 5);
+        tree.edit(&edit);
+
+        let mut content = before.to_string();
+        content.replace_range(edit.start_byte..edit.old_end_byte, " . \"ü\";\necho 1;");
+        assert_eq!(content, after);
+        let (edited, _) = parse(&content, (Some(&tree), None));
+        let (fresh, _) = parse(after, (None, None));
+        assert_eq!(edited.root_node().to_sexp(), fresh.root_node().to_sexp());
+        assert_eq!(
+            edited.root_node().end_position(),
+            fresh.root_node().end_position()
+        );
+    }
+
     #[test]
     fn invalid_byte_offsets() {
         let invalids = [Position {
@@ -0,0 +1,100 @@
+use lsp_types::{FoldingRange, FoldingRangeKind};
+use tree_sitter::Node;
+
+/// The parts of a file that can be folded away: bodies, arrays, heredocs and nowdocs, and comments
+/// over several lines.
+///
+/// The line a part ends on isn't folded, so its closing bracket or heredoc delimiter stays in
+/// view, except for comments, which fold all the way.
+pub fn folding_ranges(root: Node) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut cursor = root.walk();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        let start = node.start_position().row;
+        let end = node.end_position().row;
+        let folded = match node.kind() {
+            "compound_statement"
+            | "declaration_list"
+            | "enum_declaration_list"
+            | "switch_block"
+            | "match_block"
+            | "array_creation_expression"
+            | "heredoc"
+            | "nowdoc" => Some((end.saturating_sub(1), None)),
+            "comment" => Some((end, Some(FoldingRangeKind::Comment))),
+            _ => None,
+        };
+        if let Some((end, kind)) = folded
+            && end > start
+        {
+            ranges.push(FoldingRange {
+                start_line: start as u32,
+                end_line: end as u32,
+                kind,
+                ..FoldingRange::default()
+            });
+        }
+
+        stack.extend(node.children(&mut cursor));
+    }
+
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::FoldingRangeKind;
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use super::folding_ranges;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    #[test]
+    fn heredocs_bodies_and_comments() {
+        let content = r#"<?php
+/**
+ * Greets people.
+ */
+class Greeter {
+    public function greet(string $name): string {
+        $html = <<<HTML
+        <p>Hello, {$name}!</p>
+        <p>Welcome back.</p>
+        HTML;
+        $raw = <<<'RAW'
+        {$not} interpolated
+        RAW;
+        $options = [1, 2];
+        return $html . $raw;
+    }
+}
+"#;
+        let tree = parser().parse(content, None).unwrap();
+        let ranges: Vec<_> = folding_ranges(tree.root_node())
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect();
+
+        assert_eq!(
+            ranges,
+            vec![
+                (1, 3, Some(FoldingRangeKind::Comment)),
+                (4, 15, None),
+                (5, 14, None),
+                (6, 8, None),
+                (10, 11, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,720 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashSet;
+
+use crate::analyze::node_markup;
+use crate::compat::{to_point, to_position, to_range};
+use crate::native_types::{inferred_return_type, literal_type};
+
+pub const CONSTRUCTOR_TITLE: &str = "Generate constructor";
+pub const PHPDOC_TITLE: &str = "Generate PHPDoc";
+
+/// A property that a generated method can work with.
+struct Property<'a> {
+    /// Without the `$`.
+    name: &'a str,
+    /// As written in the declaration.
+    t: Option<&'a str>,
+    declaration: Node<'a>,
+}
+
+/// The class declaration the range is in.
+fn enclosing_class<'a>(node: Node<'a>, range: &Range) -> Option<Node<'a>> {
+    let mut node =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.start))?;
+    while node.kind() != "class_declaration" {
+        node = node.parent()?;
+    }
+
+    Some(node)
+}
+
+/// The class's non-static properties.
+fn properties<'a>(body: Node<'a>, content: &'a str) -> Vec<Property<'a>> {
+    let mut properties = Vec::new();
+    let mut cursor = body.walk();
+    for declaration in body.named_children(&mut cursor) {
+        if declaration.kind() != "property_declaration" {
+            continue;
+        }
+        let mut cursor = declaration.walk();
+        let children: Vec<_> = declaration.named_children(&mut cursor).collect();
+        if children.iter().any(|c| c.kind() == "static_modifier") {
+            continue;
+        }
+
+        let t = declaration
+            .child_by_field_name("type")
+            .map(|t| &content[t.byte_range()]);
+        for element in children.iter().filter(|c| c.kind() == "property_element") {
+            let Some(name) = element.child_by_field_name("name") else {
+                continue;
+            };
+            properties.push(Property {
+                name: content[name.byte_range()].trim_start_matches('$'),
+                t,
+                declaration: *element,
+            });
+        }
+    }
+
+    properties
+}
+
+/// The whitespace a node's line starts with.
+fn indent_of<'a>(node: Node<'_>, content: &'a str) -> &'a str {
+    let line_start = content[..node.start_byte()]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let line = &content[line_start..];
+
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// How much a class's members are indented, and how much their bodies are.
+fn member_indents(class: Node<'_>, member: Node<'_>, content: &str) -> (String, String) {
+    let outer = indent_of(class, content);
+    let member = indent_of(member, content);
+    let unit = match member.strip_prefix(outer) {
+        Some(unit) if !unit.is_empty() => unit,
+        _ => "    ",
+    };
+
+    (member.to_string(), format!("{}{}", member, unit))
+}
+
+/// Names of the properties that are assigned through `$this` somewhere under `node`.
+fn assigned_properties<'a>(node: Node<'_>, content: &'a str, assigned: &mut HashSet<&'a str>) {
+    if node.kind() == "assignment_expression" {
+        let left = node
+            .child_by_field_name("left")
+            .filter(|left| left.kind() == "member_access_expression");
+        let object = left.and_then(|left| left.child_by_field_name("object"));
+        let name = left.and_then(|left| left.child_by_field_name("name"));
+        if let (Some(object), Some(name)) = (object, name)
+            && &content[object.byte_range()] == "$this"
+        {
+            assigned.insert(&content[name.byte_range()]);
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        assigned_properties(child, content, assigned);
+    }
+}
+
+fn parameter(property: &Property<'_>) -> String {
+    match property.t {
+        Some(t) => format!("{} ${}", t, property.name),
+        None => format!("${}", property.name),
+    }
+}
+
+/// Edits that give the class at `range` a constructor that sets its uninitialized properties, or
+/// that add the missing ones to the constructor it already has.
+///
+/// Only the properties in the selection are used, if it covers any. Parameters the constructor
+/// already takes are assigned rather than added again.
+pub fn constructor(node: Node<'_>, content: &str, range: &Range) -> Option<Vec<TextEdit>> {
+    let class = enclosing_class(node, range)?;
+    let body = class.child_by_field_name("body")?;
+
+    let mut properties: Vec<_> = properties(body, content)
+        .into_iter()
+        .filter(|p| p.declaration.child_by_field_name("default_value").is_none())
+        .collect();
+    let selected = |p: &Property<'_>| {
+        let declared = to_range(&p.declaration.parent().unwrap_or(p.declaration).range());
+        range.start != range.end && declared.start <= range.end && range.start <= declared.end
+    };
+    if properties.iter().any(selected) {
+        properties.retain(selected);
+    }
+
+    let mut cursor = body.walk();
+    let constructor = body.named_children(&mut cursor).find(|member| {
+        member.kind() == "method_declaration"
+            && member
+                .child_by_field_name("name")
+                .is_some_and(|name| content[name.byte_range()].eq_ignore_ascii_case("__construct"))
+    });
+    let Some(constructor) = constructor else {
+        if properties.is_empty() {
+            return None;
+        }
+        // after all the properties, not just the ones we're setting
+        let mut cursor = body.walk();
+        let last = body
+            .named_children(&mut cursor)
+            .filter(|member| member.kind() == "property_declaration")
+            .last()?;
+        let (member, inner) = member_indents(class, last, content);
+        let parameters: Vec<_> = properties.iter().map(parameter).collect();
+        let assignments: String = properties
+            .iter()
+            .map(|p| format!("\n{}$this->{} = ${};", inner, p.name, p.name))
+            .collect();
+        let new_text = format!(
+            "\n\n{member}public function __construct({})\n{member}{{{}\n{member}}}",
+            parameters.join(", "),
+            assignments
+        );
+        let position = to_position(&last.end_position());
+
+        return Some(vec![TextEdit {
+            range: Range::new(position, position),
+            new_text,
+        }]);
+    };
+
+    let parameters = constructor.child_by_field_name("parameters")?;
+    let statements = constructor.child_by_field_name("body")?;
+    let mut cursor = parameters.walk();
+    let existing: Vec<_> = parameters.named_children(&mut cursor).collect();
+    let taken: HashSet<_> = existing
+        .iter()
+        .filter_map(|p| p.child_by_field_name("name"))
+        .map(|name| content[name.byte_range()].trim_start_matches('$'))
+        .collect();
+    let mut assigned = HashSet::new();
+    assigned_properties(statements, content, &mut assigned);
+    properties.retain(|p| !assigned.contains(p.name));
+    if properties.is_empty() {
+        return None;
+    }
+
+    let mut edits = Vec::new();
+    let new_parameters: Vec<_> = properties
+        .iter()
+        .filter(|p| !taken.contains(p.name))
+        .map(parameter)
+        .collect();
+    // required parameters can't come after optional ones
+    let optional = existing.iter().find(|p| {
+        p.kind() == "variadic_parameter" || p.child_by_field_name("default_value").is_some()
+    });
+    if !new_parameters.is_empty() {
+        let (position, new_text) = match (optional, existing.last()) {
+            (Some(optional), _) => (
+                to_position(&optional.start_position()),
+                format!("{}, ", new_parameters.join(", ")),
+            ),
+            (None, Some(last)) => (
+                to_position(&last.end_position()),
+                format!(", {}", new_parameters.join(", ")),
+            ),
+            (None, None) => (
+                Position::new(
+                    parameters.start_position().row as u32,
+                    parameters.start_position().column as u32 + 1,
+                ),
+                new_parameters.join(", "),
+            ),
+        };
+        edits.push(TextEdit {
+            range: Range::new(position, position),
+            new_text,
+        });
+    }
+
+    let (member, inner) = member_indents(class, constructor, content);
+    let assignments: String = properties
+        .iter()
+        .map(|p| format!("\n{}$this->{} = ${};", inner, p.name, p.name))
+        .collect();
+    // right before the closing brace, which gets its own line back
+    let close = statements.end_byte() - 1;
+    let before = content[..close].trim_end_matches([' ', '\t']);
+    let position = if before.ends_with('\n') {
+        offset_position(statements, content, before.len() - 1)
+    } else {
+        offset_position(statements, content, close)
+    };
+    let new_text = if before.ends_with('\n') {
+        assignments
+    } else {
+        format!("{}\n{}", assignments, member)
+    };
+    edits.push(TextEdit {
+        range: Range::new(position, position),
+        new_text,
+    });
+
+    Some(edits)
+}
+
+/// `user_name` as it appears in `getUserName`.
+fn accessor_suffix(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// The first property of a class body without a getter: the getter's name suffix, the property's
+/// type if it has one, and its name.
+pub fn missing_getter<'a>(
+    body: Node<'a>,
+    content: &'a str,
+) -> Option<(String, Option<&'a str>, &'a str)> {
+    let mut cursor = body.walk();
+    let methods: HashSet<_> = body
+        .named_children(&mut cursor)
+        .filter(|member| member.kind() == "method_declaration")
+        .filter_map(|method| method.child_by_field_name("name"))
+        .map(|name| content[name.byte_range()].to_lowercase())
+        .collect();
+
+    properties(body, content).into_iter().find_map(|property| {
+        let suffix = accessor_suffix(property.name);
+        (!methods.contains(&format!("get{}", suffix).to_lowercase())).then_some((
+            suffix,
+            property.t,
+            property.name,
+        ))
+    })
+}
+
+/// Getters and setters for the properties at `range` that don't have them yet, each as a title and
+/// the edit that inserts it at the end of the class.
+///
+/// Readonly properties only get getters.
+pub fn accessors(node: Node<'_>, content: &str, range: &Range) -> Vec<(String, TextEdit)> {
+    let Some(class) = enclosing_class(node, range) else {
+        return Vec::new();
+    };
+    let Some(body) = class.child_by_field_name("body") else {
+        return Vec::new();
+    };
+
+    let mut cursor = body.walk();
+    let members: Vec<_> = body.named_children(&mut cursor).collect();
+    let Some(last) = members.last() else {
+        return Vec::new();
+    };
+    let methods: HashSet<_> = members
+        .iter()
+        .filter(|member| member.kind() == "method_declaration")
+        .filter_map(|method| method.child_by_field_name("name"))
+        .map(|name| content[name.byte_range()].to_lowercase())
+        .collect();
+    let (member, inner) = member_indents(class, *last, content);
+    let position = to_position(&last.end_position());
+
+    let mut accessors = Vec::new();
+    for property in properties(body, content) {
+        let Some(declaration) = property.declaration.parent() else {
+            continue;
+        };
+        let declared = to_range(&declaration.range());
+        if declared.start > range.end || range.start > declared.end {
+            continue;
+        }
+
+        let suffix = accessor_suffix(property.name);
+        let name = property.name;
+        if !methods.contains(&format!("get{}", suffix).to_lowercase()) {
+            let returns = property.t.map(|t| format!(": {}", t)).unwrap_or_default();
+            accessors.push((
+                format!("Generate getter for ${}", name),
+                format!(
+                    "
+
+{member}public function get{suffix}(){returns}
+{member}{{
+{inner}return $this->{name};
+{member}}}"
+                ),
+            ));
+        }
+
+        let mut cursor = declaration.walk();
+        let readonly = declaration
+            .named_children(&mut cursor)
+            .any(|c| c.kind() == "readonly_modifier");
+        if !readonly && !methods.contains(&format!("set{}", suffix).to_lowercase()) {
+            let parameter = parameter(&property);
+            accessors.push((
+                format!("Generate setter for ${}", name),
+                format!(
+                    "
+
+{member}public function set{suffix}({parameter}): void
+{member}{{
+{inner}$this->{name} = ${name};
+{member}}}"
+                ),
+            ));
+        }
+    }
+
+    accessors
+        .into_iter()
+        .map(|(title, new_text)| {
+            (
+                title,
+                TextEdit {
+                    range: Range::new(position, position),
+                    new_text,
+                },
+            )
+        })
+        .collect()
+}
+
+/// The `@param` line for a parameter, with its declared type, or the type of its default value.
+fn param_tag(parameter: Node<'_>, content: &str) -> Option<String> {
+    let name = &content[parameter.child_by_field_name("name")?.byte_range()];
+    let default = parameter.child_by_field_name("default_value");
+    let t = match parameter.child_by_field_name("type") {
+        Some(t) => {
+            let t = &content[t.byte_range()];
+            let nullable = t.starts_with('?') || t.to_lowercase().contains("null");
+            match default {
+                Some(default)
+                    if !nullable && content[default.byte_range()].eq_ignore_ascii_case("null") =>
+                {
+                    format!("{}|null", t)
+                }
+                _ => t.to_string(),
+            }
+        }
+        None => default
+            .and_then(|default| literal_type(default, content))
+            .unwrap_or_else(|| "mixed".to_string()),
+    };
+    let variadic = if parameter.kind() == "variadic_parameter" {
+        "..."
+    } else {
+        ""
+    };
+
+    Some(format!("@param {} {}{}", t, variadic, name))
+}
+
+/// An edit that documents the function, method or class at `range` with a docblock, if it doesn't
+/// have one yet.
+///
+/// Functions get a `@param` for each parameter and a `@return`, typed as far as we know.
+pub fn phpdoc(node: Node<'_>, content: &str, range: &Range) -> Option<TextEdit> {
+    let mut declaration =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.start))?;
+    while !matches!(
+        declaration.kind(),
+        "function_definition"
+            | "method_declaration"
+            | "class_declaration"
+            | "interface_declaration"
+            | "trait_declaration"
+            | "enum_declaration"
+    ) {
+        // only on the signature, not in the body
+        if matches!(
+            declaration.kind(),
+            "compound_statement" | "declaration_list"
+        ) {
+            return None;
+        }
+        declaration = declaration.parent()?;
+    }
+    if node_markup(declaration, content).is_some() {
+        return None;
+    }
+
+    let mut tags = Vec::new();
+    if let Some(parameters) = declaration.child_by_field_name("parameters") {
+        let mut cursor = parameters.walk();
+        tags.extend(
+            parameters
+                .named_children(&mut cursor)
+                .filter_map(|parameter| param_tag(parameter, content)),
+        );
+    }
+    let name = declaration
+        .child_by_field_name("name")
+        .map(|name| &content[name.byte_range()]);
+    let is_function = matches!(
+        declaration.kind(),
+        "function_definition" | "method_declaration"
+    );
+    let is_constructor = name.is_some_and(|name| {
+        name.eq_ignore_ascii_case("__construct") || name.eq_ignore_ascii_case("__destruct")
+    });
+    if is_function && !is_constructor {
+        let t = match declaration.child_by_field_name("return_type") {
+            Some(t) => content[t.byte_range()].to_string(),
+            None => declaration
+                .child_by_field_name("body")
+                .and_then(|body| inferred_return_type(body, content))
+                .unwrap_or_else(|| "mixed".to_string()),
+        };
+        tags.push(format!("@return {}", t));
+    }
+
+    let indent = indent_of(declaration, content);
+    let mut new_text = "/**\n".to_string();
+    if tags.is_empty() {
+        new_text.push_str(&format!("{} *\n", indent));
+    }
+    for tag in tags {
+        new_text.push_str(&format!("{} * {}\n", indent, tag));
+    }
+    new_text.push_str(&format!("{} */\n{}", indent, indent));
+
+    let position = to_position(&declaration.start_position());
+    Some(TextEdit {
+        range: Range::new(position, position),
+        new_text,
+    })
+}
+
+/// The position of a byte offset inside of `node`.
+fn offset_position(node: Node<'_>, content: &str, offset: usize) -> Position {
+    let start = node.start_position();
+    let skipped = &content[node.start_byte()..offset];
+    let lines = skipped.matches('\n').count();
+    let column = match skipped.rfind('\n') {
+        Some(i) => skipped.len() - i - 1,
+        None => start.column + skipped.len(),
+    };
+
+    Position::new((start.row + lines) as u32, column as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Position, Range, TextEdit};
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    /// Apply edits that don't overlap.
+    fn apply(src: &str, mut edits: Vec<TextEdit>) -> String {
+        let offset = |position: Position| {
+            let line_start: usize = src
+                .split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum();
+            line_start + position.character as usize
+        };
+        edits.sort_by_key(|e| std::cmp::Reverse(e.range.start));
+        let mut out = src.to_string();
+        for edit in edits {
+            out.replace_range(
+                offset(edit.range.start)..offset(edit.range.end),
+                &edit.new_text,
+            );
+        }
+
+        out
+    }
+
+    fn cursor(line: u32) -> Range {
+        Range::new(Position::new(line, 0), Position::new(line, 0))
+    }
+
+    #[test]
+    fn generate_constructor() {
+        let src = "<?php
+class User {
+    private int $id;
+    protected ?string $name;
+    public static $count;
+    public array $tags = [];
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let edits = super::constructor(tree.root_node(), src, &cursor(2)).unwrap();
+        assert_eq!(
+            apply(src, edits),
+            "<?php
+class User {
+    private int $id;
+    protected ?string $name;
+    public static $count;
+    public array $tags = [];
+
+    public function __construct(int $id, ?string $name)
+    {
+        $this->id = $id;
+        $this->name = $name;
+    }
+}
+"
+        );
+
+        // only the selected ones
+        let selection = Range::new(Position::new(3, 0), Position::new(3, 10));
+        let edits = super::constructor(tree.root_node(), src, &selection).unwrap();
+        assert!(apply(src, edits).contains("__construct(?string $name)"));
+
+        assert!(super::constructor(tree.root_node(), src, &cursor(0)).is_none());
+    }
+
+    #[test]
+    fn generate_accessors() {
+        let src = "<?php
+class User {
+    private ?string $user_name;
+    private readonly int $id;
+    private $email;
+
+    public function getEmail() { return $this->email; }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let selection = Range::new(Position::new(2, 0), Position::new(4, 0));
+        let accessors = super::accessors(tree.root_node(), src, &selection);
+        let titles: Vec<_> = accessors.iter().map(|(title, _)| title.as_str()).collect();
+        assert_eq!(
+            titles,
+            [
+                "Generate getter for $user_name",
+                "Generate setter for $user_name",
+                "Generate getter for $id",
+            ]
+        );
+        assert_eq!(
+            apply(src, vec![accessors[1].1.clone()]),
+            "<?php
+class User {
+    private ?string $user_name;
+    private readonly int $id;
+    private $email;
+
+    public function getEmail() { return $this->email; }
+
+    public function setUserName(?string $user_name): void
+    {
+        $this->user_name = $user_name;
+    }
+}
+"
+        );
+        assert!(accessors[2].1.new_text.contains("getId(): int\n"));
+
+        let on_email = Range::new(Position::new(4, 14), Position::new(4, 14));
+        let accessors = super::accessors(tree.root_node(), src, &on_email);
+        let titles: Vec<_> = accessors.iter().map(|(title, _)| title.as_str()).collect();
+        assert_eq!(titles, ["Generate setter for $email"]);
+        assert!(accessors[0].1.new_text.contains("setEmail($email): void"));
+    }
+
+    #[test]
+    fn update_constructor() {
+        let src = "<?php
+class User {
+    private int $id;
+    private string $name;
+    private $email;
+
+    public function __construct(string $name, $admin = false) {
+        $this->email = null;
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let edits = super::constructor(tree.root_node(), src, &cursor(6)).unwrap();
+        assert_eq!(
+            apply(src, edits),
+            "<?php
+class User {
+    private int $id;
+    private string $name;
+    private $email;
+
+    public function __construct(string $name, int $id, $admin = false) {
+        $this->email = null;
+        $this->id = $id;
+        $this->name = $name;
+    }
+}
+"
+        );
+
+        let src = "<?php
+class User {
+    private int $id;
+    public function __construct() {}
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let edits = super::constructor(tree.root_node(), src, &cursor(2)).unwrap();
+        assert_eq!(
+            apply(src, edits),
+            "<?php
+class User {
+    private int $id;
+    public function __construct(int $id) {
+        $this->id = $id;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn generate_phpdoc() {
+        let src = "<?php
+class Mailer {
+    public function send(string $to, ?Message $message, $retries = 3, ...$headers) {
+        return true;
+    }
+
+    /** Documented already. */
+    public function flush(): void {}
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let on = |line, character| {
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            )
+        };
+
+        let edit = super::phpdoc(tree.root_node(), src, &on(2, 22)).unwrap();
+        assert_eq!(
+            apply(src, vec![edit])
+                .lines()
+                .skip(2)
+                .take(8)
+                .collect::<Vec<_>>(),
+            [
+                "    /**",
+                "     * @param string $to",
+                "     * @param ?Message $message",
+                "     * @param int $retries",
+                "     * @param mixed ...$headers",
+                "     * @return bool",
+                "     */",
+                "    public function send(string $to, ?Message $message, $retries = 3, ...$headers) {",
+            ]
+        );
+
+        let edit = super::phpdoc(tree.root_node(), src, &on(1, 8)).unwrap();
+        assert_eq!(edit.new_text, "/**\n *\n */\n");
+        assert_eq!(edit.range.start, Position::new(1, 0));
+
+        // in the body
+        assert!(super::phpdoc(tree.root_node(), src, &on(3, 10)).is_none());
+        assert!(super::phpdoc(tree.root_node(), src, &on(7, 22)).is_none());
+    }
+}
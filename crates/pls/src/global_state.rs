@@ -1,16 +1,38 @@
 use crossbeam_channel::{Receiver, Sender, select};
-use lsp_server::{Connection, Message, Notification, Request};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeWatchedFiles, Notification as _, PublishDiagnostics, ShowMessage,
+};
+use lsp_types::request::RegisterCapability;
 use lsp_types::*;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 
-use pls_types::SegmentPool;
+use pls_types::{
+    Autoload, CustomTypesDatabase, PhpNamespace, PhpVersion, SegmentPool, UriExt, class_file,
+    required_php, resolve_ns,
+};
 
-use crate::config::Config;
+use crate::analyze;
+use crate::classmap::declared_classes;
+use crate::command::Command;
+use crate::compat::diagnostics_to_utf16;
+use crate::config::{Config, InitializeOptions};
+use crate::diagnostics::{suppress, syntax};
+use crate::handlers;
+use crate::index::Index;
+use crate::inheritance;
+use crate::logging;
 use crate::messages::Task;
+use crate::psr4;
 use crate::registry::{NotificationRegistry, RequestRegistry};
-use crate::stubs::FileMapping;
+use crate::rename::php_files;
+use crate::stubs::{FileMapping, StubDoc, StubKind, discover_stubs};
+use crate::usage;
+use crate::vendor::generated_map;
+use crate::version;
 
 #[derive(Debug)]
 pub struct FileInfo {
@@ -51,6 +73,31 @@ impl Parsers {
     }
 }
 
+/// Where Composer says classes are, as globs for the client to watch.
+const COMPOSER_FILES: [&str; 4] = [
+    "**/composer.json",
+    "**/composer.lock",
+    "**/vendor/composer/installed.json",
+    "**/vendor/composer/autoload_*.php",
+];
+
+/// Whether `path` is one of the [`COMPOSER_FILES`].
+pub fn is_composer_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let in_vendor_composer = path
+        .parent()
+        .filter(|dir| dir.ends_with("composer"))
+        .and_then(Path::parent)
+        .is_some_and(|dir| dir.ends_with("vendor"));
+
+    let generated =
+        name == "installed.json" || name.starts_with("autoload_") && name.ends_with(".php");
+
+    matches!(name, "composer.json" | "composer.lock") || (in_vendor_composer && generated)
+}
+
 /// Inspired by `rust-analyzer`
 pub struct GlobalState {
     pub config: Config,
@@ -61,13 +108,35 @@ pub struct GlobalState {
 
     pub fqn_interns: SegmentPool,
     pub stub_mappings: FileMapping,
+    /// Documentation of the built-ins that have been looked at, or `None` if their stub didn't
+    /// have it. Filled on demand, since the stubs span thousands of files.
+    stub_docs: HashMap<String, Option<StubDoc>>,
+    /// Stub files whose declarations have been ingested. A stub declares hundreds of built-ins,
+    /// so it's only parsed for the first of them that's looked up.
+    stub_files: HashSet<PathBuf>,
+    pub ns_to_dir: HashMap<PhpNamespace, Vec<PathBuf>>,
+    /// Where the classes from `classmap` autoloads are declared. Looked at before `ns_to_dir`.
+    pub classmap: HashMap<PhpNamespace, PathBuf>,
+    /// The `files` autoloads, whose functions are there whether or not anything mentions them.
+    autoload_files: Vec<PathBuf>,
+    /// The lowest PHP version the `composer.json`s allow.
+    composer_php_version: Option<PhpVersion>,
+    pub types: CustomTypesDatabase,
 
     pub file_infos: HashMap<PathBuf, FileInfo>,
+    /// Open files to analyze again once the queued tasks are done, because types they may depend
+    /// on turned up. Ingesting a batch of dependencies only has each of them analyzed once.
+    stale_files: HashSet<PathBuf>,
     pub parsers: Parsers,
+
+    /// The ID of the next request we send the client.
+    next_request_id: i32,
+    /// The method of each request we sent the client that hasn't been answered yet, by its ID.
+    pub sent_requests: HashMap<RequestId, String>,
 }
 
 impl GlobalState {
-    pub fn new(stubs_filename: &str, connection: Connection) -> anyhow::Result<Self> {
+    pub fn new(stubs_filename: Option<&Path>, connection: Connection) -> anyhow::Result<Self> {
         let (id, value) = connection.initialize_start()?;
 
         // maintain backwards compatibility; we still favour `workspace_folders` over `root_uri`
@@ -75,8 +144,22 @@ impl GlobalState {
         let InitializeParams {
             root_uri,
             workspace_folders,
+            initialization_options,
+            capabilities,
+            trace,
             ..
         } = serde_json::from_value(value).expect("unable to serialize init params");
+        if let Some(trace) = trace {
+            logging::set_trace(trace);
+        }
+        let options: InitializeOptions = initialization_options
+            .map(|options| {
+                serde_json::from_value(options).unwrap_or_else(|e| {
+                    log::error!("Err in reading initialization options: {e:?}");
+                    InitializeOptions::default()
+                })
+            })
+            .unwrap_or_default();
         connection.initialize_finish(
             id,
             serde_json::json!({
@@ -88,33 +171,272 @@ impl GlobalState {
             }),
         )?;
 
-        let config = Config::new(
+        let mut config = Config::new(
             workspace_folders.unwrap_or(vec![]),
             root_uri,
-            PathBuf::from(stubs_filename),
+            stubs_filename.map(Path::to_path_buf),
         );
+        if let Some(stubs) = options.stubs {
+            config.stubs_filename = Some(match config.workspace_folders.first() {
+                Some(root) if stubs.is_relative() => root.join(stubs),
+                _ => stubs,
+            });
+        }
+        if config.stubs_filename.is_none() {
+            config.stubs_filename = discover_stubs(&config.workspace_folders);
+        }
+        config.diagnostics = options.diagnostics;
+        config.strictness = options.strictness;
+        config.import_style = options.import_style;
+        config.strict_null_comparisons = options.strict_null_comparisons;
+        config.auto_import = options.auto_import;
+        config.autoload_dev = options.autoload_dev.unwrap_or(true);
+        config.php_version = options.php_version.and_then(|v| {
+            let version = PhpVersion::parse(&v);
+            if version.is_none() {
+                log::error!("Err in reading `phpVersion` `{v}`: expected something like `8.1`");
+            }
+            version
+        });
+        let watch_files = capabilities
+            .workspace
+            .as_ref()
+            .and_then(|w| w.did_change_watched_files)
+            .and_then(|d| d.dynamic_registration)
+            .unwrap_or(false);
+        config.snippet_support = capabilities
+            .text_document
+            .and_then(|t| t.completion)
+            .and_then(|c| c.completion_item)
+            .and_then(|c| c.snippet_support)
+            .unwrap_or(false);
+        if config.stubs_filename.is_none() {
+            let message = "no PhpStorm stubs found, so built-in functions and classes won't \
+                           be recognized; run `pls.installStubs` or set the `stubs` initialization option"
+                .to_string();
+            log::warn!("{message}");
+            connection
+                .sender
+                .send(Message::Notification(Notification::new(
+                    ShowMessage::METHOD.to_string(),
+                    ShowMessageParams {
+                        typ: MessageType::WARNING,
+                        message,
+                    },
+                )))?;
+        }
+
+        let mut x = Self::with_config(config, connection);
+        if watch_files {
+            x.watch_composer_files()?;
+        }
+
+        Ok(x)
+    }
+
+    /// Analyze without a client, for `pls check`; nothing is sent through `connection`.
+    pub fn offline(config: Config, connection: Connection) -> Self {
+        if config.stubs_filename.is_none() {
+            log::warn!(
+                "no PhpStorm stubs found, so built-in functions and classes won't be recognized"
+            );
+        }
+
+        Self::with_config(config, connection)
+    }
+
+    fn with_config(config: Config, connection: Connection) -> Self {
         let (worker_send, worker_recv) = crossbeam_channel::unbounded();
-        worker_send
-            .send(Task::AnalyzeStubs)
-            .expect("stubs should be available for analysis");
+        if let Some(stubs_filename) = &config.stubs_filename {
+            log::info!("using php stubs at {stubs_filename:?}");
+            worker_send
+                .send(Task::AnalyzeStubs)
+                .expect("stubs should be available for analysis");
+        }
 
         let fqn_interns = SegmentPool::new();
         let stub_mappings = FileMapping::default();
 
-        let x = Self {
+        let mut x = Self {
             connection,
             config,
             fqn_interns,
             stub_mappings,
+            stub_docs: HashMap::new(),
+            stub_files: HashSet::new(),
+            ns_to_dir: HashMap::new(),
+            classmap: HashMap::new(),
+            autoload_files: Vec::new(),
+            composer_php_version: None,
+            types: CustomTypesDatabase::new(),
 
             worker_send,
             worker_recv,
 
             file_infos: HashMap::new(),
+            stale_files: HashSet::new(),
             parsers: Parsers::new(),
+
+            next_request_id: 0,
+            sent_requests: HashMap::new(),
         };
+        x.read_composer_files();
+        x.load_index();
 
-        Ok(x)
+        x
+    }
+
+    fn read_composer_files(&mut self) {
+        for folder in self.config.workspace_folders.clone() {
+            let composer_file = folder.join("composer.json");
+            if !composer_file.exists() {
+                continue;
+            }
+
+            if let Err(e) = self.read_composer_file(&composer_file) {
+                log::error!("Err in reading composer file `{composer_file:?}`: {e:?}");
+            }
+        }
+        self.injest_autoload_files();
+    }
+
+    fn read_composer_file(&mut self, composer_file: &Path) -> anyhow::Result<()> {
+        let autoload = Autoload::from_file(
+            composer_file,
+            &mut self.fqn_interns,
+            self.config.autoload_dev,
+        )?;
+
+        self.add_autoload(autoload, true);
+        if let Some(version) = required_php(composer_file) {
+            self.composer_php_version = Some(match self.composer_php_version {
+                Some(known) => known.min(version),
+                None => version,
+            });
+        }
+        if let Some(root) = composer_file.parent() {
+            self.read_installed_packages(&root.join("vendor").join("composer"));
+        }
+
+        Ok(())
+    }
+
+    fn add_autoload(&mut self, autoload: Autoload, with_classmap: bool) {
+        for (ns, dirs) in autoload.psr4.into_iter().chain(autoload.psr0) {
+            self.add_autoload_dirs(ns, dirs);
+        }
+        if with_classmap {
+            for path in autoload.classmap {
+                self.read_classmap(&path);
+            }
+        }
+        self.autoload_files.extend(autoload.files);
+    }
+
+    /// Packages and the project itself can both say where a namespace is; it only needs saying
+    /// once.
+    fn add_autoload_dirs(&mut self, ns: PhpNamespace, dirs: Vec<PathBuf>) {
+        let known = self.ns_to_dir.entry(ns).or_default();
+        for dir in dirs {
+            if !known.contains(&dir) {
+                known.push(dir);
+            }
+        }
+    }
+
+    /// Pick up the autoloads of every installed package from what Composer generated, rather
+    /// than reading each of their `composer.json`s.
+    fn read_installed_packages(&mut self, composer_dir: &Path) {
+        let classmap_file = composer_dir.join("autoload_classmap.php");
+        let installed_file = composer_dir.join("installed.json");
+        if installed_file.exists() {
+            match Autoload::from_installed(&installed_file, &mut self.fqn_interns) {
+                Ok(packages) => {
+                    for package in packages {
+                        // the generated classmap already has everything these would turn up
+                        self.add_autoload(package, !classmap_file.exists());
+                    }
+                }
+                Err(e) => log::error!("Err in reading `{installed_file:?}`: {e:?}"),
+            }
+        }
+
+        for (prefix, dirs) in self.read_generated_map(&composer_dir.join("autoload_psr4.php")) {
+            let ns = self.fqn_interns.intern_str(&prefix);
+            self.add_autoload_dirs(ns, dirs);
+        }
+        for (class, files) in self.read_generated_map(&classmap_file) {
+            if let Some(file) = files.into_iter().next() {
+                let fqn = self.fqn_interns.intern_str(&class);
+                self.classmap.insert(fqn, file);
+            }
+        }
+    }
+
+    fn read_generated_map(&mut self, path: &Path) -> Vec<(String, Vec<PathBuf>)> {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return Vec::new();
+        };
+        let Some(tree) = self.parsers.parse(&content, None) else {
+            return Vec::new();
+        };
+
+        generated_map(
+            tree.root_node(),
+            &content,
+            path.parent().unwrap_or(Path::new("")),
+        )
+    }
+
+    /// Ingest the `files` autoloads, since nothing would ever look them up by name.
+    fn injest_autoload_files(&mut self) {
+        for file in self.autoload_files.clone() {
+            let content = match std::fs::read_to_string(&file) {
+                Ok(content) => content,
+                Err(e) => {
+                    log::error!("Err in reading autoloaded file `{file:?}`: {e:?}");
+                    continue;
+                }
+            };
+            let Some(tree) = self.parsers.parse(&content, None) else {
+                continue;
+            };
+            let dependencies = analyze::injest_types(
+                tree.root_node(),
+                &content,
+                &file,
+                &mut self.fqn_interns,
+                &mut self.types,
+            );
+            self.queue_dependencies(dependencies);
+        }
+    }
+
+    /// Note down the classes declared in a `classmap` file, or in every PHP file under a
+    /// `classmap` directory.
+    fn read_classmap(&mut self, path: &Path) {
+        let mut files = Vec::new();
+        if path.is_dir() {
+            php_files(path, &mut files);
+        } else {
+            files.push(path.to_path_buf());
+        }
+
+        for file in files {
+            let content = match std::fs::read_to_string(&file) {
+                Ok(content) => content,
+                Err(e) => {
+                    log::error!("Err in reading classmap file `{file:?}`: {e:?}");
+                    continue;
+                }
+            };
+            let Some(tree) = self.parsers.parse(&content, None) else {
+                continue;
+            };
+            for fqn in declared_classes(tree.root_node(), &content, &mut self.fqn_interns) {
+                self.classmap.insert(fqn, file.clone());
+            }
+        }
     }
 
     pub fn main_loop(&mut self, (notif_reg, req_reg): (&NotificationRegistry, &RequestRegistry)) {
@@ -132,7 +454,7 @@ impl GlobalState {
                         Ok(Message::Notification(not)) => {
                             self.handle_notification(notif_reg, not)
                         }
-                        Ok(Message::Response(resp)) => log::error!("Unexpected response: {resp:?}"),
+                        Ok(Message::Response(resp)) => handlers::handle_response(self, resp),
                         Err(e) => {
                             log::error!("Err in receiving connection message: {e:?}");
                             break;
@@ -141,14 +463,7 @@ impl GlobalState {
                 }
                 recv(&self.worker_recv) -> task => {
                     match task {
-                        Ok(Task::AnalyzeStubs) => {
-                            match FileMapping::from_filename(&self.config.stubs_filename) {
-                                Ok(mapping) => self.stub_mappings = mapping,
-                                Err(e) => log::error!("Err in reading php stubs: {e:?}"),
-                            }
-                        }
-                        Ok(Task::AnalyzeFile(path)) => {
-                        }
+                        Ok(task) => self.run_task(task),
                         Err(e) => log::error!("Err in receiving worker tasks: {e:?}"),
                     }
                 }
@@ -156,16 +471,440 @@ impl GlobalState {
         }
     }
 
+    /// Work through the queued tasks, and whatever they queue in turn, until there are none.
+    pub fn run_pending_tasks(&mut self) {
+        while let Ok(task) = self.worker_recv.try_recv() {
+            self.run_task(task);
+        }
+    }
+
+    fn run_task(&mut self, task: Task) {
+        let _span = tracing::debug_span!("task", ?task).entered();
+        let what = format!("{task:?}");
+        self.recover(&what, |state| state.run_task_unguarded(task));
+
+        if self.worker_recv.is_empty() {
+            self.queue_stale_files();
+        }
+    }
+
+    fn run_task_unguarded(&mut self, task: Task) {
+        match task {
+            Task::AnalyzeStubs => self.analyze_stubs(),
+            Task::UseStubs(path) => {
+                self.config.stubs_filename = Some(path);
+                self.analyze_stubs();
+                self.clear_caches();
+            }
+            Task::AnalyzeFile(path) => {
+                self.stale_files.remove(&path);
+                self.analyze_file(path);
+            }
+            Task::AnalyzeNs(fqn) => self.analyze_ns(fqn),
+        }
+    }
+
+    fn analyze_stubs(&mut self) {
+        let Some(stubs_filename) = &self.config.stubs_filename else {
+            return;
+        };
+        match FileMapping::from_filename(stubs_filename) {
+            Ok(mapping) => self.stub_mappings = mapping,
+            Err(e) => log::error!("Err in reading php stubs: {e:?}"),
+        }
+    }
+
+    /// Ingest the types of an opened file, queue up its dependencies, and publish diagnostics.
+    fn analyze_file(&mut self, path: PathBuf) {
+        let target = self.php_version();
+        let Some(file_info) = self.file_infos.get_mut(&path) else {
+            log::warn!("analyzing file `{path:?}` that isn't open");
+            return;
+        };
+
+        let root_node = file_info.php_ast.root_node();
+        let mut dependencies = analyze::injest_types(
+            root_node,
+            &file_info.content,
+            &path,
+            &mut self.fqn_interns,
+            &mut self.types,
+        );
+        let mut diagnostics = syntax(root_node, &file_info.content);
+        diagnostics.extend(analyze::walk(
+            root_node,
+            &file_info.content,
+            &path,
+            &mut self.fqn_interns,
+        ));
+        diagnostics.extend(analyze::duplicate_declarations(
+            root_node,
+            &file_info.content,
+            &path,
+            &mut self.fqn_interns,
+            &self.types,
+        ));
+        diagnostics.extend(psr4::check(
+            root_node,
+            &file_info.content,
+            &path,
+            &self.ns_to_dir,
+            &mut self.fqn_interns,
+        ));
+        diagnostics.extend(inheritance::check(
+            root_node,
+            &file_info.content,
+            &mut self.fqn_interns,
+            &self.types,
+        ));
+        diagnostics.extend(analyze::loose_comparisons(
+            root_node,
+            &file_info.content,
+            self.config.strict_null_comparisons,
+        ));
+        diagnostics.extend(analyze::error_suppressions(root_node));
+        let (usage_diagnostics, usage_dependencies) = usage::check(
+            root_node,
+            &file_info.content,
+            &mut self.fqn_interns,
+            &self.types,
+            &self.stub_mappings,
+            (&self.ns_to_dir, &self.classmap),
+            self.config.strictness,
+            target,
+        );
+        diagnostics.extend(usage_diagnostics);
+        dependencies.extend(usage_dependencies);
+        if let Some(target) = target {
+            diagnostics.extend(version::check(
+                root_node,
+                &file_info.content,
+                target,
+                &self.types,
+                &mut self.fqn_interns,
+            ));
+        }
+        suppress(root_node, &file_info.content, &mut diagnostics);
+        self.config.diagnostics.apply(&mut diagnostics);
+        file_info.diagnostics = diagnostics;
+
+        let version = file_info.version;
+        let mut diagnostics = file_info.diagnostics.clone();
+        diagnostics_to_utf16(&file_info.content, &mut diagnostics);
+        if let Err(e) = self.publish_diagnostics(&path, version, diagnostics) {
+            log::error!("Err in publishing diagnostics for `{path:?}`: {e:?}");
+        }
+
+        self.queue_dependencies(dependencies);
+    }
+
+    /// The stub file declaring a built-in class, function or constant.
+    pub fn stub_path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.stub_file(self.stub_mappings.find(name)?))
+    }
+
+    /// The stub file declaring a built-in of the given kind.
+    pub fn stub_path_of(&self, kind: StubKind, name: &str) -> Option<PathBuf> {
+        Some(self.stub_file(self.stub_mappings.get(kind, name)?))
+    }
+
+    fn stub_file(&self, stub: &Path) -> PathBuf {
+        // paths in the stubs map are relative to the map itself
+        let stubs_dir = self
+            .config
+            .stubs_filename
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        stubs_dir.join(stub)
+    }
+
+    /// The signature and documentation of a built-in, read from its stub file the first time.
+    pub fn stub_doc(&mut self, name: &str) -> Option<&StubDoc> {
+        if !self.stub_docs.contains_key(name) {
+            let doc = self.read_stub_doc(name);
+            self.stub_docs.insert(name.to_string(), doc);
+        }
+
+        self.stub_docs.get(name)?.as_ref()
+    }
+
+    fn read_stub_doc(&mut self, name: &str) -> Option<StubDoc> {
+        let path = self.stub_path(name)?;
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                log::error!("Err in reading stub `{path:?}`: {e:?}");
+                return None;
+            }
+        };
+        let tree = self.parsers.parse(&content, None)?;
+
+        StubDoc::find(tree.root_node(), &content, name)
+    }
+
+    /// Find the file declaring a type through the PSR-4 autoload, or in the stubs, and ingest it.
+    ///
+    /// Open files are analyzed again once the queue is empty, since their diagnostics may depend on
+    /// the type.
+    fn analyze_ns(&mut self, fqn: String) {
+        let fqn = self.fqn_interns.intern_str(&fqn);
+        if self.types.0.contains_key(&fqn) {
+            return;
+        }
+
+        let mut ns = fqn.clone();
+        let Some(base) = ns.pop() else {
+            return;
+        };
+
+        let stub = self.stub_path(&fqn.0.join("\\"));
+        let file = match self.classmap.get(&fqn) {
+            Some(file) => Ok(Some(file.clone())),
+            None => resolve_ns(&ns, &self.ns_to_dir).map(|dir| class_file(&dir, &base)),
+        };
+        let (path, is_stub) = match (file, stub) {
+            (Ok(Some(file)), _) => (file, false),
+            // whatever the stub declares is in already, `fqn` just isn't one of them
+            (_, Some(stub)) if self.stub_files.contains(&stub) => return,
+            (_, Some(stub)) => {
+                self.stub_files.insert(stub.clone());
+                (stub, true)
+            }
+            (Ok(None), None) => {
+                log::info!("unable to resolve dependency: no file for `{fqn}`");
+                return;
+            }
+            (Err(e), None) => {
+                log::info!("unable to resolve dependency: {e}");
+                return;
+            }
+        };
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                log::error!("Err in reading dependency `{path:?}`: {e:?}");
+                return;
+            }
+        };
+
+        let Some(tree) = self.parsers.parse(&content, None) else {
+            return;
+        };
+        let (root, interns, types) = (tree.root_node(), &mut self.fqn_interns, &mut self.types);
+        let dependencies = if is_stub {
+            analyze::injest_stub_types(root, &content, &path, interns, types)
+        } else {
+            analyze::injest_types(root, &content, &path, interns, types)
+        };
+
+        self.queue_dependencies(dependencies);
+        if !self.types.0.contains_key(&fqn) {
+            return;
+        }
+
+        self.stale_files.extend(self.file_infos.keys().cloned());
+    }
+
+    fn queue_stale_files(&mut self) {
+        for path in self.stale_files.drain() {
+            if !self.file_infos.contains_key(&path) {
+                continue;
+            }
+
+            if let Err(e) = self.worker_send.send(Task::AnalyzeFile(path)) {
+                log::error!("Err in queueing file analysis: {e:?}");
+            }
+        }
+    }
+
+    fn queue_open_files(&self) {
+        for path in self.file_infos.keys() {
+            if let Err(e) = self.worker_send.send(Task::AnalyzeFile(path.clone())) {
+                log::error!("Err in queueing file analysis: {e:?}");
+            }
+        }
+    }
+
+    /// Pick up changes to the autoload in `composer.json`, and analyze the open files again.
+    pub fn reindex_workspace(&mut self) {
+        self.reload_autoload();
+        self.queue_open_files();
+    }
+
+    /// Composer's files changed, maybe through `composer dump-autoload` or an update. Classes can
+    /// be somewhere else now, and whatever we read from the old places may be out of date.
+    pub fn composer_files_changed(&mut self) {
+        self.reload_autoload();
+        self.clear_caches();
+    }
+
+    fn reload_autoload(&mut self) {
+        self.ns_to_dir.clear();
+        self.classmap.clear();
+        self.autoload_files.clear();
+        self.composer_php_version = None;
+        self.read_composer_files();
+    }
+
+    /// The PHP version the project runs on, if we were told or `composer.json` says.
+    pub fn php_version(&self) -> Option<PhpVersion> {
+        self.config.php_version.or(self.composer_php_version)
+    }
+
+    /// Ask the client to tell us when the files saying where classes are change, so that we don't
+    /// need restarting after `composer dump-autoload`.
+    fn watch_composer_files(&mut self) -> anyhow::Result<()> {
+        let watchers = COMPOSER_FILES
+            .iter()
+            .map(|glob| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(glob.to_string()),
+                kind: None,
+            })
+            .collect();
+        self.send_request::<RegisterCapability>(RegistrationParams {
+            registrations: vec![Registration {
+                id: "composer-files".to_string(),
+                method: DidChangeWatchedFiles::METHOD.to_string(),
+                register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                    watchers,
+                })
+                .ok(),
+            }],
+        })
+    }
+
+    /// Forget the types we've ingested, so that they're read again from wherever they're declared
+    /// now.
+    pub fn clear_caches(&mut self) {
+        self.types = CustomTypesDatabase::new();
+        self.stub_docs.clear();
+        self.stub_files.clear();
+        self.injest_autoload_files();
+        self.load_index();
+        self.queue_open_files();
+    }
+
+    /// Start with the types `pls index` saved, from the files that haven't changed since.
+    fn load_index(&mut self) {
+        for folder in &self.config.workspace_folders {
+            if let Some(index) = Index::read(folder) {
+                let files = index.load(&mut self.types);
+                log::info!("loaded the types of {files} files from the index in {folder:?}");
+            }
+        }
+    }
+
+    /// Send the client a request. Its response ends up in [`handlers::handle_response`].
+    pub fn send_request<R: lsp_types::request::Request>(
+        &mut self,
+        params: R::Params,
+    ) -> anyhow::Result<()> {
+        let id = RequestId::from(self.next_request_id);
+        self.next_request_id += 1;
+        self.sent_requests.insert(id.clone(), R::METHOD.to_string());
+        self.connection.sender.send(Message::Request(Request::new(
+            id,
+            R::METHOD.to_string(),
+            params,
+        )))?;
+
+        Ok(())
+    }
+
+    fn queue_dependencies(&self, dependencies: Vec<PhpNamespace>) {
+        for ns in dependencies {
+            if self.types.0.contains_key(&ns) {
+                continue;
+            }
+
+            if let Err(e) = self.worker_send.send(Task::AnalyzeNs(ns.to_string())) {
+                log::error!("Err in queueing dependency: {e:?}");
+            }
+        }
+    }
+
+    fn publish_diagnostics(
+        &self,
+        path: &Path,
+        version: i32,
+        diagnostics: Vec<Diagnostic>,
+    ) -> anyhow::Result<()> {
+        let uri = Uri::from_file_path(path).ok_or(anyhow::anyhow!("pathbuf -> uri conversion"))?;
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                PublishDiagnostics::METHOD.to_string(),
+                PublishDiagnosticsParams {
+                    uri,
+                    version: Some(version),
+                    diagnostics,
+                },
+            )))?;
+
+        Ok(())
+    }
+
     fn handle_request(&mut self, reg: &RequestRegistry, req: Request) {
-        if let Err(e) = reg.exec(self, req) {
-            log::error!("Err in handling executing request: {e:?}");
+        let _span = tracing::debug_span!("request", method = %req.method, id = %req.id).entered();
+        let id = req.id.clone();
+        let what = format!("`{}`", req.method);
+        let finished = self.recover(&what, |state| {
+            if let Err(e) = reg.exec(state, req) {
+                log::error!("Err in handling executing request: {e:?}");
+            }
+        });
+        if !finished {
+            // the client would otherwise be waiting on an answer that isn't coming
+            let response = Response::new_err(
+                id,
+                ErrorCode::InternalError as i32,
+                format!("pls panicked while handling {what}"),
+            );
+            if let Err(e) = self.connection.sender.send(Message::Response(response)) {
+                log::error!("Err in answering a request that panicked: {e:?}");
+            }
         }
     }
 
     fn handle_notification(&mut self, reg: &NotificationRegistry, notif: Notification) {
-        if let Err(e) = reg.exec(self, notif) {
-            log::error!("Err in handling executing notification: {e:?}");
+        let _span = tracing::debug_span!("notification", method = %notif.method).entered();
+        let what = format!("`{}`", notif.method);
+        self.recover(&what, |state| {
+            if let Err(e) = reg.exec(state, notif) {
+                log::error!("Err in handling executing notification: {e:?}");
+            }
+        });
+    }
+
+    /// Run `f`, and if it panics, tell the client and carry on; one bad file or request shouldn't
+    /// take down the server. The panic hook has logged the details already. Returns whether `f`
+    /// finished.
+    fn recover(&mut self, what: &str, f: impl FnOnce(&mut Self)) -> bool {
+        let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(self))) else {
+            return true;
+        };
+
+        let message = format!(
+            "pls ran into a bug handling {what} and carried on; the log has the details: {}",
+            logging::panic_message(payload.as_ref())
+        );
+        let result = self
+            .connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                ShowMessage::METHOD.to_string(),
+                ShowMessageParams {
+                    typ: MessageType::ERROR,
+                    message,
+                },
+            )));
+        if let Err(e) = result {
+            log::error!("Err in telling the client about a panic: {e:?}");
         }
+
+        false
     }
 }
 
@@ -184,14 +923,60 @@ fn supported_capabilities() -> ServerCapabilities {
         )),
         document_symbol_provider: Some(OneOf::Left(true)),
         code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
-            code_action_kinds: Some(vec![CodeActionKind::SOURCE]),
+            code_action_kinds: Some(vec![
+                CodeActionKind::SOURCE,
+                CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::REFACTOR,
+                CodeActionKind::REFACTOR_EXTRACT,
+                CodeActionKind::REFACTOR_INLINE,
+                CodeActionKind::REFACTOR_REWRITE,
+            ]),
             work_done_progress_options: WorkDoneProgressOptions {
                 work_done_progress: Some(false),
             },
             resolve_provider: Some(true),
         })),
+        completion_provider: Some(CompletionOptions {
+            resolve_provider: Some(true),
+            ..CompletionOptions::default()
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: Command::ALL
+                .iter()
+                .map(|command| command.name().to_string())
+                .collect(),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
         hover_provider: Some(HoverProviderCapability::Simple(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
         selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: None,
+            file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                will_rename: Some(FileOperationRegistrationOptions {
+                    filters: vec![
+                        FileOperationFilter {
+                            scheme: Some("file".to_string()),
+                            pattern: FileOperationPattern {
+                                glob: "**/*.php".to_string(),
+                                matches: Some(FileOperationPatternKind::File),
+                                options: None,
+                            },
+                        },
+                        FileOperationFilter {
+                            scheme: Some("file".to_string()),
+                            pattern: FileOperationPattern {
+                                glob: "**".to_string(),
+                                matches: Some(FileOperationPatternKind::Folder),
+                                options: None,
+                            },
+                        },
+                    ],
+                }),
+                ..WorkspaceFileOperationsServerCapabilities::default()
+            }),
+        }),
         ..ServerCapabilities::default()
     }
 }
@@ -3,13 +3,46 @@ pub mod request;
 
 use lsp_server::{Request, Response};
 
+use lsp_types::ApplyWorkspaceEditResponse;
 use lsp_types::notification::*;
+use lsp_types::request::Request as _;
 use lsp_types::request::*;
 
 use crate::global_state::GlobalState;
 use notification::*;
 use request::*;
 
-pub fn handle_response(_: &mut GlobalState, response: Response) {
-    log::warn!("received a response message: {:?}", response);
+/// Responses to the requests we sent, told apart by the method each was sent with. Only the ones
+/// to `workspace/applyEdit` have anything to look at; `client/registerCapability` has no result.
+pub fn handle_response(state: &mut GlobalState, response: Response) {
+    let Some(method) = state.sent_requests.remove(&response.id) else {
+        log::warn!("response to request {} that wasn't sent", response.id);
+        return;
+    };
+    if let Some(error) = response.error {
+        log::error!(
+            "Err in response to `{method}` request {}: {}",
+            response.id,
+            error.message
+        );
+        return;
+    }
+    if method != ApplyWorkspaceEdit::METHOD {
+        return;
+    }
+
+    let result = response
+        .result
+        .and_then(|result| serde_json::from_value::<ApplyWorkspaceEditResponse>(result).ok());
+    if let Some(ApplyWorkspaceEditResponse {
+        applied: false,
+        failure_reason,
+        ..
+    }) = result
+    {
+        log::warn!(
+            "client didn't apply the edit: {}",
+            failure_reason.unwrap_or_default()
+        );
+    }
 }
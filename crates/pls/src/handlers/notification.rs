@@ -1,14 +1,16 @@
 use lsp_server::{Message, Notification};
 use lsp_types::notification::{Notification as _, PublishDiagnostics};
 use lsp_types::{
-    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
-    DidSaveTextDocumentParams, PublishDiagnosticsParams,
+    DidChangeTextDocumentParams, DidChangeWatchedFilesParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, PublishDiagnosticsParams, SetTraceParams,
 };
 use pls_types::UriExt;
 
+use crate::compat::diagnostics_to_utf16;
 use crate::diagnostics::syntax;
 use crate::file::parse;
-use crate::global_state::{FileInfo, GlobalState};
+use crate::global_state::{FileInfo, GlobalState, is_composer_file};
+use crate::logging;
 use crate::messages::Task;
 
 pub fn did_save_text_document(
@@ -28,6 +30,8 @@ pub fn did_save_text_document(
 
     let (php_ast, phpdoc_ast) = parse(&content, (None, None));
     let diagnostics = syntax(php_ast.root_node(), &content);
+    let mut published = diagnostics.clone();
+    diagnostics_to_utf16(&content, &mut published);
     state
         .connection
         .sender
@@ -36,7 +40,7 @@ pub fn did_save_text_document(
             PublishDiagnosticsParams {
                 uri: params.text_document.uri,
                 version: Some(version),
-                diagnostics: diagnostics.clone(),
+                diagnostics: published,
             },
         )))?;
 
@@ -72,6 +76,8 @@ pub fn did_open_text_document(
 
     let (php_ast, phpdoc_ast) = parse(&content, (None, None));
     let diagnostics = syntax(php_ast.root_node(), &content);
+    let mut published = diagnostics.clone();
+    diagnostics_to_utf16(&content, &mut published);
     state
         .connection
         .sender
@@ -80,7 +86,7 @@ pub fn did_open_text_document(
             PublishDiagnosticsParams {
                 uri: params.text_document.uri,
                 version: Some(version),
-                diagnostics: diagnostics.clone(),
+                diagnostics: published,
             },
         )))?;
 
@@ -137,6 +143,8 @@ pub fn did_change_text_document(
         (Some(&file_info.php_ast), Some(&file_info.phpdoc_ast)),
     );
     file_info.diagnostics = syntax(file_info.php_ast.root_node(), &file_info.content);
+    let mut published = file_info.diagnostics.clone();
+    diagnostics_to_utf16(&file_info.content, &mut published);
     state
         .connection
         .sender
@@ -145,7 +153,7 @@ pub fn did_change_text_document(
             PublishDiagnosticsParams {
                 uri: params.text_document.uri,
                 version: Some(params.text_document.version),
-                diagnostics: file_info.diagnostics.clone(),
+                diagnostics: published,
             },
         )))?;
 
@@ -170,3 +178,26 @@ pub fn did_close_text_document(
 
     Ok(())
 }
+
+pub fn did_change_watched_files(
+    state: &mut GlobalState,
+    params: DidChangeWatchedFilesParams,
+) -> anyhow::Result<()> {
+    let changed = params
+        .changes
+        .iter()
+        .filter_map(|change| change.uri.to_file_path())
+        .any(|path| is_composer_file(&path));
+    if changed {
+        log::info!("composer files changed, reading the autoload again");
+        state.composer_files_changed();
+    }
+
+    Ok(())
+}
+
+pub fn set_trace(_state: &mut GlobalState, params: SetTraceParams) -> anyhow::Result<()> {
+    logging::set_trace(params.value);
+
+    Ok(())
+}
@@ -1,11 +1,46 @@
 use crossbeam_channel::SendError;
-use lsp_server::{Connection, Message, RequestId, Response};
+use lsp_server::{Connection, Message, Notification, RequestId, Response};
+use lsp_types::notification::{Notification as _, ShowMessage};
+use lsp_types::request::ApplyWorkspaceEdit;
 use lsp_types::*;
-use pls_types::UriExt as _;
+use pls_types::{CustomType, PhpNamespace, SegmentPool, UriExt as _, class_file, resolve_ns};
 use serde_json::json;
 
-use crate::code_action::{PHPECHO_TITLE, TMPLSTR_TITLE, can_change_to_tmplstr};
-use crate::global_state::GlobalState;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::code_action::{
+    EchoConversion, ORGIMPORTS_TITLE, STRICT_TYPES_TITLE, TMPLSTR_TITLE, batch_fixes,
+    can_change_to_tmplstr, can_convert_echo_tags, quick_fixes, strict_types,
+};
+use crate::command::{Command, STUBS_RELEASE, install_stubs, test_runner};
+use crate::compat::{
+    edit_to_utf16, edits_to_utf16, from_utf16, range_from_utf16, range_to_utf16, symbols_to_utf16,
+};
+use crate::completion::{
+    array_key_completions, class_completions, enum_case_completions, expected_type,
+    interpolation_completions, keyword_completions, namespace_completions, path_completions,
+    rank_by_type, resolve_stub, snippet_completions, stub_completions, stub_name,
+};
+use crate::folding::folding_ranges;
+use crate::generate::{CONSTRUCTOR_TITLE, PHPDOC_TITLE, accessors, constructor, phpdoc};
+use crate::global_state::{GlobalState, Parsers};
+use crate::hover::{
+    builtin_at, class_constant_at, class_member_at, declared_at, declared_kind, static_variable_at,
+    summary, use_clause_at, use_markdown, with_deprecation,
+};
+use crate::imports::{organize_imports, qualify_name, shorten_name};
+use crate::messages::Task;
+use crate::native_types::native_types;
+use crate::refactor::{
+    ANONYMOUS_FUNCTION_TITLE, ARROW_FUNCTION_TITLE, EXTRACT_METHOD_TITLE, EXTRACT_VARIABLE_TITLE,
+    INLINE_VARIABLE_TITLE, MOVE_CLASS_TITLE, MovedClass, SWITCH_TO_MATCH_TITLE, extract_method,
+    extract_variable, inline_variable, move_class, switch_to_match, to_anonymous_function,
+    to_arrow_function,
+};
+use crate::rename::{NamespaceMove, moved_namespace, php_files, update_references};
+use crate::stubs::{StubDoc, StubKind, stubs_install_dir};
+use crate::symbols::document_symbols;
 
 fn send_ok<T: serde::Serialize>(
     connection: &Connection,
@@ -46,6 +81,90 @@ fn send_err<T: serde::Serialize>(
         .map(|_| ())
 }
 
+/// What a file has in it as far as the client knows: what's open in the editor, or else what's on
+/// disk.
+fn client_content(state: &GlobalState, uri: &Uri) -> Option<String> {
+    let path = uri.to_file_path()?;
+    match state.file_infos.get(path.as_ref()) {
+        Some(file_info) => Some(file_info.content.clone()),
+        None => std::fs::read_to_string(&path).ok(),
+    }
+}
+
+/// A refactoring that edits a single file.
+fn refactor(
+    title: &str,
+    kind: CodeActionKind,
+    uri: &Uri,
+    version: i32,
+    edits: Vec<TextEdit>,
+) -> CodeActionOrCommand {
+    let text_document = OptionalVersionedTextDocumentIdentifier {
+        uri: uri.clone(),
+        version: Some(version),
+    };
+
+    CodeAction {
+        title: title.to_string(),
+        kind: Some(kind),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                text_document,
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            }])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    }
+    .into()
+}
+
+/// Moving a class out into a file of its own.
+fn move_class_action(uri: &Uri, version: i32, moved: MovedClass) -> Option<CodeActionOrCommand> {
+    let new_uri = Uri::from_file_path(&moved.path)?;
+    let text_document = OptionalVersionedTextDocumentIdentifier {
+        uri: uri.clone(),
+        version: Some(version),
+    };
+    let new_document = OptionalVersionedTextDocumentIdentifier {
+        uri: new_uri.clone(),
+        version: None,
+    };
+
+    Some(
+        CodeAction {
+            title: MOVE_CLASS_TITLE.to_string(),
+            kind: Some(CodeActionKind::REFACTOR),
+            edit: Some(WorkspaceEdit {
+                document_changes: Some(DocumentChanges::Operations(vec![
+                    DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                        uri: new_uri,
+                        options: Some(CreateFileOptions {
+                            overwrite: Some(false),
+                            ignore_if_exists: Some(false),
+                        }),
+                        annotation_id: None,
+                    })),
+                    DocumentChangeOperation::Edit(TextDocumentEdit {
+                        text_document: new_document,
+                        edits: vec![OneOf::Left(TextEdit {
+                            range: Range::default(),
+                            new_text: moved.content,
+                        })],
+                    }),
+                    DocumentChangeOperation::Edit(TextDocumentEdit {
+                        text_document,
+                        edits: vec![OneOf::Left(moved.removal)],
+                    }),
+                ])),
+                ..WorkspaceEdit::default()
+            }),
+            ..CodeAction::default()
+        }
+        .into(),
+    )
+}
+
 pub fn code_action(
     request_id: RequestId,
     state: &mut GlobalState,
@@ -60,13 +179,25 @@ pub fn code_action(
         .map(|x| x.to_path_buf())
     {
         if let Some(file_info) = state.file_infos.get(&file_name) {
-            if params.range.start == params.range.end {
-                if file_info.content.contains("<?php echo ") {
+            let range = range_from_utf16(&file_info.content, &params.range);
+            actions.extend(quick_fixes(
+                &params.text_document.uri,
+                file_info.version,
+                &params.context.diagnostics,
+            ));
+
+            for conversion in [EchoConversion::Shorten, EchoConversion::Expand] {
+                if can_convert_echo_tags(
+                    file_info.php_ast.root_node(),
+                    &file_info.content,
+                    &range,
+                    conversion,
+                ) {
                     actions.push(
                         CodeAction {
-                            title: PHPECHO_TITLE.to_string(),
+                            title: conversion.title().to_string(),
                             kind: Some(CodeActionKind::SOURCE),
-                            data: Some(json!({"uri": params.text_document.uri})),
+                            data: Some(json!({"uri": params.text_document.uri, "range": range})),
                             ..CodeAction::default()
                         }
                         .into(),
@@ -74,7 +205,161 @@ pub fn code_action(
                 }
             }
 
-            if can_change_to_tmplstr(file_info, &params.range) {
+            if organize_imports(
+                file_info.php_ast.root_node(),
+                &file_info.content,
+                state.config.import_style,
+            )
+            .is_some()
+            {
+                actions.push(
+                    CodeAction {
+                        title: ORGIMPORTS_TITLE.to_string(),
+                        kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+                        data: Some(json!({"uri": params.text_document.uri})),
+                        ..CodeAction::default()
+                    }
+                    .into(),
+                );
+            }
+
+            if strict_types(file_info.php_ast.root_node(), &file_info.content).is_some() {
+                actions.push(
+                    CodeAction {
+                        title: STRICT_TYPES_TITLE.to_string(),
+                        kind: Some(CodeActionKind::SOURCE),
+                        data: Some(json!({"uri": params.text_document.uri})),
+                        ..CodeAction::default()
+                    }
+                    .into(),
+                );
+            }
+
+            let uri = &params.text_document.uri;
+            let (root, content) = (file_info.php_ast.root_node(), &file_info.content);
+            let version = file_info.version;
+            if let Some(edits) = constructor(root, content, &range) {
+                actions.push(refactor(
+                    CONSTRUCTOR_TITLE,
+                    CodeActionKind::REFACTOR,
+                    uri,
+                    version,
+                    edits,
+                ));
+            }
+            for (title, edit) in accessors(root, content, &range) {
+                actions.push(refactor(
+                    &title,
+                    CodeActionKind::REFACTOR,
+                    uri,
+                    version,
+                    vec![edit],
+                ));
+            }
+            for (title, edit) in
+                native_types(root, content, &range, &state.types, &mut state.fqn_interns)
+            {
+                actions.push(refactor(
+                    &title,
+                    CodeActionKind::REFACTOR_REWRITE,
+                    uri,
+                    version,
+                    vec![edit],
+                ));
+            }
+            if let Some(edit) = phpdoc(root, content, &range) {
+                actions.push(refactor(
+                    PHPDOC_TITLE,
+                    CodeActionKind::REFACTOR,
+                    uri,
+                    version,
+                    vec![edit],
+                ));
+            }
+            if let Some(edit) = extract_variable(root, content, &range) {
+                actions.push(refactor(
+                    EXTRACT_VARIABLE_TITLE,
+                    CodeActionKind::REFACTOR_EXTRACT,
+                    uri,
+                    version,
+                    vec![edit],
+                ));
+            }
+            if let Some(edits) = inline_variable(root, content, &range) {
+                actions.push(refactor(
+                    INLINE_VARIABLE_TITLE,
+                    CodeActionKind::REFACTOR_INLINE,
+                    uri,
+                    version,
+                    edits,
+                ));
+            }
+            if let Some(edits) = extract_method(root, content, &range) {
+                actions.push(refactor(
+                    EXTRACT_METHOD_TITLE,
+                    CodeActionKind::REFACTOR_EXTRACT,
+                    uri,
+                    version,
+                    edits,
+                ));
+            }
+            if let Some(edit) = to_arrow_function(root, content, &range) {
+                actions.push(refactor(
+                    ARROW_FUNCTION_TITLE,
+                    CodeActionKind::REFACTOR_REWRITE,
+                    uri,
+                    version,
+                    vec![edit],
+                ));
+            }
+            if let Some(edit) = to_anonymous_function(root, content, &range) {
+                actions.push(refactor(
+                    ANONYMOUS_FUNCTION_TITLE,
+                    CodeActionKind::REFACTOR_REWRITE,
+                    uri,
+                    version,
+                    vec![edit],
+                ));
+            }
+            if let Some(edit) = switch_to_match(root, content, &range) {
+                actions.push(refactor(
+                    SWITCH_TO_MATCH_TITLE,
+                    CodeActionKind::REFACTOR_REWRITE,
+                    uri,
+                    version,
+                    vec![edit],
+                ));
+            }
+            if let Some((title, edits)) = shorten_name(root, content, &range) {
+                actions.push(refactor(
+                    &title,
+                    CodeActionKind::REFACTOR_REWRITE,
+                    uri,
+                    version,
+                    edits,
+                ));
+            }
+            for (title, edits) in qualify_name(root, content, &range) {
+                actions.push(refactor(
+                    &title,
+                    CodeActionKind::REFACTOR_REWRITE,
+                    uri,
+                    version,
+                    edits,
+                ));
+            }
+            if let Some(moved) = move_class(
+                root,
+                content,
+                &file_name,
+                &range,
+                &state.ns_to_dir,
+                &mut state.fqn_interns,
+            ) {
+                actions.extend(move_class_action(uri, version, moved));
+            }
+
+            if can_change_to_tmplstr(file_info, &range) {
                 actions.push(
                     CodeAction {
                         title: TMPLSTR_TITLE.to_string(),
@@ -88,6 +373,15 @@ pub fn code_action(
         }
     }
 
+    for action in actions.iter_mut() {
+        if let CodeActionOrCommand::CodeAction(CodeAction {
+            edit: Some(edit), ..
+        }) = action
+        {
+            edit_to_utf16(edit, |uri| client_content(state, uri));
+        }
+    }
+
     let _ = send_ok(&state.connection, request_id, &actions);
 
     Ok(())
@@ -98,9 +392,10 @@ pub fn code_action_resolve(
     state: &mut GlobalState,
     params: CodeAction,
 ) -> anyhow::Result<()> {
+    let conversion = EchoConversion::from_title(&params.title);
     match (params.title.as_ref(), params.data) {
-        (PHPECHO_TITLE, Some(v)) => {
-            let v: crate::code_action::PhpEchoParams = serde_json::from_value(v)?;
+        (_, Some(v)) if let Some(conversion) = conversion => {
+            let v: crate::code_action::EchoTagParams = serde_json::from_value(v)?;
             let file_name = v
                 .uri
                 .to_file_path()
@@ -110,14 +405,20 @@ pub fn code_action_resolve(
                 .file_infos
                 .get(&file_name)
                 .ok_or(anyhow::anyhow!("file `{file_name:?}` not loaded"))?;
-            let document_changes =
-                crate::code_action::changes_phpecho(&v.uri, &file_info.content, file_info.version);
+            let document_changes = crate::code_action::changes_echo_tags(
+                &v.uri,
+                file_info.php_ast.root_node(),
+                &file_info.content,
+                file_info.version,
+                &v.range,
+                conversion,
+            );
 
-            let _ = send_ok(
-                &state.connection,
+            resolved(
+                state,
                 request_id,
-                &CodeAction {
-                    title: PHPECHO_TITLE.to_string(),
+                CodeAction {
+                    title: conversion.title().to_string(),
                     kind: Some(CodeActionKind::SOURCE),
                     edit: Some(WorkspaceEdit {
                         document_changes,
@@ -127,8 +428,721 @@ pub fn code_action_resolve(
                 },
             );
         }
+        (ORGIMPORTS_TITLE, Some(v)) => {
+            let v: crate::code_action::PhpEchoParams = serde_json::from_value(v)?;
+            let file_name = v
+                .uri
+                .to_file_path()
+                .ok_or(anyhow::anyhow!("cannot convert uri to path"))?
+                .to_path_buf();
+            let file_info = state
+                .file_infos
+                .get(&file_name)
+                .ok_or(anyhow::anyhow!("file `{file_name:?}` not loaded"))?;
+            let edits = organize_imports(
+                file_info.php_ast.root_node(),
+                &file_info.content,
+                state.config.import_style,
+            );
+            let text_document = OptionalVersionedTextDocumentIdentifier {
+                uri: v.uri.clone(),
+                version: Some(file_info.version),
+            };
+
+            resolved(
+                state,
+                request_id,
+                CodeAction {
+                    title: ORGIMPORTS_TITLE.to_string(),
+                    kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+                    edit: Some(WorkspaceEdit {
+                        document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                            text_document,
+                            edits: edits.into_iter().map(OneOf::Left).collect(),
+                        }])),
+                        ..WorkspaceEdit::default()
+                    }),
+                    ..CodeAction::default()
+                },
+            );
+        }
+        (STRICT_TYPES_TITLE, Some(v)) => {
+            let v: crate::code_action::PhpEchoParams = serde_json::from_value(v)?;
+            let file_name = v
+                .uri
+                .to_file_path()
+                .ok_or(anyhow::anyhow!("cannot convert uri to path"))?
+                .to_path_buf();
+            let file_info = state
+                .file_infos
+                .get(&file_name)
+                .ok_or(anyhow::anyhow!("file `{file_name:?}` not loaded"))?;
+            let edits = strict_types(file_info.php_ast.root_node(), &file_info.content);
+            let text_document = OptionalVersionedTextDocumentIdentifier {
+                uri: v.uri.clone(),
+                version: Some(file_info.version),
+            };
+
+            resolved(
+                state,
+                request_id,
+                CodeAction {
+                    title: STRICT_TYPES_TITLE.to_string(),
+                    kind: Some(CodeActionKind::SOURCE),
+                    edit: Some(WorkspaceEdit {
+                        document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                            text_document,
+                            edits: edits.into_iter().map(OneOf::Left).collect(),
+                        }])),
+                        ..WorkspaceEdit::default()
+                    }),
+                    ..CodeAction::default()
+                },
+            );
+        }
         _ => {}
     }
 
     Ok(())
 }
+
+/// Answer a code action being resolved, with the edit's ranges as the client counts them.
+fn resolved(state: &GlobalState, request_id: RequestId, mut action: CodeAction) {
+    if let Some(edit) = &mut action.edit {
+        edit_to_utf16(edit, |uri| client_content(state, uri));
+    }
+
+    let _ = send_ok(&state.connection, request_id, &action);
+}
+
+/// A file's contents, going by the editor for open files, unless `wanted` turns them down.
+fn source(
+    state: &mut GlobalState,
+    path: &Path,
+    wanted: impl Fn(&str) -> bool,
+) -> Option<(String, tree_sitter::Tree)> {
+    if let Some(file_info) = state.file_infos.get(path) {
+        return wanted(&file_info.content)
+            .then(|| (file_info.content.clone(), file_info.php_ast.clone()));
+    }
+    let content = std::fs::read_to_string(path).ok()?;
+    if !wanted(&content) {
+        return None;
+    }
+    let tree = state.parsers.parse(&content, None)?;
+    Some((content, tree))
+}
+
+/// Keep namespace declarations, imports and fully qualified names in step with class files moving
+/// between autoloaded directories.
+///
+/// The client applies the edits before it moves anything, so they go to the files' old paths.
+pub fn will_rename_files(
+    request_id: RequestId,
+    state: &mut GlobalState,
+    params: RenameFilesParams,
+) -> anyhow::Result<()> {
+    // every PHP file that moves, including the ones in moved directories
+    let mut paths: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for rename in params.files {
+        let (Ok(old_uri), Ok(new_uri)) =
+            (rename.old_uri.parse::<Uri>(), rename.new_uri.parse::<Uri>())
+        else {
+            continue;
+        };
+        let (Some(old), Some(new)) = (old_uri.to_file_path(), new_uri.to_file_path()) else {
+            continue;
+        };
+        if old.is_dir() {
+            let mut files = Vec::new();
+            php_files(&old, &mut files);
+            paths.extend(files.into_iter().filter_map(|file| {
+                let moved = new.join(file.strip_prefix(&old).ok()?);
+                Some((file, moved))
+            }));
+        } else {
+            paths.push((old.to_path_buf(), new.to_path_buf()));
+        }
+    }
+
+    let mut moves = HashMap::new();
+    let mut renames = HashMap::new();
+    for (old, new) in paths {
+        let Some((content, tree)) = source(state, &old, |_| true) else {
+            continue;
+        };
+        if let Some(moved) = moved_namespace(
+            tree.root_node(),
+            &content,
+            &old,
+            &new,
+            &state.ns_to_dir,
+            &mut state.fqn_interns,
+        ) {
+            renames.extend(moved.renames());
+            moves.insert(old, moved);
+        }
+    }
+    if renames.is_empty() {
+        send_ok(&state.connection, request_id, &None::<WorkspaceEdit>)?;
+        return Ok(());
+    }
+
+    // going through the whole workspace takes a while, so it's done without holding up everything
+    // else; what the editor has open wins over what's on disk
+    let folders = state.config.workspace_folders.clone();
+    let open: HashMap<_, _> = state
+        .file_infos
+        .iter()
+        .map(|(path, info)| (path.clone(), (info.content.clone(), info.version)))
+        .collect();
+    let sender = state.connection.sender.clone();
+    std::thread::spawn(move || {
+        let changes = reference_changes(&folders, &open, &renames, &moves);
+        let response = Response::new_ok(
+            request_id,
+            Some(WorkspaceEdit {
+                document_changes: Some(DocumentChanges::Edits(changes)),
+                ..WorkspaceEdit::default()
+            }),
+        );
+        if let Err(e) = sender.send(Message::Response(response)) {
+            log::error!("Err in answering file renames: {e:?}");
+        }
+    });
+
+    Ok(())
+}
+
+/// The edits to every PHP file in the workspace that mentions the renamed classes, and to the
+/// moved files themselves. `open` is the content and version of the files open in the editor.
+fn reference_changes(
+    folders: &[PathBuf],
+    open: &HashMap<PathBuf, (String, i32)>,
+    renames: &HashMap<String, String>,
+    moves: &HashMap<PathBuf, NamespaceMove>,
+) -> Vec<TextDocumentEdit> {
+    let mut files = Vec::new();
+    for folder in folders {
+        php_files(folder, &mut files);
+    }
+    files.extend(open.keys().cloned());
+    files.extend(moves.keys().cloned());
+    files.sort();
+    files.dedup();
+
+    // most files never mention the moved classes, so there's no need to parse them
+    let classes: Vec<_> = renames
+        .values()
+        .filter_map(|fqn| fqn.rsplit('\\').next())
+        .map(str::to_lowercase)
+        .collect();
+    let mut parsers = Parsers::new();
+    let mut ns_store = SegmentPool::new();
+    let mut changes = Vec::new();
+    for path in files {
+        let (content, version) = match open.get(&path) {
+            Some((content, version)) => (content.clone(), Some(*version)),
+            None => match std::fs::read_to_string(&path) {
+                Ok(content) => (content, None),
+                Err(_) => continue,
+            },
+        };
+        let moved = moves.get(&path);
+        let lowercase = content.to_lowercase();
+        if moved.is_none() && !classes.iter().any(|class| lowercase.contains(class)) {
+            continue;
+        }
+        let Some(tree) = parsers.parse(&content, None) else {
+            continue;
+        };
+
+        let mut edits =
+            update_references(tree.root_node(), &content, renames, moved, &mut ns_store);
+        edits_to_utf16(&content, edits.iter_mut());
+        if let Some(uri) = Uri::from_file_path(&path)
+            && !edits.is_empty()
+        {
+            changes.push(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Run the tests of the workspace folder `file` is in, or of the first one, without holding up
+/// everything else. How it went is shown to the user when they're done.
+fn run_tests(state: &GlobalState, file: Option<PathBuf>) -> anyhow::Result<()> {
+    let mut process = state
+        .config
+        .workspace_folders
+        .iter()
+        .filter(|folder| file.as_ref().is_none_or(|file| file.starts_with(folder)))
+        .find_map(|folder| test_runner(folder, file.as_deref()))
+        .ok_or(anyhow::anyhow!("no PHPUnit or Pest in the workspace"))?;
+
+    let sender = state.connection.sender.clone();
+    std::thread::spawn(move || {
+        let (typ, message) = match process.output() {
+            Ok(output) => {
+                // runners end with a summary like `OK (3 tests, 5 assertions)`
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let summary = stdout
+                    .lines()
+                    .rev()
+                    .find(|line| !line.trim().is_empty())
+                    .unwrap_or("no output")
+                    .trim()
+                    .to_string();
+                if output.status.success() {
+                    (MessageType::INFO, summary)
+                } else {
+                    (MessageType::ERROR, summary)
+                }
+            }
+            Err(e) => (MessageType::ERROR, format!("couldn't run the tests: {e}")),
+        };
+        let notification = Notification::new(
+            ShowMessage::METHOD.to_string(),
+            ShowMessageParams { typ, message },
+        );
+        if let Err(e) = sender.send(Message::Notification(notification)) {
+            log::error!("Err in reporting test results: {e:?}");
+        }
+    });
+
+    Ok(())
+}
+
+/// Download the stubs to where we look for them without holding up everything else, and switch
+/// to them once they're in.
+fn install_stubs_in_background(state: &GlobalState) -> anyhow::Result<()> {
+    let dir = stubs_install_dir().ok_or(anyhow::anyhow!("nowhere to put the stubs"))?;
+    let sender = state.connection.sender.clone();
+    let worker_send = state.worker_send.clone();
+    std::thread::spawn(move || {
+        let (typ, message) = match install_stubs(&dir) {
+            Ok(stubs_filename) => {
+                let message = format!(
+                    "installed PhpStorm stubs {STUBS_RELEASE} at {}",
+                    stubs_filename.display()
+                );
+                if let Err(e) = worker_send.send(Task::UseStubs(stubs_filename)) {
+                    log::error!("Err in switching to the installed stubs: {e:?}");
+                }
+                (MessageType::INFO, message)
+            }
+            Err(e) => (
+                MessageType::ERROR,
+                format!("couldn't install the stubs: {e}"),
+            ),
+        };
+        let notification = Notification::new(
+            ShowMessage::METHOD.to_string(),
+            ShowMessageParams { typ, message },
+        );
+        if let Err(e) = sender.send(Message::Notification(notification)) {
+            log::error!("Err in reporting the stubs install: {e:?}");
+        }
+    });
+
+    Ok(())
+}
+
+pub fn execute_command(
+    request_id: RequestId,
+    state: &mut GlobalState,
+    params: ExecuteCommandParams,
+) -> anyhow::Result<()> {
+    let file = params
+        .arguments
+        .first()
+        .and_then(|uri| serde_json::from_value::<Uri>(uri.clone()).ok())
+        .and_then(|uri| uri.to_file_path().map(|path| path.to_path_buf()));
+
+    let result = match Command::from_name(&params.command) {
+        None => Err(anyhow::anyhow!("unknown command `{}`", params.command)),
+        Some(Command::ReindexWorkspace) => {
+            state.reindex_workspace();
+            Ok(())
+        }
+        Some(Command::ClearCaches) => {
+            state.clear_caches();
+            Ok(())
+        }
+        Some(Command::ApplyFixes) => {
+            let file_info = file
+                .as_ref()
+                .and_then(|file| state.file_infos.get(file))
+                .ok_or(anyhow::anyhow!("the file to fix isn't open"))?;
+            let only = params.arguments.get(1).and_then(|only| only.as_str());
+            let mut edits = batch_fixes(&file_info.diagnostics, only);
+            edits_to_utf16(&file_info.content, edits.iter_mut());
+            let text_document = OptionalVersionedTextDocumentIdentifier {
+                uri: Uri::from_file_path(&file_info.file_name)
+                    .ok_or(anyhow::anyhow!("pathbuf -> uri conversion"))?,
+                version: Some(file_info.version),
+            };
+
+            if edits.is_empty() {
+                Ok(())
+            } else {
+                state.send_request::<ApplyWorkspaceEdit>(ApplyWorkspaceEditParams {
+                    label: Some("Apply quick fixes".to_string()),
+                    edit: WorkspaceEdit {
+                        document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                            text_document,
+                            edits: edits.into_iter().map(OneOf::Left).collect(),
+                        }])),
+                        ..WorkspaceEdit::default()
+                    },
+                })
+            }
+        }
+        Some(Command::RunTests) => run_tests(state, file),
+        Some(Command::InstallStubs) => install_stubs_in_background(state),
+    };
+
+    match result {
+        Ok(()) => send_ok(&state.connection, request_id, &serde_json::Value::Null)?,
+        Err(e) => send_err::<()>(
+            &state.connection,
+            request_id,
+            lsp_server::ErrorCode::InvalidParams,
+            &e.to_string(),
+        )?,
+    }
+
+    Ok(())
+}
+
+pub fn completion(
+    request_id: RequestId,
+    state: &mut GlobalState,
+    params: CompletionParams,
+) -> anyhow::Result<()> {
+    let position = params.text_document_position;
+    let mut items = Vec::new();
+    if let Some(file_info) = position
+        .text_document
+        .uri
+        .to_file_path()
+        .and_then(|path| state.file_infos.get(path.as_ref()))
+    {
+        let at = from_utf16(&file_info.content, &position.position);
+        if state.config.snippet_support {
+            items.extend(snippet_completions(
+                file_info.php_ast.root_node(),
+                &file_info.content,
+                &at,
+                file_info
+                    .file_name
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()),
+            ));
+        }
+        items.extend(keyword_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+        ));
+        items.extend(class_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+            &state.types,
+            &state.stub_mappings,
+            state.config.auto_import,
+            &mut state.fqn_interns,
+        ));
+        items.extend(stub_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+            &state.stub_mappings,
+        ));
+        items.extend(namespace_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+            &state.types,
+            &state.stub_mappings,
+            &state.ns_to_dir,
+            &mut state.fqn_interns,
+        ));
+        items.extend(array_key_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+        ));
+        items.extend(path_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+            &file_info.file_name,
+            &state.config.workspace_folders,
+        ));
+        items.extend(interpolation_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+            &state.types,
+            &mut state.fqn_interns,
+        ));
+        items.extend(enum_case_completions(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+            &state.types,
+            &mut state.fqn_interns,
+        ));
+
+        if let Some(expected) = expected_type(
+            file_info.php_ast.root_node(),
+            &file_info.content,
+            &at,
+            &state.types,
+            &mut state.fqn_interns,
+        ) {
+            rank_by_type(&mut items, &expected, &state.types, &mut state.fqn_interns);
+        }
+
+        // the edits were made with byte columns
+        let content = &file_info.content;
+        for item in items.iter_mut() {
+            match &mut item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => edits_to_utf16(content, [edit]),
+                Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+                    edit.insert = range_to_utf16(content, &edit.insert);
+                    edit.replace = range_to_utf16(content, &edit.replace);
+                }
+                None => {}
+            }
+            edits_to_utf16(content, item.additional_text_edits.iter_mut().flatten());
+        }
+    }
+
+    send_ok(
+        &state.connection,
+        request_id,
+        &CompletionResponse::Array(items),
+    )?;
+
+    Ok(())
+}
+
+pub fn completion_resolve(
+    request_id: RequestId,
+    state: &mut GlobalState,
+    mut item: CompletionItem,
+) -> anyhow::Result<()> {
+    if let Some(name) = stub_name(&item).map(str::to_string)
+        && let Some(doc) = state.stub_doc(&name)
+    {
+        resolve_stub(&mut item, doc);
+    }
+
+    send_ok(&state.connection, request_id, &item)?;
+
+    Ok(())
+}
+
+/// Describe what a `use` clause imports, to check it against the autoload configuration.
+fn use_hover(state: &mut GlobalState, use_type: Option<&str>, fqn: &PhpNamespace) -> String {
+    let name = fqn.0.join("\\");
+    let declared =
+        state.types.0.get(fqn).filter(|meta| {
+            matches!(meta.t, CustomType::Function(_)) == (use_type == Some("function"))
+        });
+    let path = match use_type {
+        Some("const") => None,
+        Some(_) => declared.map(|meta| meta.src_path.clone()),
+        None => declared.map(|meta| meta.src_path.clone()).or_else(|| {
+            if let Some(file) = state.classmap.get(fqn) {
+                return Some(file.clone());
+            }
+            let mut ns = fqn.clone();
+            let base = ns.pop()?;
+            let dir = resolve_ns(&ns, &state.ns_to_dir).ok()?;
+            class_file(&dir, &base)
+        }),
+    }
+    .or_else(|| {
+        let kind = match use_type {
+            Some("function") => StubKind::Function,
+            Some("const") => StubKind::Constant,
+            _ => StubKind::Class,
+        };
+        state.stub_path_of(kind, &name)
+    });
+    let Some(path) = path else {
+        return use_markdown(&name, None, None, None);
+    };
+
+    let doc = source(state, &path, |_| true)
+        .and_then(|(content, tree)| StubDoc::find(tree.root_node(), &content, &name));
+    let kind = doc
+        .as_ref()
+        .and_then(|doc| declared_kind(&doc.signature))
+        .or(use_type);
+    let summary = doc
+        .as_ref()
+        .and_then(|doc| doc.markdown.as_deref())
+        .and_then(summary);
+    let stubs_dir = state
+        .config
+        .stubs_filename
+        .as_deref()
+        .and_then(Path::parent);
+    let file = state
+        .config
+        .workspace_folders
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(stubs_dir)
+        .find_map(|folder| path.strip_prefix(folder).ok())
+        .unwrap_or(&path);
+
+    use_markdown(
+        &name,
+        kind,
+        Some(&file.to_string_lossy()),
+        summary.as_deref(),
+    )
+}
+
+/// The declaration of a class constant, enum case, method or property, from the file declaring its
+/// class or the stubs.
+fn member_hover(state: &mut GlobalState, class: &PhpNamespace, member: &str) -> Option<String> {
+    let name = class.0.join("\\");
+    let path = match state.types.0.get(class) {
+        Some(meta) => meta.src_path.clone(),
+        None => state.stub_path_of(StubKind::Class, &name)?,
+    };
+    let (content, tree) = source(state, &path, |_| true)?;
+    let doc = StubDoc::find_member(tree.root_node(), &content, &name, member)?;
+    let deprecated = state
+        .types
+        .effective_members(class)
+        .ok()
+        .and_then(|members| match member.strip_suffix("()") {
+            Some(method) => members.method(method)?.member.deprecated.clone(),
+            None => members.constants.get(member)?.member.deprecated.clone(),
+        });
+
+    Some(with_deprecation(doc.to_markdown(), deprecated.as_deref()))
+}
+
+/// The documentation of a function or class-like from the file declaring it.
+fn declared_hover(state: &mut GlobalState, fqn: &PhpNamespace) -> Option<String> {
+    let meta = state.types.0.get(fqn)?;
+    let (path, deprecated) = (meta.src_path.clone(), meta.deprecated.clone());
+    let (content, tree) = source(state, &path, |_| true)?;
+    let doc = StubDoc::find(tree.root_node(), &content, &fqn.0.join("\\"))?;
+
+    Some(with_deprecation(doc.to_markdown(), deprecated.as_deref()))
+}
+
+pub fn hover(
+    request_id: RequestId,
+    state: &mut GlobalState,
+    params: HoverParams,
+) -> anyhow::Result<()> {
+    let position = params.text_document_position_params;
+    let (use_clause, member, declared, builtin, static_variable) = match position
+        .text_document
+        .uri
+        .to_file_path()
+        .and_then(|path| state.file_infos.get(path.as_ref()))
+    {
+        Some(file_info) => {
+            let root = file_info.php_ast.root_node();
+            let content = &file_info.content;
+            let at = from_utf16(content, &position.position);
+            let use_clause = use_clause_at(root, content, &at, &mut state.fqn_interns);
+            let member =
+                class_constant_at(root, content, &at, &state.types, &mut state.fqn_interns)
+                    .or_else(|| {
+                        class_member_at(root, content, &at, &state.types, &mut state.fqn_interns)
+                    });
+            let declared = declared_at(root, content, &at, &state.types, &mut state.fqn_interns);
+            let builtin = builtin_at(
+                root,
+                content,
+                &at,
+                &state.stub_mappings,
+                &mut state.fqn_interns,
+            );
+            let static_variable = static_variable_at(root, content, &at);
+            (use_clause, member, declared, builtin, static_variable)
+        }
+        None => (None, None, None, None, None),
+    };
+
+    let markdown = if let Some((use_type, fqn)) = use_clause {
+        Some(use_hover(state, use_type, &fqn))
+    } else if let Some(declaration) = static_variable {
+        Some(format!(
+            "```php\n<?php\n{declaration}\n```\n\nStatic variable, kept between calls."
+        ))
+    } else if let Some((class, member)) = member {
+        member_hover(state, &class, &member)
+    } else if let Some(markdown) = declared.and_then(|fqn| declared_hover(state, &fqn)) {
+        Some(markdown)
+    } else {
+        builtin.and_then(|name| state.stub_doc(&name).map(StubDoc::to_markdown))
+    };
+    let hover = markdown.map(|value| Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    });
+
+    send_ok(&state.connection, request_id, &hover)?;
+
+    Ok(())
+}
+
+pub fn folding_range(
+    request_id: RequestId,
+    state: &mut GlobalState,
+    params: FoldingRangeParams,
+) -> anyhow::Result<()> {
+    let ranges = params
+        .text_document
+        .uri
+        .to_file_path()
+        .and_then(|path| state.file_infos.get(path.as_ref()))
+        .map(|file_info| folding_ranges(file_info.php_ast.root_node()));
+
+    send_ok(&state.connection, request_id, &ranges)?;
+
+    Ok(())
+}
+
+pub fn document_symbol(
+    request_id: RequestId,
+    state: &mut GlobalState,
+    params: DocumentSymbolParams,
+) -> anyhow::Result<()> {
+    let symbols = params
+        .text_document
+        .uri
+        .to_file_path()
+        .and_then(|path| state.file_infos.get(path.as_ref()))
+        .map(|file_info| {
+            let content = &file_info.content;
+            let mut symbols = document_symbols(file_info.php_ast.root_node(), content);
+            symbols_to_utf16(content, &mut symbols);
+            DocumentSymbolResponse::Nested(symbols)
+        });
+
+    send_ok(&state.connection, request_id, &symbols)?;
+
+    Ok(())
+}
@@ -0,0 +1,1041 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::rc::Rc;
+
+use pls_types::{CustomType, CustomTypesDatabase, PhpNamespace, SegmentPool};
+
+use crate::analyze::{file_scope, resolve_class_name, use_clause_fqn};
+use crate::compat::to_point;
+use crate::imports::is_class_reference;
+use crate::scope::Scope;
+use crate::stubs::{FileMapping, StubKind};
+
+/// A docblock as markdown: the description, a table of the parameters and return value, then one
+/// line per other tag with its types and variables set in code.
+pub fn docblock_markdown(docblock: &str) -> String {
+    let lines = docblock
+        .trim_start_matches("/**")
+        .trim_end_matches("*/")
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            // keep the indentation past the asterisk for code blocks
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        });
+
+    let mut description = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+    for line in lines {
+        if line.trim_start().starts_with('@') {
+            tags.push(line.trim_start().to_string());
+        } else if let Some(tag) = tags.last_mut() {
+            if !line.is_empty() {
+                tag.push('\n');
+                tag.push_str(line);
+            }
+        } else {
+            description.push(line);
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut others = Vec::new();
+    for tag in &tags {
+        match table_row(tag) {
+            Some(row) => rows.push(row),
+            None => others.push(tag_markdown(tag)),
+        }
+    }
+
+    let mut sections = Vec::new();
+    let description = inline_markdown(&description.join("\n"));
+    if !description.trim().is_empty() {
+        sections.push(description.trim().to_string());
+    }
+    if !rows.is_empty() {
+        rows.insert(
+            0,
+            "| Parameter | Type | Description |\n| --- | --- | --- |".to_string(),
+        );
+        sections.push(rows.join("\n"));
+    }
+    if !others.is_empty() {
+        sections.push(others.join("  \n"));
+    }
+    sections.join("\n\n")
+}
+
+/// `@param int $mode What to pass` as `| `$mode` | `int` | What to pass |`, and `@return` the
+/// same way.
+fn table_row(tag: &str) -> Option<String> {
+    let (name, rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    let (first, rest) = first_word(rest);
+    let (label, t, description) = match name {
+        "@param" => {
+            // the type is optional, but the variable isn't
+            if first.starts_with('$') || first.starts_with("...$") || first.starts_with("&$") {
+                (first.to_string(), None, rest)
+            } else {
+                let (variable, rest) = first_word(rest);
+                if variable.is_empty() {
+                    return None;
+                }
+                (variable.to_string(), Some(first), rest)
+            }
+        }
+        "@return" => ("_returns_".to_string(), Some(first), rest),
+        _ => return None,
+    };
+    let t = t.filter(|t| !t.is_empty());
+
+    let cell = |text: &str| {
+        let text = inline_markdown(text);
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace('|', "\\|")
+    };
+    let label = match label.starts_with('_') {
+        true => label,
+        false => format!("`{label}`"),
+    };
+    let t = t
+        .map(|t| format!("`{}`", t.replace('|', "\\|")))
+        .unwrap_or_default();
+    Some(format!("| {label} | {t} | {} |", cell(description)))
+}
+
+/// The first word of a tag, keeping types like `array<int, string>` whole, and the rest.
+fn first_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' | '(' | '{' | '[' => depth += 1,
+            '>' | ')' | '}' | ']' => depth -= 1,
+            c if c.is_whitespace() && depth <= 0 => return (&text[..i], text[i..].trim_start()),
+            _ => {}
+        }
+    }
+
+    (text, "")
+}
+
+/// `@throws Exception When it fails` as `_@throws_ `Exception` — When it fails`.
+fn tag_markdown(tag: &str) -> String {
+    let mut words = tag.splitn(2, char::is_whitespace);
+    let name = words.next().unwrap_or(tag);
+    let rest = words.next().unwrap_or("").trim();
+
+    let code_words = match name {
+        "@property" | "@property-read" | "@property-write" => 2,
+        "@throws" | "@var" | "@see" | "@link" => 1,
+        _ => 0,
+    };
+    let mut words = rest.splitn(code_words + 1, char::is_whitespace);
+    let code: Vec<_> = words
+        .by_ref()
+        .take(code_words)
+        .filter(|word| !word.is_empty())
+        .collect();
+    let description = inline_markdown(words.next().unwrap_or(""));
+    let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let mut markdown = format!("_{name}_");
+    match code.as_slice() {
+        [] => {}
+        [url] if is_url(url) => markdown.push_str(&format!(" <{url}>")),
+        code => markdown.push_str(&format!(" `{}`", code.join(" "))),
+    }
+    if !description.is_empty() {
+        markdown.push_str(if code.is_empty() { " " } else { " — " });
+        markdown.push_str(&description);
+    }
+    markdown
+}
+
+fn is_url(text: &str) -> bool {
+    text.starts_with("https://") || text.starts_with("http://")
+}
+
+/// Phpdoc inline tags like `{@see strlen()}` and the HTML the stubs are written in, as markdown.
+fn inline_markdown(text: &str) -> String {
+    let lowercase = text.to_ascii_lowercase();
+    let mut markdown = String::new();
+    let mut href = None;
+    let mut i = 0;
+    while let Some(offset) = text[i..].find(['<', '{']) {
+        markdown.push_str(&plain_markdown(&text[i..i + offset]));
+        i += offset;
+        let rest = &text[i..];
+
+        if let Some(tag) = rest.strip_prefix("{@")
+            && let Some(end) = tag.find('}')
+        {
+            markdown.push_str(&inline_tag(&tag[..end]));
+            i += end + 3;
+            continue;
+        }
+
+        let element = rest
+            .strip_prefix('<')
+            .and_then(|rest| Some(&rest[..rest.find('>')?]));
+        let Some(element) = element else {
+            markdown.push_str(&rest[..1]);
+            i += 1;
+            continue;
+        };
+        let closing = element.starts_with('/');
+        let name = element
+            .trim_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let after = i + element.len() + 2;
+
+        let replacement = match name.as_str() {
+            "code" | "pre" if !closing => {
+                let close = format!("</{name}>");
+                let end = lowercase[after..]
+                    .find(&close)
+                    .map_or(text.len(), |end| after + end);
+                let code = decode_entities(&text[after..end]);
+                // `<pre><code>` is a single block
+                let code = code
+                    .trim_start_matches("<code>")
+                    .trim_end_matches("</code>")
+                    .trim_matches('\n');
+                i = (end + close.len()).min(text.len());
+                markdown.push_str(&match name == "pre" || code.contains('\n') {
+                    true => format!("\n\n```php\n{code}\n```\n\n"),
+                    false => format!("`{code}`"),
+                });
+                continue;
+            }
+            "b" | "strong" => "**",
+            "i" | "em" => "_",
+            "br" => "  \n",
+            "p" => "\n\n",
+            "ul" | "ol" => "\n",
+            "li" if !closing => "\n- ",
+            "li" => "",
+            "a" if !closing => {
+                href = element
+                    .split_once("href=")
+                    .map(|(_, href)| href.trim_start_matches(['"', '\'']))
+                    .and_then(|href| href.split(['"', '\'']).next())
+                    .map(str::to_string);
+                "["
+            }
+            "a" => {
+                let link = format!("]({})", href.take().unwrap_or_default());
+                markdown.push_str(&link);
+                i = after;
+                continue;
+            }
+            // not HTML, like the `<int>` of `array<int>`
+            _ => {
+                markdown.push('<');
+                i += 1;
+                continue;
+            }
+        };
+        markdown.push_str(replacement);
+        i = after;
+    }
+    markdown.push_str(&plain_markdown(&text[i..]));
+
+    // the HTML puts paragraph breaks wherever, so tidy them
+    let mut tidy = String::new();
+    for line in markdown.split('\n') {
+        if line.trim().is_empty() && tidy.ends_with("\n\n") {
+            continue;
+        }
+        tidy.push_str(line);
+        tidy.push('\n');
+    }
+    tidy.trim_end().to_string()
+}
+
+/// Text between the HTML: entities decoded, and without the indentation that markdown would take
+/// for a code block.
+fn plain_markdown(text: &str) -> String {
+    let text = decode_entities(text);
+    let mut lines = text.split('\n');
+    let mut markdown = lines.next().unwrap_or("").to_string();
+    for line in lines {
+        markdown.push('\n');
+        markdown.push_str(line.trim_start());
+    }
+    markdown
+}
+
+/// `{@see strlen()}` as code and `{@link https://php.net php.net}` as a link.
+fn inline_tag(tag: &str) -> String {
+    let mut words = tag.trim().splitn(3, char::is_whitespace);
+    let name = words.next().unwrap_or("");
+    let target = words.next().unwrap_or("");
+    let label = words
+        .next()
+        .map(str::trim)
+        .filter(|label| !label.is_empty());
+
+    match (name, target, label) {
+        ("see" | "link", target, label) if is_url(target) => {
+            format!("[{}]({target})", label.unwrap_or(target))
+        }
+        ("see" | "link", "", _) => String::new(),
+        ("see" | "link", target, Some(label)) => format!("{label} (`{target}`)"),
+        ("see" | "link", target, None) => format!("`{target}`"),
+        // `{@inheritDoc}` and the like have nothing to show
+        _ => String::new(),
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// The name at the cursor, including its namespace if it's qualified.
+pub fn name_at<'a>(node: Node<'a>, position: &Position) -> Option<Node<'a>> {
+    let point = to_point(position);
+    let mut name = node.descendant_for_point_range(point, point)?;
+    if name.kind() != "name" && point.column > 0 {
+        let before = tree_sitter::Point {
+            column: point.column - 1,
+            ..point
+        };
+        name = node.descendant_for_point_range(before, before)?;
+    }
+    if name.kind() != "name" {
+        return None;
+    }
+    while let Some(parent) = name
+        .parent()
+        .filter(|p| matches!(p.kind(), "namespace_name" | "qualified_name"))
+    {
+        name = parent;
+    }
+
+    Some(name)
+}
+
+/// The stubs name of the built-in function, class or constant at the cursor.
+pub fn builtin_at(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    stubs: &FileMapping,
+    ns_store: &mut SegmentPool,
+) -> Option<String> {
+    let name = name_at(node, position)?;
+    let parent = name.parent()?;
+    let text = &content[name.byte_range()];
+    let scope = file_scope(node, content, ns_store);
+
+    let (kind, candidates) = if parent.kind() == "function_call_expression"
+        && parent.child_by_field_name("function") == Some(name)
+    {
+        (
+            StubKind::Function,
+            callable_candidates(text, &scope.ns, ns_store),
+        )
+    } else if is_class_reference(name) {
+        let fqn = resolve_class_name(text, &scope, ns_store);
+        (StubKind::Class, vec![fqn])
+    } else if matches!(
+        parent.kind(),
+        "expression_statement"
+            | "echo_statement"
+            | "return_statement"
+            | "argument"
+            | "array_element_initializer"
+    ) || (parent.kind().ends_with("_expression")
+        && parent.child_by_field_name("name") != Some(name))
+    {
+        (
+            StubKind::Constant,
+            callable_candidates(text, &scope.ns, ns_store),
+        )
+    } else {
+        return None;
+    };
+
+    candidates
+        .into_iter()
+        .map(|fqn| fqn.0.join("\\"))
+        .find(|name| stubs.get(kind, name).is_some())
+}
+
+/// The function or class-like declared in the workspace (or ingested from the stubs) that the
+/// name at the cursor refers to.
+pub fn declared_at(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Option<PhpNamespace> {
+    let name = name_at(node, position)?;
+    let parent = name.parent()?;
+    let text = &content[name.byte_range()];
+    let scope = file_scope(node, content, ns_store);
+
+    if parent.kind() == "function_call_expression"
+        && parent.child_by_field_name("function") == Some(name)
+    {
+        callable_candidates(text, &scope.ns, ns_store)
+            .iter()
+            .find_map(|fqn| types.function(fqn))
+            .map(|(fqn, _)| fqn.clone())
+    } else if is_class_reference(name) {
+        let fqn = resolve_class_name(text, &scope, ns_store);
+        types
+            .0
+            .get(&fqn)
+            .is_some_and(|meta| !matches!(meta.t, CustomType::Function(_)))
+            .then_some(fqn)
+    } else {
+        None
+    }
+}
+
+/// Put a deprecation notice in front of a hover, unless its docblock already says so.
+pub fn with_deprecation(markdown: String, reason: Option<&str>) -> String {
+    match reason {
+        Some(_) if markdown.contains("_@deprecated_") => markdown,
+        Some("") => format!("**Deprecated**\n\n{markdown}"),
+        Some(reason) => format!("**Deprecated:** {reason}\n\n{markdown}"),
+        None => markdown,
+    }
+}
+
+/// Functions and constants fall back to the global namespace when they aren't qualified.
+fn callable_candidates(
+    name: &str,
+    namespace: &Option<PhpNamespace>,
+    ns_store: &mut SegmentPool,
+) -> Vec<PhpNamespace> {
+    let global = ns_store.intern_str(name);
+    if name.starts_with('\\') || name.contains('\\') {
+        return vec![global];
+    }
+
+    let mut local = namespace.clone().unwrap_or_else(PhpNamespace::empty);
+    local.extend(global.0.iter().cloned());
+    vec![local, global]
+}
+
+/// The class constant or enum case at the cursor, like `Status::Active`: the class-like that
+/// declares it and its name.
+pub fn class_constant_at(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Option<(PhpNamespace, String)> {
+    let text = |node: Node<'_>| &content[node.byte_range()];
+    let name = name_at(node, position)?;
+    let access = name
+        .parent()
+        .filter(|p| p.kind() == "class_constant_access_expression")
+        .filter(|p| p.named_child(1) == Some(name))?;
+    let scope = file_scope(node, content, ns_store);
+    let class = scope_class(access.named_child(0)?, content, &scope, types, ns_store)?;
+
+    let member = text(name).to_string();
+    let declared_in = match types.0.get(&class).map(|meta| &meta.t) {
+        Some(CustomType::Enumeration(e)) if e.values.contains(&member) => class,
+        Some(_) => types
+            .effective_members(&class)
+            .ok()?
+            .constants
+            .get(&member)?
+            .declared_in
+            .clone(),
+        // maybe one of the stubs, which we don't know the members of
+        None => class,
+    };
+
+    Some((declared_in, member))
+}
+
+/// The method or property at the cursor, like `$this->run()` or `Base::$count`: the class-like
+/// that declares it and its name, as `run()` or `$count`.
+///
+/// Only accesses through `$this` or a class name are followed, since those are the ones we know the
+/// class of.
+pub fn class_member_at(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Option<(PhpNamespace, String)> {
+    let text = |node: Node<'_>| &content[node.byte_range()];
+    let name = name_at(node, position)?;
+    // static properties are named with their `$`
+    let member = name
+        .parent()
+        .filter(|p| p.kind() == "variable_name")
+        .unwrap_or(name);
+    let access = member
+        .parent()
+        .filter(|p| p.child_by_field_name("name") == Some(member))?;
+    let scope = file_scope(node, content, ns_store);
+
+    let class = match access.kind() {
+        "member_access_expression"
+        | "nullsafe_member_access_expression"
+        | "member_call_expression"
+        | "nullsafe_member_call_expression" => {
+            let object = access.child_by_field_name("object")?;
+            if text(object) != "$this" {
+                return None;
+            }
+            enclosing_class(access, content, &scope)?
+        }
+        "scoped_call_expression" | "scoped_property_access_expression" => scope_class(
+            access.child_by_field_name("scope")?,
+            content,
+            &scope,
+            types,
+            ns_store,
+        )?,
+        _ => return None,
+    };
+
+    let members = types.effective_members(&class).ok()?;
+    if access.kind().ends_with("call_expression") {
+        let method = members.method(text(name))?;
+        Some((
+            method.declared_in.clone(),
+            format!("{}()", method.member.name),
+        ))
+    } else {
+        let property = format!("${}", text(name));
+        let declared_in = members.properties.get(&property)?.declared_in.clone();
+        Some((declared_in, property))
+    }
+}
+
+/// The class a scope like `Status`, `self` or `parent` refers to.
+fn scope_class(
+    class: Node<'_>,
+    content: &str,
+    scope: &Scope,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Option<PhpNamespace> {
+    let name = &content[class.byte_range()];
+    match name.to_lowercase().as_str() {
+        "self" | "static" => enclosing_class(class, content, scope),
+        "parent" => {
+            let fqn = enclosing_class(class, content, scope)?;
+            match &types.0.get(&fqn)?.t {
+                CustomType::Class(c) => c.parent_classes.first().cloned(),
+                _ => None,
+            }
+        }
+        _ => Some(resolve_class_name(name, scope, ns_store)),
+    }
+}
+
+/// The FQN of the class-like declaration `node` is in.
+pub fn enclosing_class(node: Node<'_>, content: &str, scope: &Scope) -> Option<PhpNamespace> {
+    let mut declaration = node.parent();
+    while let Some(node) = declaration {
+        if matches!(
+            node.kind(),
+            "class_declaration"
+                | "interface_declaration"
+                | "trait_declaration"
+                | "enum_declaration"
+        ) {
+            break;
+        }
+        declaration = node.parent();
+    }
+    let name = declaration?.child_by_field_name("name")?;
+    let mut fqn = scope.ns.clone().unwrap_or_else(PhpNamespace::empty);
+    fqn.push(Rc::from(&content[name.byte_range()]));
+
+    Some(fqn)
+}
+
+/// The `static` declaration of the variable at the cursor, like `static $count = 0`, if it's one
+/// of the function's static variables.
+pub fn static_variable_at(node: Node<'_>, content: &str, position: &Position) -> Option<String> {
+    let text = |node: Node<'_>| &content[node.byte_range()];
+    let variable = name_at(node, position)?
+        .parent()
+        .filter(|v| v.kind() == "variable_name")?;
+    let mut function = variable.parent();
+    while let Some(node) = function {
+        if matches!(
+            node.kind(),
+            "function_definition" | "method_declaration" | "anonymous_function"
+        ) {
+            break;
+        }
+        function = node.parent();
+    }
+
+    let mut stack = vec![function?.child_by_field_name("body")?];
+    while let Some(node) = stack.pop() {
+        match node.kind() {
+            "static_variable_declaration"
+                if node.child_by_field_name("name").map(text) == Some(text(variable)) =>
+            {
+                return Some(format!("static {}", text(node)));
+            }
+            // their static variables are their own
+            "function_definition" | "anonymous_function" | "arrow_function" => {}
+            _ => {
+                let mut cursor = node.walk();
+                stack.extend(node.named_children(&mut cursor));
+            }
+        }
+    }
+
+    None
+}
+
+/// The `use` clause at the cursor: whether it's a `use function` or a `use const`, and the FQN it
+/// imports.
+pub fn use_clause_at(
+    node: Node<'_>,
+    content: &str,
+    position: &Position,
+    ns_store: &mut SegmentPool,
+) -> Option<(Option<&'static str>, PhpNamespace)> {
+    let mut clause = name_at(node, position)?;
+    while clause.kind() != "namespace_use_clause" {
+        clause = clause.parent()?;
+    }
+
+    use_clause_fqn(clause, content, ns_store)
+}
+
+/// What a signature declares, like `class` or `function`.
+pub fn declared_kind(signature: &str) -> Option<&str> {
+    signature.split_whitespace().find(|word| {
+        matches!(
+            *word,
+            "class" | "interface" | "trait" | "enum" | "function" | "const"
+        )
+    })
+}
+
+/// The first paragraph of a docblock rendered by [`docblock_markdown()`], on one line.
+pub fn summary(markdown: &str) -> Option<String> {
+    let summary = markdown
+        .lines()
+        .take_while(|line| !line.trim().is_empty() && !line.starts_with("_@"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (!summary.is_empty()).then_some(summary)
+}
+
+/// What a `use` clause resolves to: the kind of symbol, the file declaring it and a summary of
+/// its documentation, or a note that it couldn't be found.
+pub fn use_markdown(
+    fqn: &str,
+    kind: Option<&str>,
+    file: Option<&str>,
+    summary: Option<&str>,
+) -> String {
+    let Some(file) = file else {
+        return format!("`{fqn}`\n\nNot found through the autoload or the stubs.");
+    };
+
+    let mut markdown = match kind {
+        Some(kind) => format!("{kind} `{fqn}`"),
+        None => format!("`{fqn}`"),
+    };
+    markdown.push_str(&format!("\n\nDeclared in `{file}`"));
+    if let Some(summary) = summary {
+        markdown.push_str("\n\n");
+        markdown.push_str(summary);
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Position;
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use pls_types::SegmentPool;
+
+    use std::path::{Path, PathBuf};
+
+    use pls_types::CustomTypesDatabase;
+
+    use super::{
+        builtin_at, class_constant_at, class_member_at, declared_at, docblock_markdown,
+        static_variable_at, summary, use_clause_at, use_markdown, with_deprecation,
+    };
+    use crate::analyze::injest_types;
+    use crate::stubs::{FileMapping, StubKind};
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    #[test]
+    fn docblock_to_markdown() {
+        let docblock = "/**
+     * Iterates over each value in the array
+     * passing them to the callback function.
+     *
+     * @param array $array The array to iterate over
+     * @param callable|null $callback [optional] The callback function to use
+     * If no callback is supplied, all empty entries of array will be removed.
+     * @return array the filtered array.
+     * @since 4.0.6
+     */";
+        assert_eq!(
+            docblock_markdown(docblock),
+            [
+                "Iterates over each value in the array\npassing them to the callback function.\n",
+                "| Parameter | Type | Description |",
+                "| --- | --- | --- |",
+                "| `$array` | `array` | The array to iterate over |",
+                "| `$callback` | `callable\\|null` | [optional] The callback function to use \
+                If no callback is supplied, all empty entries of array will be removed. |",
+                "| _returns_ | `array` | the filtered array. |",
+                "",
+                "_@since_ 4.0.6",
+            ]
+            .join("\n")
+        );
+
+        let docblock = "/**
+     * Returns the length of the given <b>string</b>, see {@see mb_strlen()} and
+     * {@link https://php.net/strlen the manual}.
+     * <p>
+     * For example:
+     * <pre>
+     * if (strlen($a) &gt; 2) {
+     *     echo $a;
+     * }
+     * </pre>
+     * </p>
+     * @param string $string <p>The <code>string</code> being measured.</p>
+     * @see https://php.net/manual/en/function.strlen.php
+     * @throws TypeError
+     */";
+        assert_eq!(
+            docblock_markdown(docblock),
+            [
+                "Returns the length of the given **string**, see `mb_strlen()` and",
+                "[the manual](https://php.net/strlen).",
+                "",
+                "For example:",
+                "",
+                "```php",
+                "if (strlen($a) > 2) {",
+                "    echo $a;",
+                "}",
+                "```",
+                "",
+                "| Parameter | Type | Description |",
+                "| --- | --- | --- |",
+                "| `$string` | `string` | The `string` being measured. |",
+                "",
+                "_@see_ <https://php.net/manual/en/function.strlen.php>  ",
+                "_@throws_ `TypeError`",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            docblock_markdown("/** @return array<int, string> the names {@inheritDoc} */"),
+            "| Parameter | Type | Description |\n| --- | --- | --- |\n\
+            | _returns_ | `array<int, string>` | the names |"
+        );
+        assert_eq!(docblock_markdown("/** Just this. */"), "Just this.");
+    }
+
+    #[test]
+    fn find_builtins() {
+        let mut stubs = FileMapping::default();
+        for (name, kind) in [
+            ("array_filter", StubKind::Function),
+            ("DateTimeImmutable", StubKind::Class),
+            ("PHP_EOL", StubKind::Constant),
+        ] {
+            stubs.insert(kind, name, PathBuf::from("standard/standard.php"));
+        }
+        let mut pool = SegmentPool::new();
+
+        let src = "<?php
+namespace App;
+
+$evens = array_filter($numbers);
+$date = new \\DateTimeImmutable();
+echo PHP_EOL;
+$mine = filter($numbers);
+";
+        let tree = parser().parse(src, None).unwrap();
+        let mut builtin = |line, character| {
+            builtin_at(
+                tree.root_node(),
+                src,
+                &Position::new(line, character),
+                &stubs,
+                &mut pool,
+            )
+        };
+
+        assert_eq!(builtin(3, 12), Some("array_filter".to_string()));
+        assert_eq!(builtin(4, 20), Some("DateTimeImmutable".to_string()));
+        assert_eq!(builtin(5, 8), Some("PHP_EOL".to_string()));
+        assert_eq!(builtin(6, 10), None);
+        assert_eq!(builtin(3, 3), None);
+    }
+
+    #[test]
+    fn describe_use_clauses() {
+        let src = "<?php
+use App\\Models\\User;
+use App\\{Http\\Controller, function helper};
+use const App\\VERSION;
+";
+        let tree = parser().parse(src, None).unwrap();
+        let mut pool = SegmentPool::new();
+        let mut clause = |line, character| {
+            use_clause_at(
+                tree.root_node(),
+                src,
+                &Position::new(line, character),
+                &mut pool,
+            )
+            .map(|(use_type, fqn)| (use_type, fqn.to_string()))
+        };
+
+        assert_eq!(
+            clause(1, 16),
+            Some((None, "\\App\\Models\\User".to_string()))
+        );
+        assert_eq!(
+            clause(2, 15),
+            Some((None, "\\App\\Http\\Controller".to_string()))
+        );
+        assert_eq!(
+            clause(2, 40),
+            Some((Some("function"), "\\App\\helper".to_string()))
+        );
+        assert_eq!(
+            clause(3, 16),
+            Some((Some("const"), "\\App\\VERSION".to_string()))
+        );
+        assert_eq!(clause(0, 3), None);
+
+        assert_eq!(
+            summary("The user.\nStored in a table.\n\n_@property_ `int $id`"),
+            Some("The user. Stored in a table.".to_string())
+        );
+        assert_eq!(summary("_@internal_"), None);
+        assert_eq!(
+            use_markdown(
+                "App\\Models\\User",
+                Some("class"),
+                Some("src/Models/User.php"),
+                Some("The user.")
+            ),
+            "class `App\\Models\\User`\n\nDeclared in `src/Models/User.php`\n\nThe user."
+        );
+        assert_eq!(
+            use_markdown("App\\Missing", None, None, None),
+            "`App\\Missing`\n\nNot found through the autoload or the stubs."
+        );
+    }
+
+    #[test]
+    fn find_class_constants() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let src = "<?php
+namespace App;
+
+class Base { const LIMIT = 10; }
+enum Status: string { case Active = 'active'; }
+
+class Child extends Base {
+    const OWN = 1;
+
+    public function run() {
+        echo self::LIMIT, parent::LIMIT, Status::Active, static::OWN, \\PDO::FETCH_ASSOC;
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/src/Child.php"),
+            &mut pool,
+            &mut types,
+        );
+        let mut constant = |character| {
+            class_constant_at(
+                tree.root_node(),
+                src,
+                &Position::new(10, character),
+                &types,
+                &mut pool,
+            )
+            .map(|(class, member)| format!("{class}::{member}"))
+        };
+
+        assert_eq!(constant(22), Some("\\App\\Base::LIMIT".to_string()));
+        assert_eq!(constant(37), Some("\\App\\Base::LIMIT".to_string()));
+        assert_eq!(constant(52), Some("\\App\\Status::Active".to_string()));
+        assert_eq!(constant(66), Some("\\App\\Child::OWN".to_string()));
+        assert_eq!(constant(80), Some("\\PDO::FETCH_ASSOC".to_string()));
+        assert_eq!(constant(43), None);
+    }
+
+    #[test]
+    fn find_class_members() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let src = "<?php
+namespace App;
+
+class Base { public static $count; public function run() {} }
+
+class Child extends Base {
+    private $own;
+
+    public function go() {
+        $this->RUN(); $this->own; parent::run(); Child::$count; $other->run();
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/src/Child.php"),
+            &mut pool,
+            &mut types,
+        );
+        let mut member = |character| {
+            class_member_at(
+                tree.root_node(),
+                src,
+                &Position::new(9, character),
+                &types,
+                &mut pool,
+            )
+            .map(|(class, member)| format!("{class}::{member}"))
+        };
+
+        assert_eq!(member(16), Some("\\App\\Base::run()".to_string()));
+        assert_eq!(member(30), Some("\\App\\Child::$own".to_string()));
+        assert_eq!(member(43), Some("\\App\\Base::run()".to_string()));
+        assert_eq!(member(61), Some("\\App\\Base::$count".to_string()));
+        assert_eq!(member(73), None);
+        assert_eq!(member(10), None);
+    }
+
+    #[test]
+    fn find_declarations() {
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let src = "<?php
+namespace App;
+
+function helper() {}
+#[\\Deprecated('use Fresh')]
+class Old {}
+
+new Old(); HELPER(); missing(); new Other();
+";
+        let tree = parser().parse(src, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/src/app.php"),
+            &mut pool,
+            &mut types,
+        );
+        let mut declared = |character| {
+            declared_at(
+                tree.root_node(),
+                src,
+                &Position::new(7, character),
+                &types,
+                &mut pool,
+            )
+            .map(|fqn| fqn.to_string())
+        };
+
+        assert_eq!(declared(5), Some("\\App\\Old".to_string()));
+        assert_eq!(declared(12), Some("\\App\\helper".to_string()));
+        assert_eq!(declared(23), None);
+        assert_eq!(declared(38), None);
+
+        let old = types.0.get(&pool.intern_str("App\\Old")).unwrap();
+        assert_eq!(old.deprecated.as_deref(), Some("use Fresh"));
+    }
+
+    #[test]
+    fn deprecation_notices() {
+        assert_eq!(
+            with_deprecation("Does things.".to_string(), Some("use g()")),
+            "**Deprecated:** use g()\n\nDoes things."
+        );
+        assert_eq!(
+            with_deprecation("Does things.".to_string(), Some("")),
+            "**Deprecated**\n\nDoes things."
+        );
+        assert_eq!(
+            with_deprecation("_@deprecated_ use g()".to_string(), Some("use g()")),
+            "_@deprecated_ use g()"
+        );
+        assert_eq!(
+            with_deprecation("Does things.".to_string(), None),
+            "Does things."
+        );
+    }
+
+    #[test]
+    fn find_static_variables() {
+        let src = "<?php
+function next_id() {
+    static $id = 0, $calls;
+    $id++;
+    $other = fn() => $id;
+    return $id;
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let statics = |line, character| {
+            static_variable_at(tree.root_node(), src, &Position::new(line, character))
+        };
+
+        assert_eq!(statics(3, 6), Some("static $id = 0".to_string()));
+        assert_eq!(statics(2, 22), Some("static $calls".to_string()));
+        assert_eq!(statics(5, 13), Some("static $id = 0".to_string()));
+        assert_eq!(statics(4, 7), None);
+    }
+}
@@ -0,0 +1,595 @@
+use lsp_types::*;
+use serde::Deserialize;
+use tree_sitter::Node;
+
+use pls_types::SegmentPool;
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::analyze::{referenced_names, use_clause_removal, use_clauses};
+use crate::compat::{to_point, to_range};
+use crate::usage::import_fix;
+
+/// How imports from the same namespace get written out when organizing them.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStyle {
+    /// One `use` statement per import.
+    #[default]
+    Separate,
+    /// `use Foo\{Bar, Baz};` for imports that share a namespace.
+    Grouped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum ImportKind {
+    Class,
+    Function,
+    Const,
+}
+
+impl ImportKind {
+    fn keyword(&self) -> &'static str {
+        match self {
+            ImportKind::Class => "",
+            ImportKind::Function => "function ",
+            ImportKind::Const => "const ",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Import {
+    kind: ImportKind,
+    /// Fully qualified, without the leading `\`.
+    name: String,
+    alias: Option<String>,
+}
+
+impl Import {
+    /// The name the file refers to the import by.
+    fn local_name(&self) -> &str {
+        match &self.alias {
+            Some(alias) => alias,
+            None => self.name.rsplit('\\').next().unwrap_or(&self.name),
+        }
+    }
+
+    /// The namespace the import is from, and what's left of its name.
+    fn split(&self) -> (&str, &str) {
+        self.name.rsplit_once('\\').unwrap_or(("", &self.name))
+    }
+
+    fn clause(&self, name: &str) -> String {
+        match &self.alias {
+            Some(alias) => format!("{} as {}", name, alias),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// Read the imports out of a `use` declaration.
+fn imports(declaration: Node<'_>, content: &str) -> Vec<Import> {
+    let text = |node: Node<'_>| {
+        content[node.byte_range()]
+            .trim_start_matches('\\')
+            .to_string()
+    };
+    let kind = |node: Option<Node<'_>>| match node.map(|n| n.kind()) {
+        Some("function") => Some(ImportKind::Function),
+        Some("const") => Some(ImportKind::Const),
+        _ => None,
+    };
+
+    let mut cursor = declaration.walk();
+    let prefix = declaration
+        .children(&mut cursor)
+        .find(|child| child.kind() == "namespace_name")
+        .map(text);
+    let declared_kind = kind(declaration.child_by_field_name("type"));
+
+    use_clauses(declaration)
+        .into_iter()
+        .filter_map(|clause| {
+            let mut cursor = clause.walk();
+            let name = clause
+                .children(&mut cursor)
+                .find(|child| child.kind() == "qualified_name" || child.kind() == "name")
+                .map(text)?;
+            let name = match &prefix {
+                Some(prefix) => format!("{}\\{}", prefix, name),
+                None => name,
+            };
+
+            Some(Import {
+                kind: kind(clause.child_by_field_name("type"))
+                    .or(declared_kind)
+                    .unwrap_or(ImportKind::Class),
+                name,
+                alias: clause
+                    .child_by_field_name("alias")
+                    .map(|alias| content[alias.byte_range()].to_string()),
+            })
+        })
+        .collect()
+}
+
+/// Write imports out as `use` statements, sorted by kind and then by name.
+fn render(mut imports: Vec<Import>, style: ImportStyle) -> Vec<String> {
+    imports.sort_by_key(|import| {
+        (
+            import.kind,
+            import.name.to_lowercase(),
+            import.alias.clone(),
+        )
+    });
+    imports.dedup();
+
+    if style == ImportStyle::Separate {
+        return imports
+            .iter()
+            .map(|import| {
+                format!(
+                    "use {}{};",
+                    import.kind.keyword(),
+                    import.clause(&import.name)
+                )
+            })
+            .collect();
+    }
+
+    let mut groups: BTreeMap<(ImportKind, String), Vec<&Import>> = BTreeMap::new();
+    for import in imports.iter() {
+        let (namespace, _) = import.split();
+        groups
+            .entry((import.kind, namespace.to_lowercase()))
+            .or_default()
+            .push(import);
+    }
+    groups
+        .into_values()
+        .flat_map(|group| match group.as_slice() {
+            [first, _, ..] if !first.split().0.is_empty() => {
+                let clauses: Vec<_> = group
+                    .iter()
+                    .map(|import| import.clause(import.split().1))
+                    .collect();
+                vec![format!(
+                    "use {}{}\\{{{}}};",
+                    first.kind.keyword(),
+                    first.split().0,
+                    clauses.join(", ")
+                )]
+            }
+            _ => group
+                .iter()
+                .map(|import| {
+                    format!(
+                        "use {}{};",
+                        import.kind.keyword(),
+                        import.clause(&import.name)
+                    )
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// An edit that rewrites the file's import block so that it's free of duplicates and unused
+/// imports, and sorted.
+///
+/// Only a block of `use` statements with nothing in between is touched, so that we never lose
+/// comments or code. Returns `None` if the imports are organized already.
+pub fn organize_imports(node: Node<'_>, content: &str, style: ImportStyle) -> Option<TextEdit> {
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    let first = children
+        .iter()
+        .position(|child| child.kind() == "namespace_use_declaration")?;
+    let last = children
+        .iter()
+        .rposition(|child| child.kind() == "namespace_use_declaration")?;
+    let block = &children[first..=last];
+    if block
+        .iter()
+        .any(|child| child.kind() != "namespace_use_declaration")
+    {
+        return None;
+    }
+
+    let mut names = HashSet::new();
+    referenced_names(node, content, &mut names);
+    let imports: Vec<_> = block
+        .iter()
+        .flat_map(|declaration| imports(*declaration, content))
+        // we don't know where constants get used yet, so keep them all
+        .filter(|import| {
+            import.kind == ImportKind::Const || names.contains(&import.local_name().to_lowercase())
+        })
+        .collect();
+
+    let start = block[0];
+    let end = block[block.len() - 1];
+    let line_start = content[..start.start_byte()]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let indent = &content[line_start..start.start_byte()];
+    let indent = if indent.trim().is_empty() { indent } else { "" };
+    let new_text = render(imports, style).join(&format!("\n{}", indent));
+
+    let mut range = Range {
+        start: to_range(&start.range()).start,
+        end: to_range(&end.range()).end,
+    };
+    if new_text.is_empty() && content[end.end_byte()..].starts_with('\n') {
+        range.end = Position::new(range.end.line + 1, 0);
+    }
+    if content[start.start_byte()..end.end_byte()] == new_text {
+        return None;
+    }
+
+    Some(TextEdit { range, new_text })
+}
+
+/// The imports out of the file's `use` statements that `nodes` refer to, as `use` statements of
+/// their own.
+pub fn imports_used_by(root: Node<'_>, content: &str, nodes: &[Node<'_>]) -> Vec<String> {
+    let mut names = HashSet::new();
+    for node in nodes {
+        referenced_names(*node, content, &mut names);
+    }
+
+    let mut cursor = root.walk();
+    let imports = root
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "namespace_use_declaration")
+        .flat_map(|declaration| imports(declaration, content))
+        .filter(|import| names.contains(&import.local_name().to_lowercase()))
+        .collect();
+
+    render(imports, ImportStyle::Separate)
+}
+
+/// Return true if the name is where a class goes, like after `new` or in a type.
+pub fn is_class_reference(name: Node<'_>) -> bool {
+    let Some(parent) = name.parent() else {
+        return false;
+    };
+    match parent.kind() {
+        "named_type" | "base_clause" | "class_interface_clause" | "object_creation_expression" => {
+            true
+        }
+        "class_constant_access_expression" => parent.named_child(0) == Some(name),
+        "scoped_call_expression" | "scoped_property_access_expression" => {
+            parent.child_by_field_name("scope") == Some(name)
+        }
+        "binary_expression" => {
+            parent.child_by_field_name("operator").map(|o| o.kind()) == Some("instanceof")
+                && parent.child_by_field_name("right") == Some(name)
+        }
+        _ => false,
+    }
+}
+
+/// The class name at the cursor, along with the file's class imports and namespace. Files with
+/// braced namespaces are left alone, since every namespace has imports of its own.
+fn class_name_at<'a>(
+    node: Node<'a>,
+    content: &'a str,
+    range: &Range,
+) -> Option<(Node<'a>, Vec<Import>, Option<&'a str>)> {
+    let mut name =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.start))?;
+    while matches!(name.kind(), "name" | "namespace_name")
+        && name
+            .parent()
+            .is_some_and(|p| matches!(p.kind(), "qualified_name" | "namespace_name"))
+    {
+        name = name.parent()?;
+    }
+    if !is_class_reference(name) {
+        return None;
+    }
+
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    let namespaces: Vec<_> = children
+        .iter()
+        .filter(|child| child.kind() == "namespace_definition")
+        .collect();
+    if namespaces
+        .iter()
+        .any(|n| n.child_by_field_name("body").is_some())
+    {
+        return None;
+    }
+    let namespace = namespaces
+        .first()
+        .and_then(|n| n.child_by_field_name("name"))
+        .map(|name| &content[name.byte_range()]);
+    let imports = children
+        .iter()
+        .filter(|child| child.kind() == "namespace_use_declaration")
+        .flat_map(|declaration| imports(*declaration, content))
+        .filter(|import| import.kind == ImportKind::Class)
+        .collect();
+
+    Some((name, imports, namespace))
+}
+
+/// Every class reference in the file written exactly like `name`, ignoring case.
+fn references_like<'a>(node: Node<'a>, content: &str, name: &str, found: &mut Vec<Node<'a>>) {
+    if node.kind() == "qualified_name" {
+        if content[node.byte_range()].eq_ignore_ascii_case(name) && is_class_reference(node) {
+            found.push(node);
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        references_like(child, content, name, found);
+    }
+}
+
+/// Edits that import the fully qualified class name at the cursor, and shorten every reference
+/// to it in the file.
+///
+/// Not offered when the short name already means something else in the file.
+pub fn shorten_name(
+    node: Node<'_>,
+    content: &str,
+    range: &Range,
+) -> Option<(String, Vec<TextEdit>)> {
+    let (name, imports, _) = class_name_at(node, content, range)?;
+    let text = &content[name.byte_range()];
+    let fqn = text.strip_prefix('\\')?;
+    let (_, short) = fqn.rsplit_once('\\')?;
+
+    let existing = imports
+        .iter()
+        .find(|import| import.local_name().eq_ignore_ascii_case(short));
+    let mut edits = Vec::new();
+    let title = match existing {
+        Some(import) if import.name.eq_ignore_ascii_case(fqn) => format!("Shorten to `{}`", short),
+        Some(_) => return None,
+        None => {
+            let mut names = HashSet::new();
+            referenced_names(node, content, &mut names);
+            if names.contains(&short.to_lowercase()) {
+                return None;
+            }
+            let fix = import_fix(name, &SegmentPool::new().intern_str(fqn), content);
+            edits.extend(fix.edits);
+            fix.title
+        }
+    };
+
+    let mut references = Vec::new();
+    references_like(node, content, text, &mut references);
+    edits.extend(references.into_iter().map(|reference| TextEdit {
+        range: to_range(&reference.range()),
+        new_text: short.to_string(),
+    }));
+
+    Some((title, edits))
+}
+
+/// Return true if `alias` shows up anywhere in the file other than at `except`, the way
+/// `referenced_names` would find it.
+fn alias_used_elsewhere(node: Node<'_>, content: &str, alias: &str, except: Node<'_>) -> bool {
+    if node == except || node.kind() == "namespace_use_declaration" {
+        return false;
+    }
+    if matches!(node.kind(), "name" | "qualified_name" | "comment") {
+        let mut names = HashSet::new();
+        referenced_names(node, content, &mut names);
+        return names.contains(alias);
+    }
+
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .filter(|child| child.kind() != "namespace_name")
+        .any(|child| alias_used_elsewhere(child, content, alias, except))
+}
+
+/// Edits that write the class name at the cursor out in full, as a title and the edits. If that
+/// leaves its import unused, there's a second choice that removes the import too.
+pub fn qualify_name(node: Node<'_>, content: &str, range: &Range) -> Vec<(String, Vec<TextEdit>)> {
+    let Some((name, imports, namespace)) = class_name_at(node, content, range) else {
+        return vec![];
+    };
+    let text = &content[name.byte_range()];
+    if text.starts_with('\\')
+        || ["self", "static", "parent"]
+            .iter()
+            .any(|keyword| text.eq_ignore_ascii_case(keyword))
+    {
+        return vec![];
+    }
+
+    let (first, rest) = match text.split_once('\\') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (text, None),
+    };
+    let import = imports
+        .iter()
+        .find(|import| import.local_name().eq_ignore_ascii_case(first));
+    let fqn = match (import, namespace) {
+        (Some(import), _) => match rest {
+            Some(rest) => format!("{}\\{}", import.name, rest),
+            None => import.name.clone(),
+        },
+        (None, Some(namespace)) => format!("{}\\{}", namespace, text),
+        (None, None) => text.to_string(),
+    };
+
+    let qualify = TextEdit {
+        range: to_range(&name.range()),
+        new_text: format!("\\{}", fqn),
+    };
+    let title = format!("Use fully qualified name `\\{}`", fqn);
+    let mut actions = vec![(title.clone(), vec![qualify.clone()])];
+
+    // the clause that brought the import in, going by the name it gives the class
+    let clause = import.and_then(|import| {
+        let mut cursor = node.walk();
+        node.children(&mut cursor)
+            .filter(|child| {
+                child.kind() == "namespace_use_declaration"
+                    && child.child_by_field_name("type").is_none()
+            })
+            .flat_map(use_clauses)
+            .filter(|clause| clause.child_by_field_name("type").is_none())
+            .find(|clause| {
+                let local = match clause.child_by_field_name("alias") {
+                    Some(alias) => &content[alias.byte_range()],
+                    None => content[clause.byte_range()]
+                        .rsplit('\\')
+                        .next()
+                        .unwrap_or(""),
+                };
+                local.eq_ignore_ascii_case(import.local_name())
+            })
+    });
+    if let Some(clause) = clause
+        && !alias_used_elsewhere(node, content, &first.to_lowercase(), name)
+        && let Some(removal) = use_clause_removal(clause, content)
+    {
+        actions.push((
+            format!("{} and remove the import", title),
+            vec![
+                TextEdit {
+                    range: removal,
+                    new_text: String::new(),
+                },
+                qualify,
+            ],
+        ));
+    }
+
+    actions
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Position, Range};
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use super::ImportStyle;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    fn organize(src: &str, style: ImportStyle) -> Option<String> {
+        let tree = parser().parse(src, None).unwrap();
+        super::organize_imports(tree.root_node(), src, style).map(|edit| edit.new_text)
+    }
+
+    #[test]
+    fn organize_imports() {
+        let src = "<?php
+namespace App;
+
+use Lib\\Zeta;
+use function Lib\\helper;
+use Lib\\{Alpha, Unused};
+use \\Lib\\Zeta;
+use Other\\Thing as Alias;
+use const Lib\\LIMIT;
+
+helper(new Alpha(), new Zeta(), new Alias());
+";
+        assert_eq!(
+            organize(src, ImportStyle::Separate).unwrap(),
+            "use Lib\\Alpha;
+use Lib\\Zeta;
+use Other\\Thing as Alias;
+use function Lib\\helper;
+use const Lib\\LIMIT;"
+        );
+        assert_eq!(
+            organize(src, ImportStyle::Grouped).unwrap(),
+            "use Lib\\{Alpha, Zeta};
+use Other\\Thing as Alias;
+use function Lib\\helper;
+use const Lib\\LIMIT;"
+        );
+
+        let organized = "<?php
+use Lib\\Alpha;
+use Lib\\Zeta;
+
+new Alpha(new Zeta());
+";
+        assert!(organize(organized, ImportStyle::Separate).is_none());
+
+        // comments in between are left alone
+        let src = "<?php
+use Lib\\Zeta;
+// the first letter
+use Lib\\Alpha;
+
+new Alpha(new Zeta());
+";
+        assert!(organize(src, ImportStyle::Separate).is_none());
+    }
+
+    #[test]
+    fn toggle_qualified_names() {
+        let src = "<?php
+namespace App;
+
+use Lib\\Logger;
+use Lib\\Mail\\Transport as Smtp;
+
+class Mailer extends \\App\\Service\\Base {
+    public function send(\\App\\Service\\Base $base): Smtp {
+        /** @var Logger $logger */
+        $logger = new Logger();
+        return new \\Lib\\Logger();
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let on = |line, character| {
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            )
+        };
+
+        let (title, edits) = super::shorten_name(tree.root_node(), src, &on(6, 35)).unwrap();
+        assert_eq!(title, "Import `App\\Service\\Base`");
+        let texts: Vec<_> = edits.iter().map(|e| e.new_text.as_str()).collect();
+        assert_eq!(texts, ["use App\\Service\\Base;\n", "Base", "Base"]);
+
+        // imported already
+        let (title, edits) = super::shorten_name(tree.root_node(), src, &on(10, 25)).unwrap();
+        assert_eq!(title, "Shorten to `Logger`");
+        assert_eq!(edits.len(), 1);
+
+        let actions = super::qualify_name(tree.root_node(), src, &on(7, 52));
+        assert_eq!(actions.len(), 2);
+        assert_eq!(
+            actions[1].0,
+            "Use fully qualified name `\\Lib\\Mail\\Transport` and remove the import"
+        );
+        assert_eq!(actions[1].1[0].range.start, Position::new(4, 0));
+        assert_eq!(actions[1].1[0].range.end, Position::new(5, 0));
+        assert_eq!(actions[1].1[1].new_text, "\\Lib\\Mail\\Transport");
+
+        // `Logger` is still used in the docblock
+        let actions = super::qualify_name(tree.root_node(), src, &on(9, 24));
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].1[0].new_text, "\\Lib\\Logger");
+    }
+}
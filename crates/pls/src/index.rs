@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+use pls_types::{CustomTypeMeta, CustomTypesDatabase, PhpNamespace, SegmentPool};
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::analyze::injest_types;
+use crate::global_state::Parsers;
+use crate::rename::php_files;
+
+/// The types declared in a workspace and its dependencies, saved by `pls index` so that sessions
+/// start with them instead of reading each file the first time it's needed.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Index {
+    /// The version of pls that wrote the index; another version's is ignored.
+    version: String,
+    files: Vec<IndexedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexedFile {
+    path: PathBuf,
+    /// When the file was changed last, as of indexing. Its types are stale if it's changed since.
+    modified: SystemTime,
+    types: Vec<(PhpNamespace, CustomTypeMeta)>,
+}
+
+/// Where the index of a workspace is kept.
+pub fn index_path(root: &Path) -> PathBuf {
+    root.join(".pls").join("index.json")
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Index {
+    /// Ingest every PHP file under `root`, the dependencies in `vendor` included.
+    pub fn build(root: &Path) -> Self {
+        let mut paths = Vec::new();
+        php_files(root, &mut paths);
+        php_files(&root.join("vendor"), &mut paths);
+        paths.sort();
+
+        let mut parsers = Parsers::new();
+        let mut pool = SegmentPool::new();
+        let mut files = Vec::new();
+        for path in paths {
+            let Some(modified) = modified(&path) else {
+                continue;
+            };
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    log::error!("Err in reading `{path:?}`: {e:?}");
+                    continue;
+                }
+            };
+            let Some(tree) = parsers.parse(&content, None) else {
+                continue;
+            };
+
+            let mut types = CustomTypesDatabase::new();
+            injest_types(tree.root_node(), &content, &path, &mut pool, &mut types);
+            files.push(IndexedFile {
+                path,
+                modified,
+                types: types.0.into_iter().collect(),
+            });
+        }
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            files,
+        }
+    }
+
+    /// How many files were indexed.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// The index of the workspace at `root`, if `pls index` was run there by this version.
+    pub fn read(root: &Path) -> Option<Self> {
+        let path = index_path(root);
+        let content = std::fs::read_to_string(&path).ok()?;
+        let index: Self = match serde_json::from_str(&content) {
+            Ok(index) => index,
+            Err(e) => {
+                log::error!("Err in reading the index `{path:?}`: {e:?}");
+                return None;
+            }
+        };
+
+        (index.version == env!("CARGO_PKG_VERSION")).then_some(index)
+    }
+
+    /// Save the index where [`Index::read()`] finds it, and say where that is.
+    pub fn write(&self, root: &Path) -> anyhow::Result<PathBuf> {
+        let path = index_path(root);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string(self)?)?;
+
+        Ok(path)
+    }
+
+    /// Add the types of the files that haven't changed since they were indexed, and give back how
+    /// many of those files there were. Types that are in already were read later, so they stay.
+    pub fn load(self, types: &mut CustomTypesDatabase) -> usize {
+        let mut loaded = 0;
+        for file in self.files {
+            if modified(&file.path) != Some(file.modified) {
+                continue;
+            }
+
+            for (fqn, meta) in file.types {
+                if !types.0.contains_key(&fqn) {
+                    types.insert(fqn, meta);
+                }
+            }
+            loaded += 1;
+        }
+
+        loaded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pls_types::{CustomTypesDatabase, SegmentPool};
+
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    use super::Index;
+
+    #[test]
+    fn round_trip() {
+        let root = std::env::temp_dir().join(format!("pls-index-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("vendor/acme/lib")).unwrap();
+        std::fs::write(
+            root.join("src/User.php"),
+            "<?php\nnamespace App;\n\nclass User { public function name(): string {} }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("vendor/acme/lib/helpers.php"),
+            "<?php\nnamespace Acme;\n\n/** @deprecated */\nfunction helper(int $a) {}\n",
+        )
+        .unwrap();
+
+        let index = Index::build(&root);
+        assert_eq!(index.file_count(), 2);
+        index.write(&root).unwrap();
+
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        assert_eq!(Index::read(&root).unwrap().load(&mut types), 2);
+        let user = types.0.get(&pool.intern_str("App\\User")).unwrap();
+        assert_eq!(user.src_path, root.join("src/User.php"));
+        assert_eq!(user.src_range.start_point.row, 3);
+        let helper = types.0.get(&pool.intern_str("Acme\\helper")).unwrap();
+        assert_eq!(helper.deprecated.as_deref(), Some(""));
+
+        // a file that changed since is read again when it's needed
+        File::options()
+            .write(true)
+            .open(root.join("src/User.php"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        let mut types = CustomTypesDatabase::new();
+        assert_eq!(Index::read(&root).unwrap().load(&mut types), 1);
+        assert!(!types.0.contains_key(&pool.intern_str("App\\User")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,553 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::rc::Rc;
+
+use pls_types::{
+    Class, CustomType, CustomTypesDatabase, PhpNamespace, SegmentPool, UriExt, Visibility,
+};
+
+use crate::analyze::import_aliases;
+use crate::compat::to_range;
+use crate::scope::Scope;
+
+/// Check that the classes in a file hold up their end of the bargain with the types they inherit
+/// from.
+///
+/// Run this after the file's types have been ingested. We only know as much about the ancestors as
+/// the types database does.
+pub fn check(
+    node: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    types: &CustomTypesDatabase,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if node.kind() != "program" {
+        return diagnostics;
+    }
+
+    let mut scope = Scope::empty();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        let kind = child.kind();
+        if kind == "namespace_definition" {
+            if let Some(name) = child.child_by_field_name("name") {
+                scope.ns = Some(ns_store.intern_str(&content[name.byte_range()]));
+            }
+        } else if kind == "namespace_use_declaration" {
+            import_aliases(child, content, ns_store, &mut scope);
+        } else if kind == "class_declaration" {
+            check_class_declaration(child, content, &scope, types, &mut diagnostics);
+        }
+    }
+
+    diagnostics
+}
+
+/// Check every method the class declares against the ones it overrides, and that concrete classes
+/// don't leave abstract or interface methods behind.
+fn check_class_declaration(
+    node: Node<'_>,
+    content: &str,
+    scope: &Scope,
+    types: &CustomTypesDatabase,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(name) = node.child_by_field_name("name") else {
+        return;
+    };
+    let mut ns = scope.ns.clone().unwrap_or(PhpNamespace::empty());
+    ns.push(Rc::from(&content[name.byte_range()]));
+    let Some(CustomType::Class(class)) = types.0.get(&ns).map(|meta| &meta.t) else {
+        return;
+    };
+    if !class.r#abstract {
+        check_abstract_methods(&ns, name, types, diagnostics);
+        check_interface_methods(node, &ns, class, types, diagnostics);
+    }
+
+    let Some(body) = node.child_by_field_name("body") else {
+        return;
+    };
+
+    let supertypes = class
+        .parent_classes
+        .iter()
+        .chain(class.implemented_interfaces.iter());
+    let mut cursor = body.walk();
+    for child in body.children(&mut cursor) {
+        if child.kind() != "method_declaration" {
+            continue;
+        }
+        let Some(method_name) = child.child_by_field_name("name") else {
+            continue;
+        };
+        let declared = &content[method_name.byte_range()];
+        let Some(method) = class.methods.get(declared).or_else(|| {
+            class
+                .methods
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(declared))
+                .map(|(_, method)| method)
+        }) else {
+            continue;
+        };
+
+        let mut checked = Vec::new();
+        for supertype in supertypes.clone() {
+            let Ok(members) = types.effective_members(supertype) else {
+                continue;
+            };
+            let Some(overridden) = members.method(&method.name) else {
+                continue;
+            };
+            if overridden.member.visibility == Visibility::Private
+                || checked.contains(&overridden.declared_in)
+            {
+                continue;
+            }
+            checked.push(overridden.declared_in.clone());
+            // constructors only need to match when the signature is forced on them
+            if method.name.eq_ignore_ascii_case("__construct")
+                && !overridden.member.r#abstract
+                && !is_interface(types, &overridden.declared_in)
+            {
+                continue;
+            }
+
+            if let Err(e) = types.check_override(method, &overridden.member) {
+                diagnostics.push(Diagnostic {
+                    range: to_range(&method_name.range()),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: None,
+                    code_description: None,
+                    source: Some("override".to_string()),
+                    message: format!(
+                        "declaration of {}::{}() must be compatible with {}::{}(): {}",
+                        ns, method.name, overridden.declared_in, overridden.member.name, e
+                    ),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+    }
+}
+
+/// Flag a concrete class that still has abstract methods from its parents or traits.
+///
+/// Abstract methods from interfaces are left to the interface checks.
+fn check_abstract_methods(
+    ns: &PhpNamespace,
+    name: Node<'_>,
+    types: &CustomTypesDatabase,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Ok(members) = types.effective_members(ns) else {
+        return;
+    };
+
+    // method names ignore case, so `Handle()` implements `abstract function handle()`
+    let implemented = |name: &str| {
+        members
+            .methods
+            .values()
+            .any(|m| !m.member.r#abstract && m.member.name.eq_ignore_ascii_case(name))
+    };
+    let mut missing: Vec<String> = members
+        .methods
+        .values()
+        .filter(|m| m.member.r#abstract && !is_interface(types, &m.declared_in))
+        .filter(|m| !implemented(&m.member.name))
+        .map(|m| format!("{}::{}()", m.declared_in, m.member.name))
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+    missing.sort();
+
+    diagnostics.push(Diagnostic {
+        range: to_range(&name.range()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: None,
+        code_description: None,
+        source: Some("abstract".to_string()),
+        message: format!(
+            "{} must be declared abstract or implement the remaining abstract methods: {}",
+            ns,
+            missing.join(", ")
+        ),
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// Flag a concrete class that doesn't implement every method of its interfaces, or implements them
+/// through parents and traits with the wrong signature.
+///
+/// Diagnostics go on the `implements` entry that brought the interface in, falling back to the
+/// class name when the interface came from a parent.
+fn check_interface_methods(
+    node: Node<'_>,
+    ns: &PhpNamespace,
+    class: &Class,
+    types: &CustomTypesDatabase,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Ok(members) = types.effective_members(ns) else {
+        return;
+    };
+    let Some(name) = node.child_by_field_name("name") else {
+        return;
+    };
+
+    let mut clause_names = Vec::new();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.kind() == "class_interface_clause" {
+            let mut cursor = child.walk();
+            clause_names.extend(
+                child
+                    .children(&mut cursor)
+                    .filter(|n| n.kind() == "name" || n.kind() == "qualified_name"),
+            );
+        }
+    }
+    let range_for = |interface: &PhpNamespace| {
+        class
+            .implemented_interfaces
+            .iter()
+            .zip(clause_names.iter())
+            .find(|(implemented, _)| {
+                *implemented == interface
+                    || types
+                        .effective_members(implemented)
+                        .is_ok_and(|m| m.ancestors.contains(interface))
+            })
+            .map(|(_, n)| n.range())
+            .unwrap_or(name.range())
+    };
+
+    for interface in members.ancestors.iter() {
+        let Some(meta) = types.0.get(interface) else {
+            continue;
+        };
+        let CustomType::Interface(i) = &meta.t else {
+            continue;
+        };
+        let related_information = Uri::from_file_path(&meta.src_path).map(|uri| {
+            vec![DiagnosticRelatedInformation {
+                location: Location {
+                    uri,
+                    range: to_range(&meta.src_range),
+                },
+                message: format!("{} declared here", interface),
+            }]
+        });
+
+        let mut missing = Vec::new();
+        let mut names: Vec<&String> = i.methods.keys().collect();
+        names.sort();
+        for method_name in names {
+            let Some(implementation) = members.method(method_name) else {
+                continue;
+            };
+            if is_interface(types, &implementation.declared_in) {
+                if implementation.declared_in == *interface {
+                    missing.push(format!("{}()", method_name));
+                }
+                continue;
+            }
+
+            // our own methods were already checked against the interface, and whoever declared
+            // the implementation is on the hook for it if they implement the interface too
+            if implementation.declared_in == *ns
+                || types
+                    .effective_members(&implementation.declared_in)
+                    .is_ok_and(|m| m.ancestors.contains(interface))
+            {
+                continue;
+            }
+
+            if let Err(e) = types.check_override(&implementation.member, &i.methods[method_name]) {
+                diagnostics.push(Diagnostic {
+                    range: to_range(&range_for(interface)),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: None,
+                    code_description: None,
+                    source: Some("interface".to_string()),
+                    message: format!(
+                        "{}::{}() must be compatible with {}::{}(): {}",
+                        implementation.declared_in, method_name, interface, method_name, e
+                    ),
+                    related_information: related_information.clone(),
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+
+        if !missing.is_empty() {
+            diagnostics.push(Diagnostic {
+                range: to_range(&range_for(interface)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: None,
+                code_description: None,
+                source: Some("interface".to_string()),
+                message: format!(
+                    "{} must implement {}: {}",
+                    ns,
+                    interface,
+                    missing.join(", ")
+                ),
+                related_information,
+                tags: None,
+                data: None,
+            });
+        }
+    }
+}
+
+fn is_interface(types: &CustomTypesDatabase, ns: &PhpNamespace) -> bool {
+    matches!(
+        types.0.get(ns).map(|meta| &meta.t),
+        Some(CustomType::Interface(_))
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use pls_types::{CustomTypesDatabase, SegmentPool};
+
+    use std::path::Path;
+
+    use crate::analyze::injest_types;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    fn check(src: &str) -> Vec<lsp_types::Diagnostic> {
+        let tree = parser().parse(src, None).unwrap();
+        let root_node = tree.root_node();
+        let mut types = CustomTypesDatabase::new();
+        let mut pool = SegmentPool::new();
+        injest_types(
+            root_node,
+            src,
+            Path::new("/tmp/test.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        super::check(root_node, src, &mut pool, &types)
+    }
+
+    #[test]
+    fn compatible_overrides() {
+        let srcs = [
+            "<?php
+            class Base { public function m(int $a): ?Base {} }
+            class Child extends Base { public function m(int|string $a, $b = 1): static {} }",
+            "<?php
+            namespace App;
+            class Base { public function m(array &$a) {} }
+            class Child extends Base { public function m(iterable &$a, ...$rest): void {} }",
+            "<?php
+            class Base { private function m(int $a) {} }
+            class Child extends Base { public function m(string $a, $b) {} }",
+            "<?php
+            class Base { public function __construct(int $a) {} }
+            class Child extends Base { public function __construct() {} }",
+            "<?php
+            class Child extends Unknown { public function m(int $a) {} }",
+        ];
+
+        for src in srcs {
+            let diags = check(src);
+            assert!(diags.is_empty(), "src = {}\ndiags = {:?}", src, diags);
+        }
+    }
+
+    #[test]
+    fn incompatible_overrides() {
+        let srcs = [
+            "<?php
+            class Base { public function m(int $a) {} }
+            class Child extends Base { public function m() {} }",
+            "<?php
+            class Base { public function m(int $a) {} }
+            class Child extends Base { public function m(int $a, $b) {} }",
+            "<?php
+            class Base { public function m(int $a = 1) {} }
+            class Child extends Base { public function m(int $a) {} }",
+            "<?php
+            class Base { public function m(&$a) {} }
+            class Child extends Base { public function m($a) {} }",
+            "<?php
+            class Base { public function m(int $a) {} }
+            class Child extends Base { public function m(string $a) {} }",
+            "<?php
+            class Base { public function m(): Child {} }
+            class Child extends Base { public function m(): Base {} }",
+            "<?php
+            class Base { public function m(): int {} }
+            class Child extends Base { public function m() {} }",
+        ];
+
+        for src in srcs {
+            let diags = check(src);
+            assert_eq!(diags.len(), 1, "src = {}\ndiags = {:?}", src, diags);
+            assert_eq!(diags[0].range.start.line, 2, "src = {}", src);
+        }
+    }
+
+    #[test]
+    fn overrides_in_another_case_checked() {
+        let src = "<?php
+        class Base { public function handle(int $a) {} }
+        class Child extends Base { public function Handle(string $a) {} }";
+        let diags = check(src);
+        assert_eq!(diags.len(), 1, "diags = {:?}", diags);
+        assert!(
+            diags[0].message.contains("\\Base::handle()"),
+            "{}",
+            diags[0].message
+        );
+    }
+
+    #[test]
+    fn interface_and_parent_checked_once() {
+        let src = "<?php
+        interface I { public function m(int $a); }
+        class Base implements I { public function m(int $a) {} }
+        class Child extends Base implements I { public function m(string $a) {} }";
+        let diags = check(src);
+        assert_eq!(diags.len(), 2, "diags = {:?}", diags);
+        assert!(
+            diags[0].message.contains("\\Base::m()"),
+            "{}",
+            diags[0].message
+        );
+        assert!(
+            diags[1].message.contains("\\I::m()"),
+            "{}",
+            diags[1].message
+        );
+    }
+
+    #[test]
+    fn unimplemented_abstract_methods() {
+        let src = "<?php
+        abstract class Base {
+            abstract public function a();
+            abstract protected function b(int $x);
+            public function c() {}
+        }
+        trait T { abstract public function t(); }
+        abstract class Middle extends Base { public function a() {} }
+        class Child extends Middle { use T; }
+        class Done extends Middle { use T; public function b(int $x) {} public function t() {} }";
+        let diags = check(src);
+        assert_eq!(diags.len(), 1, "diags = {:?}", diags);
+        assert_eq!(diags[0].range.start.line, 8);
+        assert!(
+            diags[0].message.ends_with("\\Base::b(), \\T::t()"),
+            "{}",
+            diags[0].message
+        );
+    }
+
+    #[test]
+    fn abstract_methods_implemented_in_another_case() {
+        let src = "<?php
+        abstract class Base { abstract public function handle(); }
+        trait T { abstract public function run(); }
+        class Child extends Base { use T; public function Handle() {} public function RUN() {} }";
+        let diags = check(src);
+        assert!(diags.is_empty(), "diags = {:?}", diags);
+    }
+
+    #[test]
+    fn abstract_interface_methods_left_alone() {
+        let src = "<?php
+        interface I { public function m(); }
+        class Child implements I {}";
+        // the interface check is what flags these, see `unimplemented_interface_methods_flagged`
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("abstract"))
+            .collect();
+        assert!(diags.is_empty(), "diags = {:?}", diags);
+    }
+
+    #[test]
+    fn unimplemented_interface_methods_flagged() {
+        let src = "<?php
+        interface I { public function m(); }
+        class Child implements I {}";
+        let diags = check(src);
+        assert_eq!(diags.len(), 1, "diags = {:?}", diags);
+        assert_eq!(diags[0].source.as_deref(), Some("interface"));
+        assert_eq!(diags[0].message, "\\Child must implement \\I: m()");
+    }
+
+    #[test]
+    fn interface_methods_implemented_in_another_case() {
+        let src = "<?php
+        interface I { public function handle(int $a); }
+        class Base { public function HANDLE(int $a) {} }
+        class Child extends Base implements I {}
+        class Own implements I { public function Handle(int $a) {} }";
+        let diags = check(src);
+        assert!(diags.is_empty(), "diags = {:?}", diags);
+    }
+
+    #[test]
+    fn missing_interface_methods() {
+        let src = "<?php
+        interface J { public function j(); }
+        interface I extends J { public function a(); public function b(); }
+        trait T { public function b() {} }
+        class Base { public function j() {} }
+        class Child extends Base implements I { use T; }";
+        let diags = check(src);
+        assert_eq!(diags.len(), 1, "diags = {:?}", diags);
+        assert_eq!(diags[0].message, "\\Child must implement \\I: a()");
+        assert_eq!(diags[0].range.start.line, 5);
+        assert_eq!(diags[0].range.start.character, 44);
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.range.start.line, 2);
+        assert_eq!(related[0].location.uri.as_str(), "file:///tmp/test.php");
+    }
+
+    #[test]
+    fn inherited_interface_methods_checked() {
+        let src = "<?php
+        interface I { public function m(int $a); }
+        abstract class Base { public function m(string $a) {} }
+        class Child extends Base implements I {}
+        abstract class Lazy implements I {}";
+        let diags = check(src);
+        assert_eq!(diags.len(), 1, "diags = {:?}", diags);
+        assert!(
+            diags[0]
+                .message
+                .starts_with("\\Base::m() must be compatible with \\I::m()"),
+            "{}",
+            diags[0].message
+        );
+        assert_eq!(diags[0].range.start.line, 3);
+    }
+}
@@ -1,11 +1,33 @@
+mod analyze;
+pub mod check;
+mod classmap;
+pub mod cli;
 mod code_action;
+mod command;
 mod compat;
+mod completion;
 mod config;
 mod diagnostics;
 mod file;
+mod folding;
+mod generate;
 pub mod global_state;
 mod handlers;
+mod hover;
+mod imports;
+mod index;
+mod inheritance;
+pub mod logging;
 mod messages;
+mod native_types;
+mod psr4;
+mod refactor;
+mod rename;
 pub mod registry;
 mod scope;
 mod stubs;
+mod symbols;
+pub mod transport;
+mod usage;
+mod vendor;
+mod version;
@@ -0,0 +1,215 @@
+use lsp_types::TraceValue;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{Registry, reload};
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// How big the log file gets before it's moved to `<file>.1` and started over.
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Changes the level once logging is set up, along with the level it started at.
+static LEVEL: OnceLock<(reload::Handle<LevelFilter, Registry>, LevelFilter)> = OnceLock::new();
+
+/// Send logs, and the `log` records everything else writes, to stderr or `log_file`.
+///
+/// The level is `level`, or what `RUST_LOG` says, or `info`. Requests and worker tasks are spans,
+/// which get logged with how long they took at `debug`.
+pub fn init(level: Option<LevelFilter>, log_file: Option<&Path>) -> anyhow::Result<()> {
+    let level = level
+        .or_else(|| std::env::var("RUST_LOG").ok()?.parse().ok())
+        .unwrap_or(LevelFilter::INFO);
+    let (filter, handle) = reload::Layer::new(level);
+    let fmt = tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE);
+    match log_file {
+        Some(log_file) => {
+            let file = RotatingFile::open(log_file, MAX_LOG_SIZE)
+                .map_err(|e| anyhow::anyhow!("unable to open log file {log_file:?}: {e}"))?;
+            tracing_subscriber::registry()
+                .with(filter)
+                .with(fmt.with_ansi(false).with_writer(Mutex::new(file)))
+                .try_init()?;
+        }
+        None => {
+            tracing_subscriber::registry()
+                .with(filter)
+                .with(fmt.with_writer(io::stderr))
+                .try_init()?;
+        }
+    }
+    let _ = LEVEL.set((handle, level));
+
+    Ok(())
+}
+
+/// Log panics, with a backtrace, where the rest of the logs go; nobody sees stderr when an editor
+/// started us. The server carries on past them where it can.
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+        log::error!(
+            "panicked at {location}: {}\n{}",
+            panic_message(info.payload()),
+            Backtrace::force_capture()
+        );
+    }));
+}
+
+/// What a panic said, if it said it with a string.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Log more or less as the client asks through `$/setTrace`.
+pub fn set_trace(trace: TraceValue) {
+    let Some((handle, initial)) = LEVEL.get() else {
+        return;
+    };
+    let level = trace_level(trace, *initial);
+    if let Err(e) = handle.modify(|filter| *filter = level) {
+        log::error!("Err in changing the log level: {e:?}");
+        return;
+    }
+    // `log` records are filtered on their own before they get to us
+    log::set_max_level(match level {
+        LevelFilter::OFF => log::LevelFilter::Off,
+        LevelFilter::ERROR => log::LevelFilter::Error,
+        LevelFilter::WARN => log::LevelFilter::Warn,
+        LevelFilter::INFO => log::LevelFilter::Info,
+        LevelFilter::DEBUG => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    });
+    log::info!("logging at {level} for trace `{trace:?}`");
+}
+
+/// Tracing only ever makes logs louder; turning it off goes back to where we started.
+fn trace_level(trace: TraceValue, initial: LevelFilter) -> LevelFilter {
+    let level = match trace {
+        TraceValue::Off => return initial,
+        TraceValue::Messages => LevelFilter::DEBUG,
+        TraceValue::Verbose => LevelFilter::TRACE,
+    };
+
+    level.max(initial)
+}
+
+/// A log file that moves itself aside to `<file>.1` once it's `max_size` long, so that a server
+/// left running doesn't fill the disk.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut old = self.path.clone().into_os_string();
+        old.push(".1");
+        fs::rename(&self.path, old)?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::TraceValue;
+    use tracing_subscriber::filter::LevelFilter;
+
+    use std::io::Write;
+
+    #[test]
+    fn trace_levels() {
+        use super::trace_level;
+
+        assert_eq!(
+            trace_level(TraceValue::Messages, LevelFilter::INFO),
+            LevelFilter::DEBUG
+        );
+        assert_eq!(
+            trace_level(TraceValue::Verbose, LevelFilter::INFO),
+            LevelFilter::TRACE
+        );
+        assert_eq!(
+            trace_level(TraceValue::Off, LevelFilter::WARN),
+            LevelFilter::WARN
+        );
+        // asking for less than we were started with doesn't quiet anything
+        assert_eq!(
+            trace_level(TraceValue::Messages, LevelFilter::TRACE),
+            LevelFilter::TRACE
+        );
+    }
+
+    #[test]
+    fn panic_messages() {
+        let payload = std::panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(super::panic_message(payload.as_ref()), "static");
+        let payload = std::panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(super::panic_message(payload.as_ref()), "formatted 1");
+        let payload = std::panic::catch_unwind(|| std::panic::panic_any(1)).unwrap_err();
+        assert_eq!(super::panic_message(payload.as_ref()), "unknown panic");
+    }
+
+    #[test]
+    fn rotating_file() {
+        let dir = std::env::temp_dir().join(format!("pls-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pls.log");
+
+        let mut file = super::RotatingFile::open(&path, 10).unwrap();
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        file.write_all(b"third\n").unwrap();
+        let current = std::fs::read_to_string(&path).unwrap();
+        let old = std::fs::read_to_string(dir.join("pls.log.1")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(current, "third\n");
+        assert_eq!(old, "second\n");
+    }
+}
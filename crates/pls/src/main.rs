@@ -1,68 +1,140 @@
+use clap::Parser;
 use lsp_server::Connection;
 
 use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
 
+mod analyze;
+mod check;
+mod classmap;
+mod cli;
 mod code_action;
+mod command;
 mod compat;
+mod completion;
 mod config;
 mod diagnostics;
 mod file;
+mod folding;
+mod generate;
 mod global_state;
 mod handlers;
+mod hover;
+mod imports;
+mod index;
+mod inheritance;
+mod logging;
 mod messages;
+mod native_types;
+mod psr4;
+mod refactor;
+mod rename;
 mod registry;
 mod scope;
 mod stubs;
+mod symbols;
+mod transport;
+mod usage;
+mod vendor;
+mod version;
 
+use cli::{Cli, CliCommand, StubsCommand};
 use global_state::GlobalState;
 
-const VERSION_ARG: &'static str = "--version";
-
 fn main() -> anyhow::Result<()> {
-    colog::init();
-
-    // no need to include `clap` when this will suffice
-    let mut stubs_filename = None;
-    for (i, arg) in env::args().enumerate() {
-        if i == 0 {
-            continue;
-        }
+    let cli = Cli::parse();
+    logging::init(cli.log_level, cli.log_file.as_deref())?;
+    logging::install_panic_hook();
 
-        if &arg == VERSION_ARG {
+    let stubs_filename = cli.stubs_filename();
+    match cli.command {
+        Some(CliCommand::Stubs {
+            command: StubsCommand::Install,
+        }) => {
+            let dir = stubs::stubs_install_dir()
+                .ok_or(anyhow::anyhow!("nowhere to put the stubs; set $HOME"))?;
+            let stubs_filename = command::install_stubs(&dir)?;
             log::info!(
-                "{} version {}",
-                env!("CARGO_PKG_NAME"),
-                env!("CARGO_PKG_VERSION")
+                "installed PhpStorm stubs {} at {stubs_filename:?}",
+                command::STUBS_RELEASE
             );
-            return Ok(());
-        } else {
-            stubs_filename = Some(arg);
-            break;
         }
-    }
-
-    match stubs_filename {
-        None => {
-            log::error!(
-                "missing argument: location of stubs file; e.g.: `{} phpstorm-stubs/PhpStormStubsMap.php`",
-                env!("CARGO_PKG_NAME")
-            );
-            return Ok(());
+        Some(CliCommand::Index) => {
+            let root = env::current_dir()?;
+            let index = index::Index::build(&root);
+            let path = index.write(&root)?;
+            log::info!("indexed {} files into {path:?}", index.file_count());
+        }
+        Some(CliCommand::Check { format, mut paths }) => {
+            let root = env::current_dir()?;
+            if paths.is_empty() {
+                paths.push(root.clone());
+            }
+            let diagnostics = check::check(&root, &paths, stubs_filename)?;
+            print!("{}", check::render(format, &root, &diagnostics));
+            if check::has_errors(&diagnostics) {
+                process::exit(1);
+            }
         }
-        Some(stubs_filename) => {
+        None => {
             log::info!("starting server version {}", env!("CARGO_PKG_VERSION"));
-
-            let (connection, _io_threads) = Connection::stdio();
-            let mut state =
-                GlobalState::new(&stubs_filename, connection).expect("global state initialization");
-            let notification_registry = registry::NotificationRegistry::default();
-            let request_registry = registry::RequestRegistry::default();
-
-            state.main_loop((&notification_registry, &request_registry));
-            // joining io_threads usually hangs everything because they are waiting for inputs in
-            // some syscall; we get our os to clean up the threads instead.
+            let stubs_filename = stubs_filename.as_deref();
+            if let Some(addr) = &cli.listen {
+                let listener = TcpListener::bind(addr)?;
+                log::info!("listening on {:?}", listener.local_addr()?);
+                transport::serve_tcp(listener, |connection| {
+                    if let Err(e) = serve(stubs_filename, connection) {
+                        log::error!("Err in client session: {e:?}");
+                    }
+                })?;
+            } else if let Some(path) = &cli.socket {
+                serve_socket(path, stubs_filename)?;
+            } else {
+                let (connection, _io_threads) = Connection::stdio();
+                serve(stubs_filename, connection)?;
+                // joining io_threads usually hangs everything because they are waiting for
+                // inputs in some syscall; we get our os to clean up the threads instead.
+            }
         }
     }
 
     Ok(())
 }
+
+/// Answer a client until it shuts us down or goes away.
+fn serve(stubs_filename: Option<&Path>, connection: Connection) -> anyhow::Result<()> {
+    // without a stubs file, we go looking for one once we know the workspace folders
+    let mut state = GlobalState::new(stubs_filename, connection)?;
+    let notification_registry = registry::NotificationRegistry::default();
+    let request_registry = registry::RequestRegistry::default();
+
+    state.main_loop((&notification_registry, &request_registry));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve_socket(path: &Path, stubs_filename: Option<&Path>) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // a server that didn't get to clean up leaves its socket behind
+    if fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    log::info!("listening on {path:?}");
+    transport::serve_unix(listener, |connection| {
+        if let Err(e) = serve(stubs_filename, connection) {
+            log::error!("Err in client session: {e:?}");
+        }
+    })
+}
+
+#[cfg(not(unix))]
+fn serve_socket(_path: &Path, _stubs_filename: Option<&Path>) -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("`--socket` needs unix domain sockets"))
+}
@@ -5,7 +5,11 @@ use std::path::PathBuf;
 #[derive(Debug)]
 pub enum Task {
     AnalyzeStubs,
+    /// Switch to the stubs map at this path.
+    UseStubs(PathBuf),
     AnalyzeFile(PathBuf),
+    /// Look for the file declaring this FQN and ingest it.
+    AnalyzeNs(String),
 }
 
 pub enum AnalysisThreadMessage {
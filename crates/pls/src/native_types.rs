@@ -0,0 +1,591 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use pls_types::{CustomType, CustomTypesDatabase, PhpNamespace, SegmentPool};
+
+use crate::analyze::{file_scope, node_markup};
+use crate::compat::{to_point, to_position};
+use crate::hover::enclosing_class;
+
+/// Where a type declaration goes, since not every type can be declared everywhere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Parameter,
+    Property,
+    Return,
+}
+
+/// The native spelling of a type written in a docblock, if it's a single type (or a nullable one)
+/// that can be declared in `slot`.
+fn native_type(doc: &str, slot: Slot) -> Option<String> {
+    let (doc, nullable) = match doc.strip_prefix('?') {
+        Some(inner) => (inner, true),
+        None => match doc.split('|').collect::<Vec<_>>()[..] {
+            [t] => (t, false),
+            [t, "null"] | ["null", t] => (t, true),
+            _ => return None,
+        },
+    };
+
+    let lower = doc.to_lowercase();
+    let t = match lower.as_str() {
+        "int" | "integer" => "int",
+        "string" => "string",
+        "bool" | "boolean" => "bool",
+        "float" | "double" => "float",
+        "array" => "array",
+        "object" => "object",
+        "iterable" => "iterable",
+        "self" => "self",
+        "callable" if slot != Slot::Property => "callable",
+        "static" | "$this" if slot == Slot::Return => "static",
+        "void" | "never" if slot == Slot::Return && !nullable => return Some(lower),
+        "callable" | "static" | "$this" | "void" | "never" | "mixed" | "resource" | "null"
+        | "false" | "true" | "list" | "scalar" | "numeric" | "number" => return None,
+        _ => {
+            let name = doc.trim_start_matches('\\');
+            if name.is_empty()
+                || name.starts_with(|c: char| c.is_ascii_digit())
+                || !name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '\\')
+            {
+                return None;
+            }
+            doc
+        }
+    };
+
+    Some(if nullable {
+        format!("?{}", t)
+    } else {
+        t.to_string()
+    })
+}
+
+/// The type a literal value has, in docblock spelling.
+pub fn literal_type(node: Node<'_>, content: &str) -> Option<String> {
+    let t = match node.kind() {
+        "integer" => "int",
+        "float" => "float",
+        "string" | "encapsed_string" | "heredoc" | "nowdoc" => "string",
+        "boolean" => "bool",
+        "array_creation_expression" => "array",
+        "unary_op_expression" => {
+            let argument = node.child_by_field_name("argument")?;
+            return matches!(argument.kind(), "integer" | "float")
+                .then(|| literal_type(argument, content))?;
+        }
+        "object_creation_expression" => {
+            let mut cursor = node.walk();
+            let class = node
+                .named_children(&mut cursor)
+                .find(|c| c.kind() == "name" || c.kind() == "qualified_name")?;
+            return Some(content[class.byte_range()].to_string());
+        }
+        _ => return None,
+    };
+
+    Some(t.to_string())
+}
+
+fn is_null(node: Node<'_>, content: &str) -> bool {
+    node.kind() == "null" || content[node.byte_range()].eq_ignore_ascii_case("null")
+}
+
+/// The type a docblock tag like `@param` gives, and what the tag is about, if anything.
+fn tags<'a>(markup: &'a str, tag: &str) -> Vec<(&'a str, Option<&'a str>)> {
+    markup
+        .lines()
+        .filter_map(|line| {
+            let mut words = line
+                .trim_start()
+                .trim_start_matches(['/', '*'])
+                .split_whitespace();
+            if words.next() != Some(tag) {
+                return None;
+            }
+            let t = words.next()?;
+            let subject = words
+                .next()
+                .filter(|w| w.trim_start_matches(['&', '.']).starts_with('$'));
+
+            Some((t, subject))
+        })
+        .collect()
+}
+
+fn insertion(position: Position, new_text: String) -> TextEdit {
+    TextEdit {
+        range: Range::new(position, position),
+        new_text,
+    }
+}
+
+fn parameter_type(
+    parameter: Node<'_>,
+    content: &str,
+    markup: Option<&str>,
+) -> Option<(String, TextEdit)> {
+    if parameter.child_by_field_name("type").is_some() {
+        return None;
+    }
+    let name = &content[parameter.child_by_field_name("name")?.byte_range()];
+    let default = parameter.child_by_field_name("default_value");
+
+    let documented = markup.and_then(|markup| {
+        tags(markup, "@param")
+            .into_iter()
+            .find(|(_, subject)| {
+                subject.is_some_and(|subject| subject.trim_start_matches(['&', '.']) == name)
+            })
+            .map(|(t, _)| t.to_string())
+    });
+    let t = match documented {
+        Some(t) => native_type(&t, Slot::Parameter)?,
+        None => native_type(&literal_type(default?, content)?, Slot::Parameter)?,
+    };
+    // a `null` default doesn't make the type nullable implicitly anymore
+    let t = match default {
+        Some(default) if is_null(default, content) && !t.starts_with('?') => format!("?{}", t),
+        _ => t,
+    };
+
+    // the type goes after attributes, but before `&` and `...`
+    let mut cursor = parameter.walk();
+    let before = parameter
+        .children(&mut cursor)
+        .find(|c| matches!(c.kind(), "reference_modifier" | "..." | "variable_name"))?;
+
+    Some((
+        format!("Add type `{}` to `{}`", t, name),
+        insertion(to_position(&before.start_position()), format!("{} ", t)),
+    ))
+}
+
+fn property_type(declaration: Node<'_>, content: &str) -> Option<(String, TextEdit)> {
+    if declaration.child_by_field_name("type").is_some() {
+        return None;
+    }
+    let mut cursor = declaration.walk();
+    let elements: Vec<_> = declaration
+        .named_children(&mut cursor)
+        .filter(|c| c.kind() == "property_element")
+        .collect();
+    let first = *elements.first()?;
+    let defaults: Vec<_> = elements
+        .iter()
+        .map(|element| element.child_by_field_name("default_value"))
+        .collect();
+
+    let documented = node_markup(declaration, content).and_then(|markup| {
+        tags(&markup, "@var")
+            .into_iter()
+            .next()
+            .map(|(t, _)| t.to_string())
+    });
+    let t = match documented {
+        Some(t) => native_type(&t, Slot::Property)?,
+        // every property has to agree on the type
+        None => {
+            let types = defaults
+                .iter()
+                .map(|default| literal_type((*default)?, content))
+                .collect::<Option<Vec<_>>>()?;
+            if types.iter().any(|t| *t != types[0]) {
+                return None;
+            }
+            native_type(&types[0], Slot::Property)?
+        }
+    };
+    let t = if !t.starts_with('?')
+        && defaults
+            .iter()
+            .flatten()
+            .any(|default| is_null(*default, content))
+    {
+        format!("?{}", t)
+    } else {
+        t
+    };
+
+    let names: Vec<_> = elements
+        .iter()
+        .filter_map(|element| element.child_by_field_name("name"))
+        .map(|name| &content[name.byte_range()])
+        .collect();
+    Some((
+        format!("Add type `{}` to `{}`", t, names.join("`, `")),
+        insertion(to_position(&first.start_position()), format!("{} ", t)),
+    ))
+}
+
+/// Every `return` of a function body, not counting nested functions. Returns `None` if the body
+/// yields, since that makes the function a generator.
+fn return_statements<'a>(node: Node<'a>, found: &mut Vec<Node<'a>>) -> Option<()> {
+    match node.kind() {
+        "return_statement" => found.push(node),
+        "yield_expression" => return None,
+        "function_definition"
+        | "anonymous_function"
+        | "arrow_function"
+        | "class_declaration"
+        | "anonymous_class" => return Some(()),
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        return_statements(child, found)?;
+    }
+
+    Some(())
+}
+
+/// The type a function body returns, if every `return` agrees on it.
+pub fn inferred_return_type(body: Node<'_>, content: &str) -> Option<String> {
+    let mut returns = Vec::new();
+    return_statements(body, &mut returns)?;
+    let values: Vec<_> = returns.iter().map(|r| r.named_child(0)).collect();
+    if values.iter().all(Option::is_none) {
+        return Some("void".to_string());
+    }
+
+    // falling off the end would return `null`
+    let mut cursor = body.walk();
+    let last = body
+        .named_children(&mut cursor)
+        .filter(|c| c.kind() != "comment")
+        .last()?;
+    if last.kind() != "return_statement" {
+        return None;
+    }
+
+    let types = values
+        .into_iter()
+        .map(|value| literal_type(value?, content))
+        .collect::<Option<Vec<_>>>()?;
+    types
+        .iter()
+        .all(|t| *t == types[0])
+        .then(|| types[0].clone())
+}
+
+fn return_type(
+    function: Node<'_>,
+    content: &str,
+    markup: Option<&str>,
+) -> Option<(String, TextEdit)> {
+    if function.child_by_field_name("return_type").is_some() {
+        return None;
+    }
+    let name = &content[function.child_by_field_name("name")?.byte_range()];
+    if name.eq_ignore_ascii_case("__construct") || name.eq_ignore_ascii_case("__destruct") {
+        return None;
+    }
+
+    let documented = markup.and_then(|markup| {
+        tags(markup, "@return")
+            .into_iter()
+            .next()
+            .map(|(t, _)| t.to_string())
+    });
+    let t = match documented {
+        Some(t) => native_type(&t, Slot::Return)?,
+        None => native_type(
+            &inferred_return_type(function.child_by_field_name("body")?, content)?,
+            Slot::Return,
+        )?,
+    };
+
+    let parameters = function.child_by_field_name("parameters")?;
+    Some((
+        format!("Add return type `{}`", t),
+        insertion(to_position(&parameters.end_position()), format!(": {}", t)),
+    ))
+}
+
+/// Whether `class` itself declares the method or property `member` (a property with its `$`).
+fn declares(types: &CustomTypesDatabase, class: &PhpNamespace, member: &str) -> bool {
+    let (properties, methods) = match types.0.get(class).map(|meta| &meta.t) {
+        Some(CustomType::Class(c)) => (Some(&c.properties), &c.methods),
+        Some(CustomType::Interface(i)) => (Some(&i.properties), &i.methods),
+        Some(CustomType::Trait(t)) => (Some(&t.properties), &t.methods),
+        Some(CustomType::Enumeration(e)) => (None, &e.methods),
+        _ => return false,
+    };
+
+    if member.starts_with('$') {
+        properties.is_some_and(|properties| properties.contains_key(member))
+    } else {
+        methods.keys().any(|name| name.eq_ignore_ascii_case(member))
+    }
+}
+
+/// Return true if a member of the class-like at `declaration` is also declared by one of its
+/// ancestors or descendants. Adding a type to only one of them would make PHP refuse the other
+/// for not being compatible.
+///
+/// We assume the worst when we can't see the whole hierarchy.
+fn is_overridden(
+    declaration: Node<'_>,
+    root: Node<'_>,
+    content: &str,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> bool {
+    let members: Vec<_> = if declaration.kind() == "property_declaration" {
+        let mut cursor = declaration.walk();
+        declaration
+            .named_children(&mut cursor)
+            .filter(|c| c.kind() == "property_element")
+            .filter_map(|element| element.child_by_field_name("name"))
+            .map(|name| &content[name.byte_range()])
+            .collect()
+    } else {
+        declaration
+            .child_by_field_name("name")
+            .map(|name| &content[name.byte_range()])
+            .into_iter()
+            .collect()
+    };
+    let scope = file_scope(root, content, ns_store);
+    let Some(class) = enclosing_class(declaration, content, &scope) else {
+        return true;
+    };
+    let Ok(effective) = types.effective_members(&class) else {
+        return true;
+    };
+    if !effective.unresolved.is_empty() {
+        return true;
+    }
+
+    members.iter().any(|member| {
+        effective
+            .ancestors
+            .iter()
+            .any(|ancestor| declares(types, ancestor, member))
+            || types.0.keys().any(|descendant| {
+                types
+                    .effective_members(descendant)
+                    .is_ok_and(|m| m.ancestors.contains(&class))
+                    && declares(types, descendant, member)
+            })
+    })
+}
+
+/// Edits that declare native types where the cursor is, for the parameters, return types and
+/// properties that have none, but whose docblock or value tells us a single concrete type.
+///
+/// On a function's signature, that's all of its parameters and its return type. Methods and
+/// properties that are overridden, or override something, are left alone.
+pub fn native_types(
+    node: Node<'_>,
+    content: &str,
+    range: &Range,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Vec<(String, TextEdit)> {
+    let root = node;
+    let Some(mut node) =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.start))
+    else {
+        return vec![];
+    };
+
+    loop {
+        match node.kind() {
+            "simple_parameter" | "variadic_parameter" => {
+                let Some(function) = node.parent().and_then(|p| p.parent()) else {
+                    return vec![];
+                };
+                if function.kind() == "method_declaration"
+                    && is_overridden(function, root, content, types, ns_store)
+                {
+                    return vec![];
+                }
+                let markup = node_markup(function, content);
+                return parameter_type(node, content, markup.as_deref())
+                    .into_iter()
+                    .collect();
+            }
+            "property_declaration" => {
+                if is_overridden(node, root, content, types, ns_store) {
+                    return vec![];
+                }
+                return property_type(node, content).into_iter().collect();
+            }
+            "function_definition" | "method_declaration" => {
+                if node.kind() == "method_declaration"
+                    && is_overridden(node, root, content, types, ns_store)
+                {
+                    return vec![];
+                }
+                let markup = node_markup(node, content);
+                let mut edits = Vec::new();
+                if let Some(parameters) = node.child_by_field_name("parameters") {
+                    let mut cursor = parameters.walk();
+                    edits.extend(parameters.named_children(&mut cursor).filter_map(|p| {
+                        matches!(p.kind(), "simple_parameter" | "variadic_parameter")
+                            .then(|| parameter_type(p, content, markup.as_deref()))
+                            .flatten()
+                    }));
+                }
+                edits.extend(return_type(node, content, markup.as_deref()));
+                return edits;
+            }
+            // not on a signature
+            "compound_statement" | "declaration_list" | "anonymous_function" | "arrow_function" => {
+                return vec![];
+            }
+            _ => {}
+        }
+
+        let Some(parent) = node.parent() else {
+            return vec![];
+        };
+        node = parent;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Position, Range};
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use pls_types::{CustomTypesDatabase, SegmentPool};
+
+    use std::path::Path;
+
+    use crate::analyze::injest_types;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    /// The types offered at a position, with where they go and what gets inserted.
+    fn native_types_at(src: &str, line: u32, character: u32) -> Vec<(String, Position, String)> {
+        let tree = parser().parse(src, None).unwrap();
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/tmp/test.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        super::native_types(
+            tree.root_node(),
+            src,
+            &Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            ),
+            &types,
+            &mut pool,
+        )
+        .into_iter()
+        .map(|(title, edit)| {
+            assert_eq!(edit.range.start, edit.range.end);
+            (title, edit.range.start, edit.new_text)
+        })
+        .collect()
+    }
+
+    #[test]
+    fn native_types() {
+        let src = "<?php
+class Invoice {
+    /** @var int */
+    public $total;
+    protected $lines = [], $notes = [];
+    private $owner = null;
+
+    /**
+     * @param integer $amount
+     * @param Customer|null $customer
+     * @param string[] $tags
+     * @return $this
+     */
+    public function add($amount, $customer, $tags, &$log = null, $rate = 1.5) {
+        return $this;
+    }
+
+    public function count() {
+        if ($this->total > 0) {
+            return 1;
+        }
+        return 0;
+    }
+
+    public function clear() {
+        $this->lines = [];
+    }
+}
+";
+        let on = |line, character| native_types_at(src, line, character);
+
+        assert_eq!(
+            on(3, 12),
+            [(
+                "Add type `int` to `$total`".to_string(),
+                Position::new(3, 11),
+                "int ".to_string()
+            )]
+        );
+        assert_eq!(on(4, 5)[0].0, "Add type `array` to `$lines`, `$notes`");
+        // nothing to go on
+        assert!(on(5, 5).is_empty());
+
+        let titles: Vec<_> = on(13, 22).into_iter().map(|(title, ..)| title).collect();
+        assert_eq!(
+            titles,
+            [
+                "Add type `int` to `$amount`",
+                "Add type `?Customer` to `$customer`",
+                "Add type `float` to `$rate`",
+                "Add return type `static`",
+            ]
+        );
+        assert_eq!(on(13, 26)[0].1, Position::new(13, 24));
+        // inside the body
+        assert!(on(14, 10).is_empty());
+
+        assert_eq!(on(17, 22)[0].0, "Add return type `int`");
+        assert_eq!(on(17, 22)[0].1, Position::new(17, 27));
+        assert_eq!(on(24, 22)[0].2, ": void");
+    }
+    #[test]
+    fn overridden_members_left_alone() {
+        let src = "<?php
+class Base {
+    public $total = 0;
+    public function add($amount) {}
+}
+class Child extends Base {
+    /** @var int */
+    public $total = 0;
+    /** @param int $amount */
+    public function add($amount) {}
+    /** @return int */
+    public function own() { return 1; }
+}
+";
+        let on = |line, character| native_types_at(src, line, character);
+
+        assert!(on(2, 12).is_empty());
+        assert!(on(3, 25).is_empty());
+        assert!(on(7, 12).is_empty());
+        assert!(on(9, 25).is_empty());
+        assert!(on(9, 28).is_empty());
+        assert_eq!(on(11, 22)[0].0, "Add return type `int`");
+    }
+}
@@ -0,0 +1,256 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use pls_types::{PhpNamespace, SegmentPool, expected_namespaces};
+
+use crate::code_action::QuickFix;
+use crate::compat::to_range;
+
+/// Check that a file is laid out the way the PSR-4 autoload expects, so that its classes can be
+/// found at all.
+///
+/// Files outside of the autoloaded directories are left alone.
+pub fn check(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    ns_to_dir: &HashMap<PhpNamespace, Vec<PathBuf>>,
+    ns_store: &mut SegmentPool,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if node.kind() != "program" {
+        return diagnostics;
+    }
+
+    let mut namespace = None;
+    let mut class = None;
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "namespace_definition" if namespace.is_none() => namespace = Some(child),
+            "class_declaration"
+            | "interface_declaration"
+            | "trait_declaration"
+            | "enum_declaration"
+                if class.is_none() =>
+            {
+                class = Some(child)
+            }
+            _ => {}
+        }
+    }
+    // function files get loaded through `files` instead, and can be namespaced however they like
+    let Some(class) = class else {
+        return diagnostics;
+    };
+
+    let expected = expected_namespaces(path, ns_to_dir, ns_store);
+    if expected.is_empty() {
+        return diagnostics;
+    }
+    if let Some(name) = namespace.and_then(|n| n.child_by_field_name("name")) {
+        check_namespace(name, content, &expected, ns_store, &mut diagnostics);
+    }
+    if let Some(name) = class.child_by_field_name("name") {
+        check_class_name(name, content, path, &mut diagnostics);
+    }
+
+    diagnostics
+}
+
+/// Flag a namespace declaration that isn't one PSR-4 expects for the file's directory.
+fn check_namespace(
+    name: Node<'_>,
+    content: &str,
+    expected: &[PhpNamespace],
+    ns_store: &mut SegmentPool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(first) = expected.first() else {
+        return;
+    };
+    // removing the namespace declaration altogether is more than we'd like to do in a quick fix
+    if first.len() == 0 {
+        return;
+    }
+    let declared = ns_store.intern_str(&content[name.byte_range()]);
+    if expected.contains(&declared) {
+        return;
+    }
+
+    let fix = QuickFix {
+        title: format!("Change namespace to `{}`", first.0.join("\\")),
+        edits: vec![TextEdit {
+            range: to_range(&name.range()),
+            new_text: first.0.join("\\"),
+        }],
+    };
+    diagnostics.push(Diagnostic {
+        range: to_range(&name.range()),
+        severity: Some(DiagnosticSeverity::WARNING),
+        source: Some("psr4".to_string()),
+        message: format!(
+            "namespace {} doesn't match the file's location, expected {}",
+            declared, first
+        ),
+        data: serde_json::to_value(fix).ok(),
+        ..Default::default()
+    });
+}
+
+/// Flag a file whose first class-like declaration isn't named after the file, since the autoloader
+/// would never look for it there.
+fn check_class_name(name: Node<'_>, content: &str, path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return;
+    };
+    let declared = &content[name.byte_range()];
+    // PSR-0 looks for `Twig_Environment` in `Twig/Environment.php`
+    if declared == stem
+        || declared
+            .rsplit_once('_')
+            .is_some_and(|(_, last)| last == stem)
+    {
+        return;
+    }
+
+    // file names like `foo-bar.php` can't be class names
+    let valid = stem
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && stem.chars().all(|c| c.is_alphanumeric() || c == '_');
+    let fix = valid.then(|| QuickFix {
+        title: format!("Rename to `{}`", stem),
+        edits: vec![TextEdit {
+            range: to_range(&name.range()),
+            new_text: stem.to_string(),
+        }],
+    });
+    diagnostics.push(Diagnostic {
+        range: to_range(&name.range()),
+        severity: Some(DiagnosticSeverity::WARNING),
+        source: Some("psr4".to_string()),
+        message: format!(
+            "{} doesn't match the file name, so it can't be autoloaded",
+            declared
+        ),
+        data: fix.and_then(|fix| serde_json::to_value(fix).ok()),
+        ..Default::default()
+    });
+}
+
+/// The directory PSR-4 expects the classes of a namespace to be in, going by the most specific
+/// autoloaded namespace it's part of.
+pub fn directory_for(
+    namespace: &PhpNamespace,
+    ns_to_dir: &HashMap<PhpNamespace, Vec<PathBuf>>,
+) -> Option<PathBuf> {
+    let (prefix, dirs) = ns_to_dir
+        .iter()
+        .filter(|(prefix, dirs)| namespace.starts_with(prefix) && !dirs.is_empty())
+        .max_by_key(|(prefix, _)| prefix.len())?;
+
+    Some(prefix.as_pathbuf(&dirs[0], namespace))
+}
+
+#[cfg(test)]
+mod test {
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    use pls_types::SegmentPool;
+
+    use crate::code_action::QuickFix;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    fn check(src: &str, path: &str) -> Vec<lsp_types::Diagnostic> {
+        let mut pool = SegmentPool::new();
+        let ns_to_dir =
+            HashMap::from([(pool.intern_str("App\\"), vec![PathBuf::from("/proj/src")])]);
+        let tree = parser().parse(src, None).unwrap();
+
+        super::check(
+            tree.root_node(),
+            src,
+            Path::new(path),
+            &ns_to_dir,
+            &mut pool,
+        )
+    }
+
+    #[test]
+    fn namespace_mismatch() {
+        let src = "<?php
+namespace App\\Controller;
+
+class Foo {}
+";
+        assert!(check(src, "/proj/src/Controller/Foo.php").is_empty());
+        assert!(check(src, "/proj/bin/Foo.php").is_empty());
+
+        let diagnostics = check(src, "/proj/src/Http/Controller/Foo.php");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "namespace \\App\\Controller doesn't match the file's location, expected \\App\\Http\\Controller"
+        );
+        let fix: QuickFix = serde_json::from_value(diagnostics[0].data.clone().unwrap()).unwrap();
+        assert_eq!(fix.edits[0].new_text, "App\\Http\\Controller");
+        assert_eq!(fix.edits[0].range, diagnostics[0].range);
+
+        // files of functions aren't autoloaded through PSR-4
+        let src = "<?php
+namespace Helpers;
+
+function helper() {}
+";
+        assert!(check(src, "/proj/src/helpers.php").is_empty());
+    }
+
+    #[test]
+    fn class_name_mismatch() {
+        let src = "<?php
+namespace App;
+
+interface Bar {}
+class Foo implements Bar {}
+";
+        assert!(check(src, "/proj/src/Bar.php").is_empty());
+        assert!(check(src, "/proj/bin/Baz.php").is_empty());
+
+        let diagnostics = check(src, "/proj/src/Baz.php");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 3);
+        assert_eq!(
+            diagnostics[0].message,
+            "Bar doesn't match the file name, so it can't be autoloaded"
+        );
+        let fix: QuickFix = serde_json::from_value(diagnostics[0].data.clone().unwrap()).unwrap();
+        assert_eq!(fix.title, "Rename to `Baz`");
+        assert_eq!(fix.edits[0].new_text, "Baz");
+
+        let diagnostics = check(src, "/proj/src/bar-baz.php");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].data.is_none());
+
+        let legacy = "<?php
+class Twig_Environment {}
+";
+        assert!(check(legacy, "/proj/src/Twig/Environment.php").is_empty());
+    }
+}
@@ -0,0 +1,1453 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use pls_types::{PhpNamespace, SegmentPool};
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::analyze::{node_markup, walk_statement, whole_lines};
+use crate::compat::{to_point, to_position, to_range};
+use crate::imports::imports_used_by;
+use crate::psr4::directory_for;
+use crate::scope::{SUPERGLOBALS, Scope};
+
+pub const EXTRACT_VARIABLE_TITLE: &str = "Extract to variable";
+pub const EXTRACT_METHOD_TITLE: &str = "Extract method";
+pub const INLINE_VARIABLE_TITLE: &str = "Inline variable";
+pub const ARROW_FUNCTION_TITLE: &str = "Convert to arrow function";
+pub const ANONYMOUS_FUNCTION_TITLE: &str = "Convert to anonymous function";
+pub const MOVE_CLASS_TITLE: &str = "Move class to new file";
+pub const SWITCH_TO_MATCH_TITLE: &str = "Convert to match expression (compares strictly)";
+
+fn is_function_like(kind: &str) -> bool {
+    matches!(
+        kind,
+        "function_definition" | "method_declaration" | "anonymous_function" | "arrow_function"
+    )
+}
+
+/// Whether a node is a value that can be stored in a variable, and that's worth storing.
+fn is_extractable(node: Node<'_>) -> bool {
+    let kind = node.kind();
+    if kind == "variable_name" || kind == "assignment_expression" || is_function_like(kind) {
+        return false;
+    }
+    // the targets of assignments aren't values
+    if node.parent().is_some_and(|p| {
+        p.kind().ends_with("assignment_expression") && p.child_by_field_name("left") == Some(node)
+    }) {
+        return false;
+    }
+
+    kind.ends_with("_expression")
+        || matches!(
+            kind,
+            "integer" | "float" | "string" | "encapsed_string" | "boolean" | "null" | "heredoc"
+        )
+}
+
+/// The scope of the function (or file) `node` is in, with every variable that shows up anywhere in
+/// it, so that new names don't clash with any of them.
+fn surrounding_scope(node: Node<'_>, content: &str) -> Scope {
+    let mut root = node;
+    while let Some(parent) = root.parent() {
+        root = parent;
+        if is_function_like(root.kind()) {
+            break;
+        }
+    }
+
+    let mut scope = Scope::empty();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.kind() == "variable_name" {
+            scope.symbols.insert(content[node.byte_range()].to_string());
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+
+    scope
+}
+
+/// A variable name based on `base` that isn't in scope yet.
+fn fresh_name(scope: &Scope, base: &str) -> String {
+    let name = format!("${}", base);
+    if !scope.symbols.contains(&name) {
+        return name;
+    }
+
+    (2..)
+        .map(|i| format!("{}{}", name, i))
+        .find(|name| !scope.symbols.contains(name))
+        .expect("some name must be free")
+}
+
+/// The statement that `node` is part of, as long as `node` gets evaluated whenever the statement
+/// runs.
+fn enclosing_statement(node: Node<'_>) -> Option<Node<'_>> {
+    let mut statement = node;
+    loop {
+        let parent = statement.parent()?;
+        if matches!(
+            parent.kind(),
+            "compound_statement" | "program" | "case_statement" | "default_statement"
+        ) {
+            return Some(statement);
+        }
+        // the expression would move out of the closure it belongs to
+        if is_function_like(parent.kind()) {
+            return None;
+        }
+        statement = parent;
+    }
+}
+
+/// An edit that stores the expression selected by `range` in a new variable, declared right before
+/// the statement it's in, and uses the variable in its place.
+pub fn extract_variable(node: Node<'_>, content: &str, range: &Range) -> Option<TextEdit> {
+    if range.start == range.end {
+        return None;
+    }
+    let mut expression =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.end))?;
+    // the selection has to be exactly the expression, but wrappers of it are fine
+    while to_range(&expression.range()) == *range && !is_extractable(expression) {
+        expression = expression.parent()?;
+    }
+    if to_range(&expression.range()) != *range || !is_extractable(expression) {
+        return None;
+    }
+
+    let statement = enclosing_statement(expression)?;
+    let name = fresh_name(&surrounding_scope(expression, content), "extracted");
+    let indent = indent_of(statement, content);
+
+    // one edit from the start of the statement, so that the declaration and the replacement can't
+    // get applied out of order
+    let new_text = format!(
+        "{} = {};\n{}{}{}",
+        name,
+        &content[expression.byte_range()],
+        indent,
+        &content[statement.start_byte()..expression.start_byte()],
+        name
+    );
+
+    Some(TextEdit {
+        range: Range::new(to_position(&statement.start_position()), range.end),
+        new_text,
+    })
+}
+
+/// The whitespace a node's line starts with, if nothing else comes before the node.
+fn indent_of<'a>(node: Node<'_>, content: &'a str) -> &'a str {
+    let line_start = content[..node.start_byte()]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let indent = &content[line_start..node.start_byte()];
+
+    if indent.trim().is_empty() { indent } else { "" }
+}
+
+/// Every variable under `node`, in order.
+fn variables<'a>(node: Node<'a>, variables: &mut Vec<Node<'a>>) {
+    if node.kind() == "variable_name" {
+        variables.push(node);
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        self::variables(child, variables);
+    }
+}
+
+/// Return true if running `node` somewhere else would change where control goes next: it returns
+/// or yields, or breaks out of a loop that starts before `start`.
+fn escapes(node: Node<'_>, start: usize) -> bool {
+    match node.kind() {
+        "return_statement" | "yield_expression" => return true,
+        "break_statement" | "continue_statement" => {
+            let mut ancestor = node.parent();
+            while let Some(a) = ancestor {
+                if matches!(
+                    a.kind(),
+                    "for_statement"
+                        | "foreach_statement"
+                        | "while_statement"
+                        | "do_statement"
+                        | "switch_statement"
+                ) {
+                    return a.start_byte() < start;
+                }
+                ancestor = a.parent();
+            }
+            return true;
+        }
+        kind if is_function_like(kind) => return false,
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .any(|child| escapes(child, start))
+}
+
+/// Edits that move the statements selected by `range` into a new method (or function, outside of
+/// classes), and call it in their place.
+///
+/// Variables the statements read that were set beforehand become parameters, and variables they
+/// set that get read afterwards are returned.
+pub fn extract_method(node: Node<'_>, content: &str, range: &Range) -> Option<Vec<TextEdit>> {
+    if range.start == range.end {
+        return None;
+    }
+    let mut block =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.end))?;
+    while block.kind() != "compound_statement" {
+        block = block.parent()?;
+    }
+    let mut function = block;
+    while !is_function_like(function.kind()) {
+        function = function.parent()?;
+    }
+    if function.kind() != "method_declaration" && function.kind() != "function_definition" {
+        return None;
+    }
+
+    let mut statements = Vec::new();
+    let mut cursor = block.walk();
+    for child in block.named_children(&mut cursor) {
+        let child_range = to_range(&child.range());
+        if child_range.start >= range.start && child_range.end <= range.end {
+            statements.push(child);
+        } else if child_range.start < range.end && range.start < child_range.end {
+            // only whole statements can be moved
+            return None;
+        }
+    }
+    let (first, last) = (*statements.first()?, *statements.last()?);
+    if statements
+        .iter()
+        .any(|statement| escapes(*statement, first.start_byte()))
+    {
+        return None;
+    }
+
+    let mut inside = Scope::empty();
+    for statement in statements.iter() {
+        walk_statement(
+            *statement,
+            content,
+            &mut SegmentPool::new(),
+            &mut inside,
+            &mut Vec::new(),
+        );
+    }
+    // in a loop, the statements can read what was set after them on the last iteration, and what
+    // they set can be read before them on the next one
+    let (mut loop_start, mut loop_end) = (first.start_byte(), last.end_byte());
+    let mut ancestor = block.parent();
+    while let Some(a) = ancestor.filter(|a| *a != function) {
+        if matches!(
+            a.kind(),
+            "for_statement" | "foreach_statement" | "while_statement" | "do_statement"
+        ) {
+            (loop_start, loop_end) = (a.start_byte(), a.end_byte());
+        }
+        ancestor = a.parent();
+    }
+    let mut around = Vec::new();
+    variables(function, &mut around);
+    let before: HashSet<_> = around
+        .iter()
+        .filter(|v| {
+            v.start_byte() < first.start_byte()
+                || (v.start_byte() >= last.end_byte() && v.end_byte() <= loop_end)
+        })
+        .map(|v| &content[v.byte_range()])
+        .collect();
+    let after: HashSet<_> = around
+        .iter()
+        .filter(|v| {
+            v.start_byte() >= last.end_byte()
+                || (v.start_byte() < first.start_byte() && v.start_byte() >= loop_start)
+        })
+        .map(|v| &content[v.byte_range()])
+        .collect();
+
+    let mut selected = Vec::new();
+    for statement in statements.iter() {
+        variables(*statement, &mut selected);
+    }
+    let mut parameters: Vec<&str> = Vec::new();
+    let mut returns: Vec<&str> = Vec::new();
+    for variable in selected.iter() {
+        let name = &content[variable.byte_range()];
+        if name == "$this" || SUPERGLOBALS.contains(name) {
+            continue;
+        }
+        if inside.read.contains(name) && before.contains(name) && !parameters.contains(&name) {
+            parameters.push(name);
+        }
+        if inside.assigned.contains_key(name) && after.contains(name) && !returns.contains(&name) {
+            returns.push(name);
+        }
+    }
+
+    // a name nothing else in the class (or the file) uses yet
+    let (taken_in, method) = match function.kind() {
+        "method_declaration" => (function.parent()?, true),
+        _ => (node, false),
+    };
+    let mut taken = HashSet::new();
+    let mut stack = vec![taken_in];
+    while let Some(n) = stack.pop() {
+        if matches!(n.kind(), "method_declaration" | "function_definition")
+            && let Some(name) = n.child_by_field_name("name")
+        {
+            taken.insert(content[name.byte_range()].to_lowercase());
+        }
+        let mut cursor = n.walk();
+        stack.extend(n.named_children(&mut cursor));
+    }
+    let name = (1..)
+        .map(|i| match i {
+            1 => "extracted".to_string(),
+            i => format!("extracted{}", i),
+        })
+        .find(|name| !taken.contains(name))?;
+
+    let mut cursor = function.walk();
+    let is_static = function
+        .named_children(&mut cursor)
+        .any(|c| c.kind() == "static_modifier");
+    let arguments = parameters.join(", ");
+    let (declaration, call) = match (method, is_static) {
+        (true, true) => (
+            format!("private static function {}({})", name, arguments),
+            format!("self::{}({})", name, arguments),
+        ),
+        (true, false) => (
+            format!("private function {}({})", name, arguments),
+            format!("$this->{}({})", name, arguments),
+        ),
+        _ => (
+            format!("function {}({})", name, arguments),
+            format!("{}({})", name, arguments),
+        ),
+    };
+    let call = match returns.as_slice() {
+        [] => format!("{};", call),
+        [one] => format!("{} = {};", one, call),
+        many => format!("[{}] = {};", many.join(", "), call),
+    };
+
+    let outer = indent_of(function, content);
+    let old = indent_of(first, content);
+    let unit = function
+        .child_by_field_name("body")
+        .and_then(|body| body.named_child(0))
+        .map(|s| indent_of(s, content))
+        .and_then(|indent| indent.strip_prefix(outer))
+        .filter(|unit| !unit.is_empty())
+        .unwrap_or("    ");
+    let inner = format!("{}{}", outer, unit);
+    let body: Vec<_> = content[first.start_byte() - old.len()..last.end_byte()]
+        .lines()
+        .map(|line| match line.strip_prefix(old) {
+            Some(line) => format!("{}{}", inner, line),
+            None => line.to_string(),
+        })
+        .collect();
+    let returned = match returns.as_slice() {
+        [] => String::new(),
+        [one] => format!("\n{}return {};", inner, one),
+        many => format!("\n{}return [{}];", inner, many.join(", ")),
+    };
+    let new_text = format!(
+        "\n\n{outer}{declaration}\n{outer}{{\n{}{returned}\n{outer}}}",
+        body.join("\n")
+    );
+    let end = to_position(&function.end_position());
+
+    Some(vec![
+        TextEdit {
+            range: Range::new(
+                to_position(&first.start_position()),
+                to_position(&last.end_position()),
+            ),
+            new_text: call,
+        },
+        TextEdit {
+            range: Range::new(end, end),
+            new_text,
+        },
+    ])
+}
+
+/// Return true if evaluating the expression can't change anything, so it doesn't matter how many
+/// times or when it happens.
+fn is_pure(node: Node<'_>) -> bool {
+    let pure = matches!(
+        node.kind(),
+        "variable_name"
+            | "name"
+            | "qualified_name"
+            | "integer"
+            | "float"
+            | "string"
+            | "string_content"
+            | "encapsed_string"
+            | "escape_sequence"
+            | "boolean"
+            | "null"
+            | "binary_expression"
+            | "unary_op_expression"
+            | "parenthesized_expression"
+            | "conditional_expression"
+            | "class_constant_access_expression"
+            | "array_creation_expression"
+            | "array_element_initializer"
+            | "subscript_expression"
+            | "member_access_expression"
+            | "nullsafe_member_access_expression"
+    );
+    let mut cursor = node.walk();
+
+    pure && node.named_children(&mut cursor).all(is_pure)
+}
+
+/// Return true if the variable is being written to (or bound) rather than read.
+fn is_write(variable: Node<'_>) -> bool {
+    let Some(parent) = variable.parent() else {
+        return false;
+    };
+    match parent.kind() {
+        "assignment_expression"
+        | "augmented_assignment_expression"
+        | "reference_assignment_expression" => parent.child_by_field_name("left") == Some(variable),
+        "update_expression"
+        | "list_literal"
+        | "by_ref"
+        | "pair"
+        | "global_declaration"
+        | "static_variable_declaration"
+        | "unset_statement"
+        | "simple_parameter"
+        | "variadic_parameter"
+        | "property_promotion_parameter" => true,
+        "foreach_statement" => parent.named_child(0) != Some(variable),
+        "array_element_initializer" => parent.parent().is_some_and(|p| p.kind() == "list_literal"),
+        _ => false,
+    }
+}
+
+/// The outermost element or property access that `variable` is the base of, or the variable itself.
+fn access_through(variable: Node<'_>) -> Node<'_> {
+    let mut target = variable;
+    while let Some(parent) = target.parent() {
+        let base = match parent.kind() {
+            "subscript_expression" => parent.named_child(0),
+            "member_access_expression" | "nullsafe_member_access_expression" => {
+                parent.child_by_field_name("object")
+            }
+            _ => None,
+        };
+        if base != Some(target) {
+            break;
+        }
+        target = parent;
+    }
+
+    target
+}
+
+/// Return true if the value of the variable changes, either by writing to it directly or to one of
+/// its elements or properties, like `$items[] = 1` or `$this->count++`.
+fn is_modified(variable: Node<'_>) -> bool {
+    is_write(access_through(variable))
+}
+
+/// Return true if the variable (or something in it) is passed to a function, which might take it by
+/// reference.
+fn is_passed(variable: Node<'_>) -> bool {
+    access_through(variable)
+        .parent()
+        .is_some_and(|p| p.kind() == "argument")
+}
+
+/// Every mention of the variable `name` in the scope of `node`, not counting nested functions and
+/// classes, which have their own. Returns `None` if a closure captures it.
+fn mentions<'a>(
+    node: Node<'a>,
+    name: &str,
+    content: &str,
+    found: &mut Vec<Node<'a>>,
+) -> Option<()> {
+    match node.kind() {
+        "variable_name" => {
+            if &content[node.byte_range()] == name {
+                found.push(node);
+            }
+            return Some(());
+        }
+        "anonymous_function" => {
+            let mut captured = Vec::new();
+            let mut cursor = node.walk();
+            for clause in node
+                .named_children(&mut cursor)
+                .filter(|c| c.kind() == "anonymous_function_use_clause")
+            {
+                variables(clause, &mut captured);
+            }
+            return (!captured.iter().any(|v| &content[v.byte_range()] == name)).then_some(());
+        }
+        "function_definition" | "method_declaration" | "class_declaration" | "anonymous_class" => {
+            return Some(());
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        mentions(child, name, content, found)?;
+    }
+
+    Some(())
+}
+
+/// Return true if the expression has to be wrapped in parentheses to be used in place of `read`.
+fn needs_parentheses(expression: Node<'_>, read: Node<'_>) -> bool {
+    if !matches!(
+        expression.kind(),
+        "binary_expression" | "conditional_expression" | "unary_op_expression"
+    ) {
+        return false;
+    }
+
+    !read.parent().is_some_and(|p| {
+        matches!(
+            p.kind(),
+            "argument"
+                | "expression_statement"
+                | "echo_statement"
+                | "return_statement"
+                | "parenthesized_expression"
+                | "array_element_initializer"
+        ) || p.kind() == "assignment_expression" && p.child_by_field_name("right") == Some(read)
+    })
+}
+
+/// Edits that replace the reads of the variable at `range` with the expression it's assigned, and
+/// remove the assignment.
+///
+/// The variable has to be assigned exactly once, from an expression without side effects whose
+/// variables don't change afterwards, and only read after that.
+pub fn inline_variable(node: Node<'_>, content: &str, range: &Range) -> Option<Vec<TextEdit>> {
+    let mut variable =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.start))?;
+    if variable.kind() == "name" {
+        variable = variable.parent()?;
+    }
+    if variable.kind() != "variable_name" {
+        return None;
+    }
+    let name = &content[variable.byte_range()];
+    if name == "$this" || SUPERGLOBALS.contains(name) {
+        return None;
+    }
+
+    let mut scope = variable;
+    while let Some(parent) = scope.parent() {
+        scope = parent;
+        if is_function_like(scope.kind()) && scope.kind() != "arrow_function" {
+            break;
+        }
+    }
+    let body = if scope.kind() == "program" {
+        scope
+    } else {
+        // parameters are assigned by the caller
+        let mut parameters = Vec::new();
+        variables(scope.child_by_field_name("parameters")?, &mut parameters);
+        if parameters.iter().any(|p| &content[p.byte_range()] == name) {
+            return None;
+        }
+        scope.child_by_field_name("body")?
+    };
+    let mut found = Vec::new();
+    mentions(body, name, content, &mut found)?;
+
+    let (writes, reads): (Vec<_>, Vec<_>) = found.into_iter().partition(|v| is_modified(*v));
+    let [write] = writes.as_slice() else {
+        return None;
+    };
+    let assignment = write.parent()?;
+    let statement = assignment.parent()?;
+    if assignment.kind() != "assignment_expression" || statement.kind() != "expression_statement" {
+        return None;
+    }
+    let expression = assignment.child_by_field_name("right")?;
+    if !is_pure(expression) || reads.is_empty() {
+        return None;
+    }
+    if reads.iter().any(|read| {
+        read.start_byte() < statement.end_byte()
+            || read.parent().is_some_and(|p| p.kind() == "encapsed_string")
+    }) {
+        return None;
+    }
+    // the expression has to mean the same thing wherever it ends up
+    let mut used = Vec::new();
+    variables(expression, &mut used);
+    for used in used.iter() {
+        let mut changes = Vec::new();
+        mentions(body, &content[used.byte_range()], content, &mut changes)?;
+        if changes
+            .iter()
+            .any(|v| v.start_byte() > statement.end_byte() && (is_modified(*v) || is_passed(*v)))
+        {
+            return None;
+        }
+    }
+
+    let text = &content[expression.byte_range()];
+    let mut edits: Vec<_> = reads
+        .iter()
+        .map(|read| TextEdit {
+            range: to_range(&read.range()),
+            new_text: if needs_parentheses(expression, *read) {
+                format!("({})", text)
+            } else {
+                text.to_string()
+            },
+        })
+        .collect();
+    edits.insert(
+        0,
+        TextEdit {
+            range: whole_lines(statement, content),
+            new_text: String::new(),
+        },
+    );
+
+    Some(edits)
+}
+
+/// The innermost closure the cursor is in, if it's of the given kind.
+fn closure_at<'a>(node: Node<'a>, range: &Range, kind: &str) -> Option<Node<'a>> {
+    let mut closure =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.start))?;
+    while !is_function_like(closure.kind()) {
+        closure = closure.parent()?;
+    }
+
+    (closure.kind() == kind).then_some(closure)
+}
+
+/// The variables `node` uses from its surroundings, in order of their first use, besides the ones
+/// in `bound`.
+fn free_variables(node: Node<'_>, content: &str, bound: &HashSet<&str>, free: &mut Vec<String>) {
+    match node.kind() {
+        "variable_name" => {
+            let name = &content[node.byte_range()];
+            if name != "$this"
+                && !SUPERGLOBALS.contains(name)
+                && !bound.contains(name)
+                && !free.iter().any(|v| v == name)
+            {
+                free.push(name.to_string());
+            }
+            return;
+        }
+        // only what gets captured comes from outside
+        "anonymous_function" => {
+            let mut cursor = node.walk();
+            for clause in node
+                .named_children(&mut cursor)
+                .filter(|c| c.kind() == "anonymous_function_use_clause")
+            {
+                free_variables(clause, content, bound, free);
+            }
+            return;
+        }
+        "arrow_function" => {
+            let mut bound = bound.clone();
+            bound.extend(parameters(node, content));
+            if let Some(body) = node.child_by_field_name("body") {
+                free_variables(body, content, &bound, free);
+            }
+            return;
+        }
+        "anonymous_class" => {
+            let mut cursor = node.walk();
+            for arguments in node
+                .named_children(&mut cursor)
+                .filter(|c| c.kind() == "arguments")
+            {
+                free_variables(arguments, content, bound, free);
+            }
+            return;
+        }
+        "function_definition" | "method_declaration" | "class_declaration" => return,
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        free_variables(child, content, bound, free);
+    }
+}
+
+/// The names of a function's parameters.
+fn parameters<'a>(function: Node<'_>, content: &'a str) -> Vec<&'a str> {
+    let Some(parameters) = function.child_by_field_name("parameters") else {
+        return vec![];
+    };
+    let mut cursor = parameters.walk();
+    parameters
+        .named_children(&mut cursor)
+        .filter_map(|p| p.child_by_field_name("name"))
+        .map(|name| &content[name.byte_range()])
+        .collect()
+}
+
+/// An edit that turns the anonymous function at the cursor into an arrow function.
+///
+/// The function has to do nothing but return an expression, and only use its parameters and the
+/// variables it captures by value.
+pub fn to_arrow_function(node: Node<'_>, content: &str, range: &Range) -> Option<TextEdit> {
+    let function = closure_at(node, range, "anonymous_function")?;
+    let parameters_node = function.child_by_field_name("parameters")?;
+    let body = function.child_by_field_name("body")?;
+    let mut cursor = body.walk();
+    let [statement] = body.named_children(&mut cursor).collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    if statement.kind() != "return_statement" {
+        return None;
+    }
+    let expression = statement.named_child(0)?;
+
+    let mut cursor = function.walk();
+    let clause = function
+        .named_children(&mut cursor)
+        .find(|c| c.kind() == "anonymous_function_use_clause");
+    let mut bound: HashSet<_> = parameters(function, content).into_iter().collect();
+    if let Some(clause) = clause {
+        let mut cursor = clause.walk();
+        for captured in clause.named_children(&mut cursor) {
+            // arrow functions can't capture by reference
+            if captured.kind() != "variable_name" {
+                return None;
+            }
+            bound.insert(&content[captured.byte_range()]);
+        }
+    }
+    // anything else would start getting captured
+    let mut free = Vec::new();
+    free_variables(expression, content, &bound, &mut free);
+    if !free.is_empty() {
+        return None;
+    }
+
+    let signature = match clause {
+        Some(clause) => format!(
+            "{}{}",
+            content[parameters_node.end_byte()..clause.start_byte()].trim_end(),
+            &content[clause.end_byte()..body.start_byte()]
+        ),
+        None => content[parameters_node.end_byte()..body.start_byte()].to_string(),
+    };
+    let new_text = format!(
+        "{}{}{} => {}",
+        content[function.start_byte()..parameters_node.start_byte()].replacen("function", "fn", 1),
+        &content[parameters_node.byte_range()],
+        signature.trim_end(),
+        &content[expression.byte_range()]
+    );
+
+    Some(TextEdit {
+        range: to_range(&function.range()),
+        new_text,
+    })
+}
+
+/// An edit that turns the arrow function at the cursor into an anonymous function, capturing the
+/// variables the arrow function used implicitly.
+pub fn to_anonymous_function(node: Node<'_>, content: &str, range: &Range) -> Option<TextEdit> {
+    let function = closure_at(node, range, "arrow_function")?;
+    let parameters_node = function.child_by_field_name("parameters")?;
+    let body = function.child_by_field_name("body")?;
+
+    let bound = parameters(function, content).into_iter().collect();
+    let mut free = Vec::new();
+    free_variables(body, content, &bound, &mut free);
+    let clause = if free.is_empty() {
+        String::new()
+    } else {
+        format!(" use ({})", free.join(", "))
+    };
+
+    let signature = content[parameters_node.end_byte()..body.start_byte()].trim_end();
+    let new_text = format!(
+        "{}{}{}{} {{ return {}; }}",
+        content[function.start_byte()..parameters_node.start_byte()].replacen("fn", "function", 1),
+        &content[parameters_node.byte_range()],
+        clause,
+        signature.strip_suffix("=>")?.trim_end(),
+        &content[body.byte_range()]
+    );
+
+    Some(TextEdit {
+        range: to_range(&function.range()),
+        new_text,
+    })
+}
+
+/// A class moved out of a file into one of its own.
+#[derive(Debug)]
+pub struct MovedClass {
+    pub path: PathBuf,
+    pub content: String,
+    /// Removes the class from the file it was in.
+    pub removal: TextEdit,
+}
+
+/// Move the class at `range` into its own file, where the PSR-4 autoload would look for it, along
+/// with its docblock and the imports it needs.
+///
+/// Only offered when the file declares more than one class, and not for the class the file is
+/// named after.
+pub fn move_class(
+    node: Node<'_>,
+    content: &str,
+    path: &Path,
+    range: &Range,
+    ns_to_dir: &HashMap<PhpNamespace, Vec<PathBuf>>,
+    ns_store: &mut SegmentPool,
+) -> Option<MovedClass> {
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    let classes: Vec<_> = children
+        .iter()
+        .filter(|child| {
+            matches!(
+                child.kind(),
+                "class_declaration"
+                    | "interface_declaration"
+                    | "trait_declaration"
+                    | "enum_declaration"
+            )
+        })
+        .collect();
+    let namespaces: Vec<_> = children
+        .iter()
+        .filter(|child| child.kind() == "namespace_definition")
+        .collect();
+    // with braced or several namespaces, there's no telling which imports go with which class
+    if classes.len() < 2
+        || namespaces.len() > 1
+        || namespaces
+            .iter()
+            .any(|n| n.child_by_field_name("body").is_some())
+    {
+        return None;
+    }
+
+    let class = **classes.iter().find(|class| {
+        let declared = to_range(&class.range());
+        declared.start <= range.start && range.start <= declared.end
+    })?;
+    let name = &content[class.child_by_field_name("name")?.byte_range()];
+    if path.file_stem().is_some_and(|stem| stem == name) {
+        return None;
+    }
+
+    let namespace = namespaces
+        .first()
+        .and_then(|n| n.child_by_field_name("name"))
+        .map(|name| &content[name.byte_range()]);
+    let directory = namespace
+        .and_then(|namespace| directory_for(&ns_store.intern_str(namespace), ns_to_dir))
+        .or_else(|| path.parent().map(Path::to_path_buf))?;
+    let new_path = directory.join(format!("{}.php", name));
+    if new_path.exists() {
+        return None;
+    }
+
+    let docblock = node_markup(class, content)
+        .is_some()
+        .then(|| class.prev_sibling())
+        .flatten();
+    let start = docblock.unwrap_or(class);
+
+    let mut sections = vec!["<?php".to_string()];
+    let strict = children.iter().any(|child| {
+        child.kind() == "declare_statement" && content[child.byte_range()].contains("strict_types")
+    });
+    if strict {
+        sections.push("declare(strict_types=1);".to_string());
+    }
+    if let Some(namespace) = namespace {
+        sections.push(format!("namespace {};", namespace));
+    }
+    let imports = imports_used_by(node, content, &[start, class]);
+    if !imports.is_empty() {
+        sections.push(imports.join("\n"));
+    }
+    sections.push(content[start.start_byte()..class.end_byte()].to_string());
+
+    // don't leave two blank lines behind
+    let mut removal = Range::new(
+        whole_lines(start, content).start,
+        whole_lines(class, content).end,
+    );
+    let blank = |line: u32| {
+        content
+            .lines()
+            .nth(line as usize)
+            .is_some_and(|l| l.trim().is_empty())
+    };
+    if removal.end.character == 0
+        && blank(removal.end.line)
+        && (removal.start.line == 0 || blank(removal.start.line - 1))
+    {
+        removal.end.line += 1;
+    } else if removal.start.line > 0
+        && blank(removal.start.line - 1)
+        && content.lines().nth(removal.end.line as usize).is_none()
+    {
+        removal.start.line -= 1;
+    }
+
+    Some(MovedClass {
+        path: new_path,
+        content: sections.join("\n\n") + "\n",
+        removal: TextEdit {
+            range: removal,
+            new_text: String::new(),
+        },
+    })
+}
+
+/// What every arm of a `switch` does, for it to work as a `match`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArmKind<'a> {
+    /// Assigns to the variable and breaks.
+    Assign(&'a str),
+    Return,
+    /// Throws, which fits either of the others.
+    Throw,
+}
+
+/// What a `case` does, and the expression it does it with. Only the `last` case may assign
+/// without a `break`, since any other would fall through into the next one.
+fn switch_arm<'a>(
+    statements: &[Node<'a>],
+    content: &'a str,
+    last: bool,
+) -> Option<(ArmKind<'a>, Node<'a>)> {
+    match statements {
+        [statement] | [statement, _] if statement.kind() == "expression_statement" => {
+            if let [_, brk] = statements
+                && (brk.kind() != "break_statement" || brk.named_child_count() > 0)
+            {
+                return None;
+            }
+            let expression = statement.named_child(0)?;
+            match expression.kind() {
+                "throw_expression" => Some((ArmKind::Throw, expression)),
+                "assignment_expression" => {
+                    if statements.len() == 1 && !last {
+                        return None;
+                    }
+                    let left = expression.child_by_field_name("left")?;
+                    if left.kind() != "variable_name" {
+                        return None;
+                    }
+                    let right = expression.child_by_field_name("right")?;
+                    Some((ArmKind::Assign(&content[left.byte_range()]), right))
+                }
+                _ => None,
+            }
+        }
+        [statement] if statement.kind() == "return_statement" => {
+            Some((ArmKind::Return, statement.named_child(0)?))
+        }
+        _ => None,
+    }
+}
+
+/// An edit that rewrites the `switch` at the cursor as a `match` expression, if every case either
+/// assigns to the same variable or returns (or throws). Cases that fall through become arms with
+/// several conditions, and comments are kept above the arms they were in.
+///
+/// There has to be a `default`, since a `match` without a matching arm throws where the `switch`
+/// would have carried on. Note that `match` compares strictly where `switch` compares loosely, which
+/// the title of the action says.
+pub fn switch_to_match(node: Node<'_>, content: &str, range: &Range) -> Option<TextEdit> {
+    let mut switch =
+        node.descendant_for_point_range(to_point(&range.start), to_point(&range.start))?;
+    while switch.kind() != "switch_statement" {
+        switch = switch.parent()?;
+    }
+    let condition = switch.child_by_field_name("condition")?;
+    let condition = match condition.kind() {
+        "parenthesized_expression" => condition.named_child(0)?,
+        _ => condition,
+    };
+    let body = switch.child_by_field_name("body")?;
+
+    let outer = indent_of(switch, content);
+    let mut cursor = body.walk();
+    let members: Vec<_> = body.named_children(&mut cursor).collect();
+    let inner = members
+        .iter()
+        .find(|member| member.kind() != "comment")
+        .map(|member| indent_of(*member, content))
+        .filter(|indent| indent.len() > outer.len())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}    ", outer));
+
+    let mut kind = None;
+    let mut arms = Vec::new();
+    let mut conditions = Vec::new();
+    let mut comments = Vec::new();
+    let mut has_default = false;
+    let mut seen_default = false;
+    let last = members
+        .iter()
+        .rposition(|member| member.kind() != "comment");
+    for (i, member) in members.into_iter().enumerate() {
+        if member.kind() == "comment" {
+            comments.push(&content[member.byte_range()]);
+            continue;
+        }
+        let value = member.child_by_field_name("value");
+        match value {
+            Some(value) => conditions.push(&content[value.byte_range()]),
+            None => has_default = true,
+        }
+
+        let mut cursor = member.walk();
+        let statements: Vec<_> = member
+            .named_children(&mut cursor)
+            .filter(|child| Some(*child) != value)
+            .collect();
+        comments.extend(
+            statements
+                .iter()
+                .filter(|s| s.kind() == "comment")
+                .map(|s| &content[s.byte_range()]),
+        );
+        let statements: Vec<_> = statements
+            .into_iter()
+            .filter(|s| s.kind() != "comment")
+            .collect();
+        // falls through to the next case
+        if statements.is_empty() {
+            continue;
+        }
+
+        let (arm, expression) = switch_arm(&statements, content, Some(i) == last)?;
+        if arm != ArmKind::Throw {
+            if kind.is_some_and(|kind| kind != arm) {
+                return None;
+            }
+            kind = Some(arm);
+        }
+        for comment in comments.drain(..) {
+            arms.push(format!("{}{}", inner, comment));
+        }
+        let matched = if has_default {
+            "default".to_string()
+        } else {
+            conditions.join(", ")
+        };
+        arms.push(format!(
+            "{}{} => {},",
+            inner,
+            matched,
+            &content[expression.byte_range()]
+        ));
+        seen_default |= has_default;
+        has_default = false;
+        conditions.clear();
+    }
+    // the last case can't fall through to nothing
+    if !conditions.is_empty() || has_default || !seen_default {
+        return None;
+    }
+    for comment in comments {
+        arms.push(format!("{}{}", inner, comment));
+    }
+
+    let target = match kind? {
+        ArmKind::Assign(variable) => format!("{} =", variable),
+        ArmKind::Return => "return".to_string(),
+        ArmKind::Throw => return None,
+    };
+    Some(TextEdit {
+        range: to_range(&switch.range()),
+        new_text: format!(
+            "{} match ({}) {{\n{}\n{}}};",
+            target,
+            &content[condition.byte_range()],
+            arms.join("\n"),
+            outer
+        ),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{Position, Range};
+    use pls_types::SegmentPool;
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    fn selection(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn extract_variable() {
+        let src = "<?php
+function total($items, $extracted) {
+    foreach ($items as $item) {
+        echo $item->price * 1.15;
+    }
+    $f = fn($x) => $x * 2;
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let edit = super::extract_variable(tree.root_node(), src, &selection(3, 13, 32)).unwrap();
+        assert_eq!(edit.range.start, Position::new(3, 8));
+        assert_eq!(edit.range.end, Position::new(3, 32));
+        assert_eq!(
+            edit.new_text,
+            "$extracted2 = $item->price * 1.15;\n        echo $extracted2"
+        );
+
+        // not an expression on its own
+        assert!(super::extract_variable(tree.root_node(), src, &selection(3, 13, 27)).is_none());
+        // just a variable
+        assert!(super::extract_variable(tree.root_node(), src, &selection(3, 13, 18)).is_none());
+        // would move out of the arrow function
+        assert!(super::extract_variable(tree.root_node(), src, &selection(5, 19, 25)).is_none());
+    }
+
+    #[test]
+    fn extract_method() {
+        let src = "<?php
+class Cart {
+    public function total($items, $tax) {
+        $sum = 0;
+        foreach ($items as $item) {
+            $sum += $item->price;
+        }
+        $withTax = $sum * $tax;
+        return $withTax;
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let selection = Range::new(Position::new(3, 0), Position::new(7, 0));
+        let edits = super::extract_method(tree.root_node(), src, &selection).unwrap();
+        assert_eq!(edits[0].range.start, Position::new(3, 8));
+        assert_eq!(edits[0].range.end, Position::new(6, 9));
+        assert_eq!(edits[0].new_text, "$sum = $this->extracted($items);");
+        assert_eq!(edits[1].range.start, Position::new(9, 5));
+        assert_eq!(
+            edits[1].new_text,
+            "
+
+    private function extracted($items)
+    {
+        $sum = 0;
+        foreach ($items as $item) {
+            $sum += $item->price;
+        }
+        return $sum;
+    }"
+        );
+
+        // only part of the `foreach`
+        let selection = Range::new(Position::new(3, 0), Position::new(5, 10));
+        assert!(super::extract_method(tree.root_node(), src, &selection).is_none());
+        // the `return` has to stay
+        let selection = Range::new(Position::new(7, 0), Position::new(9, 0));
+        assert!(super::extract_method(tree.root_node(), src, &selection).is_none());
+    }
+
+    #[test]
+    fn extract_method_in_loop() {
+        let src = "<?php
+function running($items) {
+    foreach ($items as $item) {
+        echo $item - $previous;
+        $previous = $item;
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let line = |line| Range::new(Position::new(line, 0), Position::new(line + 1, 0));
+
+        // `$previous` was set by the last iteration
+        let edits = super::extract_method(tree.root_node(), src, &line(3)).unwrap();
+        assert_eq!(edits[0].new_text, "extracted($item, $previous);");
+        // and is read again by the next one
+        let edits = super::extract_method(tree.root_node(), src, &line(4)).unwrap();
+        assert_eq!(edits[0].new_text, "$previous = extracted($item);");
+    }
+
+    #[test]
+    fn inline_variable() {
+        let src = "<?php
+function price($base, $rate) {
+    $tax = $base * $rate;
+    echo $tax;
+    return $base + $tax;
+}
+function other($items) {
+    $count = count($items);
+    $first = $items[0];
+    $items = [];
+    return $count . $first;
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let on = |line, character| {
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            )
+        };
+        let edits = super::inline_variable(tree.root_node(), src, &on(3, 10)).unwrap();
+        let texts: Vec<_> = edits.iter().map(|e| e.new_text.as_str()).collect();
+        assert_eq!(texts, ["", "$base * $rate", "($base * $rate)"]);
+        assert_eq!(edits[0].range.start, Position::new(2, 0));
+        assert_eq!(edits[0].range.end, Position::new(3, 0));
+        assert_eq!(edits[2].range.start, Position::new(4, 19));
+
+        // calls might have side effects
+        assert!(super::inline_variable(tree.root_node(), src, &on(7, 5)).is_none());
+        // `$items` changes before `$first` is read
+        assert!(super::inline_variable(tree.root_node(), src, &on(8, 5)).is_none());
+        // parameters can't be inlined
+        assert!(super::inline_variable(tree.root_node(), src, &on(2, 12)).is_none());
+    }
+
+    #[test]
+    fn inline_variable_changed_through_elements() {
+        let src = "<?php
+function first($items, $other) {
+    $first = $items[0];
+    $items[0] = 1;
+    return $first;
+}
+function sorted($items) {
+    $first = $items[0];
+    sort($items);
+    return $first;
+}
+function appended($items) {
+    $first = $items[0];
+    $first[] = 1;
+    return $first;
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let on = |line, character| {
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            )
+        };
+        // `$items` changes through its element
+        assert!(super::inline_variable(tree.root_node(), src, &on(2, 5)).is_none());
+        // `$items` might be taken by reference
+        assert!(super::inline_variable(tree.root_node(), src, &on(7, 5)).is_none());
+        // `$first` itself changes
+        assert!(super::inline_variable(tree.root_node(), src, &on(12, 5)).is_none());
+    }
+
+    #[test]
+    fn convert_closures() {
+        let src = "<?php
+$double = function ($x) use ($factor): int { return $x * $factor; };
+$log = function ($x) use (&$lines) { return $lines[] = $x; };
+$add = static fn($x) => $x + $y + array_sum(array_map(fn($z) => $z * $w, [$x]));
+";
+        let tree = parser().parse(src, None).unwrap();
+        let on = |line, character| {
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            )
+        };
+
+        let edit = super::to_arrow_function(tree.root_node(), src, &on(1, 12)).unwrap();
+        assert_eq!(edit.new_text, "fn ($x): int => $x * $factor");
+        assert_eq!(edit.range.start, Position::new(1, 10));
+        // captured by reference
+        assert!(super::to_arrow_function(tree.root_node(), src, &on(2, 10)).is_none());
+        assert!(super::to_anonymous_function(tree.root_node(), src, &on(1, 12)).is_none());
+
+        let edit = super::to_anonymous_function(tree.root_node(), src, &on(3, 10)).unwrap();
+        assert_eq!(
+            edit.new_text,
+            "static function($x) use ($y, $w) { return $x + $y + array_sum(array_map(fn($z) => $z * $w, [$x])); }"
+        );
+        // the innermost one
+        let edit = super::to_anonymous_function(tree.root_node(), src, &on(3, 55)).unwrap();
+        assert_eq!(edit.new_text, "function($z) use ($w) { return $z * $w; }");
+    }
+
+    #[test]
+    fn move_class() {
+        let src = "<?php
+declare(strict_types=1);
+
+namespace App\\Mail;
+
+use App\\Models\\User;
+use Psr\\Log\\LoggerInterface;
+
+class Mailer {
+    public function __construct(private LoggerInterface $logger) {}
+}
+
+/**
+ * Sent to a user.
+ */
+class Message {
+    public User $to;
+}
+";
+        let mut pool = SegmentPool::new();
+        let ns_to_dir =
+            HashMap::from([(pool.intern_str("App\\"), vec![PathBuf::from("/proj/src")])]);
+        let tree = parser().parse(src, None).unwrap();
+        let moved = super::move_class(
+            tree.root_node(),
+            src,
+            Path::new("/proj/src/Mail/Mailer.php"),
+            &selection(16, 8, 8),
+            &ns_to_dir,
+            &mut pool,
+        )
+        .unwrap();
+
+        assert_eq!(moved.path, PathBuf::from("/proj/src/Mail/Message.php"));
+        assert_eq!(
+            moved.content,
+            "<?php
+
+declare(strict_types=1);
+
+namespace App\\Mail;
+
+use App\\Models\\User;
+
+/**
+ * Sent to a user.
+ */
+class Message {
+    public User $to;
+}
+"
+        );
+        // along with the blank line before it, since it's last
+        assert_eq!(moved.removal.range.start, Position::new(11, 0));
+        assert_eq!(moved.removal.range.end, Position::new(18, 0));
+
+        // the file is named after this one
+        assert!(
+            super::move_class(
+                tree.root_node(),
+                src,
+                Path::new("/proj/src/Mail/Mailer.php"),
+                &selection(8, 8, 8),
+                &ns_to_dir,
+                &mut pool,
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn switch_to_match() {
+        let src = "<?php
+function label($status) {
+    switch ($status) {
+        // the good ones
+        case 200:
+        case 201:
+            $label = 'ok'; // mostly
+            break;
+        case 404:
+            throw new NotFound();
+        default:
+            $label = 'error';
+    }
+    switch ($status) {
+        case 200:
+            return 'ok';
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let edit = super::switch_to_match(tree.root_node(), src, &selection(2, 6, 6)).unwrap();
+        assert_eq!(edit.range.start, Position::new(2, 4));
+        assert_eq!(
+            edit.new_text,
+            "$label = match ($status) {
+        // the good ones
+        // mostly
+        200, 201 => 'ok',
+        404 => throw new NotFound(),
+        default => 'error',
+    };"
+        );
+
+        // no default
+        assert!(super::switch_to_match(tree.root_node(), src, &selection(14, 6, 6)).is_none());
+    }
+
+    #[test]
+    fn switch_to_match_keeps_fall_through() {
+        let src = "<?php
+function label($status) {
+    switch ($status) {
+        case 1:
+            $label = 'a';
+        case 2:
+            $label = 'b';
+            break;
+        default:
+            $label = 'c';
+    }
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        assert!(super::switch_to_match(tree.root_node(), src, &selection(2, 6, 6)).is_none());
+    }
+}
@@ -3,9 +3,13 @@ use std::panic::RefUnwindSafe;
 
 use lsp_server::{Notification, Request, RequestId};
 use lsp_types::notification::{
-    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument, DidOpenTextDocument,
+    DidSaveTextDocument, SetTrace,
+};
+use lsp_types::request::{
+    CodeActionRequest, CodeActionResolveRequest, Completion, DocumentSymbolRequest, ExecuteCommand,
+    FoldingRangeRequest, HoverRequest, ResolveCompletionItem, WillRenameFiles,
 };
-use lsp_types::request::{CodeActionRequest, CodeActionResolveRequest};
 use serde::de::DeserializeOwned;
 
 use crate::{global_state::GlobalState, handlers};
@@ -72,7 +76,9 @@ impl Default for NotificationRegistry {
         me.on::<DidOpenTextDocument, _>(handlers::notification::did_open_text_document)
             .on::<DidChangeTextDocument, _>(handlers::notification::did_change_text_document)
             .on::<DidSaveTextDocument, _>(handlers::notification::did_save_text_document)
-            .on::<DidCloseTextDocument, _>(handlers::notification::did_close_text_document);
+            .on::<DidCloseTextDocument, _>(handlers::notification::did_close_text_document)
+            .on::<DidChangeWatchedFiles, _>(handlers::notification::did_change_watched_files)
+            .on::<SetTrace, _>(handlers::notification::set_trace);
 
         me
     }
@@ -88,7 +94,14 @@ impl Default for RequestRegistry {
             handlers: Default::default(),
         };
         me.on::<CodeActionRequest, _>(handlers::request::code_action)
-            .on::<CodeActionResolveRequest, _>(handlers::request::code_action_resolve);
+            .on::<CodeActionResolveRequest, _>(handlers::request::code_action_resolve)
+            .on::<WillRenameFiles, _>(handlers::request::will_rename_files)
+            .on::<ExecuteCommand, _>(handlers::request::execute_command)
+            .on::<Completion, _>(handlers::request::completion)
+            .on::<ResolveCompletionItem, _>(handlers::request::completion_resolve)
+            .on::<HoverRequest, _>(handlers::request::hover)
+            .on::<FoldingRangeRequest, _>(handlers::request::folding_range)
+            .on::<DocumentSymbolRequest, _>(handlers::request::document_symbol);
 
         me
     }
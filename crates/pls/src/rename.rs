@@ -0,0 +1,517 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use pls_types::{PhpNamespace, SegmentPool, expected_namespaces};
+
+use crate::analyze::use_clauses;
+use crate::compat::to_range;
+use crate::imports::is_class_reference;
+use crate::usage::import_fix;
+
+/// A file moving into a directory that PSR-4 autoloads under another namespace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamespaceMove {
+    /// The namespace the file declares now, or `""` for the global one.
+    pub from: String,
+    pub to: String,
+    /// The classes, interfaces, traits and enums declared in the file.
+    pub classes: Vec<String>,
+    /// Changes the `namespace` declaration, or adds one.
+    pub edit: TextEdit,
+}
+
+impl NamespaceMove {
+    /// The old fully qualified names of the moved types, in lowercase, with their new ones.
+    pub fn renames(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.classes.iter().map(|class| {
+            (
+                qualify(&self.from, class).to_lowercase(),
+                qualify(&self.to, class),
+            )
+        })
+    }
+}
+
+fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}\\{}", namespace, name)
+    }
+}
+
+/// Every PHP file under a directory, leaving out dependencies and hidden directories.
+///
+/// Symbolic links to directories aren't followed, since one pointing back up would never end.
+pub fn php_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let skipped = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_none_or(|name| name.starts_with('.') || name == "vendor");
+            if !skipped {
+                php_files(&path, files);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "php") {
+            files.push(path);
+        }
+    }
+}
+
+/// How the namespace of a file has to change when it moves from `old_path` to `new_path`.
+///
+/// Only moves between autoloaded directories count; renaming the file itself would rename its
+/// class, and files with braced or several namespaces are left alone.
+pub fn moved_namespace(
+    node: Node<'_>,
+    content: &str,
+    old_path: &Path,
+    new_path: &Path,
+    ns_to_dir: &HashMap<PhpNamespace, Vec<PathBuf>>,
+    ns_store: &mut SegmentPool,
+) -> Option<NamespaceMove> {
+    if old_path.file_stem() != new_path.file_stem() {
+        return None;
+    }
+
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    let namespaces: Vec<_> = children
+        .iter()
+        .filter(|child| child.kind() == "namespace_definition")
+        .collect();
+    if namespaces.len() > 1
+        || namespaces
+            .iter()
+            .any(|n| n.child_by_field_name("body").is_some())
+    {
+        return None;
+    }
+    let classes: Vec<_> = children
+        .iter()
+        .filter(|child| {
+            matches!(
+                child.kind(),
+                "class_declaration"
+                    | "interface_declaration"
+                    | "trait_declaration"
+                    | "enum_declaration"
+            )
+        })
+        .filter_map(|child| child.child_by_field_name("name"))
+        .map(|name| content[name.byte_range()].to_string())
+        .collect();
+    if classes.is_empty() {
+        return None;
+    }
+
+    let to = expected_namespaces(new_path, ns_to_dir, ns_store)
+        .into_iter()
+        .next()?
+        .0
+        .join("\\");
+    let name = namespaces
+        .first()
+        .and_then(|n| n.child_by_field_name("name"));
+    let from = name
+        .map(|name| content[name.byte_range()].to_string())
+        .unwrap_or_default();
+    if from.eq_ignore_ascii_case(&to) {
+        return None;
+    }
+
+    let edit = match name {
+        Some(name) if !to.is_empty() => TextEdit {
+            range: to_range(&name.range()),
+            new_text: to.clone(),
+        },
+        // removing the namespace declaration altogether is more than we'd like to do
+        Some(_) => return None,
+        None => {
+            let tag = children.iter().find(|child| child.kind() == "php_tag")?;
+            let end = to_range(&tag.range()).end;
+            TextEdit {
+                range: Range::new(end, end),
+                new_text: format!("\n\nnamespace {};", to),
+            }
+        }
+    };
+
+    Some(NamespaceMove {
+        from,
+        to,
+        classes,
+        edit,
+    })
+}
+
+/// The `use` lines for the renamed clauses of a declaration.
+fn renamed_imports(
+    declaration: Node<'_>,
+    content: &str,
+    renames: &HashMap<String, String>,
+    edits: &mut Vec<TextEdit>,
+) {
+    if declaration.child_by_field_name("type").is_some() {
+        return;
+    }
+    let group = declaration.child_by_field_name("body");
+    let mut cursor = declaration.walk();
+    let prefix = declaration
+        .children(&mut cursor)
+        .find(|child| child.kind() == "namespace_name")
+        .map(|prefix| &content[prefix.byte_range()]);
+
+    let mut renamed = Vec::new();
+    let mut kept = Vec::new();
+    for clause in use_clauses(declaration) {
+        let mut cursor = clause.walk();
+        let name = clause
+            .children(&mut cursor)
+            .find(|child| matches!(child.kind(), "qualified_name" | "name"));
+        let new = name
+            .filter(|_| clause.child_by_field_name("type").is_none())
+            .and_then(|name| {
+                let name = content[name.byte_range()].trim_start_matches('\\');
+                let fqn = match prefix {
+                    Some(prefix) => format!("{}\\{}", prefix.trim_start_matches('\\'), name),
+                    None => name.to_string(),
+                };
+                renames.get(&fqn.to_lowercase())
+            });
+        match (new, name) {
+            (Some(new), Some(name)) if group.is_none() => edits.push(TextEdit {
+                range: to_range(&name.range()),
+                new_text: new.clone(),
+            }),
+            (Some(new), _) => {
+                let alias = clause
+                    .child_by_field_name("alias")
+                    .map(|alias| format!(" as {}", &content[alias.byte_range()]))
+                    .unwrap_or_default();
+                renamed.push(format!("use {}{};", new, alias));
+            }
+            (None, _) => kept.push(&content[clause.byte_range()]),
+        }
+    }
+    // a clause moving out of a group gets a line of its own
+    let Some(group) = group.filter(|_| !renamed.is_empty()) else {
+        return;
+    };
+    let indent = " ".repeat(declaration.start_position().column);
+    if kept.is_empty() {
+        edits.push(TextEdit {
+            range: to_range(&declaration.range()),
+            new_text: renamed.join(&format!("\n{}", indent)),
+        });
+    } else {
+        let start = to_range(&declaration.range()).start;
+        edits.push(TextEdit {
+            range: Range::new(start, start),
+            new_text: format!("{}\n{}", renamed.join(&format!("\n{}", indent)), indent),
+        });
+        edits.push(TextEdit {
+            range: to_range(&group.range()),
+            new_text: format!("{{{}}}", kept.join(", ")),
+        });
+    }
+}
+
+/// Rename `use` clauses and fully qualified names like `\App\Foo`.
+fn renamed_names(
+    node: Node<'_>,
+    content: &str,
+    renames: &HashMap<String, String>,
+    edits: &mut Vec<TextEdit>,
+) {
+    match node.kind() {
+        "namespace_use_declaration" => return renamed_imports(node, content, renames, edits),
+        "qualified_name" => {
+            let name = &content[node.byte_range()];
+            let called = node
+                .parent()
+                .is_some_and(|p| p.kind() == "function_call_expression");
+            if let Some(name) = name.strip_prefix('\\')
+                && !called
+                && let Some(new) = renames.get(&name.to_lowercase())
+            {
+                edits.push(TextEdit {
+                    range: to_range(&node.range()),
+                    new_text: format!("\\{}", new),
+                });
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        renamed_names(child, content, renames, edits);
+    }
+}
+
+/// Unqualified class names, which resolve against the namespace of the file.
+fn relative_names<'a>(node: Node<'a>, content: &str, found: &mut Vec<Node<'a>>) {
+    if node.kind() == "name" {
+        let name = &content[node.byte_range()];
+        let special = ["self", "static", "parent"]
+            .iter()
+            .any(|special| name.eq_ignore_ascii_case(special));
+        if is_class_reference(node) && !special {
+            found.push(node);
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        relative_names(child, content, found);
+    }
+}
+
+/// Imports for unqualified class names that would mean something else after the move, either
+/// because the class moved away from this file's namespace or because this file moved away from
+/// the class's.
+fn relative_imports(
+    node: Node<'_>,
+    content: &str,
+    renames: &HashMap<String, String>,
+    moved: Option<&NamespaceMove>,
+    ns_store: &mut SegmentPool,
+    edits: &mut Vec<TextEdit>,
+) {
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    let namespaces: Vec<_> = children
+        .iter()
+        .filter(|child| child.kind() == "namespace_definition")
+        .collect();
+    if namespaces.len() > 1
+        || namespaces
+            .iter()
+            .any(|n| n.child_by_field_name("body").is_some())
+    {
+        return;
+    }
+    let namespace = namespaces
+        .first()
+        .and_then(|n| n.child_by_field_name("name"))
+        .map(|name| &content[name.byte_range()])
+        .unwrap_or_default();
+    let future = moved.map(|moved| moved.to.as_str()).unwrap_or(namespace);
+
+    let aliases: HashSet<_> = children
+        .iter()
+        .filter(|child| child.kind() == "namespace_use_declaration")
+        .flat_map(|declaration| use_clauses(*declaration))
+        .filter_map(|clause| {
+            let alias = clause.child_by_field_name("alias").or_else(|| {
+                let mut cursor = clause.walk();
+                clause
+                    .children(&mut cursor)
+                    .find(|child| matches!(child.kind(), "qualified_name" | "name"))
+            })?;
+            let alias = content[alias.byte_range()].rsplit('\\').next()?;
+            Some(alias.to_lowercase())
+        })
+        .collect();
+
+    let mut names = Vec::new();
+    relative_names(node, content, &mut names);
+    let mut imported = HashSet::new();
+    let mut imports: Vec<TextEdit> = Vec::new();
+    for name in names {
+        let text = &content[name.byte_range()];
+        if aliases.contains(&text.to_lowercase()) {
+            continue;
+        }
+        let old = qualify(namespace, text);
+        let new = renames.get(&old.to_lowercase()).cloned().unwrap_or(old);
+        if new.eq_ignore_ascii_case(&qualify(future, text)) || !imported.insert(new.to_lowercase())
+        {
+            continue;
+        }
+        let fqn = ns_store.intern_str(&new);
+        for edit in import_fix(name, &fqn, content).edits {
+            // imports going in at the same place make one block, rather than a line apart each
+            match imports.last_mut() {
+                Some(last) if last.range == edit.range => {
+                    let text = edit.new_text.strip_prefix('\n');
+                    let text = text.filter(|text| text.starts_with('\n'));
+                    last.new_text.push_str(text.unwrap_or(&edit.new_text));
+                }
+                _ => imports.push(edit),
+            }
+        }
+    }
+    edits.extend(imports);
+}
+
+/// Edits that keep a file pointing at the types that moved, given as their old fully qualified
+/// names in lowercase along with their new ones. `moved` is how the file itself moves, if it does.
+///
+/// Qualified names that aren't fully qualified, like `Sub\Foo`, are left alone.
+pub fn update_references(
+    node: Node<'_>,
+    content: &str,
+    renames: &HashMap<String, String>,
+    moved: Option<&NamespaceMove>,
+    ns_store: &mut SegmentPool,
+) -> Vec<TextEdit> {
+    let mut edits: Vec<_> = moved.map(|moved| moved.edit.clone()).into_iter().collect();
+    renamed_names(node, content, renames, &mut edits);
+    relative_imports(node, content, renames, moved, ns_store, &mut edits);
+
+    edits
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Position;
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    use pls_types::SegmentPool;
+
+    use super::{moved_namespace, php_files, update_references};
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    #[test]
+    fn move_between_namespaces() {
+        let mut ns_store = SegmentPool::new();
+        let ns_to_dir = HashMap::from([(
+            ns_store.intern_str("App"),
+            vec![PathBuf::from("/project/src")],
+        )]);
+        let moved_src = "<?php
+namespace App\\Models;
+
+class User extends Model
+{
+    public function team(): Team {}
+}
+";
+        let tree = parser().parse(moved_src, None).unwrap();
+        let moved = moved_namespace(
+            tree.root_node(),
+            moved_src,
+            Path::new("/project/src/Models/User.php"),
+            Path::new("/project/src/Auth/User.php"),
+            &ns_to_dir,
+            &mut ns_store,
+        )
+        .unwrap();
+        assert_eq!(moved.from, "App\\Models");
+        assert_eq!(moved.to, "App\\Auth");
+        assert_eq!(moved.edit.new_text, "App\\Auth");
+        let renames: HashMap<_, _> = moved.renames().collect();
+        assert_eq!(renames["app\\models\\user"], "App\\Auth\\User");
+
+        // its neighbours in the old namespace need importing now
+        let edits = update_references(
+            tree.root_node(),
+            moved_src,
+            &renames,
+            Some(&moved),
+            &mut ns_store,
+        );
+        let texts: Vec<_> = edits.iter().map(|e| e.new_text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "App\\Auth",
+                "\n\nuse App\\Models\\Model;\nuse App\\Models\\Team;"
+            ]
+        );
+
+        // renaming the file renames the class, which is something else entirely
+        assert!(
+            moved_namespace(
+                tree.root_node(),
+                moved_src,
+                Path::new("/project/src/Models/User.php"),
+                Path::new("/project/src/Auth/Member.php"),
+                &ns_to_dir,
+                &mut ns_store,
+            )
+            .is_none()
+        );
+
+        let src = "<?php
+namespace App\\Models;
+
+use App\\Models\\User as Author;
+use App\\{Models\\User, Http\\Kernel};
+
+$a = new User();
+$b = \\App\\Models\\User::find(1);
+$c = new Post();
+";
+        let tree = parser().parse(src, None).unwrap();
+        let edits = update_references(tree.root_node(), src, &renames, None, &mut ns_store);
+        let texts: Vec<_> = edits
+            .iter()
+            .map(|e| (e.range.start, e.new_text.as_str()))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                (Position::new(3, 4), "App\\Auth\\User"),
+                (Position::new(4, 0), "use App\\Auth\\User;\n"),
+                (Position::new(4, 8), "{Http\\Kernel}"),
+                (Position::new(7, 5), "\\App\\Auth\\User"),
+            ]
+        );
+
+        // without an import, `User` meant the one in this namespace
+        let src = "<?php
+namespace App\\Models;
+
+$a = new User();
+";
+        let tree = parser().parse(src, None).unwrap();
+        let edits = update_references(tree.root_node(), src, &renames, None, &mut ns_store);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "\n\nuse App\\Auth\\User;");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn php_files_skip_linked_directories() {
+        let root = std::env::temp_dir().join(format!("pls-php-files-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src/Models")).unwrap();
+        std::fs::write(root.join("src/Models/User.php"), "<?php\n").unwrap();
+        std::fs::write(root.join("src/notes.txt"), "").unwrap();
+        // a link back up would be gone through over and over if it were followed
+        std::os::unix::fs::symlink(&root, root.join("src/Models/root")).unwrap();
+
+        let mut files = Vec::new();
+        php_files(&root, &mut files);
+        assert_eq!(files, vec![root.join("src/Models/User.php")]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+use lsp_types::Range;
+
 use std::collections::{HashMap, HashSet};
 use std::sync::LazyLock;
 
@@ -46,9 +48,23 @@ pub struct Scope {
     /// The namespace we are currently occupying.
     pub ns: Option<PhpNamespace>,
 
-    pub ns_aliases: HashMap<String, PhpNamespace>,
+    /// Classes and namespaces imported through `use`, and where they were imported.
+    pub ns_aliases: HashMap<String, (PhpNamespace, Range)>,
+
+    /// Functions imported through `use function`, and where they were imported.
+    pub fn_aliases: HashMap<String, (PhpNamespace, Range)>,
 
     pub symbols: HashSet<String>,
+
+    /// Variables written in the current function, and where they were first written.
+    pub assigned: HashMap<String, Range>,
+    /// Variables read in the current function.
+    pub read: HashSet<String>,
+    /// Variables whose writes can be seen from outside of the current function, like by-ref
+    /// parameters.
+    pub references: HashSet<String>,
+    /// Whether an `extract()` may have defined any variable at all by now.
+    pub extracted: bool,
 }
 
 impl Scope {
@@ -56,17 +72,105 @@ impl Scope {
         Self {
             ns: None,
             ns_aliases: HashMap::new(),
+            fn_aliases: HashMap::new(),
             symbols: SUPERGLOBALS.clone(),
+            assigned: HashMap::new(),
+            read: HashSet::new(),
+            references: HashSet::new(),
+            extracted: false,
+        }
+    }
+
+    /// A copy of the scope for the body of a function, which tracks its own variable usage.
+    pub fn function(&self) -> Self {
+        Self {
+            assigned: HashMap::new(),
+            read: HashSet::new(),
+            references: HashSet::new(),
+            extracted: false,
+            ..self.clone()
         }
     }
 
+    pub fn assign(&mut self, name: &str, range: Range) {
+        if !self.assigned.contains_key(name) {
+            self.assigned.insert(name.to_string(), range);
+        }
+    }
+
+    /// Variables written in the current function that are never read, in the order they appear.
+    pub fn unused_variables(&self) -> Vec<(&str, Range)> {
+        let mut unused: Vec<_> = self
+            .assigned
+            .iter()
+            .filter(|(name, _)| {
+                !self.read.contains(*name)
+                    && !self.references.contains(*name)
+                    && !SUPERGLOBALS.contains(*name)
+            })
+            .map(|(name, range)| (name.as_str(), *range))
+            .collect();
+        unused.sort_by_key(|(_, range)| range.start);
+
+        unused
+    }
+
+    /// The variable in scope that `name` is most likely a typo of, if any is close enough.
+    pub fn closest_symbol(&self, name: &str) -> Option<&str> {
+        // about one typo for every three characters
+        let limit = (name.chars().count() / 3).max(1);
+
+        self.symbols
+            .iter()
+            .map(|symbol| (edit_distance(name, symbol), symbol))
+            .filter(|(distance, _)| *distance <= limit)
+            .min()
+            .map(|(_, symbol)| symbol.as_str())
+    }
+
     pub fn absorb(&mut self, other: Self) {
         for symbol in other.symbols {
             self.symbols.insert(symbol);
         }
 
-        for (alias, ns) in other.ns_aliases.iter() {
-            self.ns_aliases.insert(alias.to_string(), ns.clone());
+        self.ns_aliases.extend(other.ns_aliases);
+        self.fn_aliases.extend(other.fn_aliases);
+
+        for (name, range) in other.assigned {
+            self.assign(&name, range);
+        }
+        self.read.extend(other.read);
+        self.references.extend(other.references);
+        self.extracted |= other.extracted;
+    }
+}
+
+/// How many single character insertions, deletions, substitutions or swaps of neighbours it takes
+/// to turn one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // distances[i][j] is the distance between the first i chars of `a` and the first j of `b`
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
         }
     }
+
+    distances[a.len()][b.len()]
 }
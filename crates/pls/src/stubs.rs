@@ -2,6 +2,9 @@ use tree_sitter::{Node, Parser, Query, QueryCursor, StreamingIterator};
 
 use tree_sitter_php::LANGUAGE_PHP;
 
+use crate::analyze::node_markup;
+use crate::hover::docblock_markdown;
+
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
@@ -9,12 +12,41 @@ use std::rc::Rc;
 use std::str::FromStr;
 use std::sync::LazyLock;
 
+/// The file in the PhpStorm stubs that says where everything is declared.
+pub const STUBS_MAP: &str = "PhpStormStubsMap.php";
+
 static CONST_QUERY: LazyLock<Query> =
     LazyLock::new(|| Query::new(&LANGUAGE_PHP.into(), "(array_creation_expression) @a").unwrap());
 
+/// Which of the map's constants a stub name came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StubKind {
+    Class,
+    Function,
+    Constant,
+}
+
+impl StubKind {
+    fn from_const_name(name: &str) -> Option<Self> {
+        match name {
+            "CLASSES" => Some(Self::Class),
+            "FUNCTIONS" => Some(Self::Function),
+            "CONSTANTS" => Some(Self::Constant),
+            _ => None,
+        }
+    }
+}
+
+/// Where the stubs declare each built-in. Classes, functions and constants live in different
+/// maps, like they do in PHP, so `PHP_EOL` the constant can't hide a class of the same name.
 #[derive(Default)]
 pub struct FileMapping {
-    pub mapping: HashMap<String, Rc<PathBuf>>,
+    pub classes: HashMap<String, Rc<PathBuf>>,
+    pub functions: HashMap<String, Rc<PathBuf>>,
+    pub constants: HashMap<String, Rc<PathBuf>>,
+
+    /// Function names by their lower-cased selves, since PHP doesn't care about their case.
+    folded_functions: HashMap<String, String>,
 
     /// Set of files involved, interned to probably keep memory usage low.
     pub files: HashSet<Rc<PathBuf>>,
@@ -56,6 +88,62 @@ impl Display for MappingError {
 impl std::error::Error for MappingError {}
 
 impl FileMapping {
+    /// The built-ins of one kind, and the stubs declaring them.
+    pub fn names(&self, kind: StubKind) -> &HashMap<String, Rc<PathBuf>> {
+        match kind {
+            StubKind::Class => &self.classes,
+            StubKind::Function => &self.functions,
+            StubKind::Constant => &self.constants,
+        }
+    }
+
+    /// The stub declaring a built-in of the given kind.
+    pub fn get(&self, kind: StubKind, name: &str) -> Option<&Rc<PathBuf>> {
+        self.names(kind).get(name)
+    }
+
+    /// The name a function is declared with in the stubs, ignoring case like PHP does.
+    pub fn function_name(&self, name: &str) -> Option<&str> {
+        match self.functions.get_key_value(name) {
+            Some((name, _)) => Some(name),
+            None => self
+                .folded_functions
+                .get(&name.to_lowercase())
+                .map(String::as_str),
+        }
+    }
+
+    /// The stub declaring a built-in of any kind, trying classes, then functions, then constants.
+    pub fn find(&self, name: &str) -> Option<&Rc<PathBuf>> {
+        [StubKind::Class, StubKind::Function, StubKind::Constant]
+            .into_iter()
+            .find_map(|kind| self.get(kind, name))
+    }
+
+    /// Record that a stub declares a built-in.
+    pub fn insert(&mut self, kind: StubKind, name: &str, file: PathBuf) {
+        let file = match self.files.get(&file) {
+            Some(file) => file.clone(),
+            None => Rc::new(file),
+        };
+        self.files.insert(file.clone());
+        if kind == StubKind::Function {
+            self.folded_functions
+                .insert(name.to_lowercase(), name.to_string());
+        }
+        let names = match kind {
+            StubKind::Class => &mut self.classes,
+            StubKind::Function => &mut self.functions,
+            StubKind::Constant => &mut self.constants,
+        };
+        names.insert(name.to_string(), file);
+    }
+
+    /// Return true if there are no stubs at all, like before the map has been read.
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty() && self.functions.is_empty() && self.constants.is_empty()
+    }
+
     fn node_to_string(node: Node<'_>, content: &str) -> Result<String, MappingError> {
         if node.kind() == "string" {
             let mut range = node.byte_range();
@@ -81,12 +169,20 @@ impl FileMapping {
     fn node_to_mapping(node: Node<'_>, content: &str) -> Result<Self, MappingError> {
         let mut cursor = QueryCursor::new();
         let mut captures = cursor.captures(&CONST_QUERY, node, content.as_bytes());
-        let mut files: HashSet<Rc<PathBuf>> = HashSet::new();
-        let mut mapping = HashMap::new();
+        let mut mapping = Self::default();
 
         while let Some((m, _)) = captures.next() {
             for c in m.captures.iter() {
                 let array_root = c.node;
+                // arrays that aren't one of the map's constants aren't stubs
+                let Some(kind) = array_root
+                    .parent()
+                    .filter(|parent| parent.kind() == "const_element")
+                    .and_then(|parent| parent.child(0))
+                    .and_then(|name| StubKind::from_const_name(&content[name.byte_range()]))
+                else {
+                    continue;
+                };
 
                 let mut cursor = array_root.walk();
                 for child in array_root.children(&mut cursor) {
@@ -95,20 +191,12 @@ impl FileMapping {
                     }
 
                     let (item0, item1) = Self::node_to_single_mapping(child, content)?;
-                    let file = PathBuf::from_str(&item1).unwrap();
-
-                    let file = files
-                        .get(&file)
-                        .map(|f| f.clone())
-                        .unwrap_or(Rc::from(file));
-
-                    mapping.insert(item0, file.clone());
-                    files.insert(file);
+                    mapping.insert(kind, &item0, PathBuf::from_str(&item1).unwrap());
                 }
             }
         }
 
-        Ok(Self { mapping, files })
+        Ok(mapping)
     }
 
     pub fn from_filename<P>(filename: P) -> Result<Self, MappingError>
@@ -127,6 +215,294 @@ impl FileMapping {
     }
 }
 
+/// Where to look for the stubs map when we're not told, in order: a copy bundled next to the
+/// executable, the user's data directory, then the stubs Composer installed in a workspace.
+pub fn stub_locations(
+    executable: Option<&Path>,
+    data_home: Option<&Path>,
+    workspace_folders: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+    if let Some(dir) = executable.and_then(Path::parent) {
+        locations.push(dir.join("phpstorm-stubs").join(STUBS_MAP));
+    }
+    if let Some(data_home) = data_home {
+        locations.push(data_home.join("pls").join("stubs").join(STUBS_MAP));
+    }
+    for folder in workspace_folders {
+        locations.push(
+            folder
+                .join("vendor")
+                .join("jetbrains")
+                .join("phpstorm-stubs")
+                .join(STUBS_MAP),
+        );
+    }
+
+    locations
+}
+
+/// Where user data goes: `$XDG_DATA_HOME`, or `~/.local/share`.
+fn data_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+}
+
+/// Where `pls stubs install` puts the stubs, which is one of the [`stub_locations`].
+pub fn stubs_install_dir() -> Option<PathBuf> {
+    data_home().map(|data_home| data_home.join("pls").join("stubs"))
+}
+
+/// The first stubs map in [`stub_locations`] that exists.
+pub fn discover_stubs(workspace_folders: &[PathBuf]) -> Option<PathBuf> {
+    let executable = std::env::current_exe().ok();
+    let data_home = data_home();
+
+    stub_locations(
+        executable.as_deref(),
+        data_home.as_deref(),
+        workspace_folders,
+    )
+    .into_iter()
+    .find(|location| location.is_file())
+}
+
+/// What the stubs say about a built-in: how it's declared, and its documentation as markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StubDoc {
+    pub signature: String,
+    pub markdown: Option<String>,
+}
+
+impl StubDoc {
+    /// Look for the declaration of `name` (fully qualified) in a stub file.
+    pub fn find(node: Node<'_>, content: &str, name: &str) -> Option<Self> {
+        let (namespace, short) = name.rsplit_once('\\').unwrap_or(("", name));
+        let (declaration, signature) = Self::declaration(node, content, namespace, short)?;
+
+        Some(Self {
+            signature,
+            markdown: node_markup(declaration, content).map(|markup| docblock_markdown(&markup)),
+        })
+    }
+
+    /// Look for the constant or enum case `member` of the class-like `class` (fully qualified),
+    /// with the docblock in front of that member rather than the class. Methods are looked for as
+    /// `name()`, and properties as `$name`.
+    pub fn find_member(node: Node<'_>, content: &str, class: &str, member: &str) -> Option<Self> {
+        let text = |node: Node<'_>| &content[node.byte_range()];
+        let (namespace, short) = class.rsplit_once('\\').unwrap_or(("", class));
+        let (declaration, _) = Self::declaration(node, content, namespace, short)?;
+        let body = declaration.child_by_field_name("body")?;
+
+        let mut cursor = body.walk();
+        for child in body.named_children(&mut cursor) {
+            let signature = match child.kind() {
+                "enum_case" => child
+                    .child_by_field_name("name")
+                    .filter(|name| text(*name) == member)
+                    .map(|_| text(child).trim_end_matches(';').trim_end().to_string()),
+                "const_declaration" => {
+                    let mut cursor = child.walk();
+                    let mut modifiers = Vec::new();
+                    let mut element = None;
+                    for part in child.named_children(&mut cursor) {
+                        match part.kind() {
+                            "attribute_list" => {}
+                            "const_element" => {
+                                if part.child(0).is_some_and(|name| text(name) == member) {
+                                    element = Some(part);
+                                }
+                            }
+                            _ => modifiers.push(text(part)),
+                        }
+                    }
+                    element.map(|element| {
+                        // the type sits after `const`, the modifiers before it
+                        let typed = child.child_by_field_name("type").map(text);
+                        let modifiers = modifiers.iter().filter(|m| Some(**m) != typed);
+                        let mut signature: Vec<_> = modifiers.copied().collect();
+                        signature.push("const");
+                        signature.extend(typed);
+                        signature.push(text(element));
+                        signature.join(" ")
+                    })
+                }
+                "method_declaration" => child
+                    .child_by_field_name("name")
+                    .filter(|name| {
+                        member
+                            .strip_suffix("()")
+                            .is_some_and(|member| text(*name).eq_ignore_ascii_case(member))
+                    })
+                    .map(|_| {
+                        declaration_header(child, content)
+                            .trim_end_matches(';')
+                            .to_string()
+                    }),
+                "property_declaration" => {
+                    let mut cursor = child.walk();
+                    let mut signature = Vec::new();
+                    let mut found = false;
+                    for part in child.named_children(&mut cursor) {
+                        match part.kind() {
+                            "attribute_list" => {}
+                            "property_element" => {
+                                if part
+                                    .child_by_field_name("name")
+                                    .is_some_and(|name| text(name) == member)
+                                {
+                                    signature.push(text(part));
+                                    found = true;
+                                }
+                            }
+                            _ => signature.push(text(part)),
+                        }
+                    }
+                    found.then(|| signature.join(" "))
+                }
+                _ => None,
+            };
+            if let Some(signature) = signature {
+                return Some(Self {
+                    signature,
+                    markdown: node_markup(child, content).map(|markup| docblock_markdown(&markup)),
+                });
+            }
+        }
+
+        None
+    }
+
+    /// How the stubs would be shown in a hover.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("```php\n<?php\n{}\n```", self.signature);
+        if let Some(docs) = &self.markdown {
+            markdown.push_str("\n\n");
+            markdown.push_str(docs);
+        }
+        markdown
+    }
+
+    /// The node declaring `short` in `namespace`, the one its docblock sits in front of, along
+    /// with its signature.
+    fn declaration<'a>(
+        node: Node<'a>,
+        content: &str,
+        namespace: &str,
+        short: &str,
+    ) -> Option<(Node<'a>, String)> {
+        let text = |node: Node<'_>| &content[node.byte_range()];
+        let mut current = "";
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            let in_namespace = current.eq_ignore_ascii_case(namespace);
+            match child.kind() {
+                "namespace_definition" => {
+                    let ns = child.child_by_field_name("name").map(text).unwrap_or("");
+                    match child.child_by_field_name("body") {
+                        Some(body) if ns.eq_ignore_ascii_case(namespace) => {
+                            if let Some(found) = Self::declaration(body, content, "", short) {
+                                return Some(found);
+                            }
+                        }
+                        Some(_) => {}
+                        None => current = ns,
+                    }
+                }
+                "function_definition"
+                | "class_declaration"
+                | "interface_declaration"
+                | "trait_declaration"
+                | "enum_declaration"
+                    if in_namespace =>
+                {
+                    let declared = child
+                        .child_by_field_name("name")
+                        .is_some_and(|n| text(n).eq_ignore_ascii_case(short));
+                    if declared {
+                        return Some((child, declaration_header(child, content)));
+                    }
+                }
+                "const_declaration" if in_namespace => {
+                    let mut cursor = child.walk();
+                    let element = child
+                        .named_children(&mut cursor)
+                        .filter(|e| e.kind() == "const_element")
+                        .find(|e| e.child(0).is_some_and(|n| text(n) == short));
+                    if let Some(element) = element {
+                        return Some((child, format!("const {}", text(element))));
+                    }
+                }
+                // `define('PHP_EOL', "\n");`
+                "expression_statement" if in_namespace => {
+                    let Some(call) = child
+                        .named_child(0)
+                        .filter(|call| call.kind() == "function_call_expression")
+                    else {
+                        continue;
+                    };
+                    let defines = call
+                        .child_by_field_name("function")
+                        .is_some_and(|f| text(f).eq_ignore_ascii_case("define"));
+                    let Some(arguments) = call.child_by_field_name("arguments") else {
+                        continue;
+                    };
+                    let (Some(constant), Some(value)) =
+                        (arguments.named_child(0), arguments.named_child(1))
+                    else {
+                        continue;
+                    };
+                    let constant = text(constant).trim_matches(['\'', '"']);
+                    if defines && constant == short {
+                        return Some((child, format!("const {constant} = {}", text(value))));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// A declaration up to its body, without attributes and on one line.
+fn declaration_header(declaration: Node<'_>, content: &str) -> String {
+    let end = declaration
+        .child_by_field_name("body")
+        .map(|body| body.start_byte())
+        .unwrap_or(declaration.end_byte());
+
+    let mut attributes = Vec::new();
+    let mut stack = vec![declaration];
+    while let Some(node) = stack.pop() {
+        if node.kind() == "attribute_list" {
+            attributes.push(node.byte_range());
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor).filter(|c| c.start_byte() < end));
+    }
+    attributes.sort_by_key(|range| range.start);
+
+    let mut header = String::new();
+    let mut at = declaration.start_byte();
+    for range in attributes {
+        header.push_str(&content[at..range.start]);
+        at = range.end;
+    }
+    header.push_str(&content[at..end]);
+
+    header
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("( ", "(")
+        .replace(" )", ")")
+        .replace(" ,", ",")
+}
+
 #[cfg(test)]
 mod test {
     use tree_sitter::Parser;
@@ -156,10 +532,19 @@ const CLASSES = [
   'AMQPEnvelope' => 'amqp/amqp.php',
   'AMQP\\Envelope\\Exception' => 'amqp/amqp.php',
   ];
+
+const FUNCTIONS = [
+  'array_filter' => 'standard/standard_9.php',
+  ];
+
+const CONSTANTS = [
+  'AMQPDecimal' => 'amqp/constants.php',
+  ];
 }";
 
-    use super::FileMapping;
-    use std::path::PathBuf;
+    use super::{FileMapping, StubDoc, StubKind, stub_locations};
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
     use std::str::FromStr;
 
     #[test]
@@ -168,30 +553,179 @@ const CLASSES = [
         let root = tree.root_node();
         let file_mapping = FileMapping::node_to_mapping(root, SOURCE).unwrap();
 
-        assert_eq!(file_mapping.files.len(), 1);
-        assert_eq!(file_mapping.mapping.len(), 8);
+        assert_eq!(file_mapping.files.len(), 3);
+        assert_eq!(file_mapping.classes.len(), 8);
+        assert_eq!(file_mapping.functions.len(), 1);
         assert!(
             file_mapping
                 .files
                 .contains(&PathBuf::from_str("amqp/amqp.php").unwrap())
         );
-        assert!(file_mapping.mapping.contains_key("AMQP\\annel"));
+        assert!(file_mapping.classes.contains_key("AMQP\\annel"));
         assert!(
             file_mapping
-                .mapping
+                .classes
                 .contains_key("AMQP\\Envelope\\Exception")
         );
+        assert_eq!(
+            file_mapping.get(StubKind::Function, "array_filter"),
+            Some(&Rc::new(PathBuf::from("standard/standard_9.php")))
+        );
+        assert_eq!(file_mapping.get(StubKind::Class, "array_filter"), None);
+
+        // a constant doesn't shadow the class it shares a name with
+        assert_eq!(
+            file_mapping.get(StubKind::Class, "AMQPDecimal"),
+            Some(&Rc::new(PathBuf::from("amqp/amqp.php")))
+        );
+        assert_eq!(
+            file_mapping.get(StubKind::Constant, "AMQPDecimal"),
+            Some(&Rc::new(PathBuf::from("amqp/constants.php")))
+        );
+        assert_eq!(
+            file_mapping.find("AMQPDecimal"),
+            file_mapping.get(StubKind::Class, "AMQPDecimal")
+        );
+    }
+
+    #[test]
+    fn find_stub_docs() {
+        let stub = "<?php
+/**
+ * Iterates over each value in the array.
+ * @param array $array
+ */
+#[Pure]
+function array_filter(
+    #[LanguageLevelTypeAware(['8.0' => 'array'], default: '')] array $array,
+    ?callable $callback = null,
+    int $mode = 0
+): array {}
+
+/** The line ending. */
+define('PHP_EOL', \"\\n\");
+
+namespace Ds {
+    /** A sequence. */
+    final class Vector implements Sequence {}
+}
+";
+        let tree = parser().parse(stub, None).unwrap();
+        let find = |name| StubDoc::find(tree.root_node(), stub, name);
+
+        assert_eq!(
+            find("array_filter"),
+            Some(StubDoc {
+                signature: "function array_filter(array $array, ?callable $callback = null, int $mode = 0): array".to_string(),
+                markdown: Some([
+                    "Iterates over each value in the array.",
+                    "",
+                    "| Parameter | Type | Description |",
+                    "| --- | --- | --- |",
+                    "| `$array` | `array` |  |",
+                ].join("\n")),
+            })
+        );
+        assert_eq!(
+            find("PHP_EOL").map(|doc| doc.to_markdown()),
+            Some("```php\n<?php\nconst PHP_EOL = \"\\n\"\n```\n\nThe line ending.".to_string())
+        );
+        assert_eq!(
+            find("Ds\\Vector").map(|doc| doc.signature),
+            Some("final class Vector implements Sequence".to_string())
+        );
+        assert_eq!(find("Vector"), None);
+    }
+
+    #[test]
+    fn find_member_docs() {
+        let src = "<?php
+namespace App;
+
+class Config {
+    /** The version we're at. */
+    final public const int VERSION = 2, PATCH = 1;
+
+    /** Where it's read from. */
+    protected static ?string $path = null, $fallback;
+
+    /** Read it again. */
+    #[Pure]
+    public function reload(bool $force = false): static {}
+}
+
+enum Status: string {
+    /** Shown to everyone. */
+    case Active = 'active';
+    case Hidden = 'hidden';
+}
+";
+        let tree = parser().parse(src, None).unwrap();
+        let find = |class, member| StubDoc::find_member(tree.root_node(), src, class, member);
+
+        assert_eq!(
+            find("App\\Config", "PATCH"),
+            Some(StubDoc {
+                signature: "final public const int PATCH = 1".to_string(),
+                markdown: Some("The version we're at.".to_string()),
+            })
+        );
+        assert_eq!(
+            find("App\\Status", "Active"),
+            Some(StubDoc {
+                signature: "case Active = 'active'".to_string(),
+                markdown: Some("Shown to everyone.".to_string()),
+            })
+        );
+        assert_eq!(
+            find("App\\Status", "Hidden").and_then(|doc| doc.markdown),
+            None
+        );
+        assert_eq!(
+            find("App\\Config", "$fallback"),
+            Some(StubDoc {
+                signature: "protected static ?string $fallback".to_string(),
+                markdown: Some("Where it's read from.".to_string()),
+            })
+        );
+        assert_eq!(
+            find("App\\Config", "RELOAD()"),
+            Some(StubDoc {
+                signature: "public function reload(bool $force = false): static".to_string(),
+                markdown: Some("Read it again.".to_string()),
+            })
+        );
+        assert_eq!(find("App\\Config", "reload"), None);
+        assert_eq!(find("App\\Status", "Gone"), None);
+        assert_eq!(find("Status", "Active"), None);
+    }
+
+    #[test]
+    fn stub_search_order() {
+        let locations = stub_locations(
+            Some(Path::new("/opt/pls/pls")),
+            Some(Path::new("/home/me/.local/share")),
+            &[PathBuf::from("/work/app")],
+        );
+        assert_eq!(
+            locations,
+            vec![
+                PathBuf::from("/opt/pls/phpstorm-stubs/PhpStormStubsMap.php"),
+                PathBuf::from("/home/me/.local/share/pls/stubs/PhpStormStubsMap.php"),
+                PathBuf::from("/work/app/vendor/jetbrains/phpstorm-stubs/PhpStormStubsMap.php"),
+            ]
+        );
+        assert!(stub_locations(None, None, &[]).is_empty());
     }
 
     #[test]
     fn parse_phpstorm_stubs() {
         let file_name = PathBuf::from_str("../../phpstorm-stubs/PhpStormStubsMap.php").unwrap();
         let file_mapping = FileMapping::from_filename(&file_name).unwrap();
-        assert!(file_mapping.files.len() <= file_mapping.mapping.len());
+        assert!(file_mapping.files.len() <= file_mapping.classes.len());
         assert_eq!(
             file_mapping
-                .mapping
-                .get("array_filter")
+                .get(StubKind::Function, "array_filter")
                 .unwrap()
                 .to_path_buf(),
             PathBuf::from_str("standard/standard_9.php").unwrap()
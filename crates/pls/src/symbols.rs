@@ -0,0 +1,325 @@
+use lsp_types::{DocumentSymbol, SymbolKind};
+use tree_sitter::Node;
+
+use crate::compat::to_range;
+
+/// The outline of a file: its classes, interfaces, traits and enums with their members, and its
+/// functions. Namespaces aren't part of it, since a file almost always has just the one.
+///
+/// Static variables are listed under the function or method they're in, since unlike its other
+/// variables they're kept between calls.
+pub fn document_symbols(root: Node<'_>, content: &str) -> Vec<DocumentSymbol> {
+    let mut symbols = Vec::new();
+    let mut cursor = root.walk();
+    for node in root.named_children(&mut cursor) {
+        let kind = match node.kind() {
+            "namespace_definition" => {
+                if let Some(body) = node.child_by_field_name("body") {
+                    symbols.extend(document_symbols(body, content));
+                }
+                continue;
+            }
+            "class_declaration" | "trait_declaration" => SymbolKind::CLASS,
+            "interface_declaration" => SymbolKind::INTERFACE,
+            "enum_declaration" => SymbolKind::ENUM,
+            "function_definition" => {
+                if let Some(name) = node.child_by_field_name("name") {
+                    let statics = static_variables(node, content);
+                    symbols.push(symbol(node, name, content, SymbolKind::FUNCTION, statics));
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        if let Some(name) = node.child_by_field_name("name") {
+            let members = node
+                .child_by_field_name("body")
+                .map(|body| members(body, content))
+                .unwrap_or_default();
+            symbols.push(symbol(node, name, content, kind, members));
+        }
+    }
+
+    symbols
+}
+
+#[allow(deprecated)]
+fn symbol(
+    node: Node<'_>,
+    name: Node<'_>,
+    content: &str,
+    kind: SymbolKind,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name: content[name.byte_range()].to_string(),
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: to_range(&node.range()),
+        selection_range: to_range(&name.range()),
+        children: (!children.is_empty()).then_some(children),
+    }
+}
+
+/// The constants, properties and their hooks, enum cases and methods in the body of a class-like.
+fn members(body: Node<'_>, content: &str) -> Vec<DocumentSymbol> {
+    let mut symbols = Vec::new();
+    let mut cursor = body.walk();
+    for node in body.named_children(&mut cursor) {
+        match node.kind() {
+            "const_declaration" => {
+                let mut cursor = node.walk();
+                for element in node.named_children(&mut cursor) {
+                    let name = element.named_child(0).filter(|name| name.kind() == "name");
+                    if element.kind() == "const_element"
+                        && let Some(name) = name
+                    {
+                        symbols.push(symbol(
+                            element,
+                            name,
+                            content,
+                            SymbolKind::CONSTANT,
+                            Vec::new(),
+                        ));
+                    }
+                }
+            }
+            "property_declaration" => {
+                let hooks = hooks(node, content);
+                let mut cursor = node.walk();
+                for element in node.named_children(&mut cursor) {
+                    if element.kind() == "property_element"
+                        && let Some(name) = element.child_by_field_name("name")
+                    {
+                        symbols.push(symbol(
+                            element,
+                            name,
+                            content,
+                            SymbolKind::PROPERTY,
+                            hooks.clone(),
+                        ));
+                    }
+                }
+            }
+            "enum_case" => {
+                if let Some(name) = node.child_by_field_name("name") {
+                    symbols.push(symbol(
+                        node,
+                        name,
+                        content,
+                        SymbolKind::ENUM_MEMBER,
+                        Vec::new(),
+                    ));
+                }
+            }
+            "method_declaration" => {
+                if let Some(name) = node.child_by_field_name("name") {
+                    let kind = if content[name.byte_range()].eq_ignore_ascii_case("__construct") {
+                        SymbolKind::CONSTRUCTOR
+                    } else {
+                        SymbolKind::METHOD
+                    };
+                    let statics = static_variables(node, content);
+                    symbols.push(symbol(node, name, content, kind, statics));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    symbols
+}
+
+/// The `get` and `set` hooks of a property.
+fn hooks(property: Node<'_>, content: &str) -> Vec<DocumentSymbol> {
+    let mut cursor = property.walk();
+    let Some(list) = property
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "property_hook_list")
+    else {
+        return Vec::new();
+    };
+
+    let mut cursor = list.walk();
+    list.named_children(&mut cursor)
+        .filter(|hook| hook.kind() == "property_hook")
+        .filter_map(|hook| {
+            let mut cursor = hook.walk();
+            let name = hook
+                .named_children(&mut cursor)
+                .find(|child| child.kind() == "name")?;
+            Some(symbol(hook, name, content, SymbolKind::METHOD, Vec::new()))
+        })
+        .collect()
+}
+
+/// The static variables of a function or method, leaving out the ones of closures and classes
+/// inside it.
+fn static_variables(function: Node<'_>, content: &str) -> Vec<DocumentSymbol> {
+    let mut symbols = Vec::new();
+    let mut stack: Vec<_> = function.child_by_field_name("body").into_iter().collect();
+    while let Some(node) = stack.pop() {
+        match node.kind() {
+            "static_variable_declaration" => {
+                if let Some(name) = node.child_by_field_name("name") {
+                    let mut variable =
+                        symbol(node, name, content, SymbolKind::VARIABLE, Vec::new());
+                    variable.detail = Some("static".to_string());
+                    symbols.push(variable);
+                }
+            }
+            // their static variables are their own
+            "function_definition" | "anonymous_function" | "arrow_function" => {}
+            // and so are the ones of an anonymous class's methods
+            "anonymous_class" => {}
+            _ => {
+                let mut cursor = node.walk();
+                stack.extend(node.named_children(&mut cursor));
+            }
+        }
+    }
+
+    symbols.sort_by_key(|symbol| symbol.range.start);
+    symbols
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::SymbolKind;
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use super::document_symbols;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    #[test]
+    fn outline() {
+        let content = r#"<?php
+namespace App;
+
+function counter(): int {
+    static $count = 0, $calls;
+    $reset = function () {
+        static $inner = 1;
+    };
+    return ++$count;
+}
+
+class Cache {
+    const TTL = 60, LIMIT = 10;
+    private array $items = [];
+
+    public function __construct() {}
+
+    public function get(string $key) {
+        static $hits = 0;
+        return $this->items[$key] ?? null;
+    }
+}
+
+enum Status {
+    case Active;
+}
+"#;
+        let tree = parser().parse(content, None).unwrap();
+        let symbols = document_symbols(tree.root_node(), content);
+        let outline: Vec<_> = symbols
+            .iter()
+            .map(|symbol| {
+                let children: Vec<_> = symbol
+                    .children
+                    .iter()
+                    .flatten()
+                    .map(|child| (child.name.as_str(), child.kind))
+                    .collect();
+                (symbol.name.as_str(), symbol.kind, children)
+            })
+            .collect();
+
+        assert_eq!(
+            outline,
+            vec![
+                (
+                    "counter",
+                    SymbolKind::FUNCTION,
+                    vec![
+                        ("$count", SymbolKind::VARIABLE),
+                        ("$calls", SymbolKind::VARIABLE)
+                    ]
+                ),
+                (
+                    "Cache",
+                    SymbolKind::CLASS,
+                    vec![
+                        ("TTL", SymbolKind::CONSTANT),
+                        ("LIMIT", SymbolKind::CONSTANT),
+                        ("$items", SymbolKind::PROPERTY),
+                        ("__construct", SymbolKind::CONSTRUCTOR),
+                        ("get", SymbolKind::METHOD),
+                    ]
+                ),
+                (
+                    "Status",
+                    SymbolKind::ENUM,
+                    vec![("Active", SymbolKind::ENUM_MEMBER)]
+                ),
+            ]
+        );
+
+        let count = &symbols[0].children.as_ref().unwrap()[0];
+        assert_eq!(count.detail.as_deref(), Some("static"));
+        assert_eq!(count.selection_range.start.line, 4);
+        let get = &symbols[1].children.as_ref().unwrap()[4];
+        let hits = &get.children.as_ref().unwrap()[0];
+        assert_eq!(
+            (hits.name.as_str(), hits.kind),
+            ("$hits", SymbolKind::VARIABLE)
+        );
+    }
+
+    #[test]
+    fn property_hooks() {
+        let content = r#"<?php
+class User {
+    public string $name {
+        get => ucfirst($this->name);
+        set(string $value) {
+            $this->name = strtolower($value);
+        }
+    }
+    public int $age = 0;
+}
+"#;
+        let tree = parser().parse(content, None).unwrap();
+        let symbols = document_symbols(tree.root_node(), content);
+        let properties = symbols[0].children.as_ref().unwrap();
+
+        assert_eq!(properties.len(), 2);
+        assert_eq!(properties[0].name, "$name");
+        let hooks: Vec<_> = properties[0]
+            .children
+            .iter()
+            .flatten()
+            .map(|hook| (hook.name.as_str(), hook.kind, hook.range.start.line))
+            .collect();
+        assert_eq!(
+            hooks,
+            vec![
+                ("get", SymbolKind::METHOD, 3),
+                ("set", SymbolKind::METHOD, 4)
+            ]
+        );
+        assert!(properties[1].children.is_none());
+    }
+}
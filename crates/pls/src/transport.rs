@@ -0,0 +1,182 @@
+use lsp_server::{Connection, Message};
+use lsp_types::notification::{Exit, Notification as _};
+
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener};
+use std::thread::{self, JoinHandle};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+/// Take clients on `listener` one after another, running `session` for each until they go away.
+pub fn serve_tcp(listener: TcpListener, mut session: impl FnMut(Connection)) -> anyhow::Result<()> {
+    for stream in listener.incoming() {
+        // one client failing to connect shouldn't keep the next ones out
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::error!("Err in accepting a client: {e:?}");
+                continue;
+            }
+        };
+        log::info!("client connected from {:?}", stream.peer_addr().ok());
+        let (reader, writer) = match (stream.try_clone(), stream.try_clone()) {
+            (Ok(reader), Ok(writer)) => (reader, writer),
+            (Err(e), _) | (_, Err(e)) => {
+                log::error!("Err in setting up the client connection: {e:?}");
+                continue;
+            }
+        };
+        let (connection, writer) = connection(reader, writer);
+        session(connection);
+        end_session(writer);
+        // the reader is still waiting on a client that isn't saying anything more
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    Ok(())
+}
+
+/// [`serve_tcp()`], over a unix domain socket.
+#[cfg(unix)]
+pub fn serve_unix(
+    listener: UnixListener,
+    mut session: impl FnMut(Connection),
+) -> anyhow::Result<()> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::error!("Err in accepting a client: {e:?}");
+                continue;
+            }
+        };
+        log::info!("client connected");
+        let (reader, writer) = match (stream.try_clone(), stream.try_clone()) {
+            (Ok(reader), Ok(writer)) => (reader, writer),
+            (Err(e), _) | (_, Err(e)) => {
+                log::error!("Err in setting up the client connection: {e:?}");
+                continue;
+            }
+        };
+        let (connection, writer) = connection(reader, writer);
+        session(connection);
+        end_session(writer);
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    Ok(())
+}
+
+fn end_session(writer: JoinHandle<()>) {
+    // by now the session has dropped its end, so this only waits for the last messages to go out
+    if writer.join().is_err() {
+        log::error!("Err in writing to the client: writer panicked");
+    }
+    log::info!("client disconnected");
+}
+
+/// An LSP connection over a byte stream. A client going away ends the messages, rather than
+/// taking the server down with it like [`Connection::listen()`] does.
+fn connection(
+    reader: impl Read + Send + 'static,
+    mut writer: impl Write + Send + 'static,
+) -> (Connection, JoinHandle<()>) {
+    let (reader_send, reader_recv) = crossbeam_channel::unbounded();
+    let (writer_send, writer_recv) = crossbeam_channel::unbounded::<Message>();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        loop {
+            match Message::read(&mut reader) {
+                Ok(Some(msg)) => {
+                    let is_exit =
+                        matches!(&msg, Message::Notification(n) if n.method == Exit::METHOD);
+                    if reader_send.send(msg).is_err() || is_exit {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    log::error!("Err in reading from the client: {e:?}");
+                    break;
+                }
+            }
+        }
+    });
+    let writer = thread::spawn(move || {
+        for msg in writer_recv {
+            if let Err(e) = msg.write(&mut writer) {
+                log::error!("Err in writing to the client: {e:?}");
+                break;
+            }
+        }
+    });
+
+    (
+        Connection {
+            sender: writer_send,
+            receiver: reader_recv,
+        },
+        writer,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_server::{Connection, Message, Notification};
+
+    use std::io::BufReader;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    /// Say back whatever the client says, until it leaves.
+    fn echo(connection: Connection) {
+        for msg in &connection.receiver {
+            connection.sender.send(msg).unwrap();
+        }
+    }
+
+    fn notification(method: &str) -> Message {
+        Message::Notification(Notification::new(method.to_string(), ()))
+    }
+
+    #[test]
+    fn sequential_tcp_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || super::serve_tcp(listener, echo));
+
+        for method in ["first", "second"] {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            notification(method).write(&mut stream).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            match Message::read(&mut reader).unwrap() {
+                Some(Message::Notification(n)) => assert_eq!(n.method, method),
+                msg => panic!("expected an echo, got {msg:?}"),
+            }
+            // leaving ends the session, and the server goes on to the next client
+            notification("exit").write(&mut stream).unwrap();
+            while Message::read(&mut reader).unwrap().is_some() {}
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_clients() {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = std::env::temp_dir().join(format!("pls-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || super::serve_unix(listener, echo));
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        notification("hello").write(&mut stream).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        match Message::read(&mut reader).unwrap() {
+            Some(Message::Notification(n)) => assert_eq!(n.method, "hello"),
+            msg => panic!("expected an echo, got {msg:?}"),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,2828 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use pls_types::{
+    Argument, CustomType, CustomTypesDatabase, EffectiveMembers, FromNode, Nullable, Or,
+    PhpNamespace, PhpVersion, Scalar, SegmentPool, Type, Visibility, class_file, resolve_ns,
+};
+
+use crate::analyze::{import_aliases, node_markup, raises_fatal_error, resolve_class_name};
+use crate::code_action::QuickFix;
+use crate::compat::to_range;
+use crate::config::Strictness;
+use crate::scope::Scope;
+use crate::stubs::FileMapping;
+
+/// Where we are while walking a file.
+struct Context<'a> {
+    content: &'a str,
+    ns_store: &'a mut SegmentPool,
+    types: &'a CustomTypesDatabase,
+    stubs: &'a FileMapping,
+    ns_to_dir: &'a HashMap<PhpNamespace, Vec<PathBuf>>,
+    /// Classes `classmap` autoloads say where to find.
+    classmap: &'a HashMap<PhpNamespace, PathBuf>,
+
+    scope: Scope,
+    /// FQN of the class-like declaration we're in, if any.
+    class: Option<PhpNamespace>,
+    /// What the function we're in promises to return, if we know.
+    returns: Option<Returns>,
+    /// Whether we're inside a function body, as opposed to top-level code.
+    in_function: bool,
+    /// Declared types of the parameters of the function we're in.
+    parameters: HashMap<String, Type>,
+    /// Variables holding a first-class callable like `strlen(...)`, with what they call and its
+    /// parameters.
+    callables: HashMap<String, (String, Vec<Argument>)>,
+    /// Whether the file has `declare(strict_types=1)`.
+    strict_types: bool,
+    strictness: Strictness,
+    /// The PHP version the project targets, if we know.
+    target: Option<PhpVersion>,
+
+    diagnostics: Vec<Diagnostic>,
+    /// Types and functions we know exist, but haven't ingested yet.
+    dependencies: Vec<PhpNamespace>,
+}
+
+/// A declared return type.
+struct Returns {
+    t: Type,
+    /// Declared in the signature rather than in the docblock, so PHP enforces it.
+    native: bool,
+}
+
+impl Context<'_> {
+    /// Resolve a class name the way PHP would at this point of the file.
+    ///
+    /// Returns `None` for names we can't pin down, like `static`.
+    fn resolve(&mut self, name: &str) -> Option<PhpNamespace> {
+        match name.to_lowercase().as_str() {
+            "static" => None,
+            "self" => self.class.clone(),
+            "parent" => {
+                let class = self.class.as_ref()?;
+                match &self.types.0.get(class)?.t {
+                    CustomType::Class(c) => c.parent_classes.first().cloned(),
+                    _ => None,
+                }
+            }
+            _ => Some(resolve_class_name(name, &self.scope, self.ns_store)),
+        }
+    }
+
+    /// Return true if there's a class-like type with that FQN in the workspace, the stubs, or
+    /// somewhere the autoload would find it.
+    fn class_exists(&self, ns: &PhpNamespace) -> bool {
+        if let Some(meta) = self.types.0.get(ns) {
+            return !matches!(meta.t, CustomType::Function(_));
+        }
+        if self.stubs.classes.contains_key(&ns.0.join("\\")) || self.classmap.contains_key(ns) {
+            return true;
+        }
+
+        let mut dir = ns.clone();
+        let Some(base) = dir.pop() else {
+            return false;
+        };
+        resolve_ns(&dir, self.ns_to_dir).is_ok_and(|dir| class_file(&dir, &base).is_some())
+    }
+
+    /// The class-likes in the workspace or the stubs that are called `name`, whatever namespace
+    /// they're in. Class names ignore case, so `exception` finds `Exception`.
+    fn import_candidates(&mut self, name: &str) -> Vec<PhpNamespace> {
+        let mut candidates: Vec<PhpNamespace> = self
+            .types
+            .0
+            .iter()
+            .filter(|(ns, meta)| {
+                !matches!(meta.t, CustomType::Function(_))
+                    && ns
+                        .0
+                        .last()
+                        .is_some_and(|last| last.eq_ignore_ascii_case(name))
+            })
+            .map(|(ns, _)| ns.clone())
+            .collect();
+        let stubs: Vec<_> = self
+            .stubs
+            .classes
+            .keys()
+            .filter(|key| {
+                key.rsplit('\\')
+                    .next()
+                    .is_some_and(|last| last.eq_ignore_ascii_case(name))
+            })
+            .collect();
+        for key in stubs {
+            let ns = self.ns_store.intern_str(key);
+            if !candidates.contains(&ns) {
+                candidates.push(ns);
+            }
+        }
+
+        candidates
+    }
+
+    /// Return true if code at this point of the file can see a member declared in `declared_in`.
+    fn can_access(&self, visibility: &Visibility, declared_in: &PhpNamespace) -> bool {
+        let Some(class) = &self.class else {
+            return *visibility == Visibility::Public;
+        };
+        // we don't know who ends up using the trait
+        if matches!(
+            self.types.0.get(class).map(|meta| &meta.t),
+            Some(CustomType::Trait(_))
+        ) {
+            return true;
+        }
+
+        let ancestors_of = |ns: &PhpNamespace| {
+            self.types
+                .effective_members(ns)
+                .map(|m| m.ancestors.clone())
+                .unwrap_or_default()
+        };
+        match visibility {
+            Visibility::Public => true,
+            // trait members are copied into the class using them
+            Visibility::Private => {
+                class == declared_in
+                    || matches!(
+                        self.types.0.get(declared_in).map(|meta| &meta.t),
+                        Some(CustomType::Trait(_))
+                    ) && ancestors_of(class).contains(declared_in)
+            }
+            Visibility::Protected => {
+                class == declared_in
+                    || ancestors_of(class).contains(declared_in)
+                    || ancestors_of(declared_in).contains(class)
+            }
+        }
+    }
+
+    /// Flag a member that isn't in `members`, unless one of the magic `fallbacks` methods would
+    /// catch it.
+    ///
+    /// We stay quiet when parts of the hierarchy are missing, since the member could be in there.
+    /// Same goes for traits, whose members may come from whoever uses them.
+    ///
+    /// Reading an undefined property only gets PHP to warn, anything else is an error.
+    fn check_undefined_member(
+        &mut self,
+        name: Node<'_>,
+        what: &str,
+        member: String,
+        class: &PhpNamespace,
+        members: &EffectiveMembers,
+        fallbacks: &[&str],
+    ) {
+        if !members.unresolved.is_empty()
+            || fallbacks.iter().any(|f| members.method(f).is_some())
+            || matches!(
+                self.types.0.get(class).map(|meta| &meta.t),
+                Some(CustomType::Trait(_))
+            )
+        {
+            return;
+        }
+
+        let severity = if what == "property" {
+            DiagnosticSeverity::WARNING
+        } else {
+            DiagnosticSeverity::ERROR
+        };
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&name.range()),
+            severity: Some(severity),
+            code: None,
+            code_description: None,
+            source: Some("undef".to_string()),
+            message: format!("undefined {} {}::{}", what, class, member),
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+
+    fn check_visibility(
+        &mut self,
+        name: Node<'_>,
+        what: &str,
+        member: String,
+        visibility: &Visibility,
+        declared_in: &PhpNamespace,
+    ) {
+        if self.can_access(visibility, declared_in) {
+            return;
+        }
+
+        let visibility = match visibility {
+            Visibility::Public => "public",
+            Visibility::Protected => "protected",
+            Visibility::Private => "private",
+        };
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&name.range()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: None,
+            code_description: None,
+            source: Some("visibility".to_string()),
+            message: format!(
+                "cannot access {} {} {}::{}",
+                visibility, what, declared_in, member
+            ),
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+
+    /// Hint at the use of something deprecated.
+    fn check_deprecated(
+        &mut self,
+        name: Node<'_>,
+        what: &str,
+        label: String,
+        reason: &Option<String>,
+    ) {
+        let Some(reason) = reason else {
+            return;
+        };
+
+        let mut message = format!("{} {} is deprecated", what, label);
+        if !reason.is_empty() {
+            message.push_str(": ");
+            message.push_str(reason);
+        }
+        self.diagnostics.push(Diagnostic {
+            range: to_range(&name.range()),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: None,
+            code_description: None,
+            source: Some("deprecated".to_string()),
+            message,
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::DEPRECATED]),
+            data: None,
+        });
+    }
+
+    /// Hint at a reference to a deprecated class-like type, unless it's from inside the type
+    /// itself.
+    fn check_deprecated_class(&mut self, name: Node<'_>) {
+        if name.kind() != "name" && name.kind() != "qualified_name" {
+            return;
+        }
+        let Some(ns) = self.resolve(&self.content[name.byte_range()]) else {
+            return;
+        };
+        if self.class.as_ref() == Some(&ns) {
+            return;
+        }
+        let types = self.types;
+        if let Some(meta) = types.0.get(&ns) {
+            let what = match meta.t {
+                CustomType::Function(_) => return,
+                CustomType::Interface(_) => "interface",
+                CustomType::Trait(_) => "trait",
+                CustomType::Enumeration(_) => "enum",
+                CustomType::Class(_) => "class",
+            };
+            self.check_deprecated(name, what, ns.to_string(), &meta.deprecated);
+        }
+    }
+
+    /// Resolves class names in a signature declared at this point of the file.
+    fn signature_resolver(&mut self) -> impl FnMut(&str) -> PhpNamespace + '_ {
+        let class = self.class.clone();
+        let parent = self.resolve("parent");
+        let (scope, ns_store) = (&self.scope, &mut *self.ns_store);
+        move |name: &str| match name.to_lowercase().as_str() {
+            "self" | "static" if class.is_some() => class.clone().unwrap(),
+            "parent" if parent.is_some() => parent.clone().unwrap(),
+            _ => resolve_class_name(name, scope, ns_store),
+        }
+    }
+
+    /// The return type a function-like declaration promises, from its signature or, failing
+    /// that, its `@return` tag.
+    fn declared_return_type(&mut self, node: Node<'_>) -> Option<Returns> {
+        let content = self.content;
+        let mut resolve = self.signature_resolver();
+
+        if let Some(t) = node.child_by_field_name("return_type") {
+            let t = Type::from_node(t, content, &mut resolve).ok()?;
+            return Some(Returns { t, native: true });
+        }
+
+        let markup = node_markup(node, content)?;
+        let t = markup.lines().find_map(|line| {
+            let mut words = line
+                .trim_start()
+                .trim_start_matches(['/', '*'])
+                .split_whitespace();
+            match words.next() {
+                Some("@return") => words.next(),
+                _ => None,
+            }
+        })?;
+        let t = Type::from_phpdoc(t, &mut resolve)?;
+
+        Some(Returns { t, native: false })
+    }
+
+    /// The declared types of a function-like declaration's parameters, by variable name.
+    fn parameter_types(&mut self, node: Node<'_>) -> HashMap<String, Type> {
+        let content = self.content;
+        let mut resolve = self.signature_resolver();
+        Argument::from_parameters(node, content, &mut resolve)
+            .into_iter()
+            .filter(|argument| argument.t != Type::Any && !argument.variadic)
+            .map(|argument| (argument.name, argument.t))
+            .collect()
+    }
+
+    /// Guess the type of an expression from what it looks like.
+    ///
+    /// There's no real type inference yet, so this only knows about literals and a few
+    /// expressions whose type doesn't depend on their operands.
+    fn infer(&mut self, node: Node<'_>) -> Option<Type> {
+        if is_callable_creation(node) {
+            return Some(Type::CustomType(self.ns_store.intern_str("\\Closure")));
+        }
+
+        let t = match node.kind() {
+            "integer" => Type::Scalar(Scalar::Integer),
+            "float" => Type::Scalar(Scalar::Float),
+            "string" | "encapsed_string" | "heredoc" | "nowdoc" => Type::Scalar(Scalar::String),
+            "boolean" => Type::Scalar(Scalar::BooleanLiteral(
+                self.content[node.byte_range()].eq_ignore_ascii_case("true"),
+            )),
+            "null" => Type::Scalar(Scalar::Null),
+            "array_creation_expression" => Type::Array,
+            "anonymous_function" | "arrow_function" => {
+                Type::CustomType(self.ns_store.intern_str("\\Closure"))
+            }
+            "parenthesized_expression" => return self.infer(node.named_child(0)?),
+            "variable_name" if &self.content[node.byte_range()] == "$this" => {
+                Type::CustomType(self.class.clone()?)
+            }
+            "object_creation_expression" => {
+                let name = node.named_child(0)?;
+                match name.kind() {
+                    "name" | "qualified_name" => {
+                        Type::CustomType(self.resolve(&self.content[name.byte_range()])?)
+                    }
+                    "anonymous_class" => Type::Object,
+                    _ => return None,
+                }
+            }
+            "class_constant_access_expression" => {
+                let (scope, case) = (node.named_child(0)?, node.named_child(1)?);
+                let ns = self.resolve(&self.content[scope.byte_range()])?;
+                match &self.types.0.get(&ns)?.t {
+                    CustomType::Enumeration(e)
+                        if e.values
+                            .iter()
+                            .any(|v| *v == self.content[case.byte_range()]) =>
+                    {
+                        Type::CustomType(ns)
+                    }
+                    _ => return None,
+                }
+            }
+            "member_access_expression"
+            | "nullsafe_member_access_expression"
+            | "member_call_expression"
+            | "nullsafe_member_call_expression" => return self.member_type(node),
+            "unary_op_expression" => match node.child(0)?.kind() {
+                "!" => Type::Scalar(Scalar::Boolean),
+                _ => return None,
+            },
+            "binary_expression" => match node.child_by_field_name("operator")?.kind() {
+                "." => Type::Scalar(Scalar::String),
+                "==" | "===" | "!=" | "!==" | "<>" | "<" | ">" | "<=" | ">=" | "&&" | "||"
+                | "and" | "or" | "xor" | "instanceof" => Type::Scalar(Scalar::Boolean),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(t)
+    }
+
+    /// The type of a property or of what a method returns, going by the type of the object.
+    ///
+    /// A `?->` anywhere in the chain makes it nullable, since the whole chain is `null` when its
+    /// object is.
+    fn member_type(&mut self, node: Node<'_>) -> Option<Type> {
+        let class = self.object_class(node.child_by_field_name("object")?)?;
+        let name = node
+            .child_by_field_name("name")
+            .filter(|name| name.kind() == "name")?;
+        let members = self.types.effective_members(&class).ok()?;
+        let member = &self.content[name.byte_range()];
+        let t = if node.kind().ends_with("call_expression") {
+            members.method(member)?.member.return_type.clone()
+        } else {
+            members
+                .properties
+                .get(&format!("${}", member))?
+                .member
+                .t
+                .clone()
+        };
+        if t == Type::Any {
+            return None;
+        }
+
+        let mut chain = Some(node);
+        let mut nullsafe = false;
+        while let Some(link) = chain.filter(|link| link.kind().contains("member_")) {
+            nullsafe |= link.kind().starts_with("nullsafe_");
+            chain = link.child_by_field_name("object");
+        }
+        match t {
+            Type::Nullable(_) => Some(t),
+            t if nullsafe => Some(Type::Nullable(Nullable(Box::new(t)))),
+            t => Some(t),
+        }
+    }
+
+    /// The class of the object whose members are being accessed, going by its type.
+    fn object_class(&mut self, object: Node<'_>) -> Option<PhpNamespace> {
+        let object_type = match object.kind() {
+            "variable_name" => match self.parameters.get(&self.content[object.byte_range()]) {
+                Some(t) => Some(t.clone()),
+                None => self.infer(object),
+            },
+            _ => self.infer(object),
+        };
+        match object_type? {
+            Type::CustomType(ns) => Some(ns),
+            Type::Nullable(Nullable(t)) => match *t {
+                Type::CustomType(ns) => Some(ns),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// What a first-class callable like `strlen(...)` or `$this->m(...)` calls, and its
+    /// parameters.
+    fn callable_signature(&mut self, node: Node<'_>) -> Option<(String, Vec<Argument>)> {
+        let name = match node.kind() {
+            "function_call_expression" => {
+                let candidates = function_candidates(node.child_by_field_name("function")?, self)?;
+                return candidates
+                    .iter()
+                    .find_map(|ns| match &self.types.0.get(ns)?.t {
+                        CustomType::Function(f) => Some((format!("{}()", ns), f.arguments.clone())),
+                        _ => None,
+                    });
+            }
+            _ => node
+                .child_by_field_name("name")
+                .filter(|name| name.kind() == "name")?,
+        };
+        let class = match node.kind() {
+            "member_call_expression" | "nullsafe_member_call_expression" => {
+                self.object_class(node.child_by_field_name("object")?)?
+            }
+            "scoped_call_expression" => {
+                let scope = &self.content[node.child_by_field_name("scope")?.byte_range()];
+                match scope.to_lowercase().as_str() {
+                    "static" => self.class.clone()?,
+                    _ => self.resolve(scope)?,
+                }
+            }
+            _ => return None,
+        };
+        let members = self.types.effective_members(&class).ok()?;
+        let method = members.method(&self.content[name.byte_range()])?;
+
+        Some((
+            format!("{}::{}()", method.declared_in, method.member.name),
+            method.member.arguments.clone(),
+        ))
+    }
+
+    /// The type of the values `foreach` hands out when looping over a call, from what the callee
+    /// says it yields.
+    fn element_type(&mut self, node: Node<'_>) -> Option<Type> {
+        match node.kind() {
+            "function_call_expression" => {
+                let candidates = function_candidates(node.child_by_field_name("function")?, self)?;
+                candidates
+                    .iter()
+                    .find_map(|ns| match &self.types.0.get(ns)?.t {
+                        CustomType::Function(f) => f.yields.clone(),
+                        _ => None,
+                    })
+            }
+            "member_call_expression" | "nullsafe_member_call_expression" => {
+                let class = self.object_class(node.child_by_field_name("object")?)?;
+                let name = node
+                    .child_by_field_name("name")
+                    .filter(|name| name.kind() == "name")?;
+                let members = self.types.effective_members(&class).ok()?;
+                members
+                    .method(&self.content[name.byte_range()])?
+                    .member
+                    .yields
+                    .clone()
+            }
+            "parenthesized_expression" => self.element_type(node.named_child(0)?),
+            _ => None,
+        }
+    }
+
+    /// Return true if a value of type `actual` may be used where `expected` is declared.
+    ///
+    /// Without `strict_types`, PHP coerces scalars into each other, so we only complain about
+    /// those when the file is strict, or we're told to be strict. Integers are always good for
+    /// floats.
+    fn accepts(&self, expected: &Type, actual: &Type) -> bool {
+        if self.types.is_subtype(actual, expected) {
+            return true;
+        }
+
+        let strict = self.strict_types || self.strictness == Strictness::Strict;
+        let is_scalar = |t: &Type| matches!(t, Type::Scalar(s) if *s != Scalar::Null);
+        let expected = match expected.normalize() {
+            Type::Or(Or(types)) => types,
+            t => vec![t],
+        };
+        match actual {
+            Type::Scalar(Scalar::Integer | Scalar::IntegerLiteral(_)) => expected
+                .iter()
+                .any(|t| *t == Type::Scalar(Scalar::Float) || !strict && is_scalar(t)),
+            t if is_scalar(t) => !strict && expected.iter().any(is_scalar),
+            _ => false,
+        }
+    }
+}
+
+/// Check how the types in the database are used throughout a file.
+///
+/// Run this after the file's types have been ingested. Also returns what the file uses that
+/// exists but hasn't been ingested yet, like built-in functions.
+pub fn check(
+    node: Node<'_>,
+    content: &str,
+    ns_store: &mut SegmentPool,
+    types: &CustomTypesDatabase,
+    stubs: &FileMapping,
+    (ns_to_dir, classmap): (
+        &HashMap<PhpNamespace, Vec<PathBuf>>,
+        &HashMap<PhpNamespace, PathBuf>,
+    ),
+    strictness: Strictness,
+    target: Option<PhpVersion>,
+) -> (Vec<Diagnostic>, Vec<PhpNamespace>) {
+    let mut cx = Context {
+        content,
+        ns_store,
+        types,
+        stubs,
+        ns_to_dir,
+        classmap,
+        scope: Scope::empty(),
+        class: None,
+        returns: None,
+        in_function: false,
+        parameters: HashMap::new(),
+        callables: HashMap::new(),
+        strict_types: false,
+        strictness,
+        target,
+        diagnostics: Vec::new(),
+        dependencies: Vec::new(),
+    };
+    walk(node, &mut cx);
+
+    (cx.diagnostics, cx.dependencies)
+}
+
+fn walk(node: Node<'_>, cx: &mut Context<'_>) {
+    let kind = node.kind();
+    if kind == "namespace_definition" {
+        if let Some(name) = node.child_by_field_name("name") {
+            cx.scope.ns = Some(cx.ns_store.intern_str(&cx.content[name.byte_range()]));
+        }
+    } else if kind == "namespace_use_declaration" {
+        import_aliases(node, cx.content, cx.ns_store, &mut cx.scope);
+        return;
+    } else if kind == "class_declaration"
+        || kind == "interface_declaration"
+        || kind == "trait_declaration"
+        || kind == "enum_declaration"
+    {
+        let Some(name) = node.child_by_field_name("name") else {
+            return;
+        };
+        let mut ns = cx.scope.ns.clone().unwrap_or(PhpNamespace::empty());
+        ns.push(Rc::from(&cx.content[name.byte_range()]));
+
+        let outer = cx.class.replace(ns);
+        walk_children(node, cx);
+        cx.class = outer;
+        return;
+    } else if kind == "declare_statement" {
+        let mut cursor = node.walk();
+        if node.named_children(&mut cursor).any(|directive| {
+            directive.kind() == "declare_directive"
+                && cx.content[directive.byte_range()]
+                    .split_whitespace()
+                    .collect::<String>()
+                    == "strict_types=1"
+        }) {
+            cx.strict_types = true;
+        }
+    } else if kind == "function_definition"
+        || kind == "method_declaration"
+        || kind == "anonymous_function"
+        || kind == "arrow_function"
+    {
+        // generators return a `Generator` no matter what's in their `return` statements
+        let returns = match kind {
+            "arrow_function" => None,
+            _ if node
+                .child_by_field_name("body")
+                .is_some_and(|body| contains_yield(body)) =>
+            {
+                check_generator_return(node, cx);
+                None
+            }
+            _ => cx.declared_return_type(node),
+        };
+
+        // arrow functions capture the whole scope they're declared in
+        let mut parameters = cx.parameter_types(node);
+        if kind == "arrow_function" {
+            for (name, t) in cx.parameters.iter() {
+                parameters.entry(name.clone()).or_insert_with(|| t.clone());
+            }
+        }
+
+        let outer = std::mem::replace(&mut cx.returns, returns);
+        let outer_in_function = std::mem::replace(&mut cx.in_function, true);
+        let outer_parameters = std::mem::replace(&mut cx.parameters, parameters);
+        let callables = match kind {
+            "arrow_function" => cx.callables.clone(),
+            _ => HashMap::new(),
+        };
+        let outer_callables = std::mem::replace(&mut cx.callables, callables);
+        walk_children(node, cx);
+        cx.returns = outer;
+        cx.in_function = outer_in_function;
+        cx.parameters = outer_parameters;
+        cx.callables = outer_callables;
+        return;
+    } else if kind == "base_clause" || kind == "class_interface_clause" {
+        let mut cursor = node.walk();
+        for name in node.named_children(&mut cursor) {
+            cx.check_deprecated_class(name);
+        }
+    } else if kind == "return_statement" {
+        check_return(node, cx);
+    } else if kind == "foreach_statement" {
+        bind_foreach_value(node, cx);
+    } else if kind == "assignment_expression" {
+        bind_callable(node, cx);
+    } else if kind == "match_expression" {
+        check_match(node, cx);
+    } else if cx.in_function
+        && (kind == "compound_statement" || kind == "case_statement" || kind == "default_statement")
+    {
+        check_reachability(node, cx);
+    } else if kind == "object_creation_expression" {
+        if let Some(name) = node.named_child(0) {
+            check_class_reference(name, cx);
+        }
+        check_object_creation(node, cx);
+    } else if kind == "named_type" {
+        if let Some(name) = node.named_child(0) {
+            check_class_reference(name, cx);
+        }
+    } else if kind == "binary_expression"
+        && node.child_by_field_name("operator").map(|o| o.kind()) == Some("instanceof")
+    {
+        if let Some(name) = node.child_by_field_name("right") {
+            check_class_reference(name, cx);
+        }
+    } else if kind == "function_call_expression" {
+        check_function_call(node, cx);
+    } else if kind == "member_access_expression"
+        || kind == "nullsafe_member_access_expression"
+        || kind == "member_call_expression"
+        || kind == "nullsafe_member_call_expression"
+    {
+        check_member_access(node, cx);
+    } else if kind == "scoped_call_expression"
+        || kind == "scoped_property_access_expression"
+        || kind == "class_constant_access_expression"
+    {
+        let scope = if kind == "class_constant_access_expression" {
+            node.named_child(0)
+        } else {
+            node.child_by_field_name("scope")
+        };
+        if let Some(scope) = scope {
+            check_class_reference(scope, cx);
+        }
+        check_scoped_access(node, cx);
+    }
+
+    walk_children(node, cx);
+}
+
+fn walk_children(node: Node<'_>, cx: &mut Context<'_>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk(child, cx);
+    }
+}
+
+/// Return true if there's a `yield` in the body, not counting nested functions.
+fn contains_yield(node: Node<'_>) -> bool {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .any(|child| match child.kind() {
+            "yield_expression" => true,
+            "function_definition"
+            | "anonymous_function"
+            | "arrow_function"
+            | "class_declaration"
+            | "anonymous_class" => false,
+            _ => contains_yield(child),
+        })
+}
+
+/// Flag the statements of a block that come after one control never makes it past.
+fn check_reachability(block: Node<'_>, cx: &mut Context<'_>) {
+    let value = block.child_by_field_name("value");
+    let mut terminated = false;
+    let mut unreachable: Option<(Node, Node)> = None;
+
+    let mut cursor = block.walk();
+    let statements = block
+        .named_children(&mut cursor)
+        .filter(|statement| statement.kind() != "comment" && Some(*statement) != value)
+        .collect::<Vec<_>>();
+    for statement in statements {
+        if statement.kind() == "named_label_statement" {
+            // a `goto` can land here
+            push_unreachable(unreachable.take(), cx);
+            terminated = false;
+        } else if terminated {
+            let first = unreachable.map_or(statement, |(first, _)| first);
+            unreachable = Some((first, statement));
+        } else {
+            terminated = terminates(statement, cx);
+        }
+    }
+    push_unreachable(unreachable, cx);
+}
+
+fn push_unreachable(statements: Option<(Node<'_>, Node<'_>)>, cx: &mut Context<'_>) {
+    let Some((first, last)) = statements else {
+        return;
+    };
+    cx.diagnostics.push(Diagnostic {
+        range: Range {
+            start: to_range(&first.range()).start,
+            end: to_range(&last.range()).end,
+        },
+        severity: Some(DiagnosticSeverity::HINT),
+        code: None,
+        code_description: None,
+        source: Some("unreachable".to_string()),
+        message: "unreachable code".to_string(),
+        related_information: None,
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        data: None,
+    });
+}
+
+/// Return true if control never makes it past the statement.
+///
+/// Loops and `switch` never count, since a `break` inside them only leaves the statement itself.
+fn terminates(statement: Node<'_>, cx: &mut Context<'_>) -> bool {
+    match statement.kind() {
+        "return_statement" | "exit_statement" | "break_statement" | "continue_statement"
+        | "goto_statement" => true,
+        "expression_statement" => {
+            statement
+                .named_child(0)
+                .is_some_and(|expression| match expression.kind() {
+                    "throw_expression" => true,
+                    "name" => is_exit(expression, cx.content),
+                    "function_call_expression" => {
+                        raises_fatal_error(expression, cx.content)
+                            || expression
+                                .child_by_field_name("function")
+                                .is_some_and(|function| {
+                                    is_exit(function, cx.content) || returns_never(function, cx)
+                                })
+                    }
+                    "member_call_expression" => cx.member_type(expression) == Some(Type::Never),
+                    "scoped_call_expression" => scoped_returns_never(expression, cx),
+                    _ => false,
+                })
+        }
+        "compound_statement" => {
+            let mut cursor = statement.walk();
+            let statements = statement.named_children(&mut cursor).collect::<Vec<_>>();
+            statements
+                .into_iter()
+                .take_while(|statement| statement.kind() != "named_label_statement")
+                .any(|statement| terminates(statement, cx))
+        }
+        // every branch has to terminate, and without an `else` there's a branch that does nothing
+        "if_statement" => {
+            let mut cursor = statement.walk();
+            let alternatives = statement
+                .children_by_field_name("alternative", &mut cursor)
+                .collect::<Vec<_>>();
+            statement
+                .child_by_field_name("body")
+                .is_some_and(|body| terminates(body, cx))
+                && alternatives.iter().any(|a| a.kind() == "else_clause")
+                && alternatives.into_iter().all(|alternative| {
+                    alternative
+                        .child_by_field_name("body")
+                        .is_some_and(|body| terminates(body, cx))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Return true if the name is `die` or `exit`.
+fn is_exit(name: Node<'_>, content: &str) -> bool {
+    name.kind() == "name"
+        && matches!(
+            content[name.byte_range()].to_lowercase().as_str(),
+            "die" | "exit"
+        )
+}
+
+/// Return true if the called function is declared to return `never`.
+fn returns_never(function: Node<'_>, cx: &mut Context<'_>) -> bool {
+    let Some(candidates) = function_candidates(function, cx) else {
+        return false;
+    };
+    candidates
+        .iter()
+        .find_map(|ns| match cx.types.0.get(ns).map(|meta| &meta.t) {
+            Some(CustomType::Function(f)) => Some(f.return_type == Type::Never),
+            _ => None,
+        })
+        .unwrap_or(false)
+}
+
+/// Return true if the static method called is declared to return `never`.
+fn scoped_returns_never(call: Node<'_>, cx: &mut Context<'_>) -> bool {
+    let (Some(scope), Some(name)) = (
+        call.child_by_field_name("scope"),
+        call.child_by_field_name("name"),
+    ) else {
+        return false;
+    };
+    // `static::fail()` calls an override, but that has to be `never` too
+    let class = match &cx.content[scope.byte_range()] {
+        s if s.eq_ignore_ascii_case("static") => cx.class.clone(),
+        s => cx.resolve(s),
+    };
+    let Some(members) = class.and_then(|class| cx.types.effective_members(&class).ok()) else {
+        return false;
+    };
+    members
+        .method(&cx.content[name.byte_range()])
+        .is_some_and(|m| m.member.return_type == Type::Never)
+}
+
+/// Flag `match`es on an enum that leave some of its cases out, and arms that repeat a case.
+fn check_match(node: Node<'_>, cx: &mut Context<'_>) {
+    let (Some(condition), Some(body)) = (
+        node.child_by_field_name("condition"),
+        node.child_by_field_name("body"),
+    ) else {
+        return;
+    };
+    let Some(subject) = condition.named_child(0) else {
+        return;
+    };
+    let t = match subject.kind() {
+        "variable_name" => cx
+            .parameters
+            .get(&cx.content[subject.byte_range()])
+            .cloned(),
+        _ => cx.infer(subject),
+    };
+    let Some(Type::CustomType(ns)) = t else {
+        return;
+    };
+    let types = cx.types;
+    let Some(CustomType::Enumeration(e)) = types.0.get(&ns).map(|meta| &meta.t) else {
+        return;
+    };
+
+    let mut has_default = false;
+    // anything but a plain case could match anything, as far as we know
+    let mut only_cases = true;
+    let mut handled: Vec<&str> = Vec::new();
+    let mut cursor = body.walk();
+    for arm in body.named_children(&mut cursor) {
+        if arm.kind() == "match_default_expression" {
+            has_default = true;
+            continue;
+        }
+        let Some(conditions) = arm.child_by_field_name("conditional_expressions") else {
+            continue;
+        };
+
+        let mut cursor = conditions.walk();
+        for condition in conditions.named_children(&mut cursor) {
+            let case = (condition.kind() == "class_constant_access_expression")
+                .then(|| condition.named_child(0).zip(condition.named_child(1)))
+                .flatten()
+                .filter(|(scope, _)| {
+                    cx.resolve(&cx.content[scope.byte_range()]).as_ref() == Some(&ns)
+                })
+                .and_then(|(_, name)| {
+                    let name = &cx.content[name.byte_range()];
+                    e.values.iter().find(|v| *v == name)
+                });
+            let Some(case) = case else {
+                only_cases = false;
+                continue;
+            };
+
+            if handled.contains(&case.as_str()) {
+                cx.diagnostics.push(Diagnostic {
+                    range: to_range(&condition.range()),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: None,
+                    code_description: None,
+                    source: Some("match".to_string()),
+                    message: format!("{}::{} is already handled by an earlier arm", ns, case),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            } else {
+                handled.push(case);
+            }
+        }
+    }
+    if has_default || !only_cases {
+        return;
+    }
+
+    let missing = e
+        .values
+        .iter()
+        .filter(|v| !handled.contains(&v.as_str()))
+        .map(|v| format!("{}::{}", ns, v))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return;
+    }
+    cx.diagnostics.push(Diagnostic {
+        range: Range {
+            start: to_range(&node.range()).start,
+            end: to_range(&condition.range()).end,
+        },
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: None,
+        code_description: None,
+        source: Some("match".to_string()),
+        message: format!("unhandled enum cases: {}", missing.join(", ")),
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// Flag generators whose declared return type can't hold the `Generator` they really return.
+fn check_generator_return(node: Node<'_>, cx: &mut Context<'_>) {
+    let Some(Returns { t, native }) = cx.declared_return_type(node) else {
+        return;
+    };
+    let types = match t.normalize() {
+        Type::Or(Or(types)) => types,
+        t => vec![t],
+    };
+    if types.iter().any(|t| match t {
+        Type::Any | Type::Object => true,
+        Type::CustomType(ns) => ns.0.last().is_some_and(|name| {
+            matches!(
+                name.to_lowercase().as_str(),
+                "generator" | "iterator" | "traversable"
+            )
+        }),
+        _ => false,
+    }) {
+        return;
+    }
+
+    let Some(at) = node
+        .child_by_field_name("return_type")
+        .or_else(|| node.child_by_field_name("name"))
+    else {
+        return;
+    };
+    cx.diagnostics.push(Diagnostic {
+        range: to_range(&at.range()),
+        severity: Some(if native {
+            DiagnosticSeverity::ERROR
+        } else {
+            DiagnosticSeverity::WARNING
+        }),
+        code: None,
+        code_description: None,
+        source: Some("return".to_string()),
+        message: format!(
+            "a generator returns a Generator, which doesn't fit return type {}",
+            t
+        ),
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// Return true if the expression is a call like `strlen(...)`, which makes a `Closure` out of the
+/// callee instead of calling it.
+fn is_callable_creation(node: Node<'_>) -> bool {
+    if !node.kind().ends_with("call_expression") {
+        return false;
+    }
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .find(|child| child.kind() == "arguments")
+        .and_then(|arguments| arguments.named_child(0))
+        .is_some_and(|argument| argument.kind() == "variadic_placeholder")
+}
+
+/// Remember what `$f = strlen(...)` calls, so `$f()` can be checked like `strlen()`. Closures
+/// assigned to a variable are checked against their own parameters.
+fn bind_callable(node: Node<'_>, cx: &mut Context<'_>) {
+    let (Some(left), Some(right)) = (
+        node.child_by_field_name("left"),
+        node.child_by_field_name("right"),
+    ) else {
+        return;
+    };
+    if left.kind() != "variable_name" {
+        return;
+    }
+
+    let name = cx.content[left.byte_range()].to_string();
+    let signature = if is_callable_creation(right) {
+        cx.callable_signature(right)
+    } else if matches!(right.kind(), "anonymous_function" | "arrow_function") {
+        let content = cx.content;
+        let mut resolve = cx.signature_resolver();
+        let parameters = Argument::from_parameters(right, content, &mut resolve);
+        Some(("{closure}".to_string(), parameters))
+    } else {
+        None
+    };
+    match signature {
+        Some(signature) => cx.callables.insert(name, signature),
+        None => cx.callables.remove(&name),
+    };
+}
+
+/// Give the value variable of a `foreach` the type of what it loops over, when we know it.
+fn bind_foreach_value(node: Node<'_>, cx: &mut Context<'_>) {
+    let (Some(iterated), Some(value)) = (node.named_child(0), node.named_child(1)) else {
+        return;
+    };
+    let value = match value.kind() {
+        "pair" => value.named_child(1),
+        _ => Some(value),
+    };
+    let Some(value) = value.and_then(|value| match value.kind() {
+        "by_ref" => value.named_child(0),
+        _ => Some(value),
+    }) else {
+        return;
+    };
+    if value.kind() != "variable_name" {
+        return;
+    }
+
+    let name = cx.content[value.byte_range()].to_string();
+    match cx.element_type(iterated) {
+        Some(t) if t != Type::Any => cx.parameters.insert(name, t),
+        _ => cx.parameters.remove(&name),
+    };
+}
+
+/// Flag `return` statements that don't give back what the function promised.
+fn check_return(node: Node<'_>, cx: &mut Context<'_>) {
+    let Some(Returns { t, native }) = &cx.returns else {
+        return;
+    };
+    let (expected, native) = (t.clone(), *native);
+
+    let message = match node.named_child(0) {
+        _ if expected == Type::Never => {
+            Some("a never-returning function must not return".to_string())
+        }
+        None if expected == Type::Void || !native && expected == Type::Any => None,
+        None => Some(format!("missing return value of type {}", expected)),
+        Some(_) if expected == Type::Void => {
+            Some("a void function must not return a value".to_string())
+        }
+        Some(value) => cx
+            .infer(value)
+            .filter(|actual| !cx.accepts(&expected, actual))
+            .map(|actual| format!("expected return type {}, found {}", expected, actual)),
+    };
+    if let Some(message) = message {
+        cx.diagnostics.push(Diagnostic {
+            range: to_range(&node.range()),
+            severity: Some(if native {
+                DiagnosticSeverity::ERROR
+            } else {
+                DiagnosticSeverity::WARNING
+            }),
+            code: None,
+            code_description: None,
+            source: Some("return".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        });
+    }
+}
+
+/// Flag references to classes we can't find anywhere, or that are deprecated.
+fn check_class_reference(name: Node<'_>, cx: &mut Context<'_>) {
+    cx.check_deprecated_class(name);
+    // without the stubs, every built-in class would look undefined
+    if cx.stubs.is_empty() {
+        return;
+    }
+    if name.kind() != "name" && name.kind() != "qualified_name" {
+        return;
+    }
+    let Some(ns) = cx.resolve(&cx.content[name.byte_range()]) else {
+        return;
+    };
+    if cx.class_exists(&ns) {
+        if !cx.types.0.contains_key(&ns) {
+            cx.dependencies.push(ns);
+        }
+        return;
+    }
+
+    // a bare name may just be missing its `use`
+    let fix = match cx
+        .import_candidates(&cx.content[name.byte_range()])
+        .as_slice()
+    {
+        [fqn] if name.kind() == "name" => Some(import_fix(name, fqn, cx.content)),
+        _ => None,
+    };
+    cx.diagnostics.push(Diagnostic {
+        range: to_range(&name.range()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: None,
+        code_description: None,
+        source: Some("undef".to_string()),
+        message: format!("undefined class {}", ns),
+        related_information: None,
+        tags: None,
+        data: fix.and_then(|fix| serde_json::to_value(fix).ok()),
+    });
+}
+
+/// A fix that adds `use {fqn};` to the imports of the namespace `name` is in.
+///
+/// The new import goes where it keeps the class imports sorted, or after the namespace declaration
+/// if there aren't any.
+pub fn import_fix(name: Node<'_>, fqn: &PhpNamespace, content: &str) -> QuickFix {
+    let fqn = fqn.0.join("\\");
+
+    // braced namespaces keep their imports in their body
+    let mut container = name;
+    while let Some(parent) = container.parent() {
+        container = parent;
+        if container.kind() == "compound_statement"
+            && container
+                .parent()
+                .is_some_and(|p| p.kind() == "namespace_definition")
+        {
+            break;
+        }
+    }
+
+    let mut cursor = container.walk();
+    let children: Vec<_> = container.children(&mut cursor).collect();
+    let imported = |declaration: &Node<'_>| {
+        content[declaration.byte_range()]["use".len()..]
+            .trim_start()
+            .to_lowercase()
+    };
+    let imports: Vec<_> = children
+        .iter()
+        .filter(|child| child.kind() == "namespace_use_declaration")
+        .filter(|child| {
+            let imported = imported(child);
+            !imported.starts_with("function ") && !imported.starts_with("const ")
+        })
+        .collect();
+
+    let (position, new_text) = if let Some(next) = imports
+        .iter()
+        .find(|declaration| imported(declaration) > fqn.to_lowercase())
+    {
+        let position = next.start_position();
+        let indent = " ".repeat(position.column);
+        let position = Position::new(position.row as u32, 0);
+        (position, format!("{indent}use {fqn};\n"))
+    } else if let Some(last) = imports.last() {
+        let indent = " ".repeat(last.start_position().column);
+        (to_range(&last.range()).end, format!("\n{indent}use {fqn};"))
+    } else {
+        // right after the namespace, or failing that the opening tag
+        let anchor = children
+            .iter()
+            .find(|child| child.kind() == "namespace_definition")
+            .or_else(|| children.iter().find(|child| child.kind() == "php_tag"))
+            .or_else(|| children.iter().find(|child| child.kind() == "{"));
+        match anchor {
+            Some(anchor) => (to_range(&anchor.range()).end, format!("\n\nuse {fqn};")),
+            None => (Position::new(0, 0), format!("use {fqn};\n")),
+        }
+    };
+
+    QuickFix {
+        title: format!("Import `{fqn}`"),
+        edits: vec![TextEdit {
+            range: Range::new(position, position),
+            new_text,
+        }],
+    }
+}
+
+/// Flag `new` on abstract classes and interfaces, and constructor calls with the wrong number of
+/// arguments.
+fn check_object_creation(node: Node<'_>, cx: &mut Context<'_>) {
+    let Some(name) = node.named_child(0) else {
+        return;
+    };
+    if name.kind() != "name" && name.kind() != "qualified_name" {
+        return;
+    }
+    let Some(ns) = cx.resolve(&cx.content[name.byte_range()]) else {
+        return;
+    };
+    let Some(meta) = cx.types.0.get(&ns) else {
+        return;
+    };
+
+    let what = match &meta.t {
+        CustomType::Class(c) if c.r#abstract => "abstract class",
+        CustomType::Interface(_) => "interface",
+        CustomType::Trait(_) => "trait",
+        CustomType::Enumeration(_) => "enum",
+        CustomType::Class(_) => {
+            if let Ok(members) = cx.types.effective_members(&ns)
+                && let Some(constructor) = members.method("__construct")
+            {
+                let callee = format!("{}::__construct()", constructor.declared_in);
+                cx.check_deprecated(
+                    name,
+                    "constructor",
+                    callee.clone(),
+                    &constructor.member.deprecated,
+                );
+                check_arguments(node, &constructor.member.arguments, &callee, cx);
+            }
+            return;
+        }
+        _ => return,
+    };
+    cx.diagnostics.push(Diagnostic {
+        range: to_range(&name.range()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: None,
+        code_description: None,
+        source: Some("new".to_string()),
+        message: format!("cannot instantiate {} {}", what, ns),
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// Flag calls to functions we've never heard of, and calls with the wrong number of arguments.
+///
+/// Unqualified names fall back to the global function when the namespaced one doesn't exist, like
+/// PHP does.
+fn check_function_call(node: Node<'_>, cx: &mut Context<'_>) {
+    let Some(function) = node.child_by_field_name("function") else {
+        return;
+    };
+    // `die()` and `exit()` are language constructs, not functions
+    if is_exit(function, cx.content) {
+        return;
+    }
+    if function.kind() == "variable_name" {
+        if let Some((callee, parameters)) = cx.callables.get(&cx.content[function.byte_range()]) {
+            let (callee, parameters) = (callee.clone(), parameters.clone());
+            check_arguments(node, &parameters, &callee, cx);
+        } else if let Some(class) = cx.object_class(function)
+            && let Ok(members) = cx.types.effective_members(&class)
+            && let Some(invoke) = members.method("__invoke")
+        {
+            // objects with `__invoke` can be called like functions
+            let callee = format!("{}::__invoke()", invoke.declared_in);
+            check_arguments(node, &invoke.member.arguments, &callee, cx);
+        }
+        return;
+    }
+    let Some(candidates) = function_candidates(function, cx) else {
+        return;
+    };
+
+    let types = cx.types;
+    for ns in candidates.iter() {
+        if let Some((ns, meta)) = types.function(ns)
+            && let CustomType::Function(f) = &meta.t
+        {
+            let callee = format!("{}()", ns);
+            cx.check_deprecated(function, "function", callee.clone(), &meta.deprecated);
+            check_arguments(node, &f.arguments, &callee, cx);
+            return;
+        }
+    }
+    // without the stubs, every call to the standard library would look undefined
+    if cx.stubs.is_empty() {
+        return;
+    }
+    // built-ins get ingested from the stubs once something calls them
+    let stubs = cx.stubs;
+    if let Some(name) = candidates
+        .iter()
+        .find_map(|ns| stubs.function_name(&ns.0.join("\\")))
+    {
+        let ns = cx.ns_store.intern_str(name);
+        cx.dependencies.push(ns);
+        return;
+    }
+
+    cx.diagnostics.push(Diagnostic {
+        range: to_range(&function.range()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: None,
+        code_description: None,
+        source: Some("undef".to_string()),
+        message: format!("undefined function {}", candidates[0]),
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// The FQNs a called function name could refer to, most specific first.
+///
+/// Unqualified names fall back to the global namespace, like PHP does.
+fn function_candidates(function: Node<'_>, cx: &mut Context<'_>) -> Option<Vec<PhpNamespace>> {
+    let name = &cx.content[function.byte_range()];
+    let candidates = match function.kind() {
+        "name" => match cx.scope.fn_aliases.get(name) {
+            Some((ns, _)) => vec![ns.clone()],
+            None => {
+                let global = cx.ns_store.intern_str(name);
+                let mut ns = cx.scope.ns.clone().unwrap_or(PhpNamespace::empty());
+                ns.extend(global.0.iter().cloned());
+                vec![ns, global]
+            }
+        },
+        "qualified_name" => vec![resolve_class_name(name, &cx.scope, cx.ns_store)],
+        "relative_name" => {
+            let relative = name
+                .trim_start_matches("namespace")
+                .trim_start_matches('\\');
+            let mut ns = cx.scope.ns.clone().unwrap_or(PhpNamespace::empty());
+            ns.extend(cx.ns_store.intern_str(relative).0);
+            vec![ns]
+        }
+        _ => return None,
+    };
+    Some(candidates)
+}
+
+/// Flag calls whose arguments don't fit the parameters.
+///
+/// Passing too few arguments is an error. Passing too many is only a warning, since PHP lets it
+/// slide for functions that aren't built-in.
+fn check_arguments(call: Node<'_>, parameters: &[Argument], callee: &str, cx: &mut Context<'_>) {
+    let mut cursor = call.walk();
+    let arguments = call
+        .children(&mut cursor)
+        .find(|child| child.kind() == "arguments");
+
+    let variadic = parameters.last().is_some_and(|p| p.variadic);
+    let mut count = 0;
+    let mut named: Vec<String> = Vec::new();
+    // unpacked arguments could fill any parameter
+    let mut unpacked = false;
+    if let Some(arguments) = arguments {
+        let mut cursor = arguments.walk();
+        for argument in arguments.named_children(&mut cursor) {
+            match argument.kind() {
+                "argument" => (),
+                // `f(...)` makes a closure out of `f`
+                "variadic_placeholder" => return,
+                _ => continue,
+            }
+            let Some(value) = argument.named_child(argument.named_child_count().saturating_sub(1))
+            else {
+                continue;
+            };
+
+            let parameter = match argument.child_by_field_name("name") {
+                Some(name) => {
+                    let name = format!("${}", &cx.content[name.byte_range()]);
+                    // the variadic parameter collects the names nothing else takes
+                    let position = parameters
+                        .iter()
+                        .position(|p| p.name == name && !p.variadic);
+                    let problem = match position {
+                        _ if named.contains(&name) => {
+                            Some(format!("{} is passed to {} twice", name, callee))
+                        }
+                        Some(i) if i < count => Some(format!(
+                            "{} is passed to {} by position already",
+                            name, callee
+                        )),
+                        None if !variadic => Some(format!("{} has no parameter {}", callee, name)),
+                        _ => None,
+                    };
+                    if let Some(message) = problem {
+                        argument_error(argument, message, cx);
+                    }
+                    named.push(name);
+                    position
+                        .map(|i| &parameters[i])
+                        .or(parameters.last().filter(|p| p.variadic))
+                }
+                None if value.kind() == "variadic_unpacking" => {
+                    unpacked = true;
+                    None
+                }
+                None => {
+                    if !named.is_empty() {
+                        let message = "positional argument after named arguments".to_string();
+                        argument_error(argument, message, cx);
+                    }
+                    count += 1;
+                    parameters
+                        .get(count - 1)
+                        .or(parameters.last().filter(|p| p.variadic))
+                }
+            };
+            if let Some(parameter) = parameter {
+                check_argument_type(value, parameter, callee, cx);
+            }
+        }
+    }
+    if unpacked {
+        return;
+    }
+    if !named.is_empty() {
+        // named arguments can skip optional parameters, but not required ones
+        let missing: Vec<_> = parameters
+            .iter()
+            .enumerate()
+            .filter(|(i, p)| *i >= count && !p.optional && !p.variadic && !named.contains(&p.name))
+            .map(|(_, p)| p.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            let message = format!(
+                "too few arguments to {}: missing {}",
+                callee,
+                missing.join(", ")
+            );
+            argument_error(arguments.unwrap_or(call), message, cx);
+        }
+        return;
+    }
+
+    let required = parameters.iter().filter(|p| !p.optional).count();
+    let (message, severity) = if count < required {
+        let expected = if required < parameters.len() || variadic {
+            format!("at least {}", required)
+        } else {
+            required.to_string()
+        };
+        let message = format!(
+            "too few arguments to {}: expected {}, found {}",
+            callee, expected, count
+        );
+        (message, DiagnosticSeverity::ERROR)
+    } else if count > parameters.len() && !variadic {
+        let expected = if required < parameters.len() {
+            format!("at most {}", parameters.len())
+        } else {
+            parameters.len().to_string()
+        };
+        let message = format!(
+            "too many arguments to {}: expected {}, found {}",
+            callee, expected, count
+        );
+        (message, DiagnosticSeverity::WARNING)
+    } else {
+        return;
+    };
+
+    cx.diagnostics.push(Diagnostic {
+        range: to_range(&arguments.unwrap_or(call).range()),
+        severity: Some(severity),
+        code: None,
+        code_description: None,
+        source: Some("args".to_string()),
+        message,
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// Arguments that PHP refuses to call with.
+fn argument_error(node: Node<'_>, message: String, cx: &mut Context<'_>) {
+    cx.diagnostics.push(Diagnostic {
+        range: to_range(&node.range()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: None,
+        code_description: None,
+        source: Some("args".to_string()),
+        message,
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// Warn about an argument that obviously doesn't fit the parameter's type.
+fn check_argument_type(value: Node<'_>, parameter: &Argument, callee: &str, cx: &mut Context<'_>) {
+    // whatever we pass by reference gets overwritten anyway
+    if cx.strictness == Strictness::Off || parameter.by_ref {
+        return;
+    }
+    let Some(actual) = cx.infer(value) else {
+        return;
+    };
+    if cx.accepts(&parameter.t, &actual) {
+        return;
+    }
+
+    cx.diagnostics.push(Diagnostic {
+        range: to_range(&value.range()),
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: None,
+        code_description: None,
+        source: Some("args".to_string()),
+        message: format!(
+            "{} expects {} for {}, found {}",
+            callee, parameter.t, parameter.name, actual
+        ),
+        related_information: None,
+        tags: None,
+        data: None,
+    });
+}
+
+/// Flag `->` accesses to members we can't see or that don't exist, when we know the type of the
+/// object.
+fn check_member_access(node: Node<'_>, cx: &mut Context<'_>) {
+    let (Some(object), Some(name)) = (
+        node.child_by_field_name("object"),
+        node.child_by_field_name("name"),
+    ) else {
+        return;
+    };
+    if name.kind() != "name" {
+        return;
+    }
+
+    let class = if object.kind() == "variable_name" && &cx.content[object.byte_range()] == "$this" {
+        cx.class.clone()
+    } else if object.kind() == "parenthesized_expression"
+        && let Some(creation) = object.named_child(0)
+        && creation.kind() == "object_creation_expression"
+        && let Some(class_name) = creation.named_child(0)
+        && (class_name.kind() == "name" || class_name.kind() == "qualified_name")
+    {
+        cx.resolve(&cx.content[class_name.byte_range()])
+    } else {
+        None
+    };
+    let Some(class) = class else {
+        return;
+    };
+    let Ok(members) = cx.types.effective_members(&class) else {
+        return;
+    };
+
+    let member = &cx.content[name.byte_range()];
+    if node.kind().ends_with("call_expression") {
+        if let Some(m) = members.method(member) {
+            let label = format!("{}()", member);
+            cx.check_visibility(name, "method", label, &m.member.visibility, &m.declared_in);
+            let callee = format!("{}::{}()", m.declared_in, m.member.name);
+            cx.check_deprecated(name, "method", callee.clone(), &m.member.deprecated);
+            check_arguments(node, &m.member.arguments, &callee, cx);
+        } else {
+            let label = format!("{}()", member);
+            cx.check_undefined_member(name, "method", label, &class, &members, &["__call"]);
+        }
+    } else if let Some(p) = members.properties.get(&format!("${}", member)) {
+        let label = format!("${}", member);
+        cx.check_visibility(
+            name,
+            "property",
+            label,
+            &p.member.visibility,
+            &p.declared_in,
+        );
+    } else if !allows_dynamic_properties(&class, &members, cx.types) {
+        let label = format!("${}", member);
+        if !is_property_write(node) {
+            cx.check_undefined_member(name, "property", label, &class, &members, &["__get"]);
+        } else if members.unresolved.is_empty()
+            && members.method("__set").is_none()
+            && cx
+                .target
+                .is_some_and(|target| target >= PhpVersion::new(8, 2))
+        {
+            cx.diagnostics.push(Diagnostic {
+                range: to_range(&name.range()),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("undef".to_string()),
+                message: format!(
+                    "creating dynamic property {}::{} is deprecated since PHP 8.2",
+                    class, label
+                ),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// Return true if properties can be added to instances of `class` on the fly without PHP
+/// complaining, like on `stdClass` or with `#[AllowDynamicProperties]`.
+fn allows_dynamic_properties(
+    class: &PhpNamespace,
+    members: &EffectiveMembers,
+    types: &CustomTypesDatabase,
+) -> bool {
+    std::iter::once(class)
+        .chain(members.ancestors.iter())
+        .any(|ns| {
+            ns.to_string().eq_ignore_ascii_case("\\stdClass")
+                || matches!(
+                    types.0.get(ns).map(|meta| &meta.t),
+                    Some(CustomType::Class(c)) if c.dynamic_properties
+                )
+        })
+}
+
+/// Return true if the property access is being assigned to (directly or through one of its
+/// elements) or unset, rather than read.
+fn is_property_write(access: Node<'_>) -> bool {
+    let mut target = access;
+    while let Some(parent) = target.parent()
+        && parent.kind() == "subscript_expression"
+        && parent.named_child(0) == Some(target)
+    {
+        target = parent;
+    }
+
+    target.parent().is_some_and(|parent| match parent.kind() {
+        "assignment_expression" | "reference_assignment_expression" => {
+            parent.child_by_field_name("left") == Some(target)
+        }
+        "unset_statement" => true,
+        _ => false,
+    })
+}
+
+/// Flag `::` accesses to members we can't see or that don't exist.
+fn check_scoped_access(node: Node<'_>, cx: &mut Context<'_>) {
+    let (scope, name) = if node.kind() == "class_constant_access_expression" {
+        (node.named_child(0), node.named_child(1))
+    } else {
+        (
+            node.child_by_field_name("scope"),
+            node.child_by_field_name("name"),
+        )
+    };
+    let (Some(scope), Some(name)) = (scope, name) else {
+        return;
+    };
+    if !matches!(scope.kind(), "name" | "qualified_name" | "relative_scope") {
+        return;
+    }
+    cx.check_deprecated_class(scope);
+    let Some(class) = cx.resolve(&cx.content[scope.byte_range()]) else {
+        return;
+    };
+    let Ok(members) = cx.types.effective_members(&class) else {
+        return;
+    };
+
+    let member = cx.content[name.byte_range()].to_string();
+    if node.kind() == "scoped_call_expression" {
+        if let Some(m) = members.method(&member) {
+            let label = format!("{}()", member);
+            cx.check_visibility(name, "method", label, &m.member.visibility, &m.declared_in);
+            let callee = format!("{}::{}()", m.declared_in, m.member.name);
+            cx.check_deprecated(name, "method", callee.clone(), &m.member.deprecated);
+            check_arguments(node, &m.member.arguments, &callee, cx);
+        } else {
+            // `parent::foo()` and friends are instance calls when we're in an instance method
+            let label = format!("{}()", member);
+            let fallbacks = ["__callStatic", "__call"];
+            cx.check_undefined_member(name, "method", label, &class, &members, &fallbacks);
+        }
+    } else if node.kind() == "scoped_property_access_expression" {
+        match members.properties.get(&member) {
+            Some(p) if p.member.r#static => cx.check_visibility(
+                name,
+                "property",
+                member,
+                &p.member.visibility,
+                &p.declared_in,
+            ),
+            _ => cx.check_undefined_member(name, "static property", member, &class, &members, &[]),
+        }
+    } else if let Some(c) = members.constants.get(&member) {
+        let label = format!("{}::{}", c.declared_in, member);
+        cx.check_deprecated(name, "constant", label, &c.member.deprecated);
+        cx.check_visibility(
+            name,
+            "constant",
+            member,
+            &c.member.visibility,
+            &c.declared_in,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{DiagnosticSeverity, DiagnosticTag, Position};
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use pls_types::{CustomTypesDatabase, PhpVersion, SegmentPool};
+
+    use std::collections::{BTreeSet, HashMap};
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    use crate::analyze::injest_types;
+    use crate::code_action::QuickFix;
+    use crate::config::Strictness;
+    use crate::stubs::{FileMapping, StubKind};
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    fn stubs() -> FileMapping {
+        let mut stubs = FileMapping::default();
+        for (name, kind) in [
+            ("strlen", StubKind::Function),
+            ("Ds\\deque", StubKind::Function),
+            ("Exception", StubKind::Class),
+        ] {
+            stubs.insert(kind, name, PathBuf::from("standard/standard.php"));
+        }
+
+        stubs
+    }
+
+    fn check(src: &str) -> Vec<lsp_types::Diagnostic> {
+        check_with(src, Strictness::default())
+    }
+
+    fn check_with(src: &str, strictness: Strictness) -> Vec<lsp_types::Diagnostic> {
+        let tree = parser().parse(src, None).unwrap();
+        let root_node = tree.root_node();
+        let mut types = CustomTypesDatabase::new();
+        let mut pool = SegmentPool::new();
+        injest_types(
+            root_node,
+            src,
+            Path::new("/tmp/test.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        super::check(
+            root_node,
+            src,
+            &mut pool,
+            &types,
+            &stubs(),
+            (&HashMap::new(), &HashMap::new()),
+            strictness,
+            None,
+        )
+        .0
+    }
+
+    #[test]
+    fn instantiating_abstract_types() {
+        let src = "<?php
+        namespace App;
+
+        use App\\Shapes\\Contract as Shape;
+
+        abstract class Base {
+            public static function make() { return new static(); }
+            public static function broken() { return new self(); }
+        }
+        interface Contract {}
+        class Concrete extends Base {}
+
+        new Base();
+        new \\App\\Contract;
+        new Shape();
+        new Concrete();
+        new Unknown();
+        new class extends Base {};
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("new"))
+            .collect();
+        assert_eq!(diags.len(), 3, "diags = {:?}", diags);
+        assert_eq!(
+            diags[0].message,
+            "cannot instantiate abstract class \\App\\Base"
+        );
+        assert_eq!(diags[0].range.start.line, 7);
+        assert_eq!(diags[1].range.start.line, 12);
+        assert_eq!(
+            diags[2].message,
+            "cannot instantiate interface \\App\\Contract"
+        );
+    }
+
+    #[test]
+    fn visibility_violations() {
+        let src = "<?php
+        trait Secret { private $hidden; }
+        class Base {
+            use Secret;
+            private int $mine;
+            protected int $shared;
+            public int $open;
+            private const KEY = 1;
+            protected static function helper() {}
+            private function own() { $this->mine; $this->hidden; self::KEY; }
+            public function leak() { return $this->hidden; }
+        }
+        class Child extends Base {
+            function f() { $this->shared; parent::helper(); $this->own(); Base::KEY; }
+        }
+        (new Base)->open;
+        (new Base)->shared;
+        Base::helper();
+        Child::KEY;
+        (new Child)->hidden;
+        ";
+        let diags = check(src);
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "cannot access private method \\Base::own()",
+                "cannot access private constant \\Base::KEY",
+                "cannot access protected property \\Base::$shared",
+                "cannot access protected method \\Base::helper()",
+                "cannot access private constant \\Base::KEY",
+                "cannot access private property \\Secret::$hidden",
+            ]
+        );
+        assert_eq!(diags[0].range.start.line, 13);
+        assert_eq!(diags[2].range.start.line, 16);
+    }
+
+    #[test]
+    fn undefined_functions() {
+        let src = "<?php
+        namespace App;
+
+        use function Lib\\imported;
+        use function Ds\\{deque as dq};
+
+        function local() {}
+
+        strlen('falls back to global');
+        \\strlen('global');
+        local();
+        \\App\\local();
+        namespace\\local();
+        dq();
+        $callback();
+        imported();
+        missing();
+        Sub\\local();
+        ";
+        let diags = check(src);
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "undefined function \\Lib\\imported",
+                "undefined function \\App\\missing",
+                "undefined function \\App\\Sub\\local",
+            ]
+        );
+        assert_eq!(diags[1].range.start.line, 16);
+    }
+
+    #[test]
+    fn function_names_ignore_case() {
+        let src = "<?php
+        namespace App;
+
+        function fooBar() {}
+
+        STRLEN('built-in');
+        \\StrLen('global');
+        foobar();
+        \\App\\FOOBAR();
+        foo_bar();
+        ";
+        let diags = check(src);
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["undefined function \\App\\foo_bar"]);
+    }
+
+    #[test]
+    fn undefined_classes() {
+        let src = "<?php
+        namespace App;
+
+        use Exception as Oops;
+
+        class Known {
+            public function f(self $a, Known $b, Missing1 $c): ?Missing2 {
+                try {} catch (\\Exception | Oops | Missing3 $e) {}
+                static::f();
+                return $a instanceof Missing4 ? new Missing5() : Missing6::X;
+            }
+        }
+        ";
+        let diags = check(src);
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            (1..=6)
+                .map(|i| format!("undefined class \\App\\Missing{}", i))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn classmap_classes() {
+        let src = "<?php
+namespace App;
+
+$thing = new \\Legacy_Thing();
+";
+        let tree = parser().parse(src, None).unwrap();
+        let mut pool = SegmentPool::new();
+        let classmap = HashMap::from([(
+            pool.intern_str("Legacy_Thing"),
+            PathBuf::from("/tmp/legacy.php"),
+        )]);
+        let undefined = |classmap: &HashMap<_, _>, pool: &mut SegmentPool| {
+            super::check(
+                tree.root_node(),
+                src,
+                pool,
+                &CustomTypesDatabase::new(),
+                &stubs(),
+                (&HashMap::new(), classmap),
+                Strictness::default(),
+                None,
+            )
+            .0
+            .into_iter()
+            .filter(|d| d.message.starts_with("undefined class"))
+            .count()
+        };
+
+        assert_eq!(undefined(&HashMap::new(), &mut pool), 1);
+        assert_eq!(undefined(&classmap, &mut pool), 0);
+    }
+
+    #[test]
+    fn import_missing_class() {
+        let lib = "<?php
+namespace Lib\\Widgets;
+
+class Widget {}
+class Gadget {}
+";
+        let import_fix = |src: &str| {
+            let mut types = CustomTypesDatabase::new();
+            let mut pool = SegmentPool::new();
+            let tree = parser().parse(lib, None).unwrap();
+            injest_types(
+                tree.root_node(),
+                lib,
+                Path::new("/tmp/lib.php"),
+                &mut pool,
+                &mut types,
+            );
+            let tree = parser().parse(src, None).unwrap();
+            injest_types(
+                tree.root_node(),
+                src,
+                Path::new("/tmp/test.php"),
+                &mut pool,
+                &mut types,
+            );
+            let (diags, _) = super::check(
+                tree.root_node(),
+                src,
+                &mut pool,
+                &types,
+                &stubs(),
+                (&HashMap::new(), &HashMap::new()),
+                Strictness::default(),
+                None,
+            );
+            let diags: Vec<_> = diags
+                .into_iter()
+                .filter(|d| d.message.starts_with("undefined class"))
+                .collect();
+            assert_eq!(diags.len(), 1, "diags = {:?}", diags);
+            diags[0]
+                .data
+                .clone()
+                .map(|data| serde_json::from_value::<QuickFix>(data).unwrap())
+        };
+
+        let src = "<?php
+namespace App;
+
+use Lib\\Alpha;
+use Lib\\Zeta;
+use function Lib\\helper;
+
+function f(Widget $w) {}
+";
+        let fix = import_fix(src).unwrap();
+        assert_eq!(fix.title, "Import `Lib\\Widgets\\Widget`");
+        assert_eq!(fix.edits[0].range.start, Position::new(4, 0));
+        assert_eq!(fix.edits[0].new_text, "use Lib\\Widgets\\Widget;\n");
+
+        let src = "<?php
+namespace App;
+
+use Lib\\Alpha;
+
+function f(Exception $e) {}
+";
+        let fix = import_fix(src).unwrap();
+        assert_eq!(fix.edits[0].range.start, Position::new(3, 0));
+        assert_eq!(fix.edits[0].new_text, "use Exception;\n");
+
+        // class names ignore case
+        let src = "<?php
+namespace App;
+
+function f(exception $e) {}
+";
+        let fix = import_fix(src).unwrap();
+        assert_eq!(fix.title, "Import `Exception`");
+        let src = "<?php
+namespace App;
+
+function f(widget $w) {}
+";
+        let fix = import_fix(src).unwrap();
+        assert_eq!(fix.title, "Import `Lib\\Widgets\\Widget`");
+
+        let src = "<?php
+namespace App;
+
+function f(Gadget $g) {}
+";
+        let fix = import_fix(src).unwrap();
+        assert_eq!(fix.edits[0].range.start, Position::new(1, 14));
+        assert_eq!(fix.edits[0].new_text, "\n\nuse Lib\\Widgets\\Gadget;");
+
+        // already qualified, so importing wouldn't help
+        let src = "<?php
+namespace App;
+
+function f(Sub\\Widget $w) {}
+";
+        assert!(import_fix(src).is_none());
+    }
+
+    #[test]
+    fn undefined_methods() {
+        let src = "<?php
+        trait Helps { public function help() { $this->fromUser(); } }
+        class Base { public function inherited() {} }
+        class Child extends Base {
+            use Helps;
+            public function f() {
+                $this->inherited();
+                $this->HELP();
+                $this->nope();
+                parent::nope();
+                (new Magic)->anything();
+                Magic::anything();
+                (new Partial)->anything();
+            }
+        }
+        class Magic { public function __call($n, $a) {} }
+        class Partial extends Elsewhere {}
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.message.starts_with("undefined method"))
+            .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "undefined method \\Child::nope()",
+                "undefined method \\Base::nope()",
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_properties() {
+        let src = "<?php
+        /**
+         * @property int $magic
+         * @property-read string $readOnly
+         */
+        class Base {
+            public static $count;
+            public $instance;
+            public function __construct(protected int $promoted) {}
+        }
+        class Child extends Base {
+            public function f() {
+                $this->instance;
+                $this->promoted;
+                $this->magic;
+                $this->readOnly;
+                $this->nope;
+                static::$count;
+                self::$count;
+                self::$instance;
+                parent::$nope;
+            }
+        }
+        class Lazy { public function __get($name) {} }
+        (new Lazy)->anything;
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.message.starts_with("undefined"))
+            .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "undefined property \\Child::$nope",
+                "undefined static property \\Child::$instance",
+                "undefined static property \\Base::$nope",
+            ]
+        );
+    }
+
+    #[test]
+    fn dynamic_properties() {
+        let src = "<?php
+        class Cache {
+            public function f() {
+                $this->cache = [];
+                $this->items[] = 1;
+                return $this->nope;
+            }
+        }
+        #[AllowDynamicProperties]
+        class Loose {
+            public function f() { $this->x = 1; return $this->y; }
+        }
+        class Magic {
+            public function __set($name, $value) {}
+            public function f() { $this->x = 1; }
+        }
+        ";
+        let properties = |target| {
+            let tree = parser().parse(src, None).unwrap();
+            let mut types = CustomTypesDatabase::new();
+            let mut pool = SegmentPool::new();
+            injest_types(
+                tree.root_node(),
+                src,
+                Path::new("/tmp/test.php"),
+                &mut pool,
+                &mut types,
+            );
+            super::check(
+                tree.root_node(),
+                src,
+                &mut pool,
+                &types,
+                &stubs(),
+                (&HashMap::new(), &HashMap::new()),
+                Strictness::default(),
+                target,
+            )
+            .0
+            .into_iter()
+            .filter(|d| d.message.contains("property"))
+            .map(|d| (d.severity, d.message))
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            properties(None),
+            vec![(
+                Some(DiagnosticSeverity::WARNING),
+                "undefined property \\Cache::$nope".to_string()
+            )]
+        );
+        let messages: Vec<_> = properties(Some(PhpVersion::new(8, 2)))
+            .into_iter()
+            .map(|(_, message)| message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "creating dynamic property \\Cache::$cache is deprecated since PHP 8.2",
+                "creating dynamic property \\Cache::$items is deprecated since PHP 8.2",
+                "undefined property \\Cache::$nope",
+            ]
+        );
+    }
+
+    #[test]
+    fn return_type_mismatches() {
+        let src = "<?php
+        declare(strict_types=1);
+
+        class Base {}
+        class Child extends Base {
+            public function fine(): ?Base { if (1) return null; return new Child(); }
+            public function me(): static { return $this; }
+            public function wrong(): int { return 'nope'; }
+            public function coerced(): float { return 1; }
+            public function empty(): string { return; }
+            public function void(): void { return; return 1; }
+            public function gen(): Generator { yield 1; return 2; }
+            /** @return string|false */
+            public function doc() { return false; return new Base; }
+            public function unknown(): string { return $this->doc(); }
+            public function parent(): ?Base { return null; }
+            public function known(): Base { return $this->me(); }
+            public function chained(): Base { return $this?->me()->parent(); }
+            public function nullsafe(?Child $child): Child { return $child?->me(); }
+        }
+        function never(): never { return; }
+        function outer(): int { $f = function (): string { return 'ok'; }; return 0; }
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("return"))
+            .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "expected return type int, found string",
+                "missing return value of type string",
+                "a void function must not return a value",
+                "expected return type string|false, found \\Base",
+                "expected return type \\Base, found ?\\Base",
+                "expected return type \\Child, found ?\\Child",
+                "a never-returning function must not return",
+            ]
+        );
+        assert_eq!(diags[0].range.start.line, 7);
+        assert_eq!(
+            diags[3].severity,
+            Some(lsp_types::DiagnosticSeverity::WARNING)
+        );
+    }
+
+    #[test]
+    fn generators() {
+        let src = "<?php
+        declare(strict_types=1);
+
+        class User { public function name(): string { return ''; } }
+        class Repo {
+            public function fine(): Generator { yield 1; }
+            public function loose(): iterable { yield from [1]; }
+            public function wrong(): array { yield 1; }
+            /** @return int */
+            public function doc() { yield 1; }
+            /** @return Generator<int, User> */
+            public function users() { yield new User; }
+            /** @return User[] */
+            public function list() { return []; }
+            public function names(): int {
+                foreach ($this->users() as $id => $user) { return $user->name(); }
+                foreach ($this->list() as &$user) { return $user->name(); }
+                foreach ([] as $user) { return $user->name(); }
+                return 0;
+            }
+        }
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("return"))
+            .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "a generator returns a Generator, which doesn't fit return type array",
+                "a generator returns a Generator, which doesn't fit return type int",
+                "expected return type int, found string",
+                "expected return type int, found string",
+            ]
+        );
+        assert_eq!(diags[0].range.start.line, 7);
+        assert_eq!(
+            diags[1].severity,
+            Some(lsp_types::DiagnosticSeverity::WARNING)
+        );
+    }
+
+    #[test]
+    fn argument_counts() {
+        let src = "<?php
+        function exact($a, $b) {}
+        function optional($a, $b = 1) {}
+        function variadic($a, ...$rest) {}
+
+        class Base {
+            public function __construct(public int $x) {}
+            public static function make(int $x): static { return new static($x); }
+        }
+        class Child extends Base {
+            public function f() {
+                $this->make();
+                parent::__construct(1, 2);
+                self::make(...[1]);
+            }
+        }
+
+        exact(1, 2);
+        exact(1);
+        optional(1, 2, 3);
+        optional(b: 1);
+        variadic(1, 2, 3, 4);
+        variadic();
+        exact(...);
+        new Child;
+        new Base(1);
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("args"))
+            .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "too few arguments to \\Base::make(): expected 1, found 0",
+                "too many arguments to \\Base::__construct(): expected 1, found 2",
+                "too few arguments to \\exact(): expected 2, found 1",
+                "too many arguments to \\optional(): expected at most 2, found 3",
+                "too few arguments to \\optional(): missing $a",
+                "too few arguments to \\variadic(): expected at least 1, found 0",
+                "too few arguments to \\Base::__construct(): expected 1, found 0",
+            ]
+        );
+        assert_eq!(diags[2].range.start.line, 18);
+        assert_eq!(
+            diags[3].severity,
+            Some(lsp_types::DiagnosticSeverity::WARNING)
+        );
+    }
+
+    #[test]
+    fn named_arguments() {
+        let src = "<?php
+        function send($to, $subject = '', $body = '', ...$headers) {}
+        function exact($a, $b) {}
+
+        send(to: 'a', body: 'b');
+        send('a', body: 'b', cc: 'c');
+        send('a', to: 'b');
+        send(to: 'a', to: 'b');
+        send(subject: 'a', body: 'b');
+        send(to: 'a', 'b');
+        exact(1, c: 2);
+        exact(...['a' => 1], b: 2);
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("args"))
+            .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "$to is passed to \\send() by position already",
+                "$to is passed to \\send() twice",
+                "too few arguments to \\send(): missing $to",
+                "positional argument after named arguments",
+                "\\exact() has no parameter $c",
+                "too few arguments to \\exact(): missing $b",
+            ]
+        );
+        assert_eq!(diags[0].range.start.line, 6);
+        assert!(
+            diags
+                .iter()
+                .all(|d| d.severity == Some(lsp_types::DiagnosticSeverity::ERROR))
+        );
+    }
+
+    #[test]
+    fn first_class_callables() {
+        let src = "<?php
+        function pair($a, $b) {}
+
+        class Math {
+            public static function twice(int $x): int { return $x * 2; }
+            public function half(int $x): int { return intdiv($x, 2); }
+            public function closure(): Closure { return $this->half(...); }
+            public function wrong(): int { return self::twice(...); }
+            public function call() {
+                $half = $this->half(...);
+                $half();
+                $twice = static::twice(...);
+                $twice(1, 2);
+            }
+        }
+
+        $p = pair(...);
+        $p(1);
+        $p = 'pair';
+        $p(1);
+        ";
+        let messages: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| matches!(d.source.as_deref(), Some("args" | "return")))
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "expected return type int, found \\Closure",
+                "too few arguments to \\Math::half(): expected 1, found 0",
+                "too many arguments to \\Math::twice(): expected 1, found 2",
+                "too few arguments to \\pair(): expected 2, found 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn invokable_objects() {
+        let src = "<?php
+        class Handler {
+            public function __invoke(string $request, int $status = 200) {}
+        }
+        class Plain {}
+
+        function handle(Handler $handler, Plain $plain) {
+            $handler('req');
+            $handler();
+            $plain();
+            $double = fn(int $x) => $x * 2;
+            $double(1, 2, 3);
+            $log = function ($message) {};
+            $log();
+        }
+        ";
+        let diags: Vec<_> = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("args"))
+            .collect();
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "too few arguments to \\Handler::__invoke(): expected at least 1, found 0",
+                "too many arguments to {closure}: expected 1, found 3",
+                "too few arguments to {closure}: expected 1, found 0",
+            ]
+        );
+        assert_eq!(diags[0].range.start.line, 8);
+    }
+
+    #[test]
+    fn stub_dependencies() {
+        let src = "<?php
+        strlen('a');
+        new Exception();
+        new Exception();
+        ";
+        let tree = parser().parse(src, None).unwrap();
+        let mut pool = SegmentPool::new();
+        let (_, deps) = super::check(
+            tree.root_node(),
+            src,
+            &mut pool,
+            &CustomTypesDatabase::new(),
+            &stubs(),
+            (&HashMap::new(), &HashMap::new()),
+            Strictness::default(),
+            None,
+        );
+
+        let deps: Vec<_> = deps.iter().map(|ns| ns.to_string()).collect();
+        assert_eq!(deps, vec!["\\strlen", "\\Exception", "\\Exception"]);
+    }
+
+    #[test]
+    fn argument_types() {
+        let src = "<?php
+        class Base {}
+        class Other {}
+        function f(int $i, ?Base $b = null, float ...$rest) {}
+        function g(array &$out) {}
+
+        f(1, new Base, 1, 2.5);
+        f('1');
+        f(1, new Other);
+        f(1, rest: 'x');
+        f(1, null, 1, true);
+        g($x);
+        ";
+        let args = |strictness| {
+            check_with(src, strictness)
+                .into_iter()
+                .filter(|d| d.source.as_deref() == Some("args"))
+                .map(|d| d.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            args(Strictness::Normal),
+            vec!["\\f() expects ?\\Base for $b, found \\Other"]
+        );
+        assert_eq!(
+            args(Strictness::Strict),
+            vec![
+                "\\f() expects int for $i, found string",
+                "\\f() expects ?\\Base for $b, found \\Other",
+                "\\f() expects float for $rest, found string",
+                "\\f() expects float for $rest, found true",
+            ]
+        );
+        assert!(args(Strictness::Off).is_empty());
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let src = "<?php
+        function fail(): never { throw new Exception(); }
+
+        function f($x) {
+            if ($x) {
+                return 1;
+                echo 'a';
+                echo 'b';
+            } else {
+                throw new Exception();
+            }
+            echo 'c';
+        }
+        function g($x) {
+            foreach ($x as $y) {
+                if ($y) {
+                    continue;
+                } elseif (!$y) {
+                    break;
+                }
+                echo 'reachable';
+            }
+            switch ($x) {
+                case 1:
+                    exit(1);
+                    echo 'd';
+                default:
+                    echo 'reachable';
+            }
+            fail();
+            // comment
+            echo 'e';
+        }
+        function h() {
+            die;
+            label:
+            echo 'reachable';
+            goto label;
+            echo 'skipped';
+        }
+        class Guard {
+            public function fail(): never { throw new Exception(); }
+            public static function abort(): never { exit(1); }
+            public function m() {
+                $this->fail();
+                echo 'f';
+            }
+            public function n() {
+                static::abort();
+                echo 'g';
+            }
+            public function o() {
+                trigger_error('bad', E_USER_ERROR);
+                echo 'h';
+            }
+            public function p() {
+                trigger_error('meh', E_USER_WARNING);
+                echo 'reachable';
+            }
+        }
+        return;
+        echo 'top-level code is left alone';
+        ";
+        let unreachable = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("unreachable"))
+            .map(|d| (d.range.start.line, d.range.end.line))
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            unreachable,
+            BTreeSet::from([
+                (6, 7),
+                (11, 11),
+                (25, 25),
+                (31, 31),
+                (38, 38),
+                (45, 45),
+                (49, 49),
+                (53, 53)
+            ])
+        );
+    }
+
+    #[test]
+    fn deprecations() {
+        let src = "<?php
+        /** @deprecated use Fresh instead */
+        class Old {
+            public function m() { return self::A; }
+        }
+        class Fresh {
+            #[\\Deprecated(message: 'use B')]
+            const A = 1;
+            const B = 2;
+
+            /**
+             * Does things.
+             *
+             * @deprecated
+             */
+            public static function s() {}
+            #[Deprecated(reason: \"not needed\", since: \"8.0\")]
+            public function __construct() {}
+        }
+        #[Deprecated('use g()')]
+        function f() {}
+
+        class Child extends Old {}
+        f();
+        new Fresh();
+        Fresh::s();
+        Fresh::B;
+        echo Fresh::A;
+        ";
+        let deprecations = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("deprecated"))
+            .map(|d| {
+                assert_eq!(d.tags, Some(vec![DiagnosticTag::DEPRECATED]));
+                (d.range.start.line, d.message)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            deprecations,
+            vec![
+                (
+                    22,
+                    "class \\Old is deprecated: use Fresh instead".to_string()
+                ),
+                (23, "function \\f() is deprecated: use g()".to_string()),
+                (
+                    24,
+                    "constructor \\Fresh::__construct() is deprecated: not needed".to_string()
+                ),
+                (25, "method \\Fresh::s() is deprecated".to_string()),
+                (27, "constant \\Fresh::A is deprecated: use B".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn match_exhaustiveness() {
+        let src = "<?php
+        namespace App;
+
+        enum Suit {
+            case Hearts;
+            case Spades;
+            case Clubs;
+
+            public function color(): string {
+                return match ($this) {
+                    self::Hearts => 'red',
+                    self::Spades, self::Clubs => 'black',
+                };
+            }
+        }
+
+        function f(Suit $s, $other) {
+            $a = match ($s) {
+                Suit::Hearts, Suit::Spades => 1,
+                Suit::Hearts => 2,
+            };
+            $b = match ($s) {
+                Suit::Hearts => 1,
+                default => 2,
+            };
+            $c = match ($s) {
+                $other => 1,
+            };
+            $d = fn() => match ($s) {
+                Suit::Clubs => 1,
+            };
+            $e = match (Suit::Clubs) {
+                Suit::Clubs => 1,
+            };
+        }
+        ";
+        let diagnostics = check(src)
+            .into_iter()
+            .filter(|d| d.source.as_deref() == Some("match"))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    19,
+                    "\\App\\Suit::Hearts is already handled by an earlier arm".to_string()
+                ),
+                (17, "unhandled enum cases: \\App\\Suit::Clubs".to_string()),
+                (
+                    28,
+                    "unhandled enum cases: \\App\\Suit::Hearts, \\App\\Suit::Spades".to_string()
+                ),
+                (
+                    31,
+                    "unhandled enum cases: \\App\\Suit::Hearts, \\App\\Suit::Spades".to_string()
+                ),
+            ]
+        );
+    }
+}
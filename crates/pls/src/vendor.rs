@@ -0,0 +1,158 @@
+use tree_sitter::Node;
+
+use pls_types::normalized_path;
+
+use std::path::{Path, PathBuf};
+
+/// Read one of the maps Composer generates in `vendor/composer`, like `autoload_psr4.php` or
+/// `autoload_classmap.php`: an array from namespace prefixes or class names to a path, or to an
+/// array of them.
+///
+/// The paths are built out of `$vendorDir`, `$baseDir` and `__DIR__`, which only depend on where
+/// the map is, so we work them out instead of running anything.
+pub fn generated_map(
+    node: Node<'_>,
+    content: &str,
+    composer_dir: &Path,
+) -> Vec<(String, Vec<PathBuf>)> {
+    let vendor_dir = composer_dir.parent().unwrap_or(composer_dir);
+    let base_dir = vendor_dir.parent().unwrap_or(vendor_dir);
+    let dirs = Dirs {
+        composer: composer_dir,
+        vendor: vendor_dir,
+        base: base_dir,
+    };
+
+    let mut cursor = node.walk();
+    let Some(array) = node
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "return_statement")
+        .and_then(|ret| ret.named_child(0))
+        .filter(|array| array.kind() == "array_creation_expression")
+    else {
+        return Vec::new();
+    };
+
+    let mut map = Vec::new();
+    let mut cursor = array.walk();
+    for element in array.named_children(&mut cursor) {
+        let (Some(key), Some(value)) = (element.named_child(0), element.named_child(1)) else {
+            continue;
+        };
+        let Some(key) = string_value(key, content) else {
+            continue;
+        };
+        let paths: Vec<String> = if value.kind() == "array_creation_expression" {
+            let mut cursor = value.walk();
+            value
+                .named_children(&mut cursor)
+                .filter_map(|path| path.named_child(0))
+                .filter_map(|path| path_value(path, content, &dirs))
+                .collect()
+        } else {
+            path_value(value, content, &dirs).into_iter().collect()
+        };
+        let paths = paths
+            .into_iter()
+            .map(|path| normalized_path(Path::new(&path)))
+            .collect();
+        map.push((key, paths));
+    }
+
+    map
+}
+
+struct Dirs<'a> {
+    composer: &'a Path,
+    vendor: &'a Path,
+    base: &'a Path,
+}
+
+/// The contents of a single-quoted string, which is all Composer writes.
+fn string_value(node: Node<'_>, content: &str) -> Option<String> {
+    if node.kind() != "string" {
+        return None;
+    }
+
+    let mut range = node.byte_range();
+    range.start += 1;
+    range.end -= 1;
+    Some(content[range].replace("\\\\", "\\").replace("\\'", "'"))
+}
+
+fn path_value(node: Node<'_>, content: &str, dirs: &Dirs<'_>) -> Option<String> {
+    match node.kind() {
+        "binary_expression" => {
+            let left = path_value(node.child_by_field_name("left")?, content, dirs)?;
+            let right = path_value(node.child_by_field_name("right")?, content, dirs)?;
+            Some(left + &right)
+        }
+        "variable_name" => match &content[node.byte_range()] {
+            "$vendorDir" => Some(dirs.vendor.to_string_lossy().into_owned()),
+            "$baseDir" => Some(dirs.base.to_string_lossy().into_owned()),
+            _ => None,
+        },
+        "name" if &content[node.byte_range()] == "__DIR__" => {
+            Some(dirs.composer.to_string_lossy().into_owned())
+        }
+        _ => string_value(node, content),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use std::path::{Path, PathBuf};
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    #[test]
+    fn generated_maps() {
+        let src = "<?php
+
+// autoload_psr4.php @generated by Composer
+
+$vendorDir = dirname(__DIR__);
+$baseDir = dirname($vendorDir);
+
+return array(
+    'Monolog\\\\' => array($vendorDir . '/monolog/monolog/src/Monolog'),
+    'App\\\\' => array($baseDir . '/app', __DIR__ . '/../../lib'),
+    'Attribute' => $vendorDir . '/symfony/polyfill-php80/Resources/stubs/Attribute.php',
+    'Weird' => some_function(),
+);
+";
+        let tree = parser().parse(src, None).unwrap();
+        let map = super::generated_map(tree.root_node(), src, Path::new("/proj/vendor/composer"));
+
+        assert_eq!(
+            map,
+            vec![
+                (
+                    "Monolog\\".to_string(),
+                    vec![PathBuf::from("/proj/vendor/monolog/monolog/src/Monolog")]
+                ),
+                (
+                    "App\\".to_string(),
+                    vec![PathBuf::from("/proj/app"), PathBuf::from("/proj/lib")]
+                ),
+                (
+                    "Attribute".to_string(),
+                    vec![PathBuf::from(
+                        "/proj/vendor/symfony/polyfill-php80/Resources/stubs/Attribute.php"
+                    )]
+                ),
+                ("Weird".to_string(), vec![]),
+            ]
+        );
+    }
+}
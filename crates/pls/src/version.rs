@@ -0,0 +1,368 @@
+use lsp_types::*;
+use tree_sitter::Node;
+
+use pls_types::{CustomType, CustomTypesDatabase, PhpNamespace, PhpVersion, SegmentPool};
+
+use crate::compat::to_range;
+
+/// Flag what the PHP version the project targets can't run: syntax that came later, and built-in
+/// functions that were added later.
+///
+/// Run this after the file's types have been ingested, since built-ins are only known once their
+/// stubs are.
+pub fn check(
+    node: Node<'_>,
+    content: &str,
+    target: PhpVersion,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut namespace = PhpNamespace::empty();
+    walk(
+        node,
+        content,
+        target,
+        types,
+        ns_store,
+        &mut namespace,
+        &mut diagnostics,
+    );
+
+    diagnostics
+}
+
+fn walk(
+    node: Node<'_>,
+    content: &str,
+    target: PhpVersion,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+    namespace: &mut PhpNamespace,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if node.kind() == "namespace_definition" {
+        *namespace = node
+            .child_by_field_name("name")
+            .map(|name| ns_store.intern_str(&content[name.byte_range()]))
+            .unwrap_or_else(PhpNamespace::empty);
+    }
+
+    if let Some((since, feature)) = feature(node, content)
+        && target < since
+    {
+        diagnostics.push(Diagnostic {
+            range: to_range(&node.range()),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("php-version".to_string()),
+            message: format!("{feature} needs PHP {since}, but the project targets {target}"),
+            ..Default::default()
+        });
+    }
+
+    if node.kind() == "function_call_expression"
+        && let Some(name) = node.child_by_field_name("function")
+        && let Some(since) = builtin_since(name, content, types, ns_store, namespace)
+        && target < since
+    {
+        diagnostics.push(Diagnostic {
+            range: to_range(&name.range()),
+            // polyfills can fill the gap, and we can't always tell they're there
+            severity: Some(DiagnosticSeverity::WARNING),
+            source: Some("php-version".to_string()),
+            message: format!(
+                "{} was added in PHP {since}, but the project targets {target}",
+                &content[name.byte_range()]
+            ),
+            ..Default::default()
+        });
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        walk(
+            child,
+            content,
+            target,
+            types,
+            ns_store,
+            namespace,
+            diagnostics,
+        );
+    }
+}
+
+/// The language feature `node` is, if it's one that hasn't always been around, with the version
+/// that brought it in.
+fn feature(node: Node<'_>, content: &str) -> Option<(PhpVersion, &'static str)> {
+    let has_child = |kind: &str| {
+        let mut cursor = node.walk();
+        node.children(&mut cursor).any(|child| child.kind() == kind)
+    };
+
+    let feature = match node.kind() {
+        "arrow_function" => (PhpVersion::new(7, 4), "an arrow function"),
+        "augmented_assignment_expression" if has_child("??=") => (PhpVersion::new(7, 4), "`??=`"),
+        "property_declaration" if node.child_by_field_name("type").is_some() => {
+            if has_child("readonly_modifier") {
+                (PhpVersion::new(8, 1), "a readonly property")
+            } else if has_child("property_hook_list") {
+                (PhpVersion::new(8, 4), "a property hook")
+            } else {
+                (PhpVersion::new(7, 4), "a typed property")
+            }
+        }
+        "match_expression" => (PhpVersion::new(8, 0), "`match`"),
+        "nullsafe_member_access_expression" | "nullsafe_member_call_expression" => {
+            (PhpVersion::new(8, 0), "`?->`")
+        }
+        "argument" if node.child_by_field_name("name").is_some() => {
+            (PhpVersion::new(8, 0), "a named argument")
+        }
+        "property_promotion_parameter" => (PhpVersion::new(8, 0), "constructor promotion"),
+        "union_type" if node.named_child_count() > 1 => (PhpVersion::new(8, 0), "a union type"),
+        // older versions read `#[...]` as a comment, which is fine unless it eats code
+        "attribute_list" if node.start_position().row != node.end_position().row => {
+            (PhpVersion::new(8, 0), "an attribute spanning several lines")
+        }
+        "attribute_list"
+            if node
+                .next_sibling()
+                .is_some_and(|next| next.start_position().row == node.end_position().row) =>
+        {
+            (
+                PhpVersion::new(8, 0),
+                "an attribute followed by code on its line",
+            )
+        }
+        "enum_declaration" => (PhpVersion::new(8, 1), "an enum"),
+        "intersection_type" => (PhpVersion::new(8, 1), "an intersection type"),
+        "variadic_placeholder" => (PhpVersion::new(8, 1), "a first-class callable"),
+        "bottom_type" if &content[node.byte_range()] == "never" => {
+            (PhpVersion::new(8, 1), "the `never` type")
+        }
+        "class_declaration" if has_child("readonly_modifier") => {
+            (PhpVersion::new(8, 2), "a readonly class")
+        }
+        "const_declaration" if node.child_by_field_name("type").is_some() => {
+            (PhpVersion::new(8, 3), "a typed class constant")
+        }
+        _ => return None,
+    };
+
+    Some(feature)
+}
+
+/// When the built-in function `name` calls was added, if we know. Functions in the current
+/// namespace win over built-ins, as PHP falls back to the global one only when there isn't one.
+fn builtin_since(
+    name: Node<'_>,
+    content: &str,
+    types: &CustomTypesDatabase,
+    ns_store: &mut SegmentPool,
+    namespace: &PhpNamespace,
+) -> Option<PhpVersion> {
+    let text = &content[name.byte_range()];
+    let global = match name.kind() {
+        "name" => {
+            let mut local = namespace.clone();
+            local.extend(ns_store.intern_str(text).0);
+            if namespace.len() > 0 && types.0.contains_key(&local) {
+                return None;
+            }
+            ns_store.intern_str(text)
+        }
+        "qualified_name" if text.starts_with('\\') && !text[1..].contains('\\') => {
+            ns_store.intern_str(text)
+        }
+        _ => return None,
+    };
+
+    match &types.0.get(&global)?.t {
+        CustomType::Function(function) => function.since,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tree_sitter::Parser;
+    use tree_sitter_php::LANGUAGE_PHP;
+
+    use pls_types::{CustomTypesDatabase, PhpVersion, SegmentPool};
+
+    use std::path::Path;
+
+    use crate::analyze::{injest_stub_types, injest_types};
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&LANGUAGE_PHP.into())
+            .expect("error loading PHP grammar");
+
+        parser
+    }
+
+    fn check(src: &str, target: PhpVersion) -> Vec<(u32, String)> {
+        let stub = "<?php
+/**
+ * @since 8.0
+ */
+function str_contains(string $haystack, string $needle): bool {}
+";
+        let mut pool = SegmentPool::new();
+        let mut types = CustomTypesDatabase::new();
+        let tree = parser().parse(stub, None).unwrap();
+        injest_stub_types(
+            tree.root_node(),
+            stub,
+            Path::new("/stubs/standard.php"),
+            &mut pool,
+            &mut types,
+        );
+        let tree = parser().parse(src, None).unwrap();
+        injest_types(
+            tree.root_node(),
+            src,
+            Path::new("/tmp/test.php"),
+            &mut pool,
+            &mut types,
+        );
+
+        super::check(tree.root_node(), src, target, &types, &mut pool)
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn newer_syntax() {
+        let src = "<?php
+enum Suit {}
+readonly class Point {
+    public function __construct(private int $x) {}
+}
+function f(int|string $a): never {
+    $b = $a?->b;
+    $c = match ($a) { default => 1 };
+    $d = fn() => strlen(...);
+}
+";
+        assert_eq!(
+            check(src, PhpVersion::new(7, 4)),
+            vec![
+                (
+                    1,
+                    "an enum needs PHP 8.1, but the project targets 7.4".to_string()
+                ),
+                (
+                    2,
+                    "a readonly class needs PHP 8.2, but the project targets 7.4".to_string()
+                ),
+                (
+                    3,
+                    "constructor promotion needs PHP 8.0, but the project targets 7.4".to_string()
+                ),
+                (
+                    5,
+                    "a union type needs PHP 8.0, but the project targets 7.4".to_string()
+                ),
+                (
+                    5,
+                    "the `never` type needs PHP 8.1, but the project targets 7.4".to_string()
+                ),
+                (
+                    6,
+                    "`?->` needs PHP 8.0, but the project targets 7.4".to_string()
+                ),
+                (
+                    7,
+                    "`match` needs PHP 8.0, but the project targets 7.4".to_string()
+                ),
+                (
+                    8,
+                    "a first-class callable needs PHP 8.1, but the project targets 7.4".to_string()
+                ),
+            ]
+        );
+        assert_eq!(check(src, PhpVersion::new(8, 1)).len(), 1);
+        assert!(check(src, PhpVersion::new(8, 3)).is_empty());
+    }
+
+    #[test]
+    fn newer_functions() {
+        let src = "<?php
+namespace App;
+
+if (str_contains('abc', 'b') || \\str_contains('abc', 'c')) {}
+";
+        assert_eq!(
+            check(src, PhpVersion::new(7, 4)),
+            vec![
+                (
+                    3,
+                    "str_contains was added in PHP 8.0, but the project targets 7.4".to_string()
+                ),
+                (
+                    3,
+                    "\\str_contains was added in PHP 8.0, but the project targets 7.4".to_string()
+                ),
+            ]
+        );
+        assert!(check(src, PhpVersion::new(8, 0)).is_empty());
+
+        // a function of the same name in the namespace is what gets called
+        let src = "<?php
+namespace App;
+
+function str_contains($a, $b) {}
+str_contains('abc', 'b');
+";
+        assert!(check(src, PhpVersion::new(7, 4)).is_empty());
+
+        // `@since` is only about PHP in the stubs
+        let src = "<?php
+/**
+ * @since 8.5.0
+ */
+function helper() {}
+helper();
+";
+        assert!(check(src, PhpVersion::new(7, 4)).is_empty());
+    }
+
+    #[test]
+    fn attributes_as_comments() {
+        let src = "<?php
+class Legacy implements IteratorAggregate {
+    #[\\ReturnTypeWillChange]
+    public function getIterator() {}
+
+    #[Pure] public function pure() {}
+
+    #[Route(
+        '/home',
+    )]
+    public function home() {}
+}
+";
+        assert_eq!(
+            check(src, PhpVersion::new(7, 4)),
+            vec![
+                (
+                    5,
+                    "an attribute followed by code on its line needs PHP 8.0, but the project \
+                     targets 7.4"
+                        .to_string()
+                ),
+                (
+                    7,
+                    "an attribute spanning several lines needs PHP 8.0, but the project targets \
+                     7.4"
+                    .to_string()
+                ),
+            ]
+        );
+    }
+}
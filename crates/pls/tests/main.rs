@@ -42,14 +42,15 @@ fn code_actions_on_opened_file() {
             });
             let id = client.request::<request::CodeActionRequest>(CodeActionParams {
                 text_document: TextDocumentIdentifier { uri: uri.clone() },
+                // the whole document, so that both echoes are converted
                 range: Range {
                     start: Position {
-                        line: 1,
-                        character: 1,
+                        line: 0,
+                        character: 0,
                     },
                     end: Position {
-                        line: 1,
-                        character: 1,
+                        line: 2,
+                        character: 48,
                     },
                 },
                 context: CodeActionContext {
@@ -6,6 +6,7 @@ use lsp_types::*;
 use pls::global_state::GlobalState;
 use pls::registry::{NotificationRegistry, RequestRegistry};
 
+use std::path::Path;
 use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
@@ -107,7 +108,7 @@ where
     let (connection, client) = Connection::memory();
     let mut client = FakeClient::new(client);
     thread::spawn(move || {
-        let mut state = GlobalState::new(test_cfg.stubs_filename, connection)
+        let mut state = GlobalState::new(Some(Path::new(test_cfg.stubs_filename)), connection)
             .expect("global state initialization");
         let notification_registry = NotificationRegistry::default();
         let request_registry = RequestRegistry::default();